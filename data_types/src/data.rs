@@ -23,9 +23,31 @@ pub fn type_description(value: wb::ColumnValue) -> &'static str {
         F64Value => "f64",
         BoolValue => "bool",
         StringValue => "String",
+        DecimalValue => "decimal",
     }
 }
 
+/// Returns the unscaled 128 bit integer value of `value`
+pub fn decimal_unscaled_value(value: &wb::DecimalValue<'_>) -> i128 {
+    (i128::from(value.high()) << 64) | i128::from(value.low())
+}
+
+/// Formats the unscaled decimal `unscaled` with `scale` digits after
+/// the decimal point, e.g. `12345` with a scale of 2 as `123.45`
+pub fn format_decimal(unscaled: i128, scale: u8) -> String {
+    if scale == 0 {
+        return unscaled.to_string();
+    }
+
+    let digits = unscaled.abs().to_string();
+    let scale = usize::from(scale);
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if unscaled < 0 { "-" } else { "" };
+
+    format!("{}{}.{}", sign, integer, fraction)
+}
+
 /// A friendlier wrapper to help deal with the Flatbuffers write data
 #[derive(Debug, Default, Clone)]
 pub struct ReplicatedWrite {
@@ -118,6 +140,14 @@ impl fmt::Display for ReplicatedWrite {
                                                     .value()
                                                     .unwrap_or("")
                                                     .to_string(),
+                                                wb::ColumnValue::DecimalValue => {
+                                                    let value =
+                                                        value.value_as_decimal_value().unwrap();
+                                                    format_decimal(
+                                                        decimal_unscaled_value(&value),
+                                                        value.scale(),
+                                                    )
+                                                }
                                                 wb::ColumnValue::NONE => "".to_string(),
                                             };
                                             write!(f, " {}:{}", value.column().unwrap_or(""), val)?;
@@ -3,6 +3,7 @@
 //! interface abstracts away many of the details
 mod counters;
 pub mod fieldlist;
+mod offset;
 mod planning;
 mod schema_pivot;
 pub mod seriesset;
@@ -16,9 +17,12 @@ use arrow_deps::{
 };
 use counters::ExecutionCounters;
 
+use offset::OffsetNode;
 use planning::IOxExecutionContext;
 use schema_pivot::SchemaPivotNode;
 
+pub use schema_pivot::make_schema_pivot_output_schema;
+
 use fieldlist::{FieldList, IntoFieldList};
 use seriesset::{
     Error as SeriesSetError, GroupedSeriesSetConverter, GroupedSeriesSetItem, SeriesSet,
@@ -475,6 +479,17 @@ pub fn make_schema_pivot(input: LogicalPlan) -> LogicalPlan {
     LogicalPlan::Extension { node }
 }
 
+/// Create an Offset node which skips the first `offset` rows of
+/// `input` and passes the remaining rows through unchanged.
+///
+/// Combined with a limit this can be used to page through results;
+/// the pages are only consistent if `input` has a stable ordering.
+pub fn make_offset(input: LogicalPlan, offset: usize) -> LogicalPlan {
+    let node = Arc::new(OffsetNode::new(input, offset));
+
+    LogicalPlan::Extension { node }
+}
+
 /// plans and runs the plans in parallel and collects the results
 /// run each plan in parallel and collect the results
 async fn run_logical_plans(
@@ -0,0 +1,315 @@
+//! This module contains code for the "Offset" DataFusion extension
+//! plan node
+//!
+//! An Offset node skips the first `offset` rows of its (single
+//! partition) input and passes all subsequent rows through
+//! unchanged. DataFusion's `Limit` has no notion of skipping rows, so
+//! this node is combined with a `Limit` to page through results.
+//!
+//! Note that paging only produces consistent results if the input
+//! has a stable (total) ordering.
+
+use std::{
+    any::Any,
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+
+use arrow_deps::{
+    arrow::array::ArrayRef,
+    arrow::datatypes::SchemaRef,
+    arrow::record_batch::RecordBatch,
+    datafusion::logical_plan::{self, Expr, LogicalPlan, UserDefinedLogicalNode},
+    datafusion::physical_plan::common::SizedRecordBatchStream,
+    datafusion::physical_plan::SendableRecordBatchStream,
+    datafusion::{
+        error::DataFusionError,
+        physical_plan::{Distribution, ExecutionPlan, Partitioning},
+    },
+};
+
+use tokio::stream::StreamExt;
+
+pub use arrow_deps::datafusion::error::{DataFusionError as Error, Result};
+
+/// Implements the Offset operation described in make_offset
+pub struct OffsetNode {
+    input: LogicalPlan,
+    offset: usize,
+    // these expressions represent what columns are "used" by this
+    // node (in this case all of them) -- columns that are not used
+    // are optimzied away by datafusion.
+    exprs: Vec<Expr>,
+}
+
+impl OffsetNode {
+    pub fn new(input: LogicalPlan, offset: usize) -> Self {
+        // Form exprs that refer to all of our input columns (so that
+        // datafusion doesn't opimize them away)
+        let exprs = input
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| logical_plan::col(field.name()))
+            .collect::<Vec<_>>();
+
+        Self {
+            input,
+            offset,
+            exprs,
+        }
+    }
+
+    /// The number of rows to skip
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Debug for OffsetNode {
+    /// Use explain format for the Debug format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_for_explain(f)
+    }
+}
+
+impl UserDefinedLogicalNode for OffsetNode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![&self.input]
+    }
+
+    /// Schema for Offset is the same as its input
+    fn schema(&self) -> &SchemaRef {
+        self.input.schema()
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        self.exprs.clone()
+    }
+
+    /// For example: `Offset: 10`
+    fn fmt_for_explain(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Offset: {}", self.offset)
+    }
+
+    fn from_template(
+        &self,
+        exprs: &Vec<Expr>,
+        inputs: &Vec<LogicalPlan>,
+    ) -> Arc<dyn UserDefinedLogicalNode + Send + Sync> {
+        assert_eq!(inputs.len(), 1, "Offset: input sizes inconistent");
+        assert_eq!(
+            exprs.len(),
+            self.exprs.len(),
+            "Offset: expression sizes inconistent"
+        );
+        Arc::new(Self::new(inputs[0].clone(), self.offset))
+    }
+}
+
+// ------ The implementation of Offset code follows -----
+
+/// Physical operator that implements the Offset operation
+pub struct OffsetExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Number of input rows to skip
+    offset: usize,
+}
+
+impl OffsetExec {
+    pub fn new(input: Arc<dyn ExecutionPlan>, offset: usize) -> Self {
+        Self { input, offset }
+    }
+}
+
+impl Debug for OffsetExec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OffsetExec({})", self.offset)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for OffsetExec {
+    fn as_any(&self) -> &(dyn std::any::Any + 'static) {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn required_child_distribution(&self) -> Distribution {
+        Distribution::SinglePartition
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(Self {
+                input: children[0].clone(),
+                offset: self.offset,
+            })),
+            _ => Err(DataFusionError::Internal(
+                "OffsetExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    /// Execute one partition and return an iterator over RecordBatch
+    async fn execute(&self, partition: usize) -> Result<SendableRecordBatchStream> {
+        if 0 != partition {
+            return Err(DataFusionError::Internal(format!(
+                "OffsetExec invalid partition {}",
+                partition
+            )));
+        }
+
+        let mut input_reader = self.input.execute(partition).await?;
+
+        let mut rows_to_skip = self.offset;
+        let mut batches = Vec::new();
+
+        while let Some(input_batch) = input_reader.next().await.transpose()? {
+            let num_rows = input_batch.num_rows();
+
+            if rows_to_skip >= num_rows {
+                rows_to_skip -= num_rows;
+            } else if rows_to_skip > 0 {
+                let columns = input_batch
+                    .columns()
+                    .iter()
+                    .map(|column| column.slice(rows_to_skip, num_rows - rows_to_skip))
+                    .collect::<Vec<ArrayRef>>();
+                rows_to_skip = 0;
+
+                batches.push(Arc::new(RecordBatch::try_new(
+                    input_batch.schema(),
+                    columns,
+                )?));
+            } else {
+                batches.push(Arc::new(input_batch));
+            }
+        }
+
+        Ok(Box::pin(SizedRecordBatchStream::new(
+            self.schema(),
+            batches,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_deps::{
+        arrow::{
+            array::{Array, Int64Array},
+            datatypes::{DataType, Field, Schema},
+        },
+        datafusion::physical_plan::memory::MemoryExec,
+    };
+
+    #[tokio::test]
+    async fn offset_exec_within_first_batch() -> Result<()> {
+        let output = run_offset(&[&[1, 2, 3], &[4, 5]], 2).await;
+        assert_eq!(output, vec![3, 4, 5]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offset_exec_spanning_batches() -> Result<()> {
+        let output = run_offset(&[&[1, 2, 3], &[4, 5]], 4).await;
+        assert_eq!(output, vec![5]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offset_exec_zero_and_past_end() -> Result<()> {
+        let output = run_offset(&[&[1, 2, 3], &[4, 5]], 0).await;
+        assert_eq!(output, vec![1, 2, 3, 4, 5]);
+
+        let output = run_offset(&[&[1, 2, 3], &[4, 5]], 10).await;
+        assert_eq!(output, Vec::<i64>::new());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offset_exec_bad_partition() -> Result<()> {
+        // ensure passing in a bad partition generates a reasonable error
+        let offset = OffsetExec::new(make_memory_exec(vec![]), 1);
+
+        let results = offset.execute(1).await;
+
+        let expected_error = "OffsetExec invalid partition 1";
+        let actual_error = match results {
+            Ok(_) => "Unexpected success running offset".into(),
+            Err(e) => format!("{:?}", e),
+        };
+
+        assert!(
+            actual_error.contains(expected_error),
+            "expected '{}' not found in '{}'",
+            expected_error,
+            actual_error
+        );
+
+        Ok(())
+    }
+
+    /// Runs an OffsetExec skipping `offset` rows over input batches
+    /// with a single Int64 column, returning the output values
+    async fn run_offset(input_batches: &[&[i64]], offset: usize) -> Vec<i64> {
+        let data = input_batches
+            .iter()
+            .map(|values| {
+                let array: ArrayRef = Arc::new(Int64Array::from(values.to_vec()));
+                RecordBatch::try_new(input_schema(), vec![array]).expect("creating batch")
+            })
+            .collect();
+
+        let offset = OffsetExec::new(make_memory_exec(data), offset);
+        let mut reader = offset.execute(0).await.expect("executing offset");
+
+        let mut output = Vec::new();
+        while let Some(batch) = reader.next().await.transpose().expect("reading next batch") {
+            let array = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .expect("Int64Array");
+            output.extend((0..array.len()).map(|i| array.value(i)));
+        }
+        output
+    }
+
+    fn input_schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![Field::new("A", DataType::Int64, false)]))
+    }
+
+    /// Create an ExecutionPlan that produces `data` record batches.
+    fn make_memory_exec(data: Vec<RecordBatch>) -> Arc<dyn ExecutionPlan> {
+        let partitions = vec![data]; // single partition
+        let projection = None;
+
+        let memory_exec = MemoryExec::try_new(&partitions, input_schema(), projection)
+            .expect("creating memory exec");
+
+        Arc::new(memory_exec)
+    }
+}
@@ -19,6 +19,7 @@ use arrow_deps::{
     },
 };
 
+use crate::exec::offset::{OffsetExec, OffsetNode};
 use crate::exec::schema_pivot::{SchemaPivotExec, SchemaPivotNode};
 
 use tracing::debug;
@@ -43,7 +44,7 @@ impl QueryPlanner for IOxQueryPlanner {
         logical_plan: &LogicalPlan,
         ctx_state: &ExecutionContextState,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        // Teach the default physical planner how to plan SchemaPivot nodes.
+        // Teach the default physical planner how to plan SchemaPivot and Offset nodes.
         let physical_planner =
             DefaultPhysicalPlanner::with_extension_planner(Arc::new(IOxExtensionPlanner {}));
         // Delegate most work of physical planning to the default physical planner
@@ -62,18 +63,24 @@ impl ExtensionPlanner for IOxExtensionPlanner {
         inputs: Vec<Arc<dyn ExecutionPlan>>,
         _ctx_state: &ExecutionContextState,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        match node.as_any().downcast_ref::<SchemaPivotNode>() {
-            Some(schema_pivot) => {
-                assert_eq!(inputs.len(), 1, "Inconsistent number of inputs");
-                Ok(Arc::new(SchemaPivotExec::new(
-                    inputs[0].clone(),
-                    schema_pivot.schema().clone(),
-                )))
-            }
-            None => Err(Error::Internal(format!(
+        let any = node.as_any();
+        if let Some(schema_pivot) = any.downcast_ref::<SchemaPivotNode>() {
+            assert_eq!(inputs.len(), 1, "Inconsistent number of inputs");
+            Ok(Arc::new(SchemaPivotExec::new(
+                inputs[0].clone(),
+                schema_pivot.schema().clone(),
+            )))
+        } else if let Some(offset) = any.downcast_ref::<OffsetNode>() {
+            assert_eq!(inputs.len(), 1, "Inconsistent number of inputs");
+            Ok(Arc::new(OffsetExec::new(
+                inputs[0].clone(),
+                offset.offset(),
+            )))
+        } else {
+            Err(Error::Internal(format!(
                 "Unknown extension node type {:?}",
                 node
-            ))),
+            )))
         }
     }
 }
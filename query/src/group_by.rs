@@ -2,6 +2,16 @@
 //! and Aggregate functions in IOx, designed to be compatible with
 //! InfluxDB classic
 
+use snafu::{ensure, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid percentile {}: must be between 0 and 1", percentile))]
+    InvalidPercentile { percentile: f64 },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Aggregate {
     Sum,
@@ -11,6 +21,58 @@ pub enum Aggregate {
     First,
     Last,
     Mean,
+    /// The value at the given percentile, see `Percentile`.
+    ///
+    /// Note that aggregates are not yet applied when planning grouped
+    /// queries (https://github.com/influxdata/influxdb_iox/issues/448)
+    Percentile(Percentile),
+}
+
+impl Aggregate {
+    /// Creates a `Percentile` aggregate, validating that
+    /// `percentile` is in `[0, 1]`
+    pub fn percentile(percentile: f64) -> Result<Self> {
+        Percentile::new(percentile).map(Self::Percentile)
+    }
+}
+
+/// A percentile between 0 and 1 (so 0.5 is the median). It can only
+/// be created by `Percentile::new`, which validates the value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentile(f64);
+
+// `Percentile::new` rejects NaN, so the value is always equal to itself
+impl Eq for Percentile {}
+
+impl Percentile {
+    /// Creates a `Percentile`, validating that `percentile` is in
+    /// `[0, 1]`
+    pub fn new(percentile: f64) -> Result<Self> {
+        ensure!(
+            (0.0..=1.0).contains(&percentile),
+            InvalidPercentile { percentile }
+        );
+        Ok(Self(percentile))
+    }
+
+    /// Returns the percentile, between 0 and 1
+    pub fn value(self) -> f64 {
+        self.0
+    }
+
+    /// Returns the exact percentile of `sorted_values`, which must be
+    /// sorted in ascending order, interpolating linearly between the
+    /// two closest ranks. Returns `None` if there are no values.
+    pub fn of_sorted(self, sorted_values: &[f64]) -> Option<f64> {
+        let last = sorted_values.len().checked_sub(1)?;
+        let rank = self.0 * last as f64;
+        let lower = rank.floor() as usize;
+        let upper = rank.ceil() as usize;
+        let fraction = rank - lower as f64;
+
+        let (lower, upper) = (sorted_values[lower], sorted_values[upper]);
+        Some(lower + (upper - lower) * fraction)
+    }
 }
 
 /// Defines the different ways series can be grouped and aggregated
@@ -68,3 +130,43 @@ impl WindowDuration {
         Self::Variable { months, negative }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        for &percentile in &[0.0, 0.5, 1.0] {
+            match Aggregate::percentile(percentile).unwrap() {
+                Aggregate::Percentile(p) => assert_eq!(p.value(), percentile),
+                agg => panic!("unexpected aggregate: {:?}", agg),
+            }
+        }
+
+        for percentile in &[-0.1, 1.1, f64::NAN] {
+            let res = Aggregate::percentile(*percentile);
+            assert!(
+                matches!(res, Err(Error::InvalidPercentile { .. })),
+                "unexpected result for {}: {:?}",
+                percentile,
+                res
+            );
+        }
+    }
+
+    #[test]
+    fn test_percentile_of_sorted() {
+        let percentile = |p| Percentile::new(p).unwrap();
+        let values = [1.0, 2.0, 4.0, 8.0];
+
+        assert_eq!(percentile(0.0).of_sorted(&values), Some(1.0));
+        assert_eq!(percentile(1.0).of_sorted(&values), Some(8.0));
+        // interpolates between the ranks closest to 1.5
+        assert_eq!(percentile(0.5).of_sorted(&values), Some(3.0));
+        assert_eq!(percentile(0.5).of_sorted(&values[..3]), Some(2.0));
+
+        assert_eq!(percentile(0.5).of_sorted(&[5.0]), Some(5.0));
+        assert_eq!(percentile(0.5).of_sorted(&[]), None);
+    }
+}
@@ -17,6 +17,7 @@ pub mod exec;
 pub mod group_by;
 pub mod id;
 pub mod predicate;
+pub mod simple_predicate;
 pub mod util;
 pub mod window;
 
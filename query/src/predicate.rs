@@ -5,6 +5,7 @@
 use std::collections::BTreeSet;
 
 use arrow_deps::datafusion::logical_plan::Expr;
+use regex::Regex;
 
 /// Specifies a continuous range of nanosecond timestamps. Timestamp
 /// predicates are so common and critical to performance of timeseries
@@ -48,6 +49,11 @@ pub struct Predicate {
     /// those tables whose names are in `table_names`
     pub table_names: Option<BTreeSet<String>>,
 
+    /// Optional filter. If present, restrict the results to only
+    /// those tables whose names match this regular expression
+    /// (e.g. `WITH MEASUREMENT =~ /cpu.*/`)
+    pub table_name_regex: Option<Regex>,
+
     // Optional field column selection. If present, further restrict any
     // field columns returned to only those named
     pub field_columns: Option<BTreeSet<String>>,
@@ -130,6 +136,17 @@ impl PredicateBuilder {
         self
     }
 
+    /// Sets a regular expression that table names must match
+    pub fn table_regex(mut self, table_name_regex: Regex) -> Self {
+        assert!(
+            self.inner.table_name_regex.is_none(),
+            "Multiple table regex predicate specification not yet supported"
+        );
+
+        self.inner.table_name_regex = Some(table_name_regex);
+        self
+    }
+
     /// Sets field_column restriction
     pub fn field_columns(mut self, columns: Vec<String>) -> Self {
         // We need to distinguish predicates like `column_name In
@@ -0,0 +1,386 @@
+//! This module contains a parser for a minimal predicate language,
+//! intended for REPLs and tests, such as:
+//!
+//! ```text
+//! city = 'LA' and temp > 80.0 and time >= 100
+//! ```
+//!
+//! The grammar is:
+//!
+//! ```text
+//! predicate  := comparison ( AND comparison )*
+//! comparison := column op literal
+//! column     := [A-Za-z_][A-Za-z0-9_]*
+//! op         := = | != | < | <= | > | >=
+//! literal    := 'string' | integer | float | true | false
+//! ```
+//!
+//! Keywords (`and`, `true` and `false`) are case insensitive. String
+//! literals may not contain `'`.
+//!
+//! Comparisons of the `time` column with an integer (other than `!=`)
+//! restrict the timestamp range of the predicate (several such
+//! comparisons are intersected). All other comparisons are added as
+//! general purpose expressions, comparing the column to the literal.
+
+use arrow_deps::datafusion::{
+    logical_plan::{Expr, Operator},
+    scalar::ScalarValue,
+};
+use data_types::TIME_COLUMN_NAME;
+use snafu::{OptionExt, Snafu};
+
+use crate::predicate::{Predicate, PredicateBuilder, TimestampRange};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unexpected character '{}' at position {}", character, position))]
+    UnexpectedCharacter { character: char, position: usize },
+
+    #[snafu(display("Unterminated string literal starting at position {}", position))]
+    UnterminatedString { position: usize },
+
+    #[snafu(display("Invalid number '{}'", number))]
+    InvalidNumber { number: String },
+
+    #[snafu(display("Expected {}, found '{}'", expected, found))]
+    UnexpectedToken {
+        expected: &'static str,
+        found: String,
+    },
+
+    #[snafu(display("Expected {}, found the end of the predicate", expected))]
+    UnexpectedEnd { expected: &'static str },
+
+    #[snafu(display(
+        "Unsupported time comparison '{} {}': time can only be compared with integers using =, <, <=, > or >=",
+        op,
+        literal
+    ))]
+    UnsupportedTimeComparison { op: String, literal: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Parses `predicate`, written in the language described in the
+/// module documentation, into a `Predicate`. An empty (or all
+/// whitespace) string is a predicate that matches everything.
+pub fn parse_simple_predicate(predicate: &str) -> Result<Predicate> {
+    let tokens = tokenize(predicate)?;
+    let mut tokens = tokens.iter().peekable();
+
+    let mut builder = PredicateBuilder::default();
+    let mut range: Option<TimestampRange> = None;
+
+    if tokens.peek().is_none() {
+        return Ok(builder.build());
+    }
+
+    loop {
+        let column = match tokens.next() {
+            Some(Token::Ident(column)) => column,
+            Some(token) => {
+                return UnexpectedToken {
+                    expected: "a column name",
+                    found: token.to_string(),
+                }
+                .fail()
+            }
+            None => {
+                return UnexpectedEnd {
+                    expected: "a column name",
+                }
+                .fail()
+            }
+        };
+
+        let op = match tokens.next() {
+            Some(Token::Op(op)) => *op,
+            Some(token) => {
+                return UnexpectedToken {
+                    expected: "a comparison operator",
+                    found: token.to_string(),
+                }
+                .fail()
+            }
+            None => {
+                return UnexpectedEnd {
+                    expected: "a comparison operator",
+                }
+                .fail()
+            }
+        };
+
+        let literal = tokens.next().context(UnexpectedEnd {
+            expected: "a literal",
+        })?;
+        let value = literal.to_scalar()?;
+
+        if column == TIME_COLUMN_NAME {
+            let time = match value {
+                ScalarValue::Int64(Some(time)) if op != Operator::NotEq => time,
+                _ => {
+                    return UnsupportedTimeComparison {
+                        op: operator_symbol(op),
+                        literal: literal.to_string(),
+                    }
+                    .fail()
+                }
+            };
+            let current = range.unwrap_or_else(|| TimestampRange::new(i64::MIN, i64::MAX));
+            range = Some(restrict_range(current, op, time));
+        } else {
+            builder = builder.add_expr(Expr::BinaryExpr {
+                left: Box::new(Expr::Column(column.clone())),
+                op,
+                right: Box::new(Expr::Literal(value)),
+            });
+        }
+
+        match tokens.next() {
+            None => break,
+            Some(Token::Ident(keyword)) if keyword.eq_ignore_ascii_case("and") => {}
+            Some(token) => {
+                return UnexpectedToken {
+                    expected: "AND",
+                    found: token.to_string(),
+                }
+                .fail()
+            }
+        }
+    }
+
+    Ok(builder.timestamp_range_option(range).build())
+}
+
+/// Returns the part of `range` where `time op value` holds
+fn restrict_range(range: TimestampRange, op: Operator, value: i64) -> TimestampRange {
+    // the range's end is exclusive
+    let (start, end) = match op {
+        Operator::Eq => (value, value.saturating_add(1)),
+        Operator::Lt => (i64::MIN, value),
+        Operator::LtEq => (i64::MIN, value.saturating_add(1)),
+        Operator::Gt => (value.saturating_add(1), i64::MAX),
+        Operator::GtEq => (value, i64::MAX),
+        _ => unreachable!("only comparisons are parsed"),
+    };
+
+    TimestampRange::new(range.start.max(start), range.end.min(end))
+}
+
+/// Returns the symbol of a comparison operator in the grammar
+fn operator_symbol(op: Operator) -> &'static str {
+    match op {
+        Operator::Eq => "=",
+        Operator::NotEq => "!=",
+        Operator::Lt => "<",
+        Operator::LtEq => "<=",
+        Operator::Gt => ">",
+        Operator::GtEq => ">=",
+        _ => unreachable!("only comparisons are parsed"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Op(Operator),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ident(ident) => write!(f, "{}", ident),
+            Self::Str(s) => write!(f, "'{}'", s),
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Op(op) => write!(f, "{}", operator_symbol(*op)),
+        }
+    }
+}
+
+impl Token {
+    /// Converts a literal token into the value it represents
+    fn to_scalar(&self) -> Result<ScalarValue> {
+        match self {
+            Self::Str(s) => Ok(ScalarValue::Utf8(Some(s.clone()))),
+            Self::Number(number) if number.contains('.') => number
+                .parse()
+                .map(|v| ScalarValue::Float64(Some(v)))
+                .ok()
+                .context(InvalidNumber { number }),
+            Self::Number(number) => number
+                .parse()
+                .map(|v| ScalarValue::Int64(Some(v)))
+                .ok()
+                .context(InvalidNumber { number }),
+            Self::Ident(ident) if ident.eq_ignore_ascii_case("true") => {
+                Ok(ScalarValue::Boolean(Some(true)))
+            }
+            Self::Ident(ident) if ident.eq_ignore_ascii_case("false") => {
+                Ok(ScalarValue::Boolean(Some(false)))
+            }
+            token => UnexpectedToken {
+                expected: "a literal",
+                found: token.to_string(),
+            }
+            .fail(),
+        }
+    }
+}
+
+fn tokenize(predicate: &str) -> Result<Vec<Token>> {
+    let chars = predicate.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let c = chars[position];
+        let start = position;
+        position += 1;
+
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '=' => Token::Op(Operator::Eq),
+            '!' if chars.get(position) == Some(&'=') => {
+                position += 1;
+                Token::Op(Operator::NotEq)
+            }
+            '<' | '>' => {
+                let or_eq = chars.get(position) == Some(&'=');
+                if or_eq {
+                    position += 1;
+                }
+                Token::Op(match (c, or_eq) {
+                    ('<', false) => Operator::Lt,
+                    ('<', true) => Operator::LtEq,
+                    ('>', false) => Operator::Gt,
+                    _ => Operator::GtEq,
+                })
+            }
+            '\'' => {
+                let len = chars[position..]
+                    .iter()
+                    .position(|&c| c == '\'')
+                    .context(UnterminatedString { position: start })?;
+                let s = chars[position..position + len].iter().collect();
+                position += len + 1;
+                Token::Str(s)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                while position < chars.len()
+                    && (chars[position].is_ascii_digit() || chars[position] == '.')
+                {
+                    position += 1;
+                }
+                Token::Number(chars[start..position].iter().collect())
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                while position < chars.len()
+                    && (chars[position].is_ascii_alphanumeric() || chars[position] == '_')
+                {
+                    position += 1;
+                }
+                Token::Ident(chars[start..position].iter().collect())
+            }
+            character => {
+                return UnexpectedCharacter {
+                    character,
+                    position: start,
+                }
+                .fail()
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_empty() {
+        let predicate = parse_simple_predicate("  ").unwrap();
+        assert!(predicate.exprs.is_empty());
+        assert_eq!(predicate.range, None);
+    }
+
+    #[test]
+    fn parse_exprs() {
+        let predicate =
+            parse_simple_predicate("city = 'Los Angeles' AND temp >= 80.5 and count != -2")
+                .unwrap();
+        assert_eq!(predicate.range, None);
+
+        let exprs = predicate
+            .exprs
+            .iter()
+            .map(|expr| format!("{:?}", expr))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exprs,
+            vec![
+                "#city Eq Utf8(\"Los Angeles\")",
+                "#temp GtEq Float64(80.5)",
+                "#count NotEq Int64(-2)",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_time_range() {
+        let predicate = parse_simple_predicate("time > 100 and time <= 300").unwrap();
+        assert!(predicate.exprs.is_empty());
+        assert_eq!(predicate.range, Some(TimestampRange::new(101, 301)));
+
+        let predicate =
+            parse_simple_predicate("time >= 100 and flag = true and time < 200 and time = 150")
+                .unwrap();
+        assert_eq!(predicate.exprs.len(), 1);
+        assert_eq!(predicate.range, Some(TimestampRange::new(150, 151)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = vec![
+            (
+                "city",
+                "Expected a comparison operator, found the end of the predicate",
+            ),
+            (
+                "city =",
+                "Expected a literal, found the end of the predicate",
+            ),
+            ("city = LA", "Expected a literal, found 'LA'"),
+            ("city = 'LA' or state = 'CA'", "Expected AND, found 'or'"),
+            (
+                "city = 'LA",
+                "Unterminated string literal starting at position 7",
+            ),
+            ("city ~ 'LA'", "Unexpected character '~' at position 5"),
+            ("temp > 1.2.3", "Invalid number '1.2.3'"),
+            ("= 'LA'", "Expected a column name, found '='"),
+            ("time != 100", "Unsupported time comparison '!= 100'"),
+            (
+                "time > 'yesterday'",
+                "Unsupported time comparison '> 'yesterday''",
+            ),
+        ];
+
+        for (predicate, expected) in cases {
+            let err = parse_simple_predicate(predicate).unwrap_err().to_string();
+            assert!(
+                err.starts_with(expected),
+                "predicate '{}': expected '{}' but got '{}'",
+                predicate,
+                expected,
+                err
+            );
+        }
+    }
+}
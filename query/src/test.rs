@@ -216,6 +216,7 @@ fn set_to_string(s: &BTreeSet<String>) -> String {
 fn predicate_to_test_string(predicate: &Predicate) -> String {
     let Predicate {
         table_names,
+        table_name_regex,
         field_columns,
         exprs,
         range,
@@ -228,6 +229,10 @@ fn predicate_to_test_string(predicate: &Predicate) -> String {
         write!(result, " table_names: {}", set_to_string(table_names)).unwrap();
     }
 
+    if let Some(table_name_regex) = table_name_regex {
+        write!(result, " table_name_regex: {}", table_name_regex).unwrap();
+    }
+
     if let Some(field_columns) = field_columns {
         write!(result, " field_columns: {}", set_to_string(field_columns)).unwrap();
     }
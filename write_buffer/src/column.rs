@@ -1,8 +1,13 @@
 use generated_types::wal as wb;
 use snafu::Snafu;
+use std::mem;
 
-use crate::dictionary::Dictionary;
-use data_types::{data::type_description, partition_metadata::Statistics};
+use crate::{dictionary::Dictionary, partition::Partition};
+use data_types::{
+    data::{decimal_unscaled_value, type_description},
+    partition_metadata::Statistics,
+};
+use std::fmt::{Debug, Display};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -21,10 +26,56 @@ pub enum Error {
 
     #[snafu(display("InternalError: Applying i64 range on a column with non-i64 type"))]
     InternalTypeMismatchForTimePredicate,
+
+    #[snafu(display("Can not iterate strings of a column of type {}", column_type))]
+    NotStringColumn { column_type: String },
 }
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-#[derive(Debug)]
+/// The type of the values stored in a `Column`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    F64,
+    I64,
+    String,
+    Bool,
+    Tag,
+    Decimal { scale: u8, precision: u8 },
+}
+
+impl ColumnType {
+    /// Returns the type of column that stores `value`, if any
+    pub fn from_value(value: &wb::Value<'_>) -> Option<Self> {
+        use wb::ColumnValue::*;
+
+        match value.value_type() {
+            F64Value => Some(Self::F64),
+            I64Value => Some(Self::I64),
+            StringValue => Some(Self::String),
+            BoolValue => Some(Self::Bool),
+            TagValue => Some(Self::Tag),
+            DecimalValue => value.value_as_decimal_value().map(|decimal| Self::Decimal {
+                scale: decimal.scale(),
+                precision: decimal.precision(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the same description as `Column::type_description`
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::F64 => "f64",
+            Self::I64 => "i64",
+            Self::String => "String",
+            Self::Bool => "bool",
+            Self::Tag => "tag",
+            Self::Decimal { .. } => "decimal",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 /// Stores the actual data for columns in a partition along with summary statistics
 pub enum Column {
     F64(Vec<Option<f64>>, Statistics<f64>),
@@ -32,6 +83,14 @@ pub enum Column {
     String(Vec<Option<String>>, Statistics<String>),
     Bool(Vec<Option<bool>>, Statistics<bool>),
     Tag(Vec<Option<u32>>, Statistics<String>),
+    /// Exact decimals, stored as their unscaled values: the value of a
+    /// row is its unscaled value divided by `10^scale`. No statistics
+    /// are kept.
+    Decimal {
+        values: Vec<Option<i128>>,
+        scale: u8,
+        precision: u8,
+    },
 }
 
 impl Column {
@@ -91,6 +150,18 @@ impl Column {
                 vals.push(Some(id));
                 Self::Tag(vals, Statistics::new(val.to_string()))
             }
+            DecimalValue => {
+                let val = value
+                    .value_as_decimal_value()
+                    .expect("decimal value should be present");
+                let mut values = vec![None; capacity];
+                values.push(Some(decimal_unscaled_value(&val)));
+                Self::Decimal {
+                    values,
+                    scale: val.scale(),
+                    precision: val.precision(),
+                }
+            }
             _ => {
                 return UnknownColumnType {
                     inserted_value_type: type_description(value.value_type()),
@@ -100,6 +171,33 @@ impl Column {
         })
     }
 
+    /// Creates a column of `column_type` with no rows.
+    ///
+    /// The statistics of an empty column have a count of zero, and
+    /// their min and max are meaningless until a value is added.
+    pub fn new_empty(column_type: ColumnType) -> Self {
+        match column_type {
+            ColumnType::F64 => Self::F64(vec![], empty_stats(0.0)),
+            ColumnType::I64 => Self::I64(vec![], empty_stats(0)),
+            ColumnType::String => Self::String(vec![], empty_stats(String::new())),
+            ColumnType::Bool => Self::Bool(vec![], empty_stats(false)),
+            ColumnType::Tag => Self::Tag(vec![], empty_stats(String::new())),
+            ColumnType::Decimal { scale, precision } => Self::Decimal {
+                values: vec![],
+                scale,
+                precision,
+            },
+        }
+    }
+
+    /// Creates a `String` column with `capacity` null values followed
+    /// by `value`
+    pub fn with_string_value(capacity: usize, value: &str) -> Self {
+        let mut vals = vec![None; capacity];
+        vals.push(Some(value.to_string()));
+        Self::String(vals, Statistics::new(value.to_string()))
+    }
+
     pub fn len(&self) -> usize {
         match self {
             Self::F64(v, _) => v.len(),
@@ -107,6 +205,19 @@ impl Column {
             Self::String(v, _) => v.len(),
             Self::Bool(v, _) => v.len(),
             Self::Tag(v, _) => v.len(),
+            Self::Decimal { values, .. } => values.len(),
+        }
+    }
+
+    /// Returns the number of null values in this column
+    pub fn null_count(&self) -> usize {
+        match self {
+            Self::F64(v, _) => v.iter().filter(|v| v.is_none()).count(),
+            Self::I64(v, _) => v.iter().filter(|v| v.is_none()).count(),
+            Self::String(v, _) => v.iter().filter(|v| v.is_none()).count(),
+            Self::Bool(v, _) => v.iter().filter(|v| v.is_none()).count(),
+            Self::Tag(v, _) => v.iter().filter(|v| v.is_none()).count(),
+            Self::Decimal { values, .. } => values.iter().filter(|v| v.is_none()).count(),
         }
     }
 
@@ -114,6 +225,59 @@ impl Column {
         self.len() == 0
     }
 
+    /// Returns true if the value of `row` is null
+    pub fn is_null(&self, row: usize) -> bool {
+        match self {
+            Self::F64(v, _) => v[row].is_none(),
+            Self::I64(v, _) => v[row].is_none(),
+            Self::String(v, _) => v[row].is_none(),
+            Self::Bool(v, _) => v[row].is_none(),
+            Self::Tag(v, _) => v[row].is_none(),
+            Self::Decimal { values, .. } => values[row].is_none(),
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more values
+    pub fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::F64(v, _) => v.reserve(additional),
+            Self::I64(v, _) => v.reserve(additional),
+            Self::String(v, _) => v.reserve(additional),
+            Self::Bool(v, _) => v.reserve(additional),
+            Self::Tag(v, _) => v.reserve(additional),
+            Self::Decimal { values, .. } => values.reserve(additional),
+        }
+    }
+
+    /// Returns the number of values this column can hold without
+    /// reallocating
+    pub fn capacity(&self) -> usize {
+        match self {
+            Self::F64(v, _) => v.capacity(),
+            Self::I64(v, _) => v.capacity(),
+            Self::String(v, _) => v.capacity(),
+            Self::Bool(v, _) => v.capacity(),
+            Self::Tag(v, _) => v.capacity(),
+            Self::Decimal { values, .. } => values.capacity(),
+        }
+    }
+
+    /// Returns an estimate of the memory allocated for the values of
+    /// this column, in bytes, including the contents of strings
+    pub fn size(&self) -> usize {
+        match self {
+            Self::F64(v, _) => mem::size_of::<Option<f64>>() * v.capacity(),
+            Self::I64(v, _) => mem::size_of::<Option<i64>>() * v.capacity(),
+            Self::String(v, _) => {
+                mem::size_of::<Option<String>>() * v.capacity()
+                    + v.iter().flatten().map(|s| s.capacity()).sum::<usize>()
+            }
+            Self::Bool(v, _) => mem::size_of::<Option<bool>>() * v.capacity(),
+            Self::Tag(v, _) => mem::size_of::<Option<u32>>() * v.capacity(),
+            Self::Decimal { values, .. } => mem::size_of::<Option<i128>>() * values.capacity(),
+        }
+    }
+
     pub fn type_description(&self) -> &'static str {
         match self {
             Self::F64(_, _) => "f64",
@@ -121,6 +285,23 @@ impl Column {
             Self::String(_, _) => "String",
             Self::Bool(_, _) => "bool",
             Self::Tag(_, _) => "tag",
+            Self::Decimal { .. } => "decimal",
+        }
+    }
+
+    pub fn column_type(&self) -> ColumnType {
+        match self {
+            Self::F64(_, _) => ColumnType::F64,
+            Self::I64(_, _) => ColumnType::I64,
+            Self::String(_, _) => ColumnType::String,
+            Self::Bool(_, _) => ColumnType::Bool,
+            Self::Tag(_, _) => ColumnType::Tag,
+            Self::Decimal {
+                scale, precision, ..
+            } => ColumnType::Decimal {
+                scale: *scale,
+                precision: *precision,
+            },
         }
     }
 
@@ -131,7 +312,7 @@ impl Column {
                     let tag_value = tag.value().expect("tag must have string value");
                     let id = dictionary.lookup_value_or_insert(tag_value);
                     vals.push(Some(id));
-                    Statistics::update_string(stats, tag_value);
+                    update_string_stats(stats, tag_value);
                     true
                 }
                 None => false,
@@ -140,7 +321,7 @@ impl Column {
                 Some(str_val) => {
                     let str_val = str_val.value().expect("string must have value");
                     vals.push(Some(str_val.to_string()));
-                    Statistics::update_string(stats, str_val);
+                    update_string_stats(stats, str_val);
                     true
                 }
                 None => false,
@@ -149,7 +330,7 @@ impl Column {
                 Some(bool_val) => {
                     let bool_val = bool_val.value();
                     vals.push(Some(bool_val));
-                    stats.update(bool_val);
+                    update_stats(stats, bool_val);
                     true
                 }
                 None => false,
@@ -158,7 +339,7 @@ impl Column {
                 Some(i64_val) => {
                     let i64_val = i64_val.value();
                     vals.push(Some(i64_val));
-                    stats.update(i64_val);
+                    update_stats(stats, i64_val);
                     true
                 }
                 None => false,
@@ -167,11 +348,24 @@ impl Column {
                 Some(f64_val) => {
                     let f64_val = f64_val.value();
                     vals.push(Some(f64_val));
-                    stats.update(f64_val);
+                    update_stats(stats, f64_val);
                     true
                 }
                 None => false,
             },
+            Self::Decimal {
+                values,
+                scale,
+                precision,
+            } => match value.value_as_decimal_value() {
+                // decimals with a different scale or precision are a
+                // different type
+                Some(decimal) if decimal.scale() == *scale && decimal.precision() == *precision => {
+                    values.push(Some(decimal_unscaled_value(&decimal)));
+                    true
+                }
+                _ => false,
+            },
         };
 
         if inserted {
@@ -185,6 +379,46 @@ impl Column {
         }
     }
 
+    /// Appends the string `value` to a `String` column. Errors for
+    /// other types of columns.
+    pub fn push_string(&mut self, value: &str) -> Result<()> {
+        match self {
+            Self::String(vals, stats) => {
+                vals.push(Some(value.to_string()));
+                update_string_stats(stats, value);
+                Ok(())
+            }
+            _ => TypeMismatch {
+                existing_column_type: self.type_description(),
+                inserted_value_type: "String",
+            }
+            .fail(),
+        }
+    }
+
+    /// Converts an `I64` column into an `F64` column in place,
+    /// casting all existing values (and statistics) to `f64`. Errors
+    /// if the column is not an `I64` column.
+    pub fn promote_i64_to_f64(&mut self) -> Result<()> {
+        match self {
+            Self::I64(vals, stats) => {
+                let vals = vals.iter().map(|v| v.map(|v| v as f64)).collect();
+                let stats = Statistics {
+                    min: stats.min as f64,
+                    max: stats.max as f64,
+                    count: stats.count,
+                };
+                *self = Self::F64(vals, stats);
+                Ok(())
+            }
+            _ => TypeMismatch {
+                existing_column_type: self.type_description(),
+                inserted_value_type: "f64",
+            }
+            .fail(),
+        }
+    }
+
     // push_none_if_len_equal will add a None value to the end of the Vec of values if the
     // length is equal to the passed in value. This is used to ensure columns are all the same length.
     pub fn push_none_if_len_equal(&mut self, len: usize) {
@@ -214,6 +448,243 @@ impl Column {
                     v.push(None);
                 }
             }
+            Self::Decimal { values, .. } => {
+                if values.len() == len {
+                    values.push(None);
+                }
+            }
+        }
+    }
+
+    /// Appends null values until this column has `len` rows
+    pub fn pad_with_nulls(&mut self, len: usize) {
+        match self {
+            Self::F64(v, _) => v.resize(len.max(v.len()), None),
+            Self::I64(v, _) => v.resize(len.max(v.len()), None),
+            Self::String(v, _) => v.resize(len.max(v.len()), None),
+            Self::Bool(v, _) => v.resize(len.max(v.len()), None),
+            Self::Tag(v, _) => v.resize(len.max(v.len()), None),
+            Self::Decimal { values, .. } => values.resize(len.max(values.len()), None),
+        }
+    }
+
+    /// Appends the values of `other` to this column, merging the
+    /// statistics. Errors, without modifying the column, if `other`
+    /// has a different type. `Tag` columns must use the same
+    /// dictionary.
+    pub fn extend(&mut self, other: Self) -> Result<()> {
+        match (self, other) {
+            (Self::F64(vals, stats), Self::F64(other_vals, other_stats)) => {
+                vals.extend(other_vals);
+                merge_stats(stats, other_stats);
+            }
+            (Self::I64(vals, stats), Self::I64(other_vals, other_stats)) => {
+                vals.extend(other_vals);
+                merge_stats(stats, other_stats);
+            }
+            (Self::Bool(vals, stats), Self::Bool(other_vals, other_stats)) => {
+                vals.extend(other_vals);
+                merge_stats(stats, other_stats);
+            }
+            (Self::String(vals, stats), Self::String(other_vals, other_stats)) => {
+                vals.extend(other_vals);
+                merge_stats(stats, other_stats);
+            }
+            (Self::Tag(vals, stats), Self::Tag(other_vals, other_stats)) => {
+                vals.extend(other_vals);
+                merge_stats(stats, other_stats);
+            }
+            (
+                Self::Decimal {
+                    values,
+                    scale,
+                    precision,
+                },
+                Self::Decimal {
+                    values: other_values,
+                    scale: other_scale,
+                    precision: other_precision,
+                },
+            ) if *scale == other_scale && *precision == other_precision => {
+                values.extend(other_values);
+            }
+            (this, other) => {
+                return TypeMismatch {
+                    existing_column_type: this.type_description(),
+                    inserted_value_type: other.type_description(),
+                }
+                .fail()
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces each null value of this column with the value of
+    /// `other` in the same row, if any, updating the statistics.
+    /// Errors, without modifying the column, if `other` has a
+    /// different type.
+    ///
+    /// Panics if a tag value id of `other` is not in `dictionary`.
+    pub fn coalesce(&mut self, other: &Self, dictionary: &Dictionary) -> Result<()> {
+        match (self, other) {
+            (Self::F64(vals, stats), Self::F64(other_vals, _)) => {
+                coalesce_values(vals, other_vals, |&v| update_stats(stats, v))
+            }
+            (Self::I64(vals, stats), Self::I64(other_vals, _)) => {
+                coalesce_values(vals, other_vals, |&v| update_stats(stats, v))
+            }
+            (Self::Bool(vals, stats), Self::Bool(other_vals, _)) => {
+                coalesce_values(vals, other_vals, |&v| update_stats(stats, v))
+            }
+            (Self::String(vals, stats), Self::String(other_vals, _)) => {
+                coalesce_values(vals, other_vals, |v| update_string_stats(stats, v))
+            }
+            (Self::Tag(vals, stats), Self::Tag(other_vals, _)) => {
+                coalesce_values(vals, other_vals, |&value_id| {
+                    let tag_value = dictionary
+                        .lookup_id(value_id)
+                        .expect("tag value id in dictionary");
+                    update_string_stats(stats, tag_value)
+                })
+            }
+            (
+                Self::Decimal {
+                    values,
+                    scale,
+                    precision,
+                },
+                Self::Decimal {
+                    values: other_values,
+                    scale: other_scale,
+                    precision: other_precision,
+                },
+            ) if *scale == *other_scale && *precision == *other_precision => {
+                coalesce_values(values, other_values, |_| {})
+            }
+            (this, other) => {
+                return TypeMismatch {
+                    existing_column_type: this.type_description(),
+                    inserted_value_type: other.type_description(),
+                }
+                .fail()
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the rows for which `keep` is false, recomputing the
+    /// statistics from the remaining values. If no (non null) values
+    /// remain, the count is zero and the previous min and max are
+    /// kept.
+    ///
+    /// Panics if `keep` is shorter than the column, or if a remaining
+    /// tag value id is not in `dictionary`.
+    pub fn retain_rows(&mut self, keep: &[bool], dictionary: &Dictionary) {
+        match self {
+            Self::F64(vals, stats) => {
+                retain_values(vals, keep);
+                recompute_stats(stats, vals.iter().flatten().copied());
+            }
+            Self::I64(vals, stats) => {
+                retain_values(vals, keep);
+                recompute_stats(stats, vals.iter().flatten().copied());
+            }
+            Self::Bool(vals, stats) => {
+                retain_values(vals, keep);
+                recompute_stats(stats, vals.iter().flatten().copied());
+            }
+            Self::String(vals, stats) => {
+                retain_values(vals, keep);
+                recompute_stats(stats, vals.iter().flatten().cloned());
+            }
+            Self::Tag(vals, stats) => {
+                retain_values(vals, keep);
+                let tag_values = vals.iter().flatten().map(|&value_id| {
+                    dictionary
+                        .lookup_id(value_id)
+                        .expect("tag value id in dictionary")
+                        .to_string()
+                });
+                recompute_stats(stats, tag_values);
+            }
+            Self::Decimal { values, .. } => retain_values(values, keep),
+        }
+    }
+
+    /// Replaces the value in row `to` with the value in row `from`,
+    /// unless that is null. The statistics are not updated, so this is
+    /// intended to be followed by `retain_rows` removing row `from`,
+    /// which recomputes them.
+    ///
+    /// Panics if either row is out of bounds.
+    pub fn copy_non_null_value(&mut self, from: usize, to: usize) {
+        fn copy<T: Clone>(vals: &mut [Option<T>], from: usize, to: usize) {
+            if vals[from].is_some() {
+                vals[to] = vals[from].clone();
+            }
+        }
+
+        match self {
+            Self::F64(vals, _) => copy(vals, from, to),
+            Self::I64(vals, _) => copy(vals, from, to),
+            Self::String(vals, _) => copy(vals, from, to),
+            Self::Bool(vals, _) => copy(vals, from, to),
+            Self::Tag(vals, _) => copy(vals, from, to),
+            Self::Decimal { values, .. } => copy(values, from, to),
+        }
+    }
+
+    /// Returns a new column with the values of the listed `rows`, in
+    /// that order. The statistics are copied, so `rows` should be a
+    /// permutation of the rows of this column.
+    ///
+    /// Panics if a row is out of bounds.
+    pub fn take_rows(&self, rows: &[usize]) -> Self {
+        match self {
+            Self::F64(vals, stats) => Self::F64(take_values(vals, rows), stats.clone()),
+            Self::I64(vals, stats) => Self::I64(take_values(vals, rows), stats.clone()),
+            Self::String(vals, stats) => Self::String(take_values(vals, rows), stats.clone()),
+            Self::Bool(vals, stats) => Self::Bool(take_values(vals, rows), stats.clone()),
+            Self::Tag(vals, stats) => Self::Tag(take_values(vals, rows), stats.clone()),
+            Self::Decimal {
+                values,
+                scale,
+                precision,
+            } => Self::Decimal {
+                values: take_values(values, rows),
+                scale: *scale,
+                precision: *precision,
+            },
+        }
+    }
+
+    /// Returns an iterator over the string values of a `Tag` or
+    /// `String` column. Tag value ids are resolved using the
+    /// dictionary of `partition` as the iterator is advanced. Errors
+    /// for other types of columns.
+    ///
+    /// Panics while iterating if a tag value id is not in the
+    /// dictionary.
+    pub fn iter_resolved<'a>(
+        &'a self,
+        partition: &'a Partition,
+    ) -> Result<Box<dyn Iterator<Item = Option<&'a str>> + 'a>> {
+        match self {
+            Self::Tag(vals, _) => Ok(Box::new(vals.iter().map(move |value_id| {
+                value_id.map(|value_id| {
+                    partition
+                        .dictionary
+                        .lookup_id(value_id)
+                        .expect("tag value id in partition dictionary")
+                })
+            }))),
+            Self::String(vals, _) => Ok(Box::new(vals.iter().map(|val| val.as_deref()))),
+            _ => NotStringColumn {
+                column_type: self.type_description(),
+            }
+            .fail(),
         }
     }
 
@@ -257,6 +728,142 @@ impl Column {
     }
 }
 
+/// Columns are equal if they are the same variant and hold the same
+/// values; statistics are derived from the values and are not
+/// compared. `Tag` columns compare their raw dictionary ids, so are
+/// only meaningfully comparable within the same partition.
+///
+/// To keep comparisons deterministic (and `==` reflexive), two `NaN`
+/// values in `F64` columns are considered equal.
+impl PartialEq for Column {
+    // exact float comparison is intended here
+    #[allow(clippy::float_cmp)]
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::F64(a, _), Self::F64(b, _)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| match (a, b) {
+                        (Some(a), Some(b)) => a == b || (a.is_nan() && b.is_nan()),
+                        (None, None) => true,
+                        _ => false,
+                    })
+            }
+            (Self::I64(a, _), Self::I64(b, _)) => a == b,
+            (Self::String(a, _), Self::String(b, _)) => a == b,
+            (Self::Bool(a, _), Self::Bool(b, _)) => a == b,
+            (Self::Tag(a, _), Self::Tag(b, _)) => a == b,
+            (
+                Self::Decimal {
+                    values: a,
+                    scale: a_scale,
+                    precision: a_precision,
+                },
+                Self::Decimal {
+                    values: b,
+                    scale: b_scale,
+                    precision: b_precision,
+                },
+            ) => a == b && a_scale == b_scale && a_precision == b_precision,
+            _ => false,
+        }
+    }
+}
+
+/// Fills the `None` entries of `vals` with the value of `other_vals`
+/// in the same row, calling `update` with each value filled in
+fn coalesce_values<T: Clone>(
+    vals: &mut [Option<T>],
+    other_vals: &[Option<T>],
+    mut update: impl FnMut(&T),
+) {
+    for (val, other_val) in vals.iter_mut().zip(other_vals) {
+        if let (None, Some(other_val)) = (&val, other_val) {
+            update(other_val);
+            *val = Some(other_val.clone());
+        }
+    }
+}
+
+/// Returns statistics describing no values, with `placeholder` as the
+/// min and max
+fn empty_stats<T>(placeholder: T) -> Statistics<T>
+where
+    T: PartialEq + PartialOrd + Debug + Display + Clone,
+{
+    Statistics {
+        min: placeholder.clone(),
+        max: placeholder,
+        count: 0,
+    }
+}
+
+/// Updates `stats` with `value`, replacing the placeholder min and max
+/// of statistics that describe no values yet (see `Column::new_empty`)
+fn update_stats<T>(stats: &mut Statistics<T>, value: T)
+where
+    T: PartialEq + PartialOrd + Debug + Display + Clone,
+{
+    if stats.count == 0 {
+        *stats = Statistics::new(value);
+    } else {
+        stats.update(value);
+    }
+}
+
+/// Updates `stats` with the string `value`, as `update_stats`
+fn update_string_stats(stats: &mut Statistics<String>, value: &str) {
+    if stats.count == 0 {
+        *stats = Statistics::new(value.to_string());
+    } else {
+        Statistics::update_string(stats, value);
+    }
+}
+
+/// Updates `stats` to also describe the values summarized by `other`
+fn merge_stats<T>(stats: &mut Statistics<T>, other: Statistics<T>)
+where
+    T: PartialEq + PartialOrd + Debug + Display + Clone,
+{
+    if other.count == 0 {
+        return;
+    }
+
+    let count = stats.count + other.count;
+    if stats.count == 0 {
+        *stats = other;
+    } else {
+        stats.update(other.min);
+        stats.update(other.max);
+    }
+    stats.count = count;
+}
+
+/// Removes the entries of `vals` for which `keep` is false
+fn retain_values<T>(vals: &mut Vec<Option<T>>, keep: &[bool]) {
+    let mut keep = keep.iter();
+    vals.retain(|_| *keep.next().expect("a keep flag for each row"));
+}
+
+fn take_values<T: Clone>(vals: &[Option<T>], rows: &[usize]) -> Vec<Option<T>> {
+    rows.iter().map(|&row| vals[row].clone()).collect()
+}
+
+/// Replaces `stats` with the statistics of `values`, keeping the
+/// previous min and max (with a zero count) if there are no values
+fn recompute_stats<T>(stats: &mut Statistics<T>, mut values: impl Iterator<Item = T>)
+where
+    T: PartialEq + PartialOrd + Debug + Display + Clone,
+{
+    match values.next() {
+        Some(first) => {
+            let mut new_stats = Statistics::new(first);
+            values.for_each(|v| new_stats.update(v));
+            *stats = new_stats;
+        }
+        None => stats.count = 0,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,6 +909,235 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_promote_i64_to_f64() -> Result {
+        let mut stats = Statistics::new(1);
+        stats.update(3);
+        let mut col = Column::I64(vec![Some(1), None, Some(3)], stats);
+
+        col.promote_i64_to_f64()?;
+        match col {
+            Column::F64(vals, stats) => {
+                assert_eq!(vals, vec![Some(1.0), None, Some(3.0)]);
+                assert_eq!(stats.min, 1.0);
+                assert_eq!(stats.max, 3.0);
+                assert_eq!(stats.count, 2);
+            }
+            _ => panic!("Expected F64 column, got {:?}", col),
+        }
+
+        let mut col = Column::Bool(vec![Some(true)], Statistics::new(true));
+        assert!(col.promote_i64_to_f64().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retain_rows() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let boston = partition.dictionary.lookup_value_or_insert("Boston");
+        let la = partition.dictionary.lookup_value_or_insert("LA");
+
+        let mut stats = Statistics::new(1);
+        stats.update(5);
+        stats.update(3);
+        let mut col = Column::I64(vec![Some(1), None, Some(5), Some(3)], stats);
+        col.retain_rows(&[false, true, false, true], &partition.dictionary);
+        match col {
+            Column::I64(vals, stats) => {
+                assert_eq!(vals, vec![None, Some(3)]);
+                assert_eq!((stats.min, stats.max, stats.count), (3, 3, 1));
+            }
+            _ => panic!("Expected I64 column, got {:?}", col),
+        }
+
+        let mut stats = Statistics::new("Boston".to_string());
+        stats.update("LA".to_string());
+        let mut col = Column::Tag(vec![Some(boston), Some(la), None], stats);
+        col.retain_rows(&[true, false, true], &partition.dictionary);
+        match &col {
+            Column::Tag(vals, stats) => {
+                assert_eq!(vals, &vec![Some(boston), None]);
+                assert_eq!(stats.max, "Boston");
+                assert_eq!(stats.count, 1);
+            }
+            _ => panic!("Expected Tag column, got {:?}", col),
+        }
+
+        // no values left
+        col.retain_rows(&[false, true], &partition.dictionary);
+        match &col {
+            Column::Tag(vals, stats) => {
+                assert_eq!(vals, &vec![None]);
+                assert_eq!(stats.count, 0);
+            }
+            _ => panic!("Expected Tag column, got {:?}", col),
+        }
+    }
+
+    #[test]
+    fn test_take_rows() {
+        let mut stats = Statistics::new(1.0);
+        stats.update(5.0);
+        let col = Column::F64(vec![Some(1.0), None, Some(5.0)], stats);
+
+        match col.take_rows(&[2, 0, 1]) {
+            Column::F64(vals, stats) => {
+                assert_eq!(vals, vec![Some(5.0), Some(1.0), None]);
+                assert_eq!((stats.min, stats.max, stats.count), (1.0, 5.0, 2));
+            }
+            col => panic!("Expected F64 column, got {:?}", col),
+        }
+    }
+
+    #[test]
+    fn test_new_empty() -> Result {
+        let column_types = vec![
+            ColumnType::F64,
+            ColumnType::I64,
+            ColumnType::String,
+            ColumnType::Bool,
+            ColumnType::Tag,
+        ];
+
+        for column_type in column_types {
+            let col = Column::new_empty(column_type);
+            assert_eq!(col.len(), 0);
+            assert!(col.is_empty());
+            assert_eq!(col.column_type(), column_type);
+        }
+
+        // the placeholder statistics are replaced by the first value
+        let mut col = Column::new_empty(ColumnType::String);
+        col.pad_with_nulls(1);
+        col.push_string("foo")?;
+        match &col {
+            Column::String(vals, stats) => {
+                assert_eq!(vals, &vec![None, Some("foo".to_string())]);
+                assert_eq!(stats.min, "foo");
+                assert_eq!(stats.max, "foo");
+                assert_eq!(stats.count, 1);
+            }
+            _ => panic!("Expected String column, got {:?}", col),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extend() -> Result {
+        let mut stats = Statistics::new(1.0);
+        stats.update(3.0);
+        let mut col = Column::F64(vec![Some(1.0), None, Some(3.0)], stats);
+
+        let mut other_stats = Statistics::new(-2.0);
+        other_stats.update(2.0);
+        col.extend(Column::F64(vec![Some(-2.0), Some(2.0)], other_stats))?;
+        col.pad_with_nulls(6);
+
+        match &col {
+            Column::F64(vals, stats) => {
+                assert_eq!(
+                    vals,
+                    &vec![Some(1.0), None, Some(3.0), Some(-2.0), Some(2.0), None]
+                );
+                assert_eq!(stats.min, -2.0);
+                assert_eq!(stats.max, 3.0);
+                assert_eq!(stats.count, 4);
+            }
+            _ => panic!("Expected F64 column, got {:?}", col),
+        }
+
+        let res = col.extend(Column::I64(vec![Some(1)], Statistics::new(1)));
+        assert!(matches!(res, Err(Error::TypeMismatch { .. })));
+        assert_eq!(col.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_resolved() -> Result {
+        let mut partition = Partition::new("dummy_partition_key");
+        let boston = partition.dictionary.lookup_value_or_insert("Boston");
+        let la = partition.dictionary.lookup_value_or_insert("LA");
+
+        let city = Column::Tag(
+            vec![Some(boston), None, Some(la), Some(boston)],
+            Statistics::new("Boston".to_string()),
+        );
+        let cities = city.iter_resolved(&partition)?.collect::<Vec<_>>();
+        assert_eq!(
+            cities,
+            vec![Some("Boston"), None, Some("LA"), Some("Boston")]
+        );
+
+        let desc = Column::String(
+            vec![None, Some("ok".to_string())],
+            Statistics::new("ok".to_string()),
+        );
+        let descs = desc.iter_resolved(&partition)?.collect::<Vec<_>>();
+        assert_eq!(descs, vec![None, Some("ok")]);
+
+        let temp = Column::F64(vec![Some(1.0)], Statistics::new(1.0));
+        let res = temp.iter_resolved(&partition);
+        assert!(matches!(res, Err(Error::NotStringColumn { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_eq() {
+        let f64_col = |vals: Vec<Option<f64>>| Column::F64(vals, Statistics::new(0.0));
+        assert_eq!(
+            f64_col(vec![Some(1.0), None]),
+            f64_col(vec![Some(1.0), None])
+        );
+        assert_ne!(
+            f64_col(vec![Some(1.0), None]),
+            f64_col(vec![Some(2.0), None])
+        );
+        assert_ne!(f64_col(vec![Some(1.0), None]), f64_col(vec![Some(1.0)]));
+        assert_ne!(f64_col(vec![Some(1.0)]), f64_col(vec![None]));
+        // NaNs compare equal
+        assert_eq!(f64_col(vec![Some(f64::NAN)]), f64_col(vec![Some(f64::NAN)]));
+        assert_ne!(f64_col(vec![Some(f64::NAN)]), f64_col(vec![Some(1.0)]));
+
+        let i64_col = |vals: Vec<Option<i64>>| Column::I64(vals, Statistics::new(0));
+        assert_eq!(i64_col(vec![Some(1), None]), i64_col(vec![Some(1), None]));
+        assert_ne!(i64_col(vec![Some(1), None]), i64_col(vec![None, Some(1)]));
+
+        let string_col = |vals: Vec<Option<&str>>| {
+            let vals = vals.into_iter().map(|v| v.map(|v| v.to_string())).collect();
+            Column::String(vals, Statistics::new(String::new()))
+        };
+        assert_eq!(
+            string_col(vec![Some("a"), None]),
+            string_col(vec![Some("a"), None])
+        );
+        assert_ne!(string_col(vec![Some("a")]), string_col(vec![Some("b")]));
+
+        let bool_col = |vals: Vec<Option<bool>>| Column::Bool(vals, Statistics::new(false));
+        assert_eq!(
+            bool_col(vec![Some(true), None]),
+            bool_col(vec![Some(true), None])
+        );
+        assert_ne!(bool_col(vec![Some(true)]), bool_col(vec![Some(false)]));
+
+        let tag_col = |vals: Vec<Option<u32>>| Column::Tag(vals, Statistics::new(String::new()));
+        assert_eq!(tag_col(vec![Some(1), None]), tag_col(vec![Some(1), None]));
+        assert_ne!(tag_col(vec![Some(1)]), tag_col(vec![Some(2)]));
+
+        // statistics are not compared
+        assert_eq!(
+            Column::I64(vec![Some(1)], Statistics::new(1)),
+            Column::I64(vec![Some(1)], Statistics::new(5))
+        );
+
+        // different variants are never equal
+        assert_ne!(i64_col(vec![Some(1)]), f64_col(vec![Some(1.0)]));
+        assert_ne!(tag_col(vec![None]), string_col(vec![None]));
+    }
+
     #[test]
     fn test_has_non_null_i64_range_() -> Result {
         let none_col: Vec<Option<u32>> = vec![None, None, None];
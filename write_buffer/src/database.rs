@@ -16,33 +16,42 @@ use wal::{
 
 use crate::column::Column;
 use crate::partition::Partition;
-use crate::{partition::PartitionPredicate, table::Table};
+use crate::{
+    partition::PartitionPredicate,
+    table::{FieldSelector, ScanStats, SeriesSetPlanOptions, Table},
+};
 
 use std::io::ErrorKind;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::{
-    collections::{BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     path::Path,
 };
 
 use arrow_deps::{
     arrow,
-    arrow::{datatypes::Schema as ArrowSchema, record_batch::RecordBatch},
-    datafusion::logical_plan::LogicalPlan,
+    arrow::{
+        datatypes::{DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema},
+        record_batch::RecordBatch,
+    },
+    datafusion::logical_plan::{self, LogicalPlan, LogicalPlanBuilder},
     datafusion::prelude::ExecutionConfig,
     datafusion::{
         datasource::MemTable, error::DataFusionError, execution::context::ExecutionContext,
     },
 };
-use data_types::data::{split_lines_into_write_entry_partitions, ReplicatedWrite};
+use data_types::{
+    data::{split_lines_into_write_entry_partitions, ReplicatedWrite},
+    TIME_COLUMN_NAME,
+};
 
 use crate::dictionary::Error as DictionaryError;
 use crate::partition::restore_partitions_from_wal;
 
 use async_trait::async_trait;
 use chrono::{offset::TimeZone, Utc};
-use snafu::{OptionExt, ResultExt, Snafu};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use sqlparser::{
     ast::{SetExpr, Statement, TableFactor},
     dialect::GenericDialect,
@@ -203,6 +212,24 @@ pub enum Error {
 
     #[snafu(display("replicated write from writer {} missing payload", writer))]
     MissingPayload { writer: u32 },
+
+    #[snafu(display(
+        "Column '{}' has type {:?} in one table and {:?} in another",
+        column_name,
+        first_type,
+        second_type
+    ))]
+    UnionColumnTypeMismatch {
+        column_name: String,
+        first_type: ArrowDataType,
+        second_type: ArrowDataType,
+    },
+
+    #[snafu(display("Requested column '{}' not found in any table", column_name))]
+    UnionColumnNotFound { column_name: String },
+
+    #[snafu(display("Error building union plan: {}", source))]
+    BuildingUnionPlan { source: DataFusionError },
 }
 
 impl From<crate::table::Error> for Error {
@@ -409,7 +436,11 @@ impl Database for Db {
             );
 
             for (table_name_symbol, table) in &partition.tables {
-                if table.could_match_predicate(&partition_predicate)? {
+                if table.could_match_predicate(
+                    &partition_predicate,
+                    partition,
+                    &mut ScanStats::default(),
+                )? {
                     let table_name = partition.dictionary.lookup_id(*table_name_symbol).unwrap();
 
                     if !table_names.contains(table_name) {
@@ -663,14 +694,14 @@ impl Db {
             filter.pre_visit_partition(partition)?;
 
             for table in partition.tables.values() {
-                if filter.should_visit_table(table)? {
+                if filter.should_visit_table(table, partition)? {
                     visitor.pre_visit_table(table, partition, filter)?;
 
-                    for (column_id, column_index) in &table.column_id_to_index {
+                    for (column_id, column_index) in table.column_id_to_index() {
                         visitor.visit_column(
                             table,
                             *column_id,
-                            &table.columns[*column_index],
+                            &table.columns()[*column_index],
                             filter,
                         )?
                     }
@@ -755,8 +786,12 @@ impl PartitionTableFilter {
     }
 
     /// If returns false, skips visiting _table and all its columns
-    fn should_visit_table(&mut self, table: &Table) -> Result<bool> {
-        Ok(table.could_match_predicate(self.partition_predicate())?)
+    fn should_visit_table(&mut self, table: &Table, partition: &Partition) -> Result<bool> {
+        Ok(table.could_match_predicate(
+            self.partition_predicate(),
+            partition,
+            &mut ScanStats::default(),
+        )?)
     }
 
     pub fn partition_predicate(&self) -> &PartitionPredicate {
@@ -862,8 +897,11 @@ impl Visitor for TableFieldPredVisitor {
         partition: &Partition,
         filter: &mut PartitionTableFilter,
     ) -> Result<()> {
-        self.plans
-            .push(table.field_names_plan(filter.partition_predicate(), partition)?);
+        self.plans.push(table.field_names_plan(
+            filter.partition_predicate(),
+            &FieldSelector::All,
+            partition,
+        )?);
         Ok(())
     }
 }
@@ -947,7 +985,8 @@ impl<'a> Visitor for ValueVisitor<'a> {
                     }
                     Some(range) => {
                         // filter out all values that don't match the timestmap
-                        let time_column = table.column_i64(partition_predicate.time_column_id)?;
+                        let time_column = table
+                            .column_i64(table.predicate_time_column_id(partition_predicate))?;
 
                         column
                             .iter()
@@ -1017,7 +1056,11 @@ impl<'a> Visitor for ValuePredVisitor<'a> {
         filter: &mut PartitionTableFilter,
     ) -> Result<()> {
         // skip table entirely if there are no rows that fall in the timestamp
-        if table.could_match_predicate(filter.partition_predicate())? {
+        if table.could_match_predicate(
+            filter.partition_predicate(),
+            partition,
+            &mut ScanStats::default(),
+        )? {
             self.plans.push(table.tag_values_plan(
                 self.column_name,
                 filter.partition_predicate(),
@@ -1080,6 +1123,7 @@ impl Visitor for GroupsVisitor {
         self.plans.push(table.grouped_series_set_plan(
             filter.partition_predicate(),
             &self.group_columns,
+            &SeriesSetPlanOptions::default(),
             partition,
         )?);
 
@@ -1097,6 +1141,115 @@ pub fn partition_key(line: &ParsedLine<'_>) -> String {
     dt.format("%Y-%m-%dT%H").to_string()
 }
 
+/// Creates a plan that produces the rows matching `predicate` of all
+/// `inputs`, each a table and the partition holding it, with a single
+/// schema merged from those of the tables.
+///
+/// The merged schema has every column of any input table, sorted by
+/// name, with the time column of each table named `TIME_COLUMN_NAME`.
+/// The rows of a table without some column are null in that column.
+/// Errors if a column has different types in different tables.
+/// Tables that `could_match_predicate` rules out are not scanned.
+///
+/// If `requested_columns` is provided only those columns are output,
+/// in that order, and each must be in at least one of the tables.
+///
+/// The created plan looks like:
+///
+///    Projection (requested_columns) [optional]
+///        Filter(predicate) [optional]
+///          InMemoryScan
+pub fn build_union_plan(
+    inputs: &[(&Table, &Partition)],
+    predicate: &Predicate,
+    requested_columns: Option<&[String]>,
+) -> Result<LogicalPlan> {
+    let mut merged_fields: BTreeMap<String, ArrowField> = BTreeMap::new();
+    for (table, partition) in inputs {
+        for field in table.arrow_schema_ref(partition)?.fields() {
+            let column_name = if field.name() == table.time_column_name() {
+                TIME_COLUMN_NAME
+            } else if field.name() == TIME_COLUMN_NAME {
+                // not the time column of this table, see `Table::with_time_column_name`
+                continue;
+            } else {
+                field.name().as_str()
+            };
+
+            match merged_fields.get(column_name) {
+                Some(merged) if merged.data_type() != field.data_type() => {
+                    return UnionColumnTypeMismatch {
+                        column_name,
+                        first_type: merged.data_type().clone(),
+                        second_type: field.data_type().clone(),
+                    }
+                    .fail()
+                }
+                Some(_) => {}
+                None => {
+                    // any table may lack the column
+                    let field = ArrowField::new(column_name, field.data_type().clone(), true);
+                    merged_fields.insert(column_name.to_string(), field);
+                }
+            }
+        }
+    }
+
+    if let Some(requested_columns) = requested_columns {
+        for column_name in requested_columns {
+            ensure!(
+                merged_fields.contains_key(column_name),
+                UnionColumnNotFound { column_name }
+            );
+        }
+    }
+
+    let schema = Arc::new(ArrowSchema::new(
+        merged_fields.into_iter().map(|(_, f)| f).collect(),
+    ));
+
+    let mut batches = Vec::new();
+    let mut filter_expr = None;
+    for (table, partition) in inputs {
+        let partition_predicate = partition.compile_predicate(predicate)?;
+        if !table.could_match_predicate(
+            &partition_predicate,
+            partition,
+            &mut ScanStats::default(),
+        )? {
+            continue;
+        }
+
+        // the expression only depends on `predicate`, so is the same
+        // for every partition
+        filter_expr = partition_predicate.filter_expr(TIME_COLUMN_NAME);
+        batches.push(table.to_arrow_coerced(partition, Arc::clone(&schema))?);
+    }
+
+    let mut plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+        data: vec![batches],
+        schema: Arc::clone(&schema),
+        projection: None,
+        projected_schema: schema,
+    });
+
+    if let Some(filter_expr) = filter_expr {
+        plan_builder = plan_builder
+            .filter(filter_expr)
+            .context(BuildingUnionPlan)?;
+    }
+
+    if let Some(requested_columns) = requested_columns {
+        let exprs = requested_columns
+            .iter()
+            .map(|column_name| logical_plan::col(column_name))
+            .collect::<Vec<_>>();
+        plan_builder = plan_builder.project(exprs).context(BuildingUnionPlan)?;
+    }
+
+    plan_builder.build().context(BuildingUnionPlan)
+}
+
 struct ArrowTable {
     name: String,
     schema: Arc<ArrowSchema>,
@@ -2214,6 +2367,68 @@ disk bytes=23432323i 1600136510000000000",
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_build_union_plan() -> Result {
+        let mut partition1 = Partition::new("partition1");
+        let mut table1 = Table::new(partition1.dictionary.lookup_value_or_insert("h2o"));
+        table1.append_from_line_protocol(
+            &mut partition1.dictionary,
+            "h2o,state=MA,city=Boston temp=70.4 100\n\
+             h2o,state=CA,city=LA temp=90.0 200",
+        )?;
+
+        // no city or temp, but a humidity
+        let mut partition2 = Partition::new("partition2");
+        let mut table2 = Table::new(partition2.dictionary.lookup_value_or_insert("h2o"));
+        table2.append_from_line_protocol(
+            &mut partition2.dictionary,
+            "h2o,state=MA humidity=50.0 300\n\
+             h2o,state=WA humidity=60.0 400",
+        )?;
+
+        let inputs = [(&table1, &partition1), (&table2, &partition2)];
+        let executor = Executor::default();
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(logical_plan::col("state").eq("MA".lit()))
+            .build();
+        let plan = build_union_plan(&inputs, &predicate, None)?;
+        let batches = executor.run_logical_plan(plan).await?;
+        let expected = "\
+            +--------+----------+-------+------+------+\n\
+            | city   | humidity | state | temp | time |\n\
+            +--------+----------+-------+------+------+\n\
+            | Boston |          | MA    | 70.4 | 100  |\n\
+            |        | 50       | MA    |      | 300  |\n\
+            +--------+----------+-------+------+------+\n";
+        assert_table_eq(expected, &batches);
+
+        // the first table is pruned by the timestamp range
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(250, 500)
+            .build();
+        let requested_columns = vec!["time".to_string(), "humidity".to_string()];
+        let plan = build_union_plan(&inputs, &predicate, Some(&requested_columns))?;
+        let batches = executor.run_logical_plan(plan).await?;
+        let expected = "\
+            +------+----------+\n\
+            | time | humidity |\n\
+            +------+----------+\n\
+            | 300  | 50       |\n\
+            | 400  | 60       |\n\
+            +------+----------+\n";
+        assert_table_eq(expected, &batches);
+
+        let requested_columns = vec!["pressure".to_string()];
+        let err = build_union_plan(&inputs, &predicate, Some(&requested_columns)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Requested column 'pressure' not found in any table"
+        );
+
+        Ok(())
+    }
+
     /// Run the plan and gather the results in a order that can be compared
     async fn run_and_gather_results(
         plans: SeriesSetPlans,
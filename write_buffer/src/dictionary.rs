@@ -1,6 +1,8 @@
 //! Contains a structure to map from strings to u32 symbols based on
 //! string interning.
 use snafu::{OptionExt, Snafu};
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
 use string_interner::{
     backend::StringBackend, DefaultHashBuilder, DefaultSymbol, StringInterner, Symbol,
 };
@@ -17,9 +19,24 @@ pub enum Error {
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug)]
-pub struct Dictionary(
-    StringInterner<DefaultSymbol, StringBackend<DefaultSymbol>, DefaultHashBuilder>,
-);
+pub struct Dictionary {
+    interner: StringInterner<DefaultSymbol, StringBackend<DefaultSymbol>, DefaultHashBuilder>,
+
+    /// Number of lookups by value, so tests can check how often
+    /// callers resolve the same values
+    #[cfg(test)]
+    value_lookups: AtomicUsize,
+}
+
+impl Clone for Dictionary {
+    fn clone(&self) -> Self {
+        Self {
+            interner: self.interner.clone(),
+            #[cfg(test)]
+            value_lookups: AtomicUsize::new(0),
+        }
+    }
+}
 
 impl Default for Dictionary {
     fn default() -> Self {
@@ -29,13 +46,18 @@ impl Default for Dictionary {
 
 impl Dictionary {
     pub fn new() -> Self {
-        Self(StringInterner::new())
+        Self {
+            interner: StringInterner::new(),
+            #[cfg(test)]
+            value_lookups: AtomicUsize::new(0),
+        }
     }
 
     /// Returns the id corresponding to value, adding an entry for the
     /// id if it is not yet present in the dictionary.
     pub fn lookup_value_or_insert(&mut self, value: &str) -> u32 {
-        symbol_to_u32(self.0.get_or_intern(value))
+        self.count_value_lookup();
+        symbol_to_u32(self.interner.get_or_intern(value))
     }
 
     /// Returns the ID in self.dictionary that corresponds to `value`, if any. Returns an error if
@@ -47,7 +69,19 @@ impl Dictionary {
     /// Returns the ID in self.dictionary that corresponds to `value`,
     /// if any. No error is returned to avoid an allocation when no value is present
     pub fn id(&self, value: &str) -> Option<u32> {
-        self.0.get(value).map(symbol_to_u32)
+        self.count_value_lookup();
+        self.interner.get(value).map(symbol_to_u32)
+    }
+
+    /// Returns the number of values in the dictionary. As values are
+    /// never removed, this changes whenever a value is added
+    pub fn len(&self) -> usize {
+        self.interner.len()
+    }
+
+    /// Returns true if the dictionary has no values
+    pub fn is_empty(&self) -> bool {
+        self.interner.is_empty()
     }
 
     /// Returns the str in self.dictionary that corresponds to `id`,
@@ -55,12 +89,29 @@ impl Dictionary {
     pub fn lookup_id(&self, id: u32) -> Result<&str> {
         let symbol =
             Symbol::try_from_usize(id as usize).expect("to be able to convert u32 to symbol");
-        self.0
+        self.interner
             .resolve(symbol)
             .context(DictionaryIdLookupError { id })
     }
 }
 
+impl Dictionary {
+    /// Returns the number of lookups by value (including insertions)
+    /// made so far
+    #[cfg(test)]
+    pub fn value_lookups(&self) -> usize {
+        self.value_lookups.load(Ordering::Relaxed)
+    }
+
+    #[cfg(test)]
+    fn count_value_lookup(&self) {
+        self.value_lookups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(not(test))]
+    fn count_value_lookup(&self) {}
+}
+
 fn symbol_to_u32(sym: DefaultSymbol) -> u32 {
     sym.to_usize() as u32
 }
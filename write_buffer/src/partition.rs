@@ -4,7 +4,11 @@ use arrow_deps::{
     datafusion::scalar::ScalarValue,
 };
 use generated_types::wal as wb;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::Mutex,
+};
 use wal::{Entry as WalEntry, Result as WalResult};
 
 use data_types::TIME_COLUMN_NAME;
@@ -86,11 +90,94 @@ pub struct Partition {
     pub tables: HashMap<u32, Table>,
 
     pub is_open: bool,
+
+    /// Predicates previously compiled by `compile_predicate`
+    compiled_predicates: Mutex<CompiledPredicateCache>,
+}
+
+/// Maximum number of predicates kept by `CompiledPredicateCache`
+const COMPILED_PREDICATE_CACHE_SIZE: usize = 100;
+
+/// Caches the result of `Partition::compile_predicate`, as the same
+/// predicate is typically compiled once for each table in a partition
+#[derive(Debug, Default)]
+struct CompiledPredicateCache {
+    /// The `Dictionary::len` the entries were compiled against. The
+    /// ids in compiled predicates depend on the dictionary, so the
+    /// entries are discarded when it changes
+    dictionary_len: usize,
+
+    /// Compiled predicates, with the `tick` they were last used at
+    entries: HashMap<PredicateKey, (PartitionPredicate, u64)>,
+
+    /// Incremented on each use of the cache, to find the least
+    /// recently used entry to evict when it is full
+    tick: u64,
+
+    /// Number of compilations answered from `entries`
+    hits: usize,
+}
+
+impl CompiledPredicateCache {
+    fn get(&mut self, key: &PredicateKey) -> Option<PartitionPredicate> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (partition_predicate, last_used) = self.entries.get_mut(key)?;
+        *last_used = tick;
+        self.hits += 1;
+        Some(partition_predicate.clone())
+    }
+
+    fn insert(&mut self, key: PredicateKey, partition_predicate: PartitionPredicate) {
+        if self.entries.len() >= COMPILED_PREDICATE_CACHE_SIZE {
+            let least_recently_used = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = least_recently_used {
+                self.entries.remove(&key);
+            }
+        }
+        self.entries.insert(key, (partition_predicate, self.tick));
+    }
+}
+
+/// The parts of a `Predicate` that determine its compiled form, in a
+/// form that can be hashed: `Predicate` holds `Expr`s and a `Regex`,
+/// which do not implement `Hash`, so they are represented by their
+/// `Debug` output and source pattern respectively.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PredicateKey {
+    table_names: Option<BTreeSet<String>>,
+    table_name_regex: Option<String>,
+    field_columns: Option<BTreeSet<String>>,
+    exprs: Vec<String>,
+    range: Option<(i64, i64)>,
+}
+
+impl From<&Predicate> for PredicateKey {
+    fn from(predicate: &Predicate) -> Self {
+        Self {
+            table_names: predicate.table_names.clone(),
+            table_name_regex: predicate
+                .table_name_regex
+                .as_ref()
+                .map(|regex| regex.as_str().to_string()),
+            field_columns: predicate.field_columns.clone(),
+            exprs: predicate
+                .exprs
+                .iter()
+                .map(|expr| format!("{:?}", expr))
+                .collect(),
+            range: predicate.range.map(|range| (range.start, range.end)),
+        }
+    }
 }
 
 /// Describes the result of translating a set of strings into
 /// partition specific ids
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PartitionIdSet {
     /// At least one of the strings was not present in the partitions'
     /// dictionary.
@@ -106,7 +193,7 @@ pub enum PartitionIdSet {
 /// a 'Compiled' set of predicates / filters that can be evaluated on
 /// this partition (where strings have been translated to partition
 /// specific u32 ids)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PartitionPredicate {
     /// If present, restrict the request to just those tables whose
     /// names are in table_names. If present but empty, means there
@@ -114,6 +201,10 @@ pub struct PartitionPredicate {
     /// partition (so no table can pass)
     pub table_name_predicate: Option<BTreeSet<u32>>,
 
+    /// If present, restrict the request to just those tables whose
+    /// names match this regular expression
+    pub table_name_regex: Option<Regex>,
+
     // Optional field column selection. If present, further restrict
     // any field columns returnedto only those named
     pub field_restriction: Option<BTreeSet<u32>>,
@@ -137,11 +228,12 @@ pub struct PartitionPredicate {
 
 impl PartitionPredicate {
     /// Creates and adds a datafuson predicate representing the
-    /// combination of predicate and timestamp.
-    pub fn filter_expr(&self) -> Option<Expr> {
+    /// combination of predicate and timestamp, where the timestamp
+    /// range applies to the column named `time_column_name`.
+    pub fn filter_expr(&self, time_column_name: &str) -> Option<Expr> {
         // build up a list of expressions
-        let mut builder =
-            AndExprBuilder::default().append_opt(self.make_timestamp_predicate_expr());
+        let mut builder = AndExprBuilder::default()
+            .append_opt(self.make_timestamp_predicate_expr(time_column_name));
 
         for expr in &self.partition_exprs {
             builder = builder.append_expr(expr.clone());
@@ -175,21 +267,22 @@ impl PartitionPredicate {
     /// Creates a DataFusion predicate for appliying a timestamp range:
     ///
     /// range.start <= time and time < range.end`
-    fn make_timestamp_predicate_expr(&self) -> Option<Expr> {
-        self.range.map(|range| make_range_expr(&range))
+    fn make_timestamp_predicate_expr(&self, time_column_name: &str) -> Option<Expr> {
+        self.range
+            .map(|range| make_range_expr(&range, time_column_name))
     }
 }
 
 /// Creates expression like:
 /// range.low <= time && time < range.high
-fn make_range_expr(range: &TimestampRange) -> Expr {
+fn make_range_expr(range: &TimestampRange, time_column_name: &str) -> Expr {
     let ts_low = Expr::BinaryExpr {
         left: Box::new(Expr::Literal(ScalarValue::Int64(Some(range.start)))),
         op: Operator::LtEq,
-        right: Box::new(Expr::Column(TIME_COLUMN_NAME.into())),
+        right: Box::new(Expr::Column(time_column_name.into())),
     };
     let ts_high = Expr::BinaryExpr {
-        left: Box::new(Expr::Column(TIME_COLUMN_NAME.into())),
+        left: Box::new(Expr::Column(time_column_name.into())),
         op: Operator::Lt,
         right: Box::new(Expr::Literal(ScalarValue::Int64(Some(range.end)))),
     };
@@ -208,6 +301,7 @@ impl Partition {
             dictionary: Dictionary::new(),
             tables: HashMap::new(),
             is_open: true,
+            compiled_predicates: Mutex::new(CompiledPredicateCache::default()),
         }
     }
 
@@ -240,10 +334,37 @@ impl Partition {
     }
 
     /// Translates `predicate` into per-partition ids that can be
-    /// directly evaluated against tables in this partition
+    /// directly evaluated against tables in this partition.
+    ///
+    /// Compiled predicates are cached until the dictionary changes,
+    /// evicting the least recently used one when the cache is full.
     pub fn compile_predicate(&self, predicate: &Predicate) -> Result<PartitionPredicate> {
+        let key = PredicateKey::from(predicate);
+        let mut cache = self
+            .compiled_predicates
+            .lock()
+            .expect("compiled predicate cache lock poisoned");
+
+        let dictionary_len = self.dictionary.len();
+        if cache.dictionary_len != dictionary_len {
+            cache.entries.clear();
+            cache.dictionary_len = dictionary_len;
+        }
+
+        if let Some(partition_predicate) = cache.get(&key) {
+            return Ok(partition_predicate);
+        }
+
+        let partition_predicate = self.compile_predicate_uncached(predicate)?;
+        cache.insert(key, partition_predicate.clone());
+        Ok(partition_predicate)
+    }
+
+    fn compile_predicate_uncached(&self, predicate: &Predicate) -> Result<PartitionPredicate> {
         let table_name_predicate = self.compile_string_list(predicate.table_names.as_ref());
 
+        let table_name_regex = predicate.table_name_regex.clone();
+
         let field_restriction = self.compile_string_list(predicate.field_columns.as_ref());
 
         let time_column_id = self
@@ -275,6 +396,7 @@ impl Partition {
 
         Ok(PartitionPredicate {
             table_name_predicate,
+            table_name_regex,
             field_restriction,
             partition_exprs,
             required_columns,
@@ -365,6 +487,33 @@ impl Partition {
             .context(NamedTableError { table_name })
     }
 
+    /// Renames the table `table_name` in this partition to `new_name`
+    pub fn rename_table(&mut self, table_name: &str, new_name: &str) -> Result<()> {
+        let table_id =
+            self.dictionary
+                .lookup_value(table_name)
+                .context(TableNameNotFoundInDictionary {
+                    table: table_name,
+                    partition: &self.key,
+                })?;
+
+        let mut table = self
+            .tables
+            .remove(&table_id)
+            .context(TableNotFoundInPartition {
+                table: table_id,
+                partition: &self.key,
+            })?;
+
+        let res = table
+            .rename(self, new_name)
+            .context(NamedTableError { table_name });
+
+        // put the table back (under its old id if the rename failed)
+        self.tables.insert(table.id, table);
+        res
+    }
+
     /// Translate a bunch of strings into a set of ids relative to this partition
     pub fn make_partition_ids<'a, I>(&self, predicate_columns: I) -> PartitionIdSet
     where
@@ -486,6 +635,7 @@ pub fn restore_partitions_from_wal(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use query::predicate::PredicateBuilder;
 
     #[test]
     fn test_make_range_expr() {
@@ -493,10 +643,104 @@ mod tests {
 
         let range = TimestampRange::new(101, 202);
 
-        let ts_predicate_expr = make_range_expr(&range);
+        let ts_predicate_expr = make_range_expr(&range, TIME_COLUMN_NAME);
         let expected_string = "Int64(101) LtEq #time And #time Lt Int64(202)";
         let actual_string = format!("{:?}", ts_predicate_expr);
 
         assert_eq!(actual_string, expected_string);
     }
+
+    #[test]
+    fn test_compile_predicate_cache() {
+        let mut partition = Partition::new("dummy_partition_key");
+        partition
+            .dictionary
+            .lookup_value_or_insert(TIME_COLUMN_NAME);
+        partition.dictionary.lookup_value_or_insert("h2o");
+
+        let predicate = PredicateBuilder::default()
+            .tables(vec!["h2o".into(), "o2".into()])
+            .timestamp_range(100, 200)
+            .build();
+
+        let first = partition.compile_predicate(&predicate).unwrap();
+        assert_eq!(compiled_predicate_cache_hits(&partition), 0);
+
+        let second = partition.compile_predicate(&predicate).unwrap();
+        assert_eq!(compiled_predicate_cache_hits(&partition), 1);
+        assert_eq!(format!("{:?}", first), format!("{:?}", second));
+
+        // "o2" is only resolved once it is in the dictionary, so
+        // changing the dictionary invalidates the cache
+        let o2_id = partition.dictionary.lookup_value_or_insert("o2");
+        assert!(!second.table_name_predicate.unwrap().contains(&o2_id));
+
+        let third = partition.compile_predicate(&predicate).unwrap();
+        assert_eq!(compiled_predicate_cache_hits(&partition), 1);
+        assert!(third.table_name_predicate.unwrap().contains(&o2_id));
+    }
+
+    #[test]
+    fn test_compile_predicate_cache_eviction() {
+        let mut partition = Partition::new("dummy_partition_key");
+        partition
+            .dictionary
+            .lookup_value_or_insert(TIME_COLUMN_NAME);
+
+        let predicate = |start| {
+            PredicateBuilder::default()
+                .timestamp_range(start, 1000)
+                .build()
+        };
+
+        // fill the cache, then use the first predicate again
+        for start in 0..COMPILED_PREDICATE_CACHE_SIZE as i64 {
+            partition.compile_predicate(&predicate(start)).unwrap();
+        }
+        partition.compile_predicate(&predicate(0)).unwrap();
+        assert_eq!(compiled_predicate_cache_hits(&partition), 1);
+
+        // a new predicate only evicts the least recently used one
+        partition.compile_predicate(&predicate(-1)).unwrap();
+        assert_eq!(
+            partition.compiled_predicates.lock().unwrap().entries.len(),
+            COMPILED_PREDICATE_CACHE_SIZE
+        );
+
+        partition.compile_predicate(&predicate(0)).unwrap();
+        partition.compile_predicate(&predicate(2)).unwrap();
+        assert_eq!(compiled_predicate_cache_hits(&partition), 3);
+
+        partition.compile_predicate(&predicate(1)).unwrap();
+        assert_eq!(compiled_predicate_cache_hits(&partition), 3);
+    }
+
+    fn compiled_predicate_cache_hits(partition: &Partition) -> usize {
+        partition.compiled_predicates.lock().unwrap().hits
+    }
+
+    #[test]
+    fn test_rename_table() {
+        let mut partition = Partition::new("dummy_partition_key");
+        for table_name in &["h2o", "o2"] {
+            let table_id = partition.dictionary.lookup_value_or_insert(table_name);
+            partition.tables.insert(table_id, Table::new(table_id));
+        }
+
+        partition.rename_table("h2o", "water").unwrap();
+
+        let water_id = partition.dictionary.lookup_value("water").unwrap();
+        let h2o_id = partition.dictionary.lookup_value("h2o").unwrap();
+        assert_eq!(partition.tables[&water_id].id, water_id);
+        assert!(!partition.tables.contains_key(&h2o_id));
+
+        // collisions leave the table in place
+        let res = partition.rename_table("water", "o2");
+        assert!(res.is_err(), "Expected error, got {:?}", res);
+        assert_eq!(partition.tables.len(), 2);
+        assert!(partition.tables.contains_key(&water_id));
+
+        // unknown tables are an error
+        assert!(partition.rename_table("h2o", "steam").is_err());
+    }
 }
@@ -1,31 +1,82 @@
+use data_types::data::{format_decimal, split_lines_into_write_entry_partitions, type_description};
 use generated_types::wal as wb;
-use query::exec::{make_schema_pivot, GroupedSeriesSetPlan, SeriesSetPlan};
-use tracing::debug;
-
-use std::{collections::BTreeSet, collections::HashMap, sync::Arc};
+use influxdb_line_protocol::parse_lines;
+use query::exec::{
+    make_offset, make_schema_pivot, make_schema_pivot_output_schema, GroupedSeriesSetPlan,
+    SeriesSetPlan,
+};
+use regex::Regex;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::hash_map::Entry,
+    collections::BTreeMap,
+    collections::BTreeSet,
+    collections::HashMap,
+    collections::HashSet,
+    convert::TryFrom,
+    future::Future,
+    hash::{Hash, Hasher},
+    mem,
+    ops::Range,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
 
 use crate::{
     column,
-    column::Column,
+    column::{Column, ColumnType},
     dictionary::{Dictionary, Error as DictionaryError},
     partition::PartitionIdSet,
     partition::{Partition, PartitionPredicate},
 };
-use data_types::TIME_COLUMN_NAME;
-use snafu::{OptionExt, ResultExt, Snafu};
+use data_types::{partition_metadata::Statistics, TIME_COLUMN_NAME};
+use query::{
+    group_by::{Aggregate, Percentile},
+    predicate::TimestampRange,
+};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 
 use arrow_deps::{
     arrow,
     arrow::{
-        array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
-        datatypes::{DataType as ArrowDataType, Field as ArrowField, Schema as ArrowSchema},
+        array::{
+            Array, ArrayRef, BooleanArray, BooleanBuilder, Date64Array, DecimalArray,
+            DecimalBuilder, DurationNanosecondArray, FixedSizeBinaryBuilder, Float64Array,
+            Float64Builder, Int64Array, Int64Builder, LargeStringBuilder, PrimitiveBuilder,
+            StringArray, StringBuilder, StringDictionaryBuilder, StructArray,
+        },
+        datatypes::{
+            DataType as ArrowDataType, DateUnit, Field as ArrowField, Int32Type,
+            Schema as ArrowSchema, SchemaRef as ArrowSchemaRef, TimeUnit,
+        },
         record_batch::RecordBatch,
     },
     datafusion,
     datafusion::logical_plan::Expr,
-    datafusion::logical_plan::LogicalPlan,
     datafusion::logical_plan::LogicalPlanBuilder,
+    datafusion::logical_plan::Operator,
+    datafusion::logical_plan::{self, LogicalPlan},
+    datafusion::optimizer::utils::expr_to_column_names,
+    datafusion::physical_plan::{
+        functions::ScalarFunctionImplementation, RecordBatchStream, SendableRecordBatchStream,
+    },
+    datafusion::scalar::ScalarValue,
+    parquet,
+    parquet::{
+        arrow::ArrowWriter,
+        basic::Compression,
+        file::{properties::WriterProperties, writer::ParquetWriter},
+        schema::types::ColumnPath,
+    },
 };
+use tokio::stream::Stream;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -102,6 +153,17 @@ pub enum Error {
         source: DictionaryError,
     },
 
+    #[snafu(display(
+        "Internal: Table id '{}' not found in dictionary of partition {}",
+        table_id,
+        partition
+    ))]
+    TableIdNotFoundInDictionary {
+        table_id: u32,
+        partition: String,
+        source: DictionaryError,
+    },
+
     #[snafu(display(
         "Schema mismatch: for column {}: can't insert {} into column with type {}",
         column,
@@ -137,7 +199,7 @@ pub enum Error {
 
     #[snafu(display("Error creating column from wal for column {}: {}", column, source))]
     CreatingFromWal {
-        column: u32,
+        column: String,
         source: crate::column::Error,
     },
 
@@ -147,9 +209,19 @@ pub enum Error {
         source: crate::column::Error,
     },
 
+    #[snafu(display("Table {} has no time column", table))]
+    TimeColumnNotFound { table: u32 },
+
     #[snafu(display("Row insert to table {} missing column name", table))]
     ColumnNameNotInRow { table: u32 },
 
+    #[snafu(display(
+        "Row insert to table {} contains column {} more than once",
+        table,
+        column
+    ))]
+    DuplicateColumnInRow { table: u32, column: String },
+
     #[snafu(display(
         "Group column '{}' not found in tag columns: {}",
         column_name,
@@ -162,874 +234,13029 @@ pub enum Error {
 
     #[snafu(display("Duplicate group column '{}'", column_name))]
     DuplicateGroupColumn { column_name: String },
-}
-pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-#[derive(Debug)]
-pub struct Table {
-    /// Name of the table as a u32 in the partition dictionary
-    pub id: u32,
+    #[snafu(display(
+        "Can not group by the time column '{}': use a window aggregate to group rows by time",
+        column_name
+    ))]
+    GroupByTimeColumn { column_name: String },
 
-    /// Maps column name (as a u32 in the partition dictionary) to an index in self.columns
-    pub column_id_to_index: HashMap<u32, usize>,
+    #[snafu(display("Alias column '{}' is not an output tag or field", column_name))]
+    AliasColumnNotFound { column_name: String },
 
-    /// Actual column storage
-    pub columns: Vec<Column>,
-}
+    #[snafu(display("Sort column '{}' not found in table", column_name))]
+    SortColumnNotFound { column_name: String },
 
-type ArcStringVec = Vec<Arc<String>>;
+    #[snafu(display("Arrow conversion cancelled"))]
+    Cancelled,
 
-impl Table {
-    pub fn new(id: u32) -> Self {
-        Self {
-            id,
-            column_id_to_index: HashMap::new(),
-            columns: Vec::new(),
-        }
-    }
+    #[snafu(display("Column '{}' to set metadata on not found in table", column_name))]
+    MetadataColumnNotFound { column_name: String },
 
-    fn append_row(
-        &mut self,
-        dictionary: &mut Dictionary,
-        values: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Value<'_>>>,
-    ) -> Result<()> {
-        let row_count = self.row_count();
+    #[snafu(display("Column '{}' of select expression not found in table", column_name))]
+    SelectColumnNotFound { column_name: String },
 
-        // insert new columns and validate existing ones
-        for value in values {
-            let column_name = value
-                .column()
-                .context(ColumnNameNotInRow { table: self.id })?;
-            let column_id = dictionary.lookup_value_or_insert(column_name);
+    #[snafu(display(
+        "Unsupported delete predicate: only tag equality comparisons and a timestamp range are supported"
+    ))]
+    UnsupportedDeletePredicate,
 
-            let column = match self.column_id_to_index.get(&column_id) {
-                Some(idx) => &mut self.columns[*idx],
-                None => {
-                    // Add the column and make all values for existing rows None
-                    let idx = self.columns.len();
-                    self.column_id_to_index.insert(column_id, idx);
-                    self.columns.push(
-                        Column::with_value(dictionary, row_count, value)
-                            .context(CreatingFromWal { column: column_id })?,
-                    );
+    #[snafu(display(
+        "Can not append column '{}' of arrow type {:?}: {}",
+        column_name,
+        data_type,
+        reason
+    ))]
+    UnsupportedArrowType {
+        column_name: String,
+        data_type: ArrowDataType,
+        reason: &'static str,
+    },
 
-                    continue;
-                }
-            };
+    #[snafu(display("Column '{}' is not in the expected schema", column_name))]
+    UnexpectedColumn { column_name: String },
 
-            column.push(dictionary, &value).context(ColumnError {
-                column: column_name,
-            })?;
-        }
+    #[snafu(display(
+        "Column '{}' has {} values, but is of arrow type {:?} in the expected schema",
+        column_name,
+        value_type,
+        data_type
+    ))]
+    UnexpectedColumnType {
+        column_name: String,
+        value_type: &'static str,
+        data_type: ArrowDataType,
+    },
 
-        // make sure all the columns are of the same length
-        for col in &mut self.columns {
-            col.push_none_if_len_equal(row_count);
-        }
+    #[snafu(display(
+        "Timestamp in column '{}' can not be represented in nanoseconds",
+        column_name
+    ))]
+    TimestampOverflow { column_name: String },
 
-        Ok(())
-    }
+    #[snafu(display(
+        "Row with time {} is outside the partition time bounds [{}, {}]",
+        time,
+        min_time,
+        max_time
+    ))]
+    RowOutsidePartition {
+        time: i64,
+        min_time: i64,
+        max_time: i64,
+    },
 
-    pub fn row_count(&self) -> usize {
-        self.columns.first().map_or(0, |v| v.len())
-    }
+    #[snafu(display(
+        "Unsupported filtered conversion predicate: only tag equality comparisons and a timestamp range are supported"
+    ))]
+    UnsupportedFilteredToArrowPredicate,
 
-    /// Returns a reference to the specified column
-    fn column(&self, column_id: u32) -> Result<&Column> {
-        Ok(self
-            .column_id_to_index
-            .get(&column_id)
-            .map(|&column_index| &self.columns[column_index])
-            .expect("invalid column id"))
-    }
+    #[snafu(display(
+        "Unsupported parquet export predicate: only tag equality comparisons and a timestamp range are supported"
+    ))]
+    UnsupportedParquetPredicate,
 
-    /// Returns a reference to the specified column as a slice of
-    /// i64s. Errors if the type is not i64
-    pub fn column_i64(&self, column_id: u32) -> Result<&[Option<i64>]> {
-        let column = self.column(column_id)?;
-        match column {
-            Column::I64(vals, _) => Ok(vals),
-            _ => InternalColumnTypeMismatch {
-                column_id,
-                expected_column_type: "i64",
-                actual_column_type: column.type_description(),
-            }
-            .fail(),
-        }
-    }
+    #[snafu(display("Error writing parquet: {}", source))]
+    ParquetWriteError {
+        source: parquet::errors::ParquetError,
+    },
 
-    pub fn append_rows(
-        &mut self,
-        dictionary: &mut Dictionary,
-        rows: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Row<'_>>>,
-    ) -> Result<()> {
-        for row in rows {
-            if let Some(values) = row.values() {
-                self.append_row(dictionary, &values)?;
-            }
-        }
+    #[snafu(display("Invalid field selector: {:?} columns are not fields", column_type))]
+    InvalidFieldSelectorType { column_type: ColumnType },
 
-        Ok(())
-    }
+    #[snafu(display(
+        "Can not rename table to '{}': a table with that name already exists",
+        table_name
+    ))]
+    TableNameCollision { table_name: String },
 
-    /// Creates and adds a datafuson filtering expression, if any out of the
-    /// combination of predicate and timestamp. Returns the builder
-    fn add_datafusion_predicate(
-        plan_builder: LogicalPlanBuilder,
-        partition_predicate: &PartitionPredicate,
-    ) -> Result<LogicalPlanBuilder> {
-        match partition_predicate.filter_expr() {
-            Some(df_predicate) => plan_builder.filter(df_predicate).context(BuildingPlan),
-            None => Ok(plan_builder),
-        }
-    }
+    #[snafu(display("Can not merge table {}: its rows are not sorted on time", table))]
+    TableNotTimeSorted { table: u32 },
 
-    /// Creates a DataFusion LogicalPlan that returns column *names* as a
-    /// single column of Strings
-    ///
-    /// The created plan looks like:
-    ///
-    ///  Extension(PivotSchema)
-    ///    (Optional Projection to get rid of time)
-    ///        Filter(predicate)
-    ///          InMemoryScan
-    pub fn tag_column_names_plan(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        partition: &Partition,
-    ) -> Result<LogicalPlan> {
-        let need_time_column = partition_predicate.range.is_some();
+    #[snafu(display(
+        "The rows of table {} are not sorted by series: row {} is out of order",
+        table,
+        row
+    ))]
+    TableNotSeriesSorted { table: u32, row: usize },
 
-        let time_column_id = partition_predicate.time_column_id;
+    #[snafu(display("Invalid histogram field '{}': not a numeric field", column_name))]
+    InvalidHistogramField { column_name: String },
 
-        // figure out the tag columns
-        let requested_columns_with_index = self
-            .column_id_to_index
-            .iter()
-            .filter_map(|(&column_id, &column_index)| {
-                // keep tag columns and the timestamp column, if needed to evaluate a timestamp predicate
-                let need_column = if let Column::Tag(_, _) = self.columns[column_index] {
-                    true
-                } else {
-                    need_time_column && column_id == time_column_id
-                };
+    #[snafu(display(
+        "Invalid histogram bucket width {}: must be greater than zero",
+        bucket_width
+    ))]
+    InvalidBucketWidth { bucket_width: f64 },
 
-                if need_column {
-                    // the id came out of our map, so it should always be valid
-                    let column_name = partition.dictionary.lookup_id(column_id).unwrap();
-                    Some((column_name, column_index))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
+    #[snafu(display(
+        "Converting to arrow would use an estimated {} bytes, exceeding the limit of {} bytes",
+        estimated_bytes,
+        memory_limit
+    ))]
+    ArrowMemoryLimitExceeded {
+        estimated_bytes: usize,
+        memory_limit: usize,
+    },
 
-        // TODO avoid materializing here
-        let data = self.to_arrow_impl(partition, &requested_columns_with_index)?;
+    #[snafu(display("Invalid row range [{}, {}): start is after end", start, end))]
+    InvalidRowRange { start: usize, end: usize },
 
-        let schema = data.schema();
+    #[snafu(display("Invalid batch size {}: must be greater than zero", batch_size))]
+    InvalidBatchSize { batch_size: usize },
 
-        let projection = None;
-        let projected_schema = schema.clone();
+    #[snafu(display(
+        "Invalid sample of rows with index mod {} == {}: the residue must be less than the modulus",
+        modulus,
+        residue
+    ))]
+    InvalidSample { modulus: usize, residue: usize },
 
-        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
-            data: vec![vec![data]],
-            schema,
-            projection,
-            projected_schema,
-        });
+    #[snafu(display("General predicate expressions are not supported when listing group keys"))]
+    UnsupportedGroupKeysPredicate,
 
-        // Shouldn't have field selections here (as we are getting the tags...)
-        assert!(!partition_predicate.has_field_restriction());
+    #[snafu(display("General predicate expressions are not supported when counting tag values"))]
+    UnsupportedTagValueCountsPredicate,
 
-        let plan_builder = Self::add_datafusion_predicate(plan_builder, partition_predicate)?;
+    #[snafu(display(
+        "General predicate expressions are not supported when listing distinct field values"
+    ))]
+    UnsupportedDistinctFieldValuesPredicate,
 
-        // add optional selection to remove time column
-        let plan_builder = if !need_time_column {
-            plan_builder
-        } else {
-            // Create expressions for all columns except time
-            let select_exprs = requested_columns_with_index
-                .iter()
-                .filter_map(|&(column_name, _)| {
-                    if column_name != TIME_COLUMN_NAME {
-                        Some(Expr::Column(column_name.into()))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+    #[snafu(display(
+        "General predicate expressions are not supported when listing distinct tag values"
+    ))]
+    UnsupportedDistinctTagValuesPredicate,
 
-            plan_builder.project(select_exprs).context(BuildingPlan)?
-        };
+    #[snafu(display("Can not coalesce column '{}': not found in table", column_name))]
+    CoalesceColumnNotFound { column_name: String },
 
-        let plan = plan_builder.build().context(BuildingPlan)?;
+    #[snafu(display(
+        "Can not coalesce column '{}' into '{}': the time column can not be removed",
+        secondary,
+        primary
+    ))]
+    InvalidCoalesceColumns { primary: String, secondary: String },
 
-        // And finally pivot the plan
-        let plan = make_schema_pivot(plan);
+    #[snafu(display("Can not rename column '{}': not found in table", column_name))]
+    RenameColumnNotFound { column_name: String },
 
-        debug!(
-            "Created column_name plan for table '{}':\n{}",
-            partition.dictionary.lookup_id(self.id).unwrap(),
-            plan.display_indent_schema()
-        );
+    #[snafu(display(
+        "Can not rename column '{}' to '{}': the time column can not be renamed",
+        from,
+        to
+    ))]
+    InvalidTimeColumnRename { from: String, to: String },
 
-        Ok(plan)
-    }
+    #[snafu(display(
+        "Can not rename column '{}' to '{}': another column would have that name",
+        from,
+        to
+    ))]
+    RenameColumnCollision { from: String, to: String },
 
-    /// Creates a DataFusion LogicalPlan that returns column *values* as a
-    /// single column of Strings
-    ///
-    /// The created plan looks like:
-    ///
-    ///    Projection
-    ///        Filter(predicate)
-    ///          InMemoryScan
-    pub fn tag_values_plan(
-        &self,
-        column_name: &str,
-        partition_predicate: &PartitionPredicate,
-        partition: &Partition,
-    ) -> Result<LogicalPlan> {
-        // TODO avoid materializing all the columns here (ideally
-        // DataFusion can prune them out)
-        let data = self.all_to_arrow(partition)?;
+    #[snafu(display(
+        "Can not coalesce column '{}' of type {} into column '{}' of type {}",
+        secondary,
+        secondary_type,
+        primary,
+        primary_type
+    ))]
+    CoalesceColumnTypeMismatch {
+        primary: String,
+        primary_type: String,
+        secondary: String,
+        secondary_type: String,
+    },
 
-        let schema = data.schema();
+    #[snafu(display("General predicate expressions are not supported in top n plans"))]
+    UnsupportedTopNPredicate,
 
-        let projection = None;
-        let projected_schema = schema.clone();
-        let select_exprs = vec![Expr::Column(column_name.into())];
+    #[snafu(display(
+        "General predicate expressions are not supported when collapsing duplicate timestamps"
+    ))]
+    UnsupportedDuplicateTimestampsPredicate,
 
-        // And build the plan!
-        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
-            data: vec![vec![data]],
-            schema,
-            projection,
-            projected_schema,
-        });
+    #[snafu(display("A series has more than one row with timestamp {}", time))]
+    DuplicateSeriesTimestamp { time: i64 },
 
-        // shouldn't have columns selection (as this is getting tag values...)
-        assert!(!partition_predicate.has_field_restriction());
+    #[snafu(display("General predicate expressions are not supported in time delta plans"))]
+    UnsupportedTimeDeltaPredicate,
 
-        let plan_builder = Self::add_datafusion_predicate(plan_builder, partition_predicate)?;
+    #[snafu(display("General predicate expressions are not supported in gap fill plans"))]
+    UnsupportedGapFillPredicate,
 
-        plan_builder
-            .project(select_exprs)
-            .context(BuildingPlan)?
-            .build()
-            .context(BuildingPlan)
-    }
+    #[snafu(display(
+        "Invalid gap fill interval {}: must be greater than zero",
+        interval_nanos
+    ))]
+    InvalidGapFillInterval { interval_nanos: i64 },
 
-    /// Creates a SeriesSet plan that produces an output table with rows that match the predicate
-    ///
-    /// The output looks like:
-    /// (tag_col1, tag_col2, ... field1, field2, ... timestamp)
-    ///
-    /// The order of the tag_columns is orderd by name.
-    ///
-    /// The data is sorted on tag_col1, tag_col2, ...) so that all
-    /// rows for a particular series (groups where all tags are the
-    /// same) occur together in the plan
-    pub fn series_set_plan(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        partition: &Partition,
-    ) -> Result<SeriesSetPlan> {
-        self.series_set_plan_impl(partition_predicate, None, partition)
-    }
+    #[snafu(display(
+        "Gap filling would produce {} intervals per group, exceeding the limit of {}",
+        num_intervals,
+        MAX_GAP_FILL_INTERVALS
+    ))]
+    TooManyGapFillIntervals { num_intervals: u64 },
 
-    /// Creates the plans for computing series set, pulling prefix_columns, if any, as a prefix of the ordering
-    /// The created plan looks like:
-    ///
-    ///    Projection (select the columns columns needed)
-    ///      Order by (tag_columns, timestamp_column)
-    ///        Filter(predicate)
-    ///          InMemoryScan
-    pub fn series_set_plan_impl(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        prefix_columns: Option<&[String]>,
-        partition: &Partition,
-    ) -> Result<SeriesSetPlan> {
-        // I wonder if all this string creation will be too slow?
-        let table_name = partition
-            .dictionary
-            .lookup_id(self.id)
-            .expect("looking up table name in dictionary")
-            .to_string();
+    #[snafu(display("Fill policy {:?} is not supported yet", fill))]
+    UnsupportedFillPolicy { fill: FillPolicy },
 
-        let table_name = Arc::new(table_name);
-        let (mut tag_columns, field_columns) =
-            self.tag_and_field_column_names(partition_predicate, partition)?;
+    #[snafu(display(
+        "Internal error: table has {} columns but {} column ids",
+        num_columns,
+        num_column_ids
+    ))]
+    ColumnIdsOutOfSync {
+        num_columns: usize,
+        num_column_ids: usize,
+    },
 
-        // reorder tag_columns to have the prefix columns, if requested
-        if let Some(prefix_columns) = prefix_columns {
-            tag_columns = reorder_prefix(prefix_columns, tag_columns)?;
-        }
+    #[snafu(display("Invalid top n field '{}': not a numeric field", column_name))]
+    InvalidTopNField { column_name: String },
 
-        // TODO avoid materializing all the columns here (ideally
-        // DataFusion can prune them out)
-        let data = self.all_to_arrow(partition)?;
+    #[snafu(display(
+        "General predicate expressions are not supported when ordering groups by an aggregate"
+    ))]
+    UnsupportedGroupOrderPredicate,
 
-        let schema = data.schema();
+    #[snafu(display("Invalid group order field '{}': {}", column_name, reason))]
+    InvalidGroupOrderField {
+        column_name: String,
+        reason: &'static str,
+    },
 
-        let projection = None;
-        let projected_schema = schema.clone();
+    #[snafu(display("General predicate expressions are not supported in aggregate plans"))]
+    UnsupportedAggregatePredicate,
 
-        // And build the plan from the bottom up
-        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
-            data: vec![vec![data]],
-            schema,
-            projection,
-            projected_schema,
-        });
+    #[snafu(display("Invalid aggregate field '{}': {}", column_name, reason))]
+    InvalidAggregateField {
+        column_name: String,
+        reason: &'static str,
+    },
 
-        // Filtering
-        let plan_builder = Self::add_datafusion_predicate(plan_builder, partition_predicate)?;
+    #[snafu(display("Aggregate {:?} is not supported by the write buffer", aggregate))]
+    UnsupportedAggregate { aggregate: Aggregate },
 
-        let mut sort_exprs = Vec::new();
-        sort_exprs.extend(tag_columns.iter().map(|c| c.into_sort_expr()));
-        sort_exprs.push(TIME_COLUMN_NAME.into_sort_expr());
+    #[snafu(display("Internal error: can not create a null array of type {:?}", data_type))]
+    InternalUnsupportedNullArrayType { data_type: ArrowDataType },
 
-        // Order by
-        let plan_builder = plan_builder.sort(sort_exprs).context(BuildingPlan)?;
+    #[snafu(display("Column '{}' is not a field of the table", column_name))]
+    NotAFieldColumn { column_name: String },
 
-        // Selection
-        let mut select_exprs = Vec::new();
-        select_exprs.extend(tag_columns.iter().map(|c| c.into_expr()));
-        select_exprs.extend(field_columns.iter().map(|c| c.into_expr()));
-        select_exprs.push(TIME_COLUMN_NAME.into_expr());
+    #[snafu(display("Column '{}' is not a tag of the table", column_name))]
+    NotATagColumn { column_name: String },
 
-        let plan_builder = plan_builder.project(select_exprs).context(BuildingPlan)?;
+    #[snafu(display(
+        "Column '{}' is a tag: use tag_values_plan for distinct tag values",
+        column_name
+    ))]
+    DistinctOnTag { column_name: String },
 
-        // and finally create the plan
-        let plan = plan_builder.build().context(BuildingPlan)?;
+    #[snafu(display(
+        "Column '{}' is a decimal column, which {} does not support yet",
+        column_name,
+        operation
+    ))]
+    UnsupportedDecimalOperation {
+        column_name: String,
+        operation: String,
+    },
 
-        Ok(SeriesSetPlan {
-            table_name,
-            plan,
-            tag_columns,
-            field_columns,
-        })
+    #[snafu(display("Error parsing line protocol: {}", source))]
+    ParsingLineProtocol {
+        source: influxdb_line_protocol::Error,
+    },
+
+    #[snafu(display(
+        "Can not append a line of measurement '{}' to table {}",
+        measurement,
+        table
+    ))]
+    MeasurementMismatch { measurement: String, table: u32 },
+
+    #[snafu(display("Error serializing schema to JSON: {}", source))]
+    SerializingSchemaJson { source: serde_json::Error },
+}
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Describes how `Table::append_rows` handles a value whose type
+/// differs from the type of the column it is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeConflictPolicy {
+    /// Reject the write with an error (matches InfluxDB)
+    Reject,
+
+    /// Promote an existing `I64` column to `F64` (converting all
+    /// existing values) when a `F64` value is written to it. All
+    /// other type conflicts are still rejected.
+    PromoteNumeric,
+}
+
+impl Default for TypeConflictPolicy {
+    fn default() -> Self {
+        Self::Reject
     }
+}
 
-    /// Creates a GroupedSeriesSet plan that produces an output table with rows that match the predicate
-    ///
-    /// The output looks like:
-    /// (group_tag_column1, group_tag_column2, ... tag_col1, tag_col2, ... field1, field2, ... timestamp)
-    ///
-    /// The order of the tag_columns is ordered by name.
-    ///
-    /// The data is sorted on tag_col1, tag_col2, ...) so that all
-    /// rows for a particular series (groups where all tags are the
-    /// same) occur together in the plan
-    ///
-    /// The created plan looks like:
-    ///
-    ///    Projection (select the columns columns needed)
-    ///      Order by (tag_columns, timestamp_column)
-    ///        Filter(predicate)
-    ///          InMemoryScan
-    pub fn grouped_series_set_plan(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        group_columns: &[String],
-        partition: &Partition,
-    ) -> Result<GroupedSeriesSetPlan> {
-        let series_set_plan =
-            self.series_set_plan_impl(partition_predicate, Some(&group_columns), partition)?;
-        let num_prefix_tag_group_columns = group_columns.len();
+/// Describes how `Table::append_rows` handles a value of a type that
+/// no `Column` can store (such as an unsigned integer, or a value type
+/// added to the WAL by a newer writer)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownValueTypePolicy {
+    /// Reject the write with an error
+    Reject,
 
-        Ok(GroupedSeriesSetPlan {
-            series_set_plan,
-            num_prefix_tag_group_columns,
-        })
+    /// Ignore the value, as if it were not in the row
+    Skip,
+
+    /// Store the value as a string in a `Column::String` column, if
+    /// it can be converted to a string, and otherwise ignore it
+    Stringify,
+}
+
+impl Default for UnknownValueTypePolicy {
+    fn default() -> Self {
+        Self::Reject
     }
+}
 
-    /// Creates a plan that produces an output table with rows that
-    /// match the predicate for all fields in the table.
-    ///
-    /// The output looks like (field0, field1, ..., time)
-    ///
-    /// The data is not sorted in any particular order
-    ///
-    /// The created plan looks like:
-    ///
-    ///    Projection (select the field columns needed)
-    ///        Filter(predicate) [optional]
-    ///          InMemoryScan
-    pub fn field_names_plan(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        partition: &Partition,
-    ) -> Result<LogicalPlan> {
-        // TODO avoid materializing all the columns here (ideally
-        // DataFusion can prune them out)
-        let data = self.all_to_arrow(partition)?;
+/// Describes how `Table::append_rows` handles a row whose time is
+/// outside `Table::partition_time_bounds`, which usually indicates a
+/// write routed to the wrong partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfBoundsPolicy {
+    /// Reject the row with a `RowOutsidePartition` error
+    Reject,
 
-        let schema = data.schema();
+    /// Append the row, logging a warning
+    Warn,
+}
 
-        let projection = None;
-        let projected_schema = schema.clone();
+impl Default for OutOfBoundsPolicy {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
 
-        // And build the plan from the bottom up
-        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
-            data: vec![vec![data]],
-            schema,
-            projection,
-            projected_schema,
-        });
+/// Options that control how the columns of a `Table` are converted
+/// to arrow arrays
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ToArrowOptions {
+    /// The arrow type emitted for `Column::String` columns
+    pub string_type: StringArrowType,
+
+    /// If true, `Column::I64` columns annotated with a
+    /// `SemanticType` (see `Table::set_semantic_type`) are emitted
+    /// using the corresponding arrow temporal type rather than `Int64`
+    pub emit_semantic_types: bool,
+
+    /// If set, the maximum number of bytes the converted arrays may
+    /// use. The size is estimated before converting, and conversions
+    /// exceeding the limit fail with `ArrowMemoryLimitExceeded`
+    /// rather than allocating.
+    pub memory_limit: Option<usize>,
+
+    /// If true, `Column::Tag` columns whose (non null) values all have
+    /// the same length `n`, such as hex encoded hashes, are emitted as
+    /// `FixedSizeBinary(n)` rather than `Utf8`. Tag columns with values
+    /// of different lengths, or no values, are still emitted as `Utf8`
+    pub fixed_size_binary_tags: bool,
+
+    /// If true, only fields for columns that contain null values are
+    /// marked nullable, so for example the time column (which is
+    /// normally never null) is emitted as non-nullable. Otherwise all
+    /// fields are nullable, so the schema does not depend on the data.
+    pub exact_nullability: bool,
+
+    /// If set, `Column::String` columns with at most this many distinct
+    /// (non null) values, such as status fields that are constant over
+    /// long runs, are emitted as `Dictionary(Int32, Utf8)` so each
+    /// value is stored once. Other string columns are emitted as per
+    /// `string_type`. (The version of arrow we depend on has no run
+    /// length encoded arrays, so dictionary encoding is used instead)
+    pub dictionary_strings_max_distinct: Option<usize>,
+}
 
-        // Filtering
-        let plan_builder = Self::add_datafusion_predicate(plan_builder, partition_predicate)?;
+/// Selects the arrow type emitted for `Column::String` columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringArrowType {
+    /// Always emit `Utf8` (32-bit offsets)
+    Utf8,
 
-        // Selection
-        let select_exprs = self
-            .field_and_time_column_names(partition_predicate, partition)
-            .into_iter()
-            .map(|c| c.into_expr())
-            .collect::<Vec<_>>();
+    /// Always emit `LargeUtf8` (64-bit offsets)
+    LargeUtf8,
 
-        let plan_builder = plan_builder.project(select_exprs).context(BuildingPlan)?;
+    /// Emit `LargeUtf8` for columns whose values total more than the
+    /// specified number of bytes, and `Utf8` otherwise
+    LargeUtf8Above(usize),
+}
 
-        // and finally create the plan
-        plan_builder.build().context(BuildingPlan)
+impl Default for StringArrowType {
+    fn default() -> Self {
+        Self::Utf8
     }
+}
 
-    // Returns (tag_columns, field_columns) vectors with the names of
-    // all tag and field columns, respectively. The vectors are sorted
-    // by name.
-    fn tag_and_field_column_names(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        partition: &Partition,
-    ) -> Result<(ArcStringVec, ArcStringVec)> {
-        let mut tag_columns = Vec::with_capacity(self.column_id_to_index.len());
-        let mut field_columns = Vec::with_capacity(self.column_id_to_index.len());
-
-        for (&column_id, &column_index) in &self.column_id_to_index {
-            let column_name = partition
-                .dictionary
-                .lookup_id(column_id)
-                .expect("Find column name in dictionary");
+/// Options for writing a table to parquet with `Table::write_parquet`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParquetProps {
+    /// The compression codec used for all columns
+    pub compression: Compression,
 
-            if column_name != TIME_COLUMN_NAME {
-                let column_name = Arc::new(column_name.to_string());
+    /// The maximum number of rows in each parquet row group
+    pub max_row_group_size: usize,
+}
 
-                match self.columns[column_index] {
-                    Column::Tag(_, _) => tag_columns.push(column_name),
-                    _ => {
-                        if partition_predicate.should_include_field(column_id) {
-                            field_columns.push(column_name)
-                        }
-                    }
-                }
-            }
+impl Default for ParquetProps {
+    fn default() -> Self {
+        Self {
+            compression: Compression::UNCOMPRESSED,
+            max_row_group_size: 1024 * 1024,
         }
+    }
+}
 
-        // tag columns are always sorted by name (aka sorted by tag
-        // key) in the output schema, so ensure the columns are sorted
-        // (the select exprs)
-        tag_columns.sort();
+/// What the values of an integer field column represent, beyond
+/// being integers. Used to emit a more specific arrow type, see
+/// `ToArrowOptions::emit_semantic_types`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemanticType {
+    /// A duration in nanoseconds, emitted as arrow
+    /// `Duration(Nanosecond)`
+    Duration,
+
+    /// A date as milliseconds since the UNIX epoch, emitted as arrow
+    /// `Date64`
+    Date,
+}
 
-        // Sort the field columns too so that the output always comes
-        // out in a predictable order
-        field_columns.sort();
+/// Restricts the rows produced by a series set plan to a single
+/// "page" of its (sorted) output.
+///
+/// Paging is only meaningful when the output has a stable ordering:
+/// series set plans sort on the tag columns and then time, so rows
+/// that tie on all of those may appear in a different order (and
+/// thus on a different page) from one request to the next.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    /// Number of (sorted) rows to skip
+    pub offset: Option<usize>,
+
+    /// Maximum number of rows to return after skipping `offset`
+    pub limit: Option<usize>,
+}
 
-        Ok((tag_columns, field_columns))
-    }
+/// Describes how a series set plan handles rows of the same series
+/// (that is, with the same tag values) with the same timestamp, which
+/// many visualization tools can not display unambiguously. Rows are
+/// collapsed as a whole, regardless of which fields they have values
+/// for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateTimestamps {
+    /// Output all the rows
+    KeepAll,
 
-    // Returns (field_columns and time) in sorted order
-    fn field_and_time_column_names(
-        &self,
-        partition_predicate: &PartitionPredicate,
-        partition: &Partition,
-    ) -> ArcStringVec {
-        let mut field_columns = self
-            .column_id_to_index
-            .iter()
-            .filter_map(|(&column_id, &column_index)| {
-                match self.columns[column_index] {
-                    Column::Tag(_, _) => None, // skip tags
-                    _ => {
-                        if partition_predicate.should_include_field(column_id)
-                            || partition_predicate.is_time_column(column_id)
-                        {
-                            let column_name = partition
-                                .dictionary
-                                .lookup_id(column_id)
-                                .expect("Find column name in dictionary");
-                            Some(Arc::new(column_name.to_string()))
-                        } else {
-                            None
-                        }
-                    }
-                }
-            })
-            .collect::<Vec<_>>();
+    /// Output only the first row written
+    KeepFirst,
 
-        // Sort the field columns too so that the output always comes
-        // out in a predictable order
-        field_columns.sort();
+    /// Output only the last row written
+    KeepLast,
+
+    /// Fail with a `DuplicateSeriesTimestamp` error
+    Error,
+}
 
-        field_columns
+impl Default for DuplicateTimestamps {
+    fn default() -> Self {
+        Self::KeepAll
     }
+}
 
-    /// Converts this table to an arrow record batch.
-    pub fn to_arrow(
-        &self,
-        partition: &Partition,
-        requested_columns: &[&str],
-    ) -> Result<RecordBatch> {
-        // if requested columns is empty, retrieve all columns in the table
-        if requested_columns.is_empty() {
-            self.all_to_arrow(partition)
-        } else {
-            let columns_with_index = self.column_names_with_index(partition, requested_columns)?;
+/// How `Table::merge_from` combines the rows of another table with
+/// the rows of the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Append all the rows of the other table
+    Append,
+
+    /// Combine each row of the other table with the row of the table
+    /// (the last one, if several) of the same series (that is, with
+    /// the same tag values) and time, if any: the non null values of
+    /// the other row replace those of the existing row, so where both
+    /// rows have a value for a field, the other table's value wins.
+    /// Other rows, including those without a time, are appended.
+    CoalesceBySeriesTime,
+}
 
-            self.to_arrow_impl(partition, &columns_with_index)
-        }
+impl Default for MergeStrategy {
+    fn default() -> Self {
+        Self::Append
     }
+}
 
-    fn column_names_with_index<'a>(
-        &self,
-        partition: &Partition,
-        columns: &[&'a str],
-    ) -> Result<Vec<(&'a str, usize)>> {
-        columns
-            .iter()
-            .map(|&column_name| {
-                let column_id = partition.dictionary.lookup_value(column_name).context(
-                    ColumnNameNotFoundInDictionary {
-                        column_name,
-                        partition: &partition.key,
-                    },
-                )?;
+/// How `Table::gap_fill_plan` fills the fields of intervals without
+/// a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillPolicy {
+    /// The fields are null
+    Null,
+    /// The fields take the value of the previous interval of the same
+    /// group, if any
+    Previous,
+    /// The fields are interpolated between the surrounding values (not
+    /// supported yet)
+    Linear,
+}
 
-                let column_index =
-                    *self
-                        .column_id_to_index
-                        .get(&column_id)
-                        .context(InternalNoColumnInIndex {
-                            column_name,
-                            column_id,
-                        })?;
+/// How the groups of `Table::grouped_series_set_plan` are ordered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupOrder {
+    /// By the values of the group columns
+    ByKey,
+
+    /// By the aggregate of the named column over the rows of each
+    /// group, ascending if the flag is true. Groups with the same
+    /// aggregate, and then groups with no aggregate (such as when the
+    /// column has no values in the group) follow, ordered by key.
+    ByAggregate(AggregateKind, String, bool),
+}
 
-                Ok((column_name, column_index))
-            })
-            .collect()
+impl Default for GroupOrder {
+    fn default() -> Self {
+        Self::ByKey
     }
+}
 
-    /// Convert all columns to an arrow record batch
-    pub fn all_to_arrow(&self, partition: &Partition) -> Result<RecordBatch> {
-        let mut requested_columns_with_index = self
-            .column_id_to_index
-            .iter()
-            .map(|(&column_id, &column_index)| {
-                let column_name = partition.dictionary.lookup_id(column_id).context(
-                    ColumnIdNotFoundInDictionary {
-                        column_id,
-                        partition: &partition.key,
-                    },
-                )?;
-                Ok((column_name, column_index))
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        requested_columns_with_index.sort_by(|(a, _), (b, _)| a.cmp(b));
+/// Optional settings of the plans created by
+/// `Table::series_set_plan_impl` and `Table::grouped_series_set_plan`.
+/// The defaults produce the same plan as `Table::series_set_plan`.
+#[derive(Debug, Clone)]
+pub struct SeriesSetPlanOptions {
+    /// Tags ordered before the remaining tags (but after any prefix
+    /// columns), in the order given; the remaining tags stay ordered
+    /// by name. Unlike prefix columns, pinned tags that are not tag
+    /// columns of the output are ignored.
+    pub pinned_tags: Vec<String>,
+
+    /// Whether rows without a value for a tag (or group column) are
+    /// ordered before the rows with a value, rather than after them
+    pub nulls_first: bool,
+
+    /// The fields included in the output
+    pub field_selector: FieldSelector,
+
+    /// The page of the sorted output to produce
+    pub pagination: Pagination,
+
+    /// How rows of the same series with the same timestamp are
+    /// handled. Which rows are kept is determined before the plan is
+    /// created, so unless this is `DuplicateTimestamps::KeepAll` only
+    /// the predicate's timestamp range is supported and general
+    /// predicate expressions result in an error.
+    pub duplicates: DuplicateTimestamps,
+
+    /// The names of tag and field columns renamed in the output (and
+    /// in the `tag_columns` and `field_columns` of the plan), keyed
+    /// by column name. Plans can not be created if an alias names a
+    /// column that is not a tag or field in the output.
+    pub aliases: HashMap<String, String>,
+
+    /// How the groups of rows with the same values of the prefix (or
+    /// group) columns are ordered. Ordering by an aggregate computes
+    /// it before the plan is created so, as for `Table::top_n_plan`,
+    /// general predicate expressions are then not supported.
+    pub group_order: GroupOrder,
+}
 
-        self.to_arrow_impl(partition, &requested_columns_with_index)
+impl Default for SeriesSetPlanOptions {
+    fn default() -> Self {
+        Self {
+            pinned_tags: vec![],
+            nulls_first: true,
+            field_selector: FieldSelector::default(),
+            pagination: Pagination::default(),
+            duplicates: DuplicateTimestamps::default(),
+            aliases: HashMap::new(),
+            group_order: GroupOrder::default(),
+        }
     }
+}
 
-    /// Converts this table to an arrow record batch,
-    ///
-    /// requested columns with index are tuples of column_name, column_index
-    pub fn to_arrow_impl(
-        &self,
-        partition: &Partition,
-        requested_columns_with_index: &[(&str, usize)],
-    ) -> Result<RecordBatch> {
-        let mut fields = Vec::with_capacity(requested_columns_with_index.len());
-        let mut columns: Vec<ArrayRef> = Vec::with_capacity(requested_columns_with_index.len());
+/// The aggregate computed for each group by `GroupOrder::ByAggregate`
+/// and `Table::aggregate_plan`: the aggregates of
+/// `query::group_by::Aggregate` the write buffer can compute, see
+/// `AggregateKind::try_from`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateKind {
+    /// The number of non null values of the column, of any type.
+    /// Counting the time column gives the number of rows of the group
+    Count,
 
-        for &(column_name, column_index) in requested_columns_with_index.iter() {
-            let arrow_col: ArrayRef = match &self.columns[column_index] {
-                Column::String(vals, _) => {
-                    fields.push(ArrowField::new(column_name, ArrowDataType::Utf8, true));
-                    let mut builder = StringBuilder::with_capacity(vals.len(), vals.len() * 10);
+    /// The sum of the (numeric) column's values
+    Sum,
 
-                    for v in vals {
-                        match v {
-                            None => builder.append_null(),
-                            Some(s) => builder.append_value(s),
-                        }
-                        .context(ArrowError {})?;
-                    }
+    /// The minimum of the (numeric) column's values
+    Min,
 
-                    Arc::new(builder.finish())
-                }
-                Column::Tag(vals, _) => {
-                    fields.push(ArrowField::new(column_name, ArrowDataType::Utf8, true));
-                    let mut builder = StringBuilder::with_capacity(vals.len(), vals.len() * 10);
-
-                    for v in vals {
-                        match v {
-                            None => builder.append_null(),
-                            Some(value_id) => {
-                                let tag_value = partition.dictionary.lookup_id(*value_id).context(
-                                    TagValueIdNotFoundInDictionary {
-                                        value: *value_id,
-                                        partition: &partition.key,
-                                    },
-                                )?;
-                                builder.append_value(tag_value)
-                            }
-                        }
-                        .context(ArrowError {})?;
-                    }
+    /// The maximum of the (numeric) column's values
+    Max,
 
-                    Arc::new(builder.finish())
-                }
-                Column::F64(vals, _) => {
-                    fields.push(ArrowField::new(column_name, ArrowDataType::Float64, true));
-                    let mut builder = Float64Builder::new(vals.len());
+    /// The exact percentile of the (numeric) column's values, see
+    /// `Percentile::of_sorted`
+    Percentile(Percentile),
+}
 
-                    for v in vals {
-                        builder.append_option(*v).context(ArrowError {})?;
-                    }
+impl AggregateKind {
+    /// Returns the aggregate of `values`, which must not be empty or
+    /// contain NaN. The values may be reordered.
+    fn aggregate(self, values: &mut [f64]) -> f64 {
+        match self {
+            Self::Count => values.len() as f64,
+            Self::Sum => values.iter().sum(),
+            Self::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Self::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Self::Percentile(percentile) => {
+                values.sort_by(|a, b| a.partial_cmp(b).expect("values are not NaN"));
+                percentile.of_sorted(values).expect("values are not empty")
+            }
+        }
+    }
+}
 
-                    Arc::new(builder.finish())
+impl TryFrom<&Aggregate> for AggregateKind {
+    type Error = Error;
+
+    fn try_from(aggregate: &Aggregate) -> Result<Self> {
+        Ok(match aggregate {
+            Aggregate::Count => Self::Count,
+            Aggregate::Sum => Self::Sum,
+            Aggregate::Min => Self::Min,
+            Aggregate::Max => Self::Max,
+            Aggregate::Percentile(percentile) => Self::Percentile(*percentile),
+            Aggregate::First | Aggregate::Last | Aggregate::Mean => {
+                return UnsupportedAggregate {
+                    aggregate: aggregate.clone(),
                 }
-                Column::I64(vals, _) => {
-                    fields.push(ArrowField::new(column_name, ArrowDataType::Int64, true));
-                    let mut builder = Int64Builder::new(vals.len());
+                .fail()
+            }
+        })
+    }
+}
 
-                    for v in vals {
-                        builder.append_option(*v).context(ArrowError {})?;
-                    }
+/// The result of `Table::group_aggregates`
+struct GroupAggregates {
+    /// The group columns, in the order of the keys
+    group_tag_columns: ArcStringVec,
 
-                    Arc::new(builder.finish())
-                }
-                Column::Bool(vals, _) => {
-                    fields.push(ArrowField::new(column_name, ArrowDataType::Boolean, true));
-                    let mut builder = BooleanBuilder::new(vals.len());
+    /// The group key (tag value ids) of each row
+    row_keys: Vec<Vec<Option<u32>>>,
 
-                    for v in vals {
-                        builder.append_option(*v).context(ArrowError {})?;
-                    }
+    /// The aggregate of each group that has one, by group key
+    aggregates: HashMap<Vec<Option<u32>>, f64>,
+}
 
-                    Arc::new(builder.finish())
-                }
-            };
+/// Selects which field columns are included in the output of
+/// `field_names_plan` and series set plans. This is applied in
+/// addition to any field restriction in the predicate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldSelector {
+    /// All field columns
+    All,
+
+    /// Only the field columns with these names
+    ByName(Vec<String>),
+
+    /// Only the field columns of this type (which must not be
+    /// `ColumnType::Tag`)
+    ByType(ColumnType),
+
+    /// Only the field columns whose names match this glob pattern,
+    /// where `*` matches any sequence of characters and `?` matches
+    /// any single character. For example `cpu.*`
+    Glob(String),
+}
 
-            columns.push(arrow_col);
+impl Default for FieldSelector {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl FieldSelector {
+    /// Returns an error if this selector can never select a field
+    fn validate(&self) -> Result<()> {
+        match self {
+            Self::ByType(ColumnType::Tag) => InvalidFieldSelectorType {
+                column_type: ColumnType::Tag,
+            }
+            .fail(),
+            _ => Ok(()),
         }
+    }
 
-        let schema = ArrowSchema::new(fields);
+    /// Returns true if the field `column` named `column_name` is selected
+    fn selects(&self, column_name: &str, column: &Column) -> bool {
+        match self {
+            Self::All => true,
+            Self::ByName(names) => names.iter().any(|name| name == column_name),
+            Self::ByType(column_type) => column.column_type() == *column_type,
+            Self::Glob(pattern) => glob_matches(pattern, column_name),
+        }
+    }
+}
 
-        RecordBatch::try_new(Arc::new(schema), columns).context(ArrowError {})
+/// Returns true if `name` matches the glob `pattern` (see
+/// `FieldSelector::Glob`)
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` seen, and the position in name it
+    // was matched against, to backtrack to on a mismatch
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` consume one more character
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
     }
 
-    /// returns true if any row in this table could possible match the
-    /// predicate. true does not mean any rows will *actually* match,
-    /// just that the entire table can not be ruled out.
-    ///
-    /// false means that no rows in this table could possibly match
-    pub fn could_match_predicate(&self, partition_predicate: &PartitionPredicate) -> Result<bool> {
-        Ok(
-            self.matches_column_selection(partition_predicate.field_restriction.as_ref())
-                && self.matches_table_name_predicate(
-                    partition_predicate.table_name_predicate.as_ref(),
-                )
-                && self.matches_timestamp_predicate(partition_predicate)?
-                && self.has_columns(partition_predicate.required_columns.as_ref()),
-        )
+    // any remaining pattern must be `*`s, which match nothing
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Summary of the data added by `Table::append_from_line_protocol`
+/// and `Table::append_record_batch`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AppendStats {
+    /// Number of rows appended to the table
+    pub rows_appended: usize,
+
+    /// Number of columns the table did not previously have
+    pub columns_added: usize,
+}
+
+/// Counters updated while appending rows to a table, see
+/// `Table::append_rows_tracked`. Callers can aggregate them across
+/// tables and batches.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IngestMetrics {
+    /// Number of rows appended
+    pub rows_appended: usize,
+
+    /// Number of columns added to the table
+    pub columns_created: usize,
+
+    /// Number of values whose type conflicted with the existing
+    /// column, whether rejected or resolved by promoting the column
+    pub type_conflicts: usize,
+
+    /// Number of null values added to columns missing from a row,
+    /// including the nulls for the existing rows of a new column
+    pub null_backfills: usize,
+
+    /// Number of rows appended (under `OutOfBoundsPolicy::Warn`)
+    /// whose time is outside the table's partition time bounds
+    pub rows_outside_partition: usize,
+}
+
+/// The tag values of the partition dictionary referenced by a table,
+/// see `Table::dictionary_stats`. Sizes are the bytes of the values
+/// themselves, excluding the dictionary's overhead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryStats {
+    /// Number of distinct values referenced only by this table
+    pub exclusive_values: usize,
+
+    /// Size of the values referenced only by this table, in bytes
+    pub exclusive_bytes: usize,
+
+    /// Number of distinct values also referenced by other tables
+    pub shared_values: usize,
+
+    /// Size of the values also referenced by other tables, in bytes
+    pub shared_bytes: usize,
+}
+
+/// Work done by a single column while evaluating a predicate
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnScanStats {
+    /// Number of times the column was consulted
+    pub checks: usize,
+
+    /// Number of column values read. Checks answered from column
+    /// statistics (such as min / max) examine no values.
+    pub rows_examined: usize,
+
+    /// Number of checks that ruled out the table
+    pub pruned: usize,
+}
+
+/// Accumulates, per column id, the work done while evaluating
+/// predicates against tables (see `Table::could_match_predicate`),
+/// so callers can report which columns a query touched.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanStats {
+    columns: BTreeMap<u32, ColumnScanStats>,
+}
+
+impl ScanStats {
+    /// Returns the stats recorded for `column_id`, if it was consulted
+    pub fn column(&self, column_id: u32) -> Option<&ColumnScanStats> {
+        self.columns.get(&column_id)
     }
 
-    /// Returns true if the table contains at least one of the fields
-    /// requested or there are no specific fields requested.
-    fn matches_column_selection(&self, column_selection: Option<&BTreeSet<u32>>) -> bool {
-        match column_selection {
-            Some(column_selection) => {
-                // figure out if any of the columns exists
-                self.column_id_to_index
-                    .keys()
-                    .any(|column_id| column_selection.contains(column_id))
-            }
-            None => true, // no specific selection
+    /// Returns the stats of all consulted columns, by column id
+    pub fn columns(&self) -> impl Iterator<Item = (u32, &ColumnScanStats)> {
+        self.columns
+            .iter()
+            .map(|(&column_id, stats)| (column_id, stats))
+    }
+
+    /// Records one check of `column_id` which read `rows_examined`
+    /// values and ruled out the table if `pruned` is true
+    fn record(&mut self, column_id: u32, rows_examined: usize, pruned: bool) {
+        let stats = self.columns.entry(column_id).or_default();
+        stats.checks += 1;
+        stats.rows_examined += rows_examined;
+        if pruned {
+            stats.pruned += 1;
         }
     }
+}
 
-    fn matches_table_name_predicate(&self, table_name_predicate: Option<&BTreeSet<u32>>) -> bool {
-        match table_name_predicate {
-            Some(table_name_predicate) => table_name_predicate.contains(&self.id),
-            None => true, // no table predicate
+/// The rows of a table split into groups, see `Table::grouped_frames`
+#[derive(Debug)]
+pub struct GroupedFrames {
+    pub groups: Vec<GroupFrame>,
+}
+
+/// One group of `GroupedFrames`
+#[derive(Debug)]
+pub struct GroupFrame {
+    /// (group tag name, tag value) pairs, in the order of the group
+    /// columns. The value is `None` for rows without the tag.
+    pub key: Vec<(Arc<String>, Option<Arc<String>>)>,
+
+    /// Produces the rows of this group
+    pub series_set_plan: SeriesSetPlan,
+}
+
+/// The measurement schema produced by `Table::schema_json`
+#[derive(Debug, Serialize)]
+struct SchemaJson<'a> {
+    measurement: &'a str,
+    tags: Vec<&'a str>,
+    fields: Vec<SchemaJsonField<'a>>,
+}
+
+/// A field key and its type in `SchemaJson`
+#[derive(Debug, Serialize)]
+struct SchemaJsonField<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    field_type: &'static str,
+}
+
+#[derive(Debug)]
+pub struct Table {
+    /// Name of the table as a u32 in the partition dictionary
+    pub id: u32,
+
+    /// Maps column name (as a u32 in the partition dictionary) to an index in self.columns
+    column_id_to_index: HashMap<u32, usize>,
+
+    /// Actual column storage. Private, as changing the columns must
+    /// also update `column_ids` and invalidate `cached_arrow_schema`
+    columns: Vec<Column>,
+
+    /// The id of each column in `columns` (in the same order), from
+    /// which `column_id_to_index` can be rebuilt
+    column_ids: Vec<u32>,
+
+    /// How to handle writes of a value whose type conflicts with the
+    /// existing column type
+    pub type_conflict_policy: TypeConflictPolicy,
+
+    /// How to handle writes of a value whose type no column can store
+    pub unknown_value_type_policy: UnknownValueTypePolicy,
+
+    /// If set, the inclusive `(min_time, max_time)` range of the
+    /// partition holding this table. Rows with a time outside it are
+    /// handled according to `out_of_bounds_policy`; rows without a
+    /// time are not checked.
+    pub partition_time_bounds: Option<(i64, i64)>,
+
+    /// How to handle rows outside `partition_time_bounds`
+    pub out_of_bounds_policy: OutOfBoundsPolicy,
+
+    /// Arrow schema of all columns, sorted by name, reused across
+    /// calls to `all_to_arrow`. Cleared when the columns change.
+    cached_arrow_schema: Mutex<Option<ArrowSchemaRef>>,
+
+    /// Id of the time column, once it has been added
+    time_column_id: Option<u32>,
+
+    /// Result of `is_time_sorted`. Cleared when rows are appended.
+    cached_time_sorted: Mutex<Option<bool>>,
+
+    /// Semantic type annotations, keyed by column id
+    semantic_types: HashMap<u32, SemanticType>,
+
+    /// Arrow field metadata (such as units), keyed by column id
+    column_metadata: HashMap<u32, BTreeMap<String, String>>,
+
+    /// Name of the time column, if it is not `TIME_COLUMN_NAME`
+    time_column_name: Option<String>,
+
+    /// Bloom filters over the value ids of each tag column, keyed by
+    /// column id, if enabled by `enable_tag_value_filters`
+    tag_value_filters: Option<HashMap<u32, TagValueFilter>>,
+}
+
+type ArcStringVec = Vec<Arc<String>>;
+
+/// Default name of the column added by `Table::add_partition_key_column`
+pub const PARTITION_KEY_COLUMN_NAME: &str = "_partition";
+
+/// Name of the output column of `Table::tag_keys_plan`
+pub const TAG_KEY_COLUMN_NAME: &str = "tag_key";
+
+/// Name of the field name column of `Table::field_keys_plan`
+pub const FIELD_KEY_COLUMN_NAME: &str = "field_key";
+
+/// Name of the field type column of `Table::field_keys_plan`
+pub const FIELD_TYPE_COLUMN_NAME: &str = "field_type";
+
+/// Name of the tags column of `Table::to_arrow_with_serialized_tags`
+pub const SERIALIZED_TAGS_COLUMN_NAME: &str = "tags";
+
+/// Name of the row number column added by `Table::sorted_plan_impl`
+pub const ROW_NUMBER_COLUMN_NAME: &str = "_row";
+
+/// Name of the column `Table::top_n_plan` uses to mark the selected
+/// rows, which is not part of the output
+const TOP_N_SELECTED_COLUMN_NAME: &str = "_top_n_selected";
+
+/// Name of the column series set plans use to mark the rows kept when
+/// collapsing duplicate timestamps, which is not part of the output
+const DEDUPLICATED_COLUMN_NAME: &str = "_deduplicated";
+
+/// Name of the column `Table::grouped_series_set_plan` uses to order
+/// groups by an aggregate, which is not part of the output
+const GROUP_RANK_COLUMN_NAME: &str = "_group_rank";
+
+/// Name of the bucket index column of `Table::histogram_plan`
+pub const HISTOGRAM_BUCKET_COLUMN_NAME: &str = "bucket";
+
+/// Name of the count column of `Table::histogram_plan`
+pub const HISTOGRAM_COUNT_COLUMN_NAME: &str = "count";
+
+/// Name of the delta column of `Table::time_delta_plan`
+pub const TIME_DELTA_COLUMN_NAME: &str = "delta";
+
+/// Maximum number of intervals `Table::gap_fill_plan` emits for each
+/// group
+pub const MAX_GAP_FILL_INTERVALS: u64 = 100_000;
+
+impl Table {
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            column_id_to_index: HashMap::new(),
+            columns: Vec::new(),
+            column_ids: Vec::new(),
+            type_conflict_policy: TypeConflictPolicy::default(),
+            unknown_value_type_policy: UnknownValueTypePolicy::default(),
+            partition_time_bounds: None,
+            out_of_bounds_policy: OutOfBoundsPolicy::default(),
+            cached_arrow_schema: Mutex::new(None),
+            time_column_id: None,
+            cached_time_sorted: Mutex::new(None),
+            semantic_types: HashMap::new(),
+            column_metadata: HashMap::new(),
+            time_column_name: None,
+            tag_value_filters: None,
         }
     }
 
-    /// returns true if there are any timestamps in this table that
-    /// fall within the timestamp range
-    fn matches_timestamp_predicate(
-        &self,
-        partition_predicate: &PartitionPredicate,
-    ) -> Result<bool> {
-        match &partition_predicate.range {
-            None => Ok(true),
-            Some(range) => {
-                let time_column_id = partition_predicate.time_column_id;
-                let time_column = self.column(time_column_id)?;
-                time_column.has_i64_range(range.start, range.end).context(
-                    ColumnPredicateEvaluation {
-                        column: time_column_id,
-                    },
-                )
-            }
+    /// Creates a table whose time column is named `time_column_name`
+    /// rather than `TIME_COLUMN_NAME`, for ingestion sources that use
+    /// a different name for the time field.
+    ///
+    /// Values written to `time_column_name` are the row times used by
+    /// timestamp predicates, and the time column of the series set
+    /// and field name plans is still output as `TIME_COLUMN_NAME`. A
+    /// column named `TIME_COLUMN_NAME` in such a table is neither the
+    /// time column nor reported as a field.
+    pub fn with_time_column_name(id: u32, time_column_name: impl Into<String>) -> Self {
+        Self {
+            time_column_name: Some(time_column_name.into()),
+            ..Self::new(id)
         }
     }
 
-    /// returns true if no columns are specified, or the table has all
-    /// columns specified
-    fn has_columns(&self, columns: Option<&PartitionIdSet>) -> bool {
-        if let Some(columns) = columns {
-            match columns {
-                PartitionIdSet::AtLeastOneMissing => return false,
-                PartitionIdSet::Present(symbols) => {
-                    for symbol in symbols {
-                        if !self.column_id_to_index.contains_key(symbol) {
-                            return false;
-                        }
-                    }
-                }
-            }
+    /// Creates a table with no rows and an empty column for each of
+    /// `columns`, so the table's schema is known before any data is
+    /// written. Errors if the same column name is given twice with
+    /// different types.
+    pub fn with_schema(
+        id: u32,
+        dictionary: &mut Dictionary,
+        columns: &[(&str, ColumnType)],
+    ) -> Result<Self> {
+        let mut table = Self::new(id);
+        for &(column_name, column_type) in columns {
+            table.add_column(dictionary, column_name, column_type)?;
         }
-        true
+        Ok(table)
     }
 
-    /// returns true if there are any rows in column that are non-null
-    /// and within the timestamp range specified by pred
-    pub fn column_matches_predicate<T>(
-        &self,
-        column: &[Option<T>],
-        partition_predicate: &PartitionPredicate,
-    ) -> Result<bool> {
-        match partition_predicate.range {
-            None => Ok(true),
-            Some(range) => {
-                let time_column_id = partition_predicate.time_column_id;
-                let time_column = self.column(time_column_id)?;
-                time_column
-                    .has_non_null_i64_range(column, range.start, range.end)
-                    .context(ColumnPredicateEvaluation {
-                        column: time_column_id,
-                    })
+    /// Adds a column named `column_name` of `column_type`, with nulls
+    /// for all existing rows. Does nothing if the column already
+    /// exists with the same type, and errors if it exists with a
+    /// different type.
+    pub fn add_column(
+        &mut self,
+        dictionary: &mut Dictionary,
+        column_name: &str,
+        column_type: ColumnType,
+    ) -> Result<()> {
+        let column_id = dictionary.lookup_value_or_insert(column_name);
+
+        if let Some(&column_index) = self.column_id_to_index.get(&column_id) {
+            let existing = &self.columns[column_index];
+            if existing.column_type() == column_type {
+                return Ok(());
             }
+            return Err(Error::ColumnError {
+                column: column_name.to_string(),
+                source: column::Error::TypeMismatch {
+                    existing_column_type: existing.type_description().into(),
+                    inserted_value_type: column_type.description().into(),
+                },
+            });
+        }
+
+        let mut column = Column::new_empty(column_type);
+        column.pad_with_nulls(self.row_count());
+
+        self.push_column(column_id, column);
+        self.invalidate_cached_arrow_schema();
+        if column_name == self.time_column_name() {
+            self.time_column_id = Some(column_id);
         }
+
+        Ok(())
     }
-}
 
-/// Reorders tag_columns so that its prefix matches exactly
-/// prefix_columns. Returns an error if there are duplicates, or other
-/// untoward inputs
-fn reorder_prefix(
-    prefix_columns: &[String],
-    tag_columns: Vec<Arc<String>>,
-) -> Result<Vec<Arc<String>>> {
-    // tag_used_set[i[ is true if we have used the value in tag_columns[i]
-    let mut tag_used_set = vec![false; tag_columns.len()];
+    /// Adds `column` with id `column_id` after the existing columns
+    fn push_column(&mut self, column_id: u32, column: Column) {
+        self.column_id_to_index
+            .insert(column_id, self.columns.len());
+        self.columns.push(column);
+        self.column_ids.push(column_id);
+    }
+
+    /// Returns the id of each column, in the order the columns are
+    /// stored: `column_ids()[i]` is the id of the column at index `i`
+    pub fn column_ids(&self) -> &[u32] {
+        &self.column_ids
+    }
+
+    /// Returns the columns of this table, in the order they are stored
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Returns the map from column id to the index of the column in
+    /// `columns()`
+    pub fn column_id_to_index(&self) -> &HashMap<u32, usize> {
+        &self.column_id_to_index
+    }
+
+    /// Reconstructs `column_id_to_index` (and the id of the time
+    /// column) from the ids of the columns, recovering from an index
+    /// that is out of sync with `columns`. Errors if a column id is
+    /// not in the dictionary of `partition`, or if columns were added
+    /// to `columns` directly so their ids are unknown.
+    pub fn rebuild_index(&mut self, partition: &Partition) -> Result<()> {
+        ensure!(
+            self.column_ids.len() == self.columns.len(),
+            ColumnIdsOutOfSync {
+                num_columns: self.columns.len(),
+                num_column_ids: self.column_ids.len(),
+            }
+        );
+
+        let mut column_id_to_index = HashMap::with_capacity(self.column_ids.len());
+        let mut time_column_id = None;
+        for (column_index, &column_id) in self.column_ids.iter().enumerate() {
+            let column_name = partition.dictionary.lookup_id(column_id).context(
+                ColumnIdNotFoundInDictionary {
+                    column_id,
+                    partition: &partition.key,
+                },
+            )?;
+            if column_name == self.time_column_name() {
+                time_column_id = Some(column_id);
+            }
+            column_id_to_index.insert(column_id, column_index);
+        }
+
+        self.column_id_to_index = column_id_to_index;
+        self.time_column_id = time_column_id;
+        self.invalidate_cached_arrow_schema();
+
+        Ok(())
+    }
+
+    /// Maintains a bloom filter over the value ids of each tag column
+    /// from now on (starting with the existing rows), so that
+    /// `maybe_contains_tag_value`, and thus `could_match_predicate`,
+    /// can rule out tag values without scanning the column.
+    pub fn enable_tag_value_filters(&mut self) {
+        self.tag_value_filters = Some(HashMap::new());
+        self.update_tag_value_filters(0..self.row_count());
+    }
+
+    /// Returns false if the tag column `column_id` definitely has no
+    /// row with the value `value_id`, and true if it may have. Always
+    /// true if tag value filters are not enabled (see
+    /// `enable_tag_value_filters`), and false if `column_id` is not a
+    /// tag column of this table.
+    pub fn maybe_contains_tag_value(&self, column_id: u32, value_id: u32) -> bool {
+        if !matches!(self.column(column_id), Ok(Column::Tag(_, _))) {
+            return false;
+        }
+
+        match &self.tag_value_filters {
+            Some(tag_value_filters) => tag_value_filters
+                .get(&column_id)
+                .map_or(false, |filter| filter.maybe_contains(value_id)),
+            None => true,
+        }
+    }
+
+    /// Adds the tag values in `rows` to the tag value filters, if
+    /// enabled
+    fn update_tag_value_filters(&mut self, rows: Range<usize>) {
+        let tag_value_filters = match &mut self.tag_value_filters {
+            Some(tag_value_filters) => tag_value_filters,
+            None => return,
+        };
+
+        for (&column_id, &column_index) in &self.column_id_to_index {
+            if let Column::Tag(vals, _) = &self.columns[column_index] {
+                let filter = tag_value_filters
+                    .entry(column_id)
+                    .or_insert_with(TagValueFilter::new);
+                let end = rows.end.min(vals.len());
+                let start = rows.start.min(end);
+                for value_id in vals[start..end].iter().flatten() {
+                    filter.insert(*value_id);
+                }
+            }
+        }
+    }
+
+    /// Returns the name of the time column of this table
+    pub fn time_column_name(&self) -> &str {
+        self.time_column_name.as_deref().unwrap_or(TIME_COLUMN_NAME)
+    }
+
+    /// Returns the id of the column timestamp predicates are evaluated
+    /// against: the time column of this table if it has one, and
+    /// otherwise the time column of the partition
+    pub fn predicate_time_column_id(&self, partition_predicate: &PartitionPredicate) -> u32 {
+        self.time_column_id
+            .unwrap_or(partition_predicate.time_column_id)
+    }
+
+    /// Return true if `column_id` is the time column of this table
+    fn is_time_column(&self, partition_predicate: &PartitionPredicate, column_id: u32) -> bool {
+        self.predicate_time_column_id(partition_predicate) == column_id
+    }
+
+    /// Appends a single row.
+    ///
+    /// The row's values are checked against the types of the existing
+    /// columns before anything is modified, so a row that is rejected
+    /// (e.g. because of a type conflict on a column introduced by an
+    /// earlier row) neither adds columns nor leaves values for some of
+    /// its columns behind.
+    ///
+    /// Column names are stored in the dictionary in their canonical,
+    /// unescaped form (e.g. the tag key `a\ b` in line protocol is
+    /// stored as `a b`), which is the form used to refer to columns
+    /// in predicates. The line protocol parser unescapes keys before
+    /// they are written to the WAL, so names are stored as they
+    /// appear in the row; unescaping them again here would corrupt
+    /// names containing a (previously escaped) backslash.
+    fn append_row<'a>(
+        &mut self,
+        dictionary: &mut Dictionary,
+        values: &flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<wb::Value<'a>>>,
+        batch_columns: &mut BatchColumns<'a>,
+        mut metrics: Option<&mut IngestMetrics>,
+    ) -> Result<()> {
+        if let Err(e) = self.check_row_types(dictionary, values, batch_columns) {
+            if let (Error::ColumnError { source, .. }, Some(metrics)) = (&e, metrics.as_deref_mut())
+            {
+                if matches!(source, column::Error::TypeMismatch { .. }) {
+                    metrics.type_conflicts += 1;
+                }
+            }
+            return Err(e);
+        }
+
+        let row_count = self.row_count();
+
+        // insert new columns and validate existing ones
+        for value in values {
+            let column_name = value
+                .column()
+                .context(ColumnNameNotInRow { table: self.id })?;
+
+            // values of types no column can store are only left in the
+            // row by `check_row_types` under the `Skip` and `Stringify`
+            // policies
+            let stringified = match ColumnType::from_value(&value) {
+                Some(_) => None,
+                None => match stringify_value(&value) {
+                    Some(s)
+                        if self.unknown_value_type_policy == UnknownValueTypePolicy::Stringify =>
+                    {
+                        Some(s)
+                    }
+                    _ => continue,
+                },
+            };
+
+            // column_name is already unescaped, see above. Names
+            // `check_row_types` found in the dictionary are not looked
+            // up again
+            let column_id = match batch_columns.id(column_name) {
+                Some(column_id) => column_id,
+                None => {
+                    let column_id = dictionary.lookup_value_or_insert(column_name);
+                    batch_columns.set_id(column_name, column_id);
+                    column_id
+                }
+            };
+
+            let column_index = match self.column_id_to_index.get(&column_id) {
+                Some(&idx) => idx,
+                None => {
+                    // Add the column and make all values for existing rows None
+                    let column = match &stringified {
+                        Some(s) => Column::with_string_value(row_count, s),
+                        None => Column::with_value(dictionary, row_count, value).context(
+                            CreatingFromWal {
+                                column: column_name,
+                            },
+                        )?,
+                    };
+                    self.push_column(column_id, column);
+                    self.invalidate_cached_arrow_schema();
+                    if column_name == self.time_column_name() {
+                        self.time_column_id = Some(column_id);
+                    }
+                    if let Some(metrics) = metrics.as_deref_mut() {
+                        metrics.columns_created += 1;
+                        metrics.null_backfills += row_count;
+                    }
+
+                    continue;
+                }
+            };
+
+            if self.type_conflict_policy == TypeConflictPolicy::PromoteNumeric
+                && matches!(self.columns[column_index], Column::I64(_, _))
+                && value.value_as_f64value().is_some()
+            {
+                if let Some(metrics) = metrics.as_deref_mut() {
+                    metrics.type_conflicts += 1;
+                }
+                self.columns[column_index]
+                    .promote_i64_to_f64()
+                    .context(ColumnError {
+                        column: column_name,
+                    })?;
+                self.invalidate_cached_arrow_schema();
+            }
+
+            let column = &mut self.columns[column_index];
+            match &stringified {
+                Some(s) => column.push_string(s),
+                None => column.push(dictionary, &value),
+            }
+            .context(ColumnError {
+                column: column_name,
+            })?;
+        }
+
+        if let Some(metrics) = metrics {
+            metrics.null_backfills += self
+                .columns
+                .iter()
+                .filter(|col| col.len() == row_count)
+                .count();
+            metrics.rows_appended += 1;
+        }
+
+        self.update_tag_value_filters(row_count..row_count + 1);
+
+        // make sure all the columns are of the same length
+        for col in &mut self.columns {
+            col.push_none_if_len_equal(row_count);
+        }
+
+        Ok(())
+    }
+
+    /// Renames this table to `new_name`, interning it in the
+    /// dictionary of `partition`. Plans created after the rename use
+    /// the new name.
+    ///
+    /// Errors if `partition` already has a (different) table named
+    /// `new_name`. Note this only updates the table itself: a table
+    /// stored in `partition.tables` must also be re-keyed, which
+    /// `Partition::rename_table` does.
+    pub fn rename(&mut self, partition: &mut Partition, new_name: &str) -> Result<()> {
+        let new_id = partition.dictionary.lookup_value_or_insert(new_name);
+        if new_id == self.id {
+            return Ok(());
+        }
+
+        ensure!(
+            !partition.tables.contains_key(&new_id),
+            TableNameCollision {
+                table_name: new_name
+            }
+        );
+
+        self.id = new_id;
+        Ok(())
+    }
+
+    /// Coalesces the columns `primary` and `secondary`, which must
+    /// have the same type, into `primary`: null values of `primary`
+    /// are filled with the value of `secondary` in the same row, and
+    /// `secondary` is then removed from the table.
+    ///
+    /// Errors if either column is not in the table, if the types
+    /// differ, or if `secondary` is the time column (or `primary`).
+    pub fn coalesce_columns(
+        &mut self,
+        partition: &Partition,
+        primary: &str,
+        secondary: &str,
+    ) -> Result<()> {
+        ensure!(
+            primary != secondary && secondary != self.time_column_name(),
+            InvalidCoalesceColumns { primary, secondary }
+        );
+
+        let column_id_and_index = |column_name: &str| {
+            partition
+                .dictionary
+                .lookup_value(column_name)
+                .ok()
+                .and_then(|column_id| {
+                    self.column_id_to_index
+                        .get(&column_id)
+                        .map(|&column_index| (column_id, column_index))
+                })
+                .context(CoalesceColumnNotFound { column_name })
+        };
+        let (primary_id, primary_index) = column_id_and_index(primary)?;
+        let (secondary_id, secondary_index) = column_id_and_index(secondary)?;
+
+        let primary_type = self.columns[primary_index].type_description();
+        let secondary_type = self.columns[secondary_index].type_description();
+        ensure!(
+            primary_type == secondary_type,
+            CoalesceColumnTypeMismatch {
+                primary,
+                primary_type,
+                secondary,
+                secondary_type,
+            }
+        );
+
+        // coalesce into a copy, so the table is unchanged on error
+        let mut coalesced = self.columns[primary_index].clone();
+        coalesced
+            .coalesce(&self.columns[secondary_index], &partition.dictionary)
+            .context(ColumnError { column: primary })?;
+        self.columns[primary_index] = coalesced;
+
+        self.columns.remove(secondary_index);
+        self.column_ids.remove(secondary_index);
+        self.column_id_to_index.remove(&secondary_id);
+        for column_index in self.column_id_to_index.values_mut() {
+            if *column_index > secondary_index {
+                *column_index -= 1;
+            }
+        }
+        self.semantic_types.remove(&secondary_id);
+        self.column_metadata.remove(&secondary_id);
+        self.invalidate_cached_arrow_schema();
+
+        // filling null times can change the order of the times
+        if primary_id == self.time_column_id {
+            *self
+                .cached_time_sorted
+                .get_mut()
+                .expect("cached time sorted lock poisoned") = None;
+        }
+
+        if let Some(tag_value_filters) = &mut self.tag_value_filters {
+            tag_value_filters.remove(&secondary_id);
+        }
+        self.update_tag_value_filters(0..self.row_count());
+
+        Ok(())
+    }
+
+    /// Renames the columns of this table named by the keys of
+    /// `renames` to the corresponding values, all at once, so that
+    /// columns can swap names (e.g. `a -> b` along with `b -> a`).
+    ///
+    /// Errors, without renaming any column, if a column to rename is
+    /// not in the table, if the time column would be renamed (or
+    /// another column would become the time column), or if two
+    /// columns would end up with the same name.
+    pub fn apply_rename_map(
+        &mut self,
+        partition: &mut Partition,
+        renames: &HashMap<String, String>,
+    ) -> Result<()> {
+        let time_column_name = self.time_column_name();
+        let mut targets = HashSet::new();
+        for (from, to) in renames {
+            let column_id = partition
+                .dictionary
+                .id(from)
+                .filter(|column_id| self.column_id_to_index.contains_key(column_id))
+                .context(RenameColumnNotFound { column_name: from })?;
+
+            ensure!(
+                from == to || (from != time_column_name && to != time_column_name),
+                InvalidTimeColumnRename { from, to }
+            );
+
+            // the target name must not be taken by a column that
+            // keeps its name, nor by the target of another rename
+            let target_taken = partition.dictionary.id(to).map_or(false, |target_id| {
+                target_id != column_id
+                    && self.column_id_to_index.contains_key(&target_id)
+                    && !renames.contains_key(to)
+            });
+            ensure!(
+                !target_taken && targets.insert(to),
+                RenameColumnCollision { from, to }
+            );
+        }
+
+        // all renames are valid, so apply them together
+        let id_renames = renames
+            .iter()
+            .map(|(from, to)| {
+                (
+                    partition.dictionary.lookup_value_or_insert(from),
+                    partition.dictionary.lookup_value_or_insert(to),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let rename_id = |column_id: u32| *id_renames.get(&column_id).unwrap_or(&column_id);
+
+        for column_id in &mut self.column_ids {
+            *column_id = rename_id(*column_id);
+        }
+        self.column_id_to_index = self
+            .column_ids
+            .iter()
+            .enumerate()
+            .map(|(column_index, &column_id)| (column_id, column_index))
+            .collect();
+        self.semantic_types = self
+            .semantic_types
+            .drain()
+            .map(|(column_id, semantic_type)| (rename_id(column_id), semantic_type))
+            .collect();
+        self.column_metadata = self
+            .column_metadata
+            .drain()
+            .map(|(column_id, metadata)| (rename_id(column_id), metadata))
+            .collect();
+        if let Some(tag_value_filters) = &mut self.tag_value_filters {
+            *tag_value_filters = tag_value_filters
+                .drain()
+                .map(|(column_id, filter)| (rename_id(column_id), filter))
+                .collect();
+        }
+        self.invalidate_cached_arrow_schema();
+
+        Ok(())
+    }
+
+    /// Merges the time sorted tables `a` and `b` into a new table
+    /// which is also sorted on time, without re-sorting. Rows with
+    /// equal times keep the rows of `a` first and null times are
+    /// emitted as soon as they are reached. The merged table has the
+    /// union of the columns of both inputs, with nulls for the rows of
+    /// the input lacking a column.
+    ///
+    /// The merged table has the name of `a` and uses the dictionary of
+    /// `a_partition`, to which any column names and tag values only
+    /// found in `b` are added. As for semantic types, the metadata of
+    /// a column in both inputs is taken from `a`.
+    pub fn merge_sorted(
+        a: &Self,
+        a_partition: &mut Partition,
+        b: &Self,
+        b_partition: &Partition,
+    ) -> Result<Self> {
+        ensure!(a.is_time_sorted()?, TableNotTimeSorted { table: a.id });
+        ensure!(b.is_time_sorted()?, TableNotTimeSorted { table: b.id });
+
+        let order = merge_order(&a.time_values_or_nulls()?, &b.time_values_or_nulls()?);
+
+        let mut merged = Self::new(a.id);
+        merged.type_conflict_policy = a.type_conflict_policy;
+        merged.unknown_value_type_policy = a.unknown_value_type_policy;
+        merged.partition_time_bounds = a.partition_time_bounds;
+        merged.out_of_bounds_policy = a.out_of_bounds_policy;
+        merged.semantic_types = a.semantic_types.clone();
+        merged.column_metadata = a.column_metadata.clone();
+        merged.time_column_name = a.time_column_name.clone();
+
+        // pair up the columns of both inputs by id in a_partition
+        let mut columns: BTreeMap<u32, (Option<&Column>, Option<&Column>)> = a
+            .column_id_to_index
+            .iter()
+            .map(|(&column_id, &column_index)| (column_id, (Some(&a.columns[column_index]), None)))
+            .collect();
+
+        for (&b_column_id, &column_index) in &b.column_id_to_index {
+            let column_name = b_partition.dictionary.lookup_id(b_column_id).context(
+                ColumnIdNotFoundInDictionary {
+                    column_id: b_column_id,
+                    partition: &b_partition.key,
+                },
+            )?;
+            let column_id = a_partition.dictionary.lookup_value_or_insert(column_name);
+
+            columns.entry(column_id).or_default().1 = Some(&b.columns[column_index]);
+            if let Some(semantic_type) = b.semantic_type(b_column_id) {
+                merged
+                    .semantic_types
+                    .entry(column_id)
+                    .or_insert(semantic_type);
+            }
+            if let Some(metadata) = b.column_metadata.get(&b_column_id) {
+                merged
+                    .column_metadata
+                    .entry(column_id)
+                    .or_insert_with(|| metadata.clone());
+            }
+        }
+
+        for (column_id, (a_column, b_column)) in columns {
+            let column = merge_columns(
+                column_id,
+                a_column,
+                b_column,
+                &order,
+                a_partition,
+                b_partition,
+            )?;
+            merged.push_column(column_id, column);
+        }
+
+        merged.time_column_id = a_partition
+            .dictionary
+            .lookup_value(a.time_column_name())
+            .ok()
+            .filter(|column_id| merged.column_id_to_index.contains_key(column_id));
+
+        if a.tag_value_filters.is_some() {
+            merged.enable_tag_value_filters();
+        }
+
+        Ok(merged)
+    }
+
+    /// Returns a copy of this table with its rows in ascending time
+    /// order, so that the copy `is_time_sorted`, for example when
+    /// compacting a table so queries need not sort it. Rows with equal
+    /// times keep their relative order, and rows without a time come
+    /// first. The copy shares the dictionary of the partition.
+    pub fn compact_to_sorted(&self) -> Result<Self> {
+        let times = self.time_values_or_nulls()?;
+        let mut rows = (0..self.row_count()).collect::<Vec<_>>();
+        rows.sort_by_key(|&row| times[row]);
+
+        let mut sorted = Self::new(self.id);
+        sorted.type_conflict_policy = self.type_conflict_policy;
+        sorted.unknown_value_type_policy = self.unknown_value_type_policy;
+        sorted.partition_time_bounds = self.partition_time_bounds;
+        sorted.out_of_bounds_policy = self.out_of_bounds_policy;
+        sorted.semantic_types = self.semantic_types.clone();
+        sorted.column_metadata = self.column_metadata.clone();
+        sorted.time_column_name = self.time_column_name.clone();
+
+        for (column, &column_id) in self.columns.iter().zip(&self.column_ids) {
+            sorted.push_column(column_id, column.take_rows(&rows));
+        }
+        sorted.time_column_id = self.time_column_id;
+
+        if self.tag_value_filters.is_some() {
+            sorted.enable_tag_value_filters();
+        }
+        *sorted
+            .cached_time_sorted
+            .get_mut()
+            .expect("cached time sorted lock poisoned") = Some(true);
+
+        Ok(sorted)
+    }
+
+    /// Merges the rows of `other`, whose tag values and column names
+    /// are in the dictionary of `other_partition`, into this table as
+    /// specified by `strategy`. The rows are converted with
+    /// `append_record_batch`, so columns only in `other` are added and
+    /// on error this table is unchanged.
+    pub fn merge_from(
+        &mut self,
+        partition: &mut Partition,
+        other: &Self,
+        other_partition: &Partition,
+        strategy: MergeStrategy,
+    ) -> Result<()> {
+        if other.row_count() == 0 {
+            return Ok(());
+        }
+
+        let batch = other.all_to_arrow(other_partition)?;
+        let tag_columns = other
+            .column_id_to_index
+            .iter()
+            .filter(|&(_, &column_index)| matches!(other.columns[column_index], Column::Tag(_, _)))
+            .map(|(&column_id, _)| {
+                other_partition.dictionary.lookup_id(column_id).context(
+                    ColumnIdNotFoundInDictionary {
+                        column_id,
+                        partition: &other_partition.key,
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let row_count = self.row_count();
+        self.append_record_batch(&mut partition.dictionary, &batch, &tag_columns)?;
+
+        match strategy {
+            MergeStrategy::Append => Ok(()),
+            MergeStrategy::CoalesceBySeriesTime => {
+                self.coalesce_series_time_rows(row_count, &partition.dictionary)
+            }
+        }
+    }
+
+    /// Combines each row from `first_merged_row` on with the last
+    /// earlier row of the same series and time, if any, and removes
+    /// it, see `MergeStrategy::CoalesceBySeriesTime`
+    fn coalesce_series_time_rows(
+        &mut self,
+        first_merged_row: usize,
+        dictionary: &Dictionary,
+    ) -> Result<()> {
+        let times = self.time_values_or_nulls()?;
+        let tag_values = self
+            .columns
+            .iter()
+            .filter_map(|column| match column {
+                Column::Tag(vals, _) => Some(vals),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let series_time = |row: usize| {
+            times[row].map(|time| {
+                let series = tag_values.iter().map(|vals| vals[row]).collect::<Vec<_>>();
+                (series, time)
+            })
+        };
+
+        let mut existing_rows = HashMap::new();
+        for row in 0..first_merged_row {
+            if let Some(key) = series_time(row) {
+                existing_rows.insert(key, row);
+            }
+        }
+
+        // (merged row, existing row) pairs to combine
+        let combined_rows = (first_merged_row..self.row_count())
+            .filter_map(|row| {
+                series_time(row)
+                    .and_then(|key| existing_rows.get(&key))
+                    .map(|&existing_row| (row, existing_row))
+            })
+            .collect::<Vec<_>>();
+        if combined_rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut keep = vec![true; self.row_count()];
+        for &(row, _) in &combined_rows {
+            keep[row] = false;
+        }
+        for column in &mut self.columns {
+            for &(row, existing_row) in &combined_rows {
+                column.copy_non_null_value(row, existing_row);
+            }
+            column.retain_rows(&keep, dictionary);
+        }
+        self.release_caches();
+
+        Ok(())
+    }
+
+    /// Returns the values of the time column, looked up by name in
+    /// the dictionary of `partition`. Errors if the table has no time
+    /// column.
+    pub fn time_values(&self, partition: &Partition) -> Result<&[Option<i64>]> {
+        let time_column_id = partition
+            .dictionary
+            .lookup_value(self.time_column_name())
+            .ok()
+            .filter(|column_id| self.column_id_to_index.contains_key(column_id))
+            .context(TimeColumnNotFound { table: self.id })?;
+
+        self.column_i64(time_column_id)
+    }
+
+    /// Returns the values of the tag column `column_name`, resolved
+    /// using the dictionary of `partition`. Errors as `tag_value_ids`.
+    fn decoded_tag_values<'a>(
+        &self,
+        partition: &'a Partition,
+        column_name: &str,
+    ) -> Result<Vec<Option<&'a str>>> {
+        self.tag_value_ids(partition, column_name)?
+            .iter()
+            .map(|value_id| {
+                value_id
+                    .map(|value_id| {
+                        partition.dictionary.lookup_id(value_id).context(
+                            TagValueIdNotFoundInDictionary {
+                                value: value_id,
+                                partition: &partition.key,
+                            },
+                        )
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Returns the values of the column timestamp predicates are
+    /// evaluated against (see `predicate_time_column_id`), or `None`
+    /// if this table has no such column, so none of its rows has a
+    /// time
+    fn predicate_times(
+        &self,
+        partition_predicate: &PartitionPredicate,
+    ) -> Result<Option<&[Option<i64>]>> {
+        let time_column_id = self.predicate_time_column_id(partition_predicate);
+        if self.column_id_to_index.contains_key(&time_column_id) {
+            self.column_i64(time_column_id).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the values of the time column, which are all null if
+    /// the table has no time column
+    fn time_values_or_nulls(&self) -> Result<Vec<Option<i64>>> {
+        match self.time_column_id {
+            Some(time_column_id) => Ok(self.column_i64(time_column_id)?.to_vec()),
+            None => Ok(vec![None; self.row_count()]),
+        }
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map_or(0, |v| v.len())
+    }
+
+    /// Returns the number of rows matching `partition_predicate` if it
+    /// can be computed from the row count and the statistics of the
+    /// time column alone, without reading any values, and `None` if
+    /// answering would require a scan.
+    ///
+    /// This is the case when the predicate has no expressions, field
+    /// restriction or table name regex, and its timestamp range (if
+    /// any) either contains or excludes all of the table's times.
+    pub fn row_count_matching(
+        &self,
+        partition_predicate: &PartitionPredicate,
+    ) -> Result<Option<usize>> {
+        if !partition_predicate.partition_exprs.is_empty()
+            || partition_predicate.field_restriction.is_some()
+            || partition_predicate.table_name_regex.is_some()
+        {
+            return Ok(None);
+        }
+
+        let excluded = partition_predicate
+            .table_name_predicate
+            .as_ref()
+            .map_or(false, |table_ids| !table_ids.contains(&self.id));
+        if excluded || !self.has_columns(partition_predicate.required_columns.as_ref()) {
+            return Ok(Some(0));
+        }
+
+        let range = match &partition_predicate.range {
+            Some(range) => range,
+            None => return Ok(Some(self.row_count())),
+        };
+
+        // rows without a time never match a timestamp range
+        let time_column_id = self.predicate_time_column_id(partition_predicate);
+        let stats = match self.column_id_to_index.get(&time_column_id) {
+            Some(&column_index) => match &self.columns[column_index] {
+                Column::I64(_, stats) => stats,
+                column => {
+                    return InternalColumnTypeMismatch {
+                        column_id: time_column_id,
+                        expected_column_type: "i64",
+                        actual_column_type: column.type_description(),
+                    }
+                    .fail()
+                }
+            },
+            None => return Ok(Some(0)),
+        };
+
+        if stats.count == 0 || stats.max < range.start || stats.min >= range.end {
+            Ok(Some(0))
+        } else if range.start <= stats.min && stats.max < range.end {
+            Ok(Some(stats.count as usize))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the number of columns (tags, fields and time) in this table
+    pub fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Returns the number of tag columns in this table
+    pub fn num_tag_columns(&self) -> usize {
+        self.columns
+            .iter()
+            .filter(|c| matches!(c, Column::Tag(_, _)))
+            .count()
+    }
+
+    /// Returns the ids in the dictionary of `partition` of the names
+    /// the time column may have: `TIME_COLUMN_NAME` and the name set
+    /// with `with_time_column_name`
+    fn time_column_ids(&self, partition: &Partition) -> Vec<u32> {
+        [TIME_COLUMN_NAME, self.time_column_name()]
+            .iter()
+            .filter_map(|column_name| partition.dictionary.lookup_value(column_name).ok())
+            .collect()
+    }
+
+    /// Returns the number of field columns in this table (all
+    /// columns other than tags and time, see `with_time_column_name`)
+    pub fn num_field_columns(&self, partition: &Partition) -> usize {
+        let time_column_ids = self.time_column_ids(partition);
+
+        self.column_id_to_index
+            .iter()
+            .filter(|&(&column_id, &column_index)| {
+                !time_column_ids.contains(&column_id)
+                    && !matches!(self.columns[column_index], Column::Tag(_, _))
+            })
+            .count()
+    }
+
+    /// Returns the number of columns of each type in this table. As
+    /// for `num_field_columns`, the time column is not counted, so the
+    /// histogram describes the tags and fields.
+    pub fn field_type_histogram(&self, partition: &Partition) -> HashMap<ColumnType, usize> {
+        let time_column_ids = self.time_column_ids(partition);
+
+        let mut histogram = HashMap::new();
+        for (column_id, &column_index) in &self.column_id_to_index {
+            if !time_column_ids.contains(column_id) {
+                *histogram
+                    .entry(self.columns[column_index].column_type())
+                    .or_default() += 1;
+            }
+        }
+
+        histogram
+    }
+
+    /// Returns the schema of this table as a JSON object in the shape
+    /// of an InfluxDB measurement schema: the tag keys, and the field
+    /// keys with their types (named as in `field_keys_plan`), each
+    /// sorted by name. The time column is not included.
+    ///
+    /// `{"measurement":"h2o","tags":["city"],"fields":[{"name":"temp","type":"float"}]}`
+    pub fn schema_json(&self, partition: &Partition) -> Result<String> {
+        let measurement =
+            partition
+                .dictionary
+                .lookup_id(self.id)
+                .context(ColumnIdNotFoundInDictionary {
+                    column_id: self.id,
+                    partition: &partition.key,
+                })?;
+
+        let time_column_ids = self.time_column_ids(partition);
+
+        let mut tags = Vec::new();
+        let mut fields = Vec::new();
+        for (&column_id, &column_index) in &self.column_id_to_index {
+            if time_column_ids.contains(&column_id) {
+                continue;
+            }
+
+            let name = partition.dictionary.lookup_id(column_id).context(
+                ColumnIdNotFoundInDictionary {
+                    column_id,
+                    partition: &partition.key,
+                },
+            )?;
+            let field_type = match &self.columns[column_index] {
+                Column::Tag(_, _) => {
+                    tags.push(name);
+                    continue;
+                }
+                Column::F64(_, _) => "float",
+                Column::I64(_, _) => "integer",
+                Column::String(_, _) => "string",
+                Column::Bool(_, _) => "boolean",
+                Column::Decimal { .. } => "decimal",
+            };
+            fields.push(SchemaJsonField { name, field_type });
+        }
+
+        tags.sort_unstable();
+        fields.sort_unstable_by_key(|field| field.name);
+
+        let schema = SchemaJson {
+            measurement,
+            tags,
+            fields,
+        };
+        serde_json::to_string(&schema).context(SerializingSchemaJson)
+    }
+
+    /// Returns a fingerprint of this table's schema, computed by
+    /// hashing the sorted (column name, column type) pairs. Tables
+    /// with identical schemas have equal fingerprints regardless of
+    /// the dictionary ids assigned to their columns, so comparing
+    /// fingerprints is a cheap check before computing a full schema
+    /// union.
+    pub fn schema_fingerprint(&self, partition: &Partition) -> Result<u64> {
+        let mut columns = self
+            .column_id_to_index
+            .iter()
+            .map(|(&column_id, &column_index)| {
+                let column_name = partition.dictionary.lookup_id(column_id).context(
+                    ColumnIdNotFoundInDictionary {
+                        column_id,
+                        partition: &partition.key,
+                    },
+                )?;
+                Ok((column_name, self.columns[column_index].type_description()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        columns.sort_unstable();
+
+        let mut hasher = DefaultHasher::new();
+        columns.hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Returns true if the non null values of the time column are in
+    /// non-decreasing order (null times are ignored), meaning the
+    /// rows do not need to be sorted on time. A table without a time
+    /// column is sorted.
+    ///
+    /// The result is cached until more rows are appended.
+    pub fn is_time_sorted(&self) -> Result<bool> {
+        let mut cached_time_sorted = self
+            .cached_time_sorted
+            .lock()
+            .expect("cached time sorted lock poisoned");
+
+        if let Some(time_sorted) = *cached_time_sorted {
+            return Ok(time_sorted);
+        }
+
+        let time_sorted = match self.time_column_id {
+            None => true,
+            Some(time_column_id) => {
+                let mut times = self.column_i64(time_column_id)?.iter().flatten();
+                match times.next() {
+                    None => true,
+                    Some(first) => times
+                        .try_fold(first, |prev, t| if prev <= t { Some(t) } else { None })
+                        .is_some(),
+                }
+            }
+        };
+
+        *cached_time_sorted = Some(time_sorted);
+        Ok(time_sorted)
+    }
+
+    /// Returns the series of this table, that is the groups of rows
+    /// with the same value (or null) for every tag, in order. Each
+    /// series is returned as its tag values, in the order of the tag
+    /// names, and its range of rows.
+    ///
+    /// The rows must already be sorted by series: each series must be
+    /// contiguous, and the series must be in ascending order of their
+    /// tag values (with nulls first, as in series set plans). Errors,
+    /// before returning any series, if they are not.
+    pub fn iter_series(
+        &self,
+        partition: &Partition,
+    ) -> Result<impl Iterator<Item = (Vec<Option<String>>, Range<usize>)>> {
+        let tag_values = self
+            .all_columns_with_index(partition)?
+            .into_iter()
+            .filter_map(|(_, column_index)| match &self.columns[column_index] {
+                Column::Tag(vals, _) => Some(vals.as_slice()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let series_key = |row: usize| {
+            tag_values
+                .iter()
+                .map(|vals| {
+                    vals[row]
+                        .map(|value_id| {
+                            partition
+                                .dictionary
+                                .lookup_id(value_id)
+                                .map(|value| value.to_string())
+                                .context(TagValueIdNotFoundInDictionary {
+                                    value: value_id,
+                                    partition: &partition.key,
+                                })
+                        })
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+
+        let row_count = self.row_count();
+        let mut series = Vec::new();
+        // the key and first row of the current series
+        let mut current: Option<(Vec<Option<String>>, usize)> = None;
+        for row in 0..row_count {
+            if row > 0 && tag_values.iter().all(|vals| vals[row] == vals[row - 1]) {
+                continue;
+            }
+
+            let key = series_key(row)?;
+            if let Some((previous_key, start)) = current.take() {
+                ensure!(
+                    previous_key < key,
+                    TableNotSeriesSorted {
+                        table: self.id,
+                        row
+                    }
+                );
+                series.push((previous_key, start..row));
+            }
+            current = Some((key, row));
+        }
+        if let Some((key, start)) = current {
+            series.push((key, start..row_count));
+        }
+
+        Ok(series.into_iter())
+    }
+
+    /// Annotates the integer column `column_id` as holding values of
+    /// `semantic_type`. The annotation only affects conversion to
+    /// arrow when `ToArrowOptions::emit_semantic_types` is set, and is
+    /// ignored if the column is not a `Column::I64`.
+    pub fn set_semantic_type(&mut self, column_id: u32, semantic_type: SemanticType) {
+        self.semantic_types.insert(column_id, semantic_type);
+    }
+
+    /// Returns the semantic type annotation of `column_id`, if any
+    pub fn semantic_type(&self, column_id: u32) -> Option<SemanticType> {
+        self.semantic_types.get(&column_id).copied()
+    }
+
+    /// Sets the metadata `key` of the column `column_name` to `value`,
+    /// replacing any previous value. Column metadata is attached to
+    /// the column's field in arrow schemas, for downstream consumers
+    /// that rely on it (for example to find the unit of a field).
+    pub fn set_column_metadata(
+        &mut self,
+        partition: &Partition,
+        column_name: &str,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<()> {
+        let column_id = partition
+            .dictionary
+            .lookup_value(column_name)
+            .ok()
+            .filter(|column_id| self.column_id_to_index.contains_key(column_id))
+            .context(MetadataColumnNotFound { column_name })?;
+
+        self.column_metadata
+            .entry(column_id)
+            .or_default()
+            .insert(key.into(), value.into());
+        self.invalidate_cached_arrow_schema();
+
+        Ok(())
+    }
+
+    /// Returns the indexes of the rows where each `(tag name, tag
+    /// value)` in `tag_eqs` holds and whose time is within
+    /// `time_range` (if any), checking both in a single pass over the
+    /// rows.
+    ///
+    /// A tag (or tag value) that does not appear in this table
+    /// matches no rows. Errors if a name in `tag_eqs` is not a tag.
+    ///
+    /// Null values never satisfy a comparison: a row with a null tag
+    /// or a null time does not match. This is the same as the SQL
+    /// three valued logic DataFusion applies when evaluating the
+    /// equivalent predicate (a comparison with null yields null, which
+    /// is not true), so this fast path must return exactly the rows a
+    /// DataFusion plan would.
+    pub fn matching_rows(
+        &self,
+        tag_eqs: &[(&str, &str)],
+        time_range: Option<TimestampRange>,
+        partition: &Partition,
+    ) -> Result<Vec<usize>> {
+        // (tag value ids, id that must match) for each tag equality
+        let mut tag_matches = Vec::with_capacity(tag_eqs.len());
+        for &(tag_name, tag_value) in tag_eqs {
+            let column_id = partition.dictionary.lookup_value(tag_name).ok();
+            let column = match column_id.and_then(|id| self.column_id_to_index.get(&id)) {
+                Some(&column_index) => &self.columns[column_index],
+                None => return Ok(vec![]),
+            };
+            let vals = match column {
+                Column::Tag(vals, _) => vals,
+                _ => {
+                    return InternalColumnTypeMismatch {
+                        column_id: column_id.expect("column id was found"),
+                        expected_column_type: "tag",
+                        actual_column_type: column.type_description(),
+                    }
+                    .fail()
+                }
+            };
+            let value_id = match partition.dictionary.lookup_value(tag_value) {
+                Ok(value_id) => value_id,
+                Err(_) => return Ok(vec![]),
+            };
+            tag_matches.push((vals, value_id));
+        }
+
+        let times = match (time_range, self.time_column_id) {
+            (Some(_), Some(time_column_id)) => Some(self.column_i64(time_column_id)?),
+            (Some(_), None) => return Ok(vec![]),
+            (None, _) => None,
+        };
+
+        let rows = (0..self.row_count())
+            .filter(|&row| {
+                // a null tag value (`None`) never equals `Some(value_id)`
+                tag_matches
+                    .iter()
+                    .all(|(vals, value_id)| vals[row] == Some(*value_id))
+                    && match (time_range, times) {
+                        (Some(range), Some(times)) => range.contains_opt(times[row]),
+                        _ => true,
+                    }
+            })
+            .collect();
+
+        Ok(rows)
+    }
+
+    /// Evaluates `partition_predicate` against the rows of this table
+    /// without DataFusion, returning a bitmap with `true` for each row
+    /// that matches. The bitmap can be computed once and applied (with
+    /// the arrow `filter` kernels) to several projections of the table.
+    ///
+    /// Only timestamp ranges and equality comparisons of tags with
+    /// string literals (e.g. `state = 'MA'`) are evaluated directly;
+    /// returns `None` if the predicate has any other expression. As
+    /// for `matching_rows`, nulls never match, and a tag that does not
+    /// appear in this table matches no rows.
+    ///
+    /// No row matches if the predicate excludes the table itself (by
+    /// its table names, table name regex or required columns).
+    pub fn predicate_bitmap(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<Option<BooleanArray>> {
+        let table_matches = self.matches_table_name_predicate(
+            partition_predicate.table_name_predicate.as_ref(),
+            partition_predicate.table_name_regex.as_ref(),
+            &partition.dictionary,
+        ) && self.has_columns(partition_predicate.required_columns.as_ref());
+        if !table_matches {
+            return Ok(Some(BooleanArray::from(vec![false; self.row_count()])));
+        }
+
+        // (tag values, id that must match) for each tag equality.
+        // `None` means no row can match
+        let mut tag_matches = Vec::with_capacity(partition_predicate.partition_exprs.len());
+        for expr in &partition_predicate.partition_exprs {
+            let (column_name, tag_value) = match tag_equality(expr) {
+                Some(tag_equality) => tag_equality,
+                None => return Ok(None),
+            };
+
+            let column = partition
+                .dictionary
+                .lookup_value(column_name)
+                .ok()
+                .and_then(|column_id| self.column_id_to_index.get(&column_id))
+                .map(|&column_index| &self.columns[column_index]);
+
+            match column {
+                Some(Column::Tag(vals, _)) => {
+                    let value_id = partition.dictionary.lookup_value(tag_value).ok();
+                    tag_matches.push(value_id.map(|value_id| (vals, value_id)));
+                }
+                None => tag_matches.push(None),
+                // comparing other columns with strings is left to DataFusion
+                Some(_) => return Ok(None),
+            }
+        }
+
+        let time_column_id = self.predicate_time_column_id(partition_predicate);
+        let times = match partition_predicate.range {
+            Some(_) if self.column_id_to_index.contains_key(&time_column_id) => {
+                Some(self.column_i64(time_column_id)?)
+            }
+            _ => None,
+        };
+
+        let bitmap = (0..self.row_count())
+            .map(|row| {
+                tag_matches.iter().all(|tag_match| match tag_match {
+                    Some((vals, value_id)) => vals[row] == Some(*value_id),
+                    None => false,
+                }) && match partition_predicate.range {
+                    Some(range) => times.map_or(false, |times| range.contains_opt(times[row])),
+                    None => true,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Some(BooleanArray::from(bitmap)))
+    }
+
+    /// Removes the rows matching `partition_predicate` from every
+    /// column, returning the number of rows removed. The remaining
+    /// rows keep their order, and the columns (even those left with
+    /// only null values) are kept.
+    ///
+    /// Only predicates that `predicate_bitmap` can evaluate (tag
+    /// equality comparisons and a timestamp range) are supported;
+    /// others result in an error, without removing any rows. No rows
+    /// are removed if the predicate excludes this table (for example
+    /// by naming only other tables).
+    pub fn delete_where(
+        &mut self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<usize> {
+        let bitmap = self
+            .predicate_bitmap(partition_predicate, partition)?
+            .context(UnsupportedDeletePredicate)?;
+
+        let keep = (0..bitmap.len())
+            .map(|row| !bitmap.value(row))
+            .collect::<Vec<_>>();
+        let deleted = keep.iter().filter(|&&keep| !keep).count();
+
+        if deleted > 0 {
+            for column in &mut self.columns {
+                column.retain_rows(&keep, &partition.dictionary);
+            }
+            self.release_caches();
+        }
+
+        Ok(deleted)
+    }
+
+    /// Returns the number of rows with each distinct (non null) value
+    /// of the tag `column_name` within the predicate's timestamp
+    /// range, sorted by descending count (and then by value).
+    ///
+    /// A tag that does not appear in this table has no values. Errors
+    /// if `column_name` is not a tag. General predicate expressions
+    /// are not supported and result in an error.
+    pub fn tag_value_counts(
+        &self,
+        column_name: &str,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<Vec<(String, usize)>> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedTagValueCountsPredicate
+        );
+
+        let column_id = partition.dictionary.lookup_value(column_name).ok();
+        let column = match column_id.and_then(|id| self.column_id_to_index.get(&id)) {
+            Some(&column_index) => &self.columns[column_index],
+            None => return Ok(vec![]),
+        };
+        let vals = match column {
+            Column::Tag(vals, _) => vals,
+            _ => {
+                return InternalColumnTypeMismatch {
+                    column_id: column_id.expect("column id was found"),
+                    expected_column_type: "tag",
+                    actual_column_type: column.type_description(),
+                }
+                .fail()
+            }
+        };
+
+        // without a time column, no row is in a range
+        let times = match partition_predicate.range {
+            Some(_) => match self.predicate_times(partition_predicate)? {
+                Some(times) => Some(times),
+                None => return Ok(vec![]),
+            },
+            None => None,
+        };
+
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for (row, value_id) in vals.iter().enumerate() {
+            if let (Some(range), Some(times)) = (partition_predicate.range, times) {
+                if !range.contains_opt(times[row]) {
+                    continue;
+                }
+            }
+            if let Some(value_id) = value_id {
+                *counts.entry(*value_id).or_default() += 1;
+            }
+        }
+
+        let mut counts = counts
+            .into_iter()
+            .map(|(value_id, count)| {
+                let tag_value = partition.dictionary.lookup_id(value_id).context(
+                    TagValueIdNotFoundInDictionary {
+                        value: value_id,
+                        partition: &partition.key,
+                    },
+                )?;
+                Ok((tag_value.to_string(), count))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        counts.sort_by(|(a_value, a_count), (b_value, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_value.cmp(b_value))
+        });
+
+        Ok(counts)
+    }
+
+    /// Returns the distinct (non null) values of the field
+    /// `column_name` within the predicate's timestamp range, sorted
+    /// ascending, as a batch with a single column named
+    /// `column_name`. `NaN` sorts after all other float values.
+    ///
+    /// Errors if `column_name` is a tag (see `tag_values_plan`) or
+    /// not a field of this table. General predicate expressions are
+    /// not supported and result in an error.
+    pub fn distinct_field_values(
+        &self,
+        column_name: &str,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<RecordBatch> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedDistinctFieldValuesPredicate
+        );
+
+        let column = partition
+            .dictionary
+            .lookup_value(column_name)
+            .ok()
+            .filter(|&column_id| !self.is_time_column(partition_predicate, column_id))
+            .and_then(|column_id| self.column_id_to_index.get(&column_id))
+            .map(|&column_index| &self.columns[column_index])
+            .context(NotAFieldColumn { column_name })?;
+
+        let range = partition_predicate.range;
+        let times = match range {
+            Some(_) => self.predicate_times(partition_predicate)?,
+            None => None,
+        };
+
+        let array: ArrayRef = match column {
+            Column::F64(vals, _) => {
+                let (nans, mut values): (Vec<f64>, Vec<f64>) = values_in_range(vals, times, range)
+                    .copied()
+                    .partition(|v| v.is_nan());
+                values.sort_by(|a, b| a.partial_cmp(b).expect("NaNs were removed"));
+                values.dedup();
+                if !nans.is_empty() {
+                    values.push(f64::NAN);
+                }
+                Arc::new(Float64Array::from(values))
+            }
+            Column::I64(vals, _) => {
+                let values = values_in_range(vals, times, range)
+                    .copied()
+                    .collect::<BTreeSet<_>>();
+                Arc::new(Int64Array::from(values.into_iter().collect::<Vec<_>>()))
+            }
+            Column::String(vals, _) => {
+                let values = values_in_range(vals, times, range)
+                    .map(|v| v.as_str())
+                    .collect::<BTreeSet<_>>();
+                Arc::new(StringArray::from(values.into_iter().collect::<Vec<_>>()))
+            }
+            Column::Bool(vals, _) => {
+                let values = values_in_range(vals, times, range)
+                    .copied()
+                    .collect::<BTreeSet<_>>();
+                Arc::new(BooleanArray::from(values.into_iter().collect::<Vec<_>>()))
+            }
+            Column::Tag(_, _) => return DistinctOnTag { column_name }.fail(),
+            Column::Decimal {
+                values,
+                scale,
+                precision,
+            } => {
+                let values = values_in_range(values, times, range)
+                    .copied()
+                    .collect::<BTreeSet<_>>();
+                let values = values.into_iter().map(Some).collect::<Vec<_>>();
+                decimal_array(values.iter(), *precision, *scale)?
+            }
+        };
+
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            column_name,
+            array.data_type().clone(),
+            false,
+        )]));
+
+        RecordBatch::try_new(schema, vec![array]).context(ArrowError {})
+    }
+
+    /// Returns the distinct (non null) values of the tag
+    /// `column_name` within the predicate's timestamp range, sorted
+    /// ascending, as a batch with a single Utf8 column named
+    /// `column_name`. Only the tag and time columns are read.
+    ///
+    /// Errors if `column_name` is not a tag of this table. General
+    /// predicate expressions are not supported and result in an error.
+    pub fn distinct_tag_values(
+        &self,
+        column_name: &str,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<RecordBatch> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedDistinctTagValuesPredicate
+        );
+
+        let vals = match self.tag_column(partition, column_name) {
+            Some(Column::Tag(vals, _)) => vals,
+            _ => return NotATagColumn { column_name }.fail(),
+        };
+
+        // without a time column, no row is in a range
+        let range = partition_predicate.range;
+        let times = match range {
+            Some(_) => self.predicate_times(partition_predicate)?,
+            None => None,
+        };
+
+        let value_ids = values_in_range(vals, times, range).collect::<BTreeSet<_>>();
+        let mut values = value_ids
+            .into_iter()
+            .map(|&value_id| {
+                partition
+                    .dictionary
+                    .lookup_id(value_id)
+                    .context(TagValueIdNotFoundInDictionary {
+                        value: value_id,
+                        partition: &partition.key,
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        values.sort_unstable();
+
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            column_name,
+            ArrowDataType::Utf8,
+            false,
+        )]));
+        let array: ArrayRef = Arc::new(StringArray::from(values));
+
+        RecordBatch::try_new(schema, vec![array]).context(ArrowError {})
+    }
+
+    /// Returns the column `column_name` if it is a tag of this table
+    fn tag_column(&self, partition: &Partition, column_name: &str) -> Option<&Column> {
+        partition
+            .dictionary
+            .lookup_value(column_name)
+            .ok()
+            .and_then(|column_id| self.column_id_to_index.get(&column_id))
+            .map(|&column_index| &self.columns[column_index])
+            .filter(|column| matches!(column, Column::Tag(_, _)))
+    }
+
+    /// Returns the value ids of the tag column `column_name`. Errors
+    /// if the name is not in the dictionary of `partition` or is not
+    /// the name of a tag column of this table.
+    fn tag_value_ids(&self, partition: &Partition, column_name: &str) -> Result<&[Option<u32>]> {
+        let column_id = partition.dictionary.lookup_value(column_name).context(
+            ColumnNameNotFoundInDictionary {
+                column_name,
+                partition: &partition.key,
+            },
+        )?;
+        let column_index =
+            *self
+                .column_id_to_index
+                .get(&column_id)
+                .context(InternalNoColumnInIndex {
+                    column_name,
+                    column_id,
+                })?;
+
+        match &self.columns[column_index] {
+            Column::Tag(vals, _) => Ok(vals),
+            column => InternalColumnTypeMismatch {
+                column_id,
+                expected_column_type: "tag",
+                actual_column_type: column.type_description(),
+            }
+            .fail(),
+        }
+    }
+
+    /// Returns the minimum and maximum of the non null values of the
+    /// column `column_name`, computed from the current values (rather
+    /// than the column statistics), or `None` if all values are null.
+    ///
+    /// Strings (and tag values, which are resolved using the
+    /// dictionary of `partition`) are compared lexicographically and
+    /// `false` sorts before `true`. `NaN` float values are ignored,
+    /// unless all values are `NaN`.
+    pub fn column_min_max(
+        &self,
+        partition: &Partition,
+        column_name: &str,
+    ) -> Result<Option<(ScalarValue, ScalarValue)>> {
+        let columns_with_index = self.column_names_with_index(partition, &[column_name])?;
+        let (_, column_index) = columns_with_index[0];
+
+        let min_max = match &self.columns[column_index] {
+            Column::F64(vals, _) => vals
+                .iter()
+                .flatten()
+                .fold(None, |min_max, &v| {
+                    Some(match min_max {
+                        None => (v, v),
+                        Some((min, max)) => (f64::min(min, v), f64::max(max, v)),
+                    })
+                })
+                .map(|(min, max)| {
+                    (
+                        ScalarValue::Float64(Some(min)),
+                        ScalarValue::Float64(Some(max)),
+                    )
+                }),
+            Column::I64(vals, _) => values_min_max(vals.iter().flatten().copied())
+                .map(|(min, max)| (ScalarValue::Int64(Some(min)), ScalarValue::Int64(Some(max)))),
+            Column::Bool(vals, _) => {
+                values_min_max(vals.iter().flatten().copied()).map(|(min, max)| {
+                    (
+                        ScalarValue::Boolean(Some(min)),
+                        ScalarValue::Boolean(Some(max)),
+                    )
+                })
+            }
+            Column::String(vals, _) => values_min_max(vals.iter().flatten().map(|v| v.as_str()))
+                .map(|(min, max)| {
+                    (
+                        ScalarValue::Utf8(Some(min.to_string())),
+                        ScalarValue::Utf8(Some(max.to_string())),
+                    )
+                }),
+            Column::Tag(vals, _) => {
+                let tag_values = vals
+                    .iter()
+                    .flatten()
+                    .map(|&value_id| {
+                        partition.dictionary.lookup_id(value_id).context(
+                            TagValueIdNotFoundInDictionary {
+                                value: value_id,
+                                partition: &partition.key,
+                            },
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                values_min_max(tag_values.into_iter()).map(|(min, max)| {
+                    (
+                        ScalarValue::Utf8(Some(min.to_string())),
+                        ScalarValue::Utf8(Some(max.to_string())),
+                    )
+                })
+            }
+            // there is no decimal `ScalarValue`
+            Column::Decimal { .. } => {
+                return UnsupportedDecimalOperation {
+                    column_name,
+                    operation: "column_min_max",
+                }
+                .fail()
+            }
+        };
+
+        Ok(min_max)
+    }
+
+    /// Returns a reference to the specified column
+    fn column(&self, column_id: u32) -> Result<&Column> {
+        Ok(self
+            .column_id_to_index
+            .get(&column_id)
+            .map(|&column_index| &self.columns[column_index])
+            .expect("invalid column id"))
+    }
+
+    /// Returns a reference to the specified column as a slice of
+    /// i64s. Errors if the type is not i64
+    pub fn column_i64(&self, column_id: u32) -> Result<&[Option<i64>]> {
+        let column = self.column(column_id)?;
+        match column {
+            Column::I64(vals, _) => Ok(vals),
+            _ => InternalColumnTypeMismatch {
+                column_id,
+                expected_column_type: "i64",
+                actual_column_type: column.type_description(),
+            }
+            .fail(),
+        }
+    }
+
+    /// Returns the values of the specified numeric field column as
+    /// `f64`s: `I64` values are cast to `f64` and `F64` values are
+    /// returned directly. Errors if the column is not numeric
+    pub fn numeric_field_as_f64(&self, column_id: u32) -> Result<Vec<Option<f64>>> {
+        let column = self.column(column_id)?;
+        match column {
+            Column::F64(vals, _) => Ok(vals.clone()),
+            Column::I64(vals, _) => Ok(vals.iter().map(|v| v.map(|v| v as f64)).collect()),
+            _ => InternalColumnTypeMismatch {
+                column_id,
+                expected_column_type: "i64 or f64",
+                actual_column_type: column.type_description(),
+            }
+            .fail(),
+        }
+    }
+
+    /// Returns the null mask of the specified column, where `true`
+    /// means a value is present in that row and `false` means null
+    pub fn null_mask(&self, column_id: u32) -> Result<BooleanArray> {
+        fn mask<T>(vals: &[Option<T>]) -> BooleanArray {
+            BooleanArray::from(vals.iter().map(|v| v.is_some()).collect::<Vec<_>>())
+        }
+
+        let column = self.column(column_id)?;
+        Ok(match column {
+            Column::F64(vals, _) => mask(vals),
+            Column::I64(vals, _) => mask(vals),
+            Column::String(vals, _) => mask(vals),
+            Column::Bool(vals, _) => mask(vals),
+            Column::Tag(vals, _) => mask(vals),
+            Column::Decimal { values, .. } => mask(values),
+        })
+    }
+
+    /// Reserves capacity for at least `additional_rows` more rows in
+    /// every existing column, so appending that many rows does not
+    /// reallocate them
+    pub fn reserve(&mut self, additional_rows: usize) {
+        for column in &mut self.columns {
+            column.reserve(additional_rows);
+        }
+    }
+
+    pub fn append_rows(
+        &mut self,
+        dictionary: &mut Dictionary,
+        rows: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Row<'_>>>,
+    ) -> Result<()> {
+        self.append_rows_tracked(dictionary, rows, None).map(|_| ())
+    }
+
+    /// Appends `rows` like `append_rows`, returning the `(min_time,
+    /// max_time)` of the appended rows, so callers can maintain a
+    /// time range without scanning the table. Rows without a time
+    /// value are ignored; returns `None` if no appended row has one.
+    ///
+    /// If `metrics` is provided, its counters are incremented as rows
+    /// are appended (including for the work done before any error).
+    pub fn append_rows_tracked<'a>(
+        &mut self,
+        dictionary: &mut Dictionary,
+        rows: &flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<wb::Row<'a>>>,
+        mut metrics: Option<&mut IngestMetrics>,
+    ) -> Result<Option<(i64, i64)>> {
+        // grow existing columns once up front rather than on push
+        self.reserve(rows.len());
+
+        *self
+            .cached_time_sorted
+            .lock()
+            .expect("cached time sorted lock poisoned") = None;
+
+        let mut time_range: Option<(i64, i64)> = None;
+
+        // the same column names recur in most rows, so resolve each
+        // name in the dictionary once per batch
+        let mut batch_columns = BatchColumns::default();
+
+        for row in rows {
+            if let Some(values) = row.values() {
+                let time = self.row_time(&values);
+                let outside_partition = !self.check_row_time(time)?;
+                if outside_partition {
+                    warn!(
+                        "Row of table {} with time {:?} is outside partition bounds {:?}",
+                        self.id, time, self.partition_time_bounds
+                    );
+                }
+
+                self.append_row(
+                    dictionary,
+                    &values,
+                    &mut batch_columns,
+                    metrics.as_deref_mut(),
+                )?;
+
+                // only count rows that were actually appended
+                if outside_partition {
+                    if let Some(metrics) = metrics.as_deref_mut() {
+                        metrics.rows_outside_partition += 1;
+                    }
+                }
+
+                if let Some(time) = time {
+                    time_range = Some(match time_range {
+                        Some((min_time, max_time)) => (min_time.min(time), max_time.max(time)),
+                        None => (time, time),
+                    });
+                }
+            }
+        }
+
+        Ok(time_range)
+    }
+
+    /// Checks that `rows` could be appended to this table by
+    /// `append_rows` without a type conflict, without modifying the
+    /// table or the dictionary. Returns the first error `append_rows`
+    /// would encounter.
+    ///
+    /// Rows are checked in order, so a column first added by one row
+    /// must be used with the same type by later rows in the batch.
+    pub fn validate_rows(
+        &self,
+        dictionary: &Dictionary,
+        rows: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Row<'_>>>,
+    ) -> Result<()> {
+        // the type each column would have after appending the rows
+        // checked so far
+        let mut batch_columns = BatchColumns::default();
+
+        for row in rows {
+            if let Some(values) = row.values() {
+                self.check_row_time(self.row_time(&values))?;
+                self.check_row_types(dictionary, &values, &mut batch_columns)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends `rows` like `append_rows`, provided every value is of a
+    /// column in the `expected` schema and of the type that stores
+    /// that column's arrow type (see `append_record_batch`). Tag values
+    /// are allowed for `Utf8` columns.
+    ///
+    /// All rows are checked, against both `expected` and the existing
+    /// columns (see `validate_rows`), before any is appended, so on
+    /// error the table is unchanged.
+    pub fn append_rows_strict(
+        &mut self,
+        dictionary: &mut Dictionary,
+        rows: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Row<'_>>>,
+        expected: &ArrowSchema,
+    ) -> Result<()> {
+        for row in rows {
+            if let Some(values) = row.values() {
+                self.check_row_schema(&values, expected)?;
+            }
+        }
+        self.validate_rows(dictionary, rows)?;
+
+        self.append_rows(dictionary, rows)
+    }
+
+    /// Returns an error if a value of the row `values` is of a column
+    /// that is not in `expected`, or whose type differs from the one
+    /// `expected` specifies, see `append_rows_strict`
+    fn check_row_schema(
+        &self,
+        values: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Value<'_>>>,
+        expected: &ArrowSchema,
+    ) -> Result<()> {
+        for value in values {
+            let column_name = value
+                .column()
+                .context(ColumnNameNotInRow { table: self.id })?;
+            let field = expected
+                .field_with_name(column_name)
+                .ok()
+                .context(UnexpectedColumn { column_name })?;
+
+            // values of types no column can store are rejected or
+            // skipped as usual, unless they are stored as strings
+            let value_type = match ColumnType::from_value(&value) {
+                Some(value_type) => value_type,
+                None if self.unknown_value_type_policy == UnknownValueTypePolicy::Stringify
+                    && stringify_value(&value).is_some() =>
+                {
+                    ColumnType::String
+                }
+                None => continue,
+            };
+
+            let is_tag = value_type == ColumnType::Tag && field.data_type() == &ArrowDataType::Utf8;
+            ensure!(
+                self.arrow_column_type(field, is_tag)? == value_type,
+                UnexpectedColumnType {
+                    column_name,
+                    value_type: value_type.description(),
+                    data_type: field.data_type().clone(),
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value of the time column in the row `values`, if any
+    fn row_time(
+        &self,
+        values: &flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<wb::Value<'_>>>,
+    ) -> Option<i64> {
+        values
+            .iter()
+            .find(|value| value.column() == Some(self.time_column_name()))
+            .and_then(|value| value.value_as_i64value())
+            .map(|time| time.value())
+    }
+
+    /// Checks a row's `time` against `partition_time_bounds`,
+    /// returning false if it is outside them and
+    /// `OutOfBoundsPolicy::Warn` is in effect. Errors if it is outside
+    /// them under `OutOfBoundsPolicy::Reject`.
+    fn check_row_time(&self, time: Option<i64>) -> Result<bool> {
+        let (time, (min_time, max_time)) = match (time, self.partition_time_bounds) {
+            (Some(time), Some(bounds)) => (time, bounds),
+            _ => return Ok(true),
+        };
+
+        if min_time <= time && time <= max_time {
+            return Ok(true);
+        }
+
+        match self.out_of_bounds_policy {
+            OutOfBoundsPolicy::Reject => RowOutsidePartition {
+                time,
+                min_time,
+                max_time,
+            }
+            .fail(),
+            OutOfBoundsPolicy::Warn => Ok(false),
+        }
+    }
+
+    /// Checks that the `values` of a row could be appended without a
+    /// type conflict and contain no duplicate columns (appending such
+    /// a row would push two values to one column, leaving the columns
+    /// with different lengths). `batch_columns` holds the types of
+    /// columns added or changed by previously checked rows of the
+    /// batch, which take precedence over the table's columns, and is
+    /// updated with this row's columns. Each column name is looked up
+    /// in the dictionary the first time it is seen in the batch only.
+    fn check_row_types<'a>(
+        &self,
+        dictionary: &Dictionary,
+        values: &flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<wb::Value<'a>>>,
+        batch_columns: &mut BatchColumns<'a>,
+    ) -> Result<()> {
+        batch_columns.num_rows += 1;
+        let row = batch_columns.num_rows;
+
+        for value in values {
+            let column_name = value
+                .column()
+                .context(ColumnNameNotInRow { table: self.id })?;
+
+            let column = batch_columns.columns.entry(column_name).or_insert_with(|| {
+                let id = dictionary.id(column_name);
+                let column_type = id
+                    .and_then(|column_id| self.column_id_to_index.get(&column_id))
+                    .map(|&column_index| self.columns[column_index].column_type());
+                BatchColumn {
+                    id,
+                    column_type,
+                    last_row: 0,
+                }
+            });
+            ensure!(
+                column.last_row != row,
+                DuplicateColumnInRow {
+                    table: self.id,
+                    column: column_name,
+                }
+            );
+            column.last_row = row;
+
+            let value_type = match ColumnType::from_value(&value) {
+                Some(value_type) => value_type,
+                None => match self.unknown_value_type_policy {
+                    UnknownValueTypePolicy::Reject => {
+                        return Err(Error::ColumnError {
+                            column: column_name.to_string(),
+                            source: column::Error::UnknownColumnType {
+                                inserted_value_type: type_description(value.value_type()).into(),
+                            },
+                        })
+                    }
+                    UnknownValueTypePolicy::Skip => continue,
+                    UnknownValueTypePolicy::Stringify => match stringify_value(&value) {
+                        Some(_) => ColumnType::String,
+                        None => continue,
+                    },
+                },
+            };
+
+            let new_type = match column.column_type {
+                None => value_type,
+                Some(existing_type) if existing_type == value_type => existing_type,
+                Some(ColumnType::I64)
+                    if value_type == ColumnType::F64
+                        && self.type_conflict_policy == TypeConflictPolicy::PromoteNumeric =>
+                {
+                    ColumnType::F64
+                }
+                Some(existing_type) => {
+                    return Err(Error::ColumnError {
+                        column: column_name.to_string(),
+                        source: column::Error::TypeMismatch {
+                            existing_column_type: existing_type.description().into(),
+                            inserted_value_type: type_description(value.value_type()).into(),
+                        },
+                    })
+                }
+            };
+
+            column.column_type = Some(new_type);
+        }
+
+        Ok(())
+    }
+
+    /// Parses `lp` as line protocol and appends every line to this
+    /// table. Errors, without appending any line, if a line is for a
+    /// measurement other than this table.
+    ///
+    /// The lines are converted to a WAL write entry and appended via
+    /// `append_rows`, exactly as data written to a `Db` is, so the
+    /// results are the same as ingesting the lines that way.
+    pub fn append_from_line_protocol(
+        &mut self,
+        dictionary: &mut Dictionary,
+        lp: &str,
+    ) -> Result<AppendStats> {
+        let lines = parse_lines(lp)
+            .collect::<Result<Vec<_>, _>>()
+            .context(ParsingLineProtocol)?;
+
+        let other_measurement = lines.iter().find(|line| {
+            dictionary
+                .lookup_value(line.series.measurement.as_str())
+                .map_or(true, |table_id| table_id != self.id)
+        });
+        if let Some(line) = other_measurement {
+            return MeasurementMismatch {
+                measurement: line.series.measurement.to_string(),
+                table: self.id,
+            }
+            .fail();
+        }
+
+        let initial_row_count = self.row_count();
+        let initial_column_count = self.columns.len();
+
+        // all lines go to a single partition (this table)
+        let data = split_lines_into_write_entry_partitions(|_| String::new(), &lines);
+        let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+
+        for entry in batch.entries().into_iter().flatten() {
+            for table_batch in entry.table_batches().into_iter().flatten() {
+                if let Some(rows) = table_batch.rows() {
+                    self.append_rows(dictionary, &rows)?;
+                }
+            }
+        }
+
+        Ok(AppendStats {
+            rows_appended: self.row_count() - initial_row_count,
+            columns_added: self.columns.len() - initial_column_count,
+        })
+    }
+
+    /// Appends the rows of an arrow `batch` to this table. The columns
+    /// named in `tag_columns` (which must be `Utf8`) are tags, and the
+    /// others are fields, converted as follows:
+    ///
+    /// * `Float32` and `Float64` to `F64`
+    /// * `Int8`, `Int16`, `Int32` and `Int64` to `I64`
+    /// * `Utf8` to `String`
+    /// * `Boolean` to `Bool`
+    /// * `Timestamp` (of any unit) to nanoseconds in an `I64` column,
+    ///   for the time column only
+    ///
+    /// Other types (such as `UInt64`, which may not fit in an `I64`)
+    /// are rejected rather than converted with a loss of precision, as
+    /// are columns whose type conflicts with an existing column (see
+    /// `type_conflict_policy`). All columns are checked and converted
+    /// before the table is modified, so on error no rows are appended.
+    ///
+    /// New columns whose values in `batch` are all null are not added.
+    pub fn append_record_batch(
+        &mut self,
+        dictionary: &mut Dictionary,
+        batch: &RecordBatch,
+        tag_columns: &[&str],
+    ) -> Result<AppendStats> {
+        let schema = batch.schema();
+        let row_count = self.row_count();
+
+        let mut column_names = BTreeSet::new();
+        let mut new_columns = Vec::with_capacity(schema.fields().len());
+        for (field, array) in schema.fields().iter().zip(batch.columns()) {
+            let column_name = field.name().as_str();
+            ensure!(
+                column_names.insert(column_name),
+                DuplicateColumnInRow {
+                    table: self.id,
+                    column: column_name,
+                }
+            );
+
+            let is_tag = tag_columns.contains(&column_name);
+            let column_type = self.arrow_column_type(field, is_tag)?;
+
+            let existing_type = dictionary
+                .lookup_value(column_name)
+                .ok()
+                .and_then(|column_id| self.column_id_to_index.get(&column_id))
+                .map(|&column_index| self.columns[column_index].column_type());
+
+            match existing_type {
+                Some(existing_type) if existing_type == column_type => {}
+                Some(ColumnType::I64)
+                    if column_type == ColumnType::F64
+                        && self.type_conflict_policy == TypeConflictPolicy::PromoteNumeric => {}
+                Some(existing_type) => {
+                    return Err(Error::ColumnError {
+                        column: column_name.to_string(),
+                        source: column::Error::TypeMismatch {
+                            existing_column_type: existing_type.description().into(),
+                            inserted_value_type: column_type.description().into(),
+                        },
+                    })
+                }
+                None => {}
+            }
+
+            // a new column has no value for the existing rows
+            let leading_nulls = if existing_type.is_none() {
+                row_count
+            } else {
+                0
+            };
+            let column =
+                arrow_to_column(dictionary, column_name, array, column_type, leading_nulls)?;
+            new_columns.push((column_name, column));
+        }
+
+        // everything is checked: now modify the table
+        let initial_column_count = self.columns.len();
+        for (column_name, column) in new_columns {
+            let column = match column {
+                Some(column) => column,
+                None => continue,
+            };
+
+            let column_id = dictionary.lookup_value_or_insert(column_name);
+            match self.column_id_to_index.get(&column_id) {
+                Some(&column_index) => {
+                    let existing = &mut self.columns[column_index];
+                    if matches!(existing, Column::I64(_, _)) && matches!(column, Column::F64(_, _))
+                    {
+                        existing.promote_i64_to_f64().context(ColumnError {
+                            column: column_name,
+                        })?;
+                    }
+                    existing.extend(column).context(ColumnError {
+                        column: column_name,
+                    })?;
+                }
+                None => {
+                    self.push_column(column_id, column);
+                    if column_name == self.time_column_name() {
+                        self.time_column_id = Some(column_id);
+                    }
+                }
+            }
+        }
+
+        let new_row_count = row_count + batch.num_rows();
+        for column in &mut self.columns {
+            column.pad_with_nulls(new_row_count);
+        }
+        self.update_tag_value_filters(row_count..new_row_count);
+        self.release_caches();
+
+        Ok(AppendStats {
+            rows_appended: batch.num_rows(),
+            columns_added: self.columns.len() - initial_column_count,
+        })
+    }
+
+    /// Appends a single row holding the fixed size vector `values` at
+    /// `time`. As line protocol has no array type, each element is
+    /// stored in its own `F64` field column, named `base_name`
+    /// suffixed with the element's index (e.g. `rgb_0`, `rgb_1` and
+    /// `rgb_2`). The columns are checked as by `append_record_batch`.
+    pub fn append_vector_field(
+        &mut self,
+        partition: &mut Partition,
+        base_name: &str,
+        values: &[f64],
+        time: i64,
+    ) -> Result<()> {
+        let mut fields = Vec::with_capacity(values.len() + 1);
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(values.len() + 1);
+        for (i, &value) in values.iter().enumerate() {
+            fields.push(ArrowField::new(
+                &format!("{}_{}", base_name, i),
+                ArrowDataType::Float64,
+                true,
+            ));
+            columns.push(Arc::new(Float64Array::from(vec![value])));
+        }
+        fields.push(ArrowField::new(
+            self.time_column_name(),
+            ArrowDataType::Int64,
+            true,
+        ));
+        columns.push(Arc::new(Int64Array::from(vec![time])));
+
+        let batch = RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+            .context(ArrowError {})?;
+        self.append_record_batch(&mut partition.dictionary, &batch, &[])?;
+        Ok(())
+    }
+
+    /// Returns the type of column that stores the values of the arrow
+    /// `field`, see `append_record_batch`
+    fn arrow_column_type(&self, field: &ArrowField, is_tag: bool) -> Result<ColumnType> {
+        let unsupported = |reason| UnsupportedArrowType {
+            column_name: field.name(),
+            data_type: field.data_type().clone(),
+            reason,
+        };
+
+        match field.data_type() {
+            ArrowDataType::Utf8 if is_tag => Ok(ColumnType::Tag),
+            _ if is_tag => unsupported("tag columns must be Utf8").fail(),
+            ArrowDataType::Float32 | ArrowDataType::Float64 => Ok(ColumnType::F64),
+            ArrowDataType::Int8
+            | ArrowDataType::Int16
+            | ArrowDataType::Int32
+            | ArrowDataType::Int64 => Ok(ColumnType::I64),
+            ArrowDataType::Utf8 => Ok(ColumnType::String),
+            ArrowDataType::Boolean => Ok(ColumnType::Bool),
+            &ArrowDataType::Decimal(precision, scale) => {
+                match (u8::try_from(precision), u8::try_from(scale)) {
+                    (Ok(precision), Ok(scale)) => Ok(ColumnType::Decimal { scale, precision }),
+                    _ => unsupported("the decimal precision or scale is too large").fail(),
+                }
+            }
+            ArrowDataType::Timestamp(_, _) if field.name() == self.time_column_name() => {
+                Ok(ColumnType::I64)
+            }
+            ArrowDataType::Timestamp(_, _) => {
+                unsupported("only the time column may be a timestamp").fail()
+            }
+            _ => unsupported("the type can not be converted without loss").fail(),
+        }
+    }
+
+    /// Returns a sink that appends line protocol to this table one
+    /// line at a time, for pull based ingest where the caller controls
+    /// the pacing. Each line is converted to a WAL entry and appended
+    /// exactly as by `append_from_line_protocol`.
+    pub fn line_protocol_sink<'a>(
+        &'a mut self,
+        dictionary: &'a mut Dictionary,
+    ) -> LineProtocolSink<'a> {
+        LineProtocolSink {
+            table: self,
+            dictionary,
+            stats: AppendStats::default(),
+        }
+    }
+
+    /// Creates and adds a datafuson filtering expression, if any out of the
+    /// combination of predicate and timestamp. Returns the builder
+    ///
+    /// Comparisons against null literals (e.g. `temp > NULL`) are
+    /// never true, following SQL three-valued logic: a row only
+    /// passes the filter if the predicate evaluates to TRUE. Rather
+    /// than relying on DataFusion to infer the type of (and evaluate)
+    /// such comparisons, they are replaced by a boolean constant with
+    /// the same effect, see `rewrite_null_comparisons`.
+    fn add_datafusion_predicate(
+        &self,
+        plan_builder: LogicalPlanBuilder,
+        partition_predicate: &PartitionPredicate,
+    ) -> Result<LogicalPlanBuilder> {
+        match partition_predicate.filter_expr(self.time_column_name()) {
+            Some(df_predicate) => {
+                let df_predicate = rewrite_null_comparisons(df_predicate, false);
+                plan_builder.filter(df_predicate).context(BuildingPlan)
+            }
+            None => Ok(plan_builder),
+        }
+    }
+
+    /// Returns an expression selecting the time column of this table,
+    /// named `TIME_COLUMN_NAME` in the output
+    fn time_column_expr(&self) -> Expr {
+        match &self.time_column_name {
+            Some(time_column_name) => time_column_name.into_expr().alias(TIME_COLUMN_NAME),
+            None => TIME_COLUMN_NAME.into_expr(),
+        }
+    }
+
+    /// Reorders `tag_columns` to start with `group_columns`, as
+    /// `reorder_prefix`. Grouping by the time column (unless a tag has
+    /// the same name) is reported as a `GroupByTimeColumn` error,
+    /// rather than as a missing tag column.
+    fn reorder_group_prefix(
+        &self,
+        group_columns: &[String],
+        tag_columns: Vec<Arc<String>>,
+    ) -> Result<Vec<Arc<String>>> {
+        let time_column = group_columns.iter().find(|&column_name| {
+            (column_name == self.time_column_name() || column_name == TIME_COLUMN_NAME)
+                && !tag_columns.iter().any(|c| c.as_ref() == column_name)
+        });
+        if let Some(column_name) = time_column {
+            return GroupByTimeColumn { column_name }.fail();
+        }
+
+        reorder_prefix(group_columns, tag_columns)
+    }
+
+    /// Wraps `plan` in a projection that passes through all of its
+    /// columns and appends a constant Utf8 column named
+    /// `column_name` (typically `PARTITION_KEY_COLUMN_NAME`) holding
+    /// `partition.key`. This lets consumers that union plans from
+    /// several partitions tell which partition each row came from.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Projection (all input columns, partition.key AS column_name)
+    ///      plan
+    pub fn add_partition_key_column(
+        plan: LogicalPlan,
+        partition: &Partition,
+        column_name: &str,
+    ) -> Result<LogicalPlan> {
+        let mut select_exprs = plan
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| Expr::Column(field.name().clone()))
+            .collect::<Vec<_>>();
+
+        let partition_key = Expr::Literal(ScalarValue::Utf8(Some(partition.key.clone())));
+        select_exprs.push(Expr::Alias(Box::new(partition_key), column_name.into()));
+
+        LogicalPlanBuilder::from(&plan)
+            .project(select_exprs)
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)
+    }
+
+    /// Creates a DataFusion LogicalPlan that returns column *names* as a
+    /// single column of Strings
+    ///
+    /// The created plan looks like:
+    ///
+    ///  Extension(PivotSchema)
+    ///    (Optional Projection to get rid of time)
+    ///        Filter(predicate)
+    ///          InMemoryScan
+    pub fn tag_column_names_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        // A table without tags has no tag names to report. Return an
+        // empty result directly rather than a degenerate plan (that
+        // would project away every column)
+        if !self.columns.iter().any(|c| matches!(c, Column::Tag(_, _))) {
+            return Self::empty_tag_column_names_plan();
+        }
+
+        let need_time_column = partition_predicate.range.is_some();
+
+        let time_column_id = self.predicate_time_column_id(partition_predicate);
+
+        // figure out the tag columns
+        let requested_columns_with_index = self
+            .column_id_to_index
+            .iter()
+            .filter(|&(&column_id, &column_index)| {
+                // keep tag columns and the timestamp column, if needed to evaluate a timestamp predicate
+                if let Column::Tag(_, _) = self.columns[column_index] {
+                    true
+                } else {
+                    need_time_column && column_id == time_column_id
+                }
+            })
+            .map(|(&column_id, &column_index)| {
+                // the id came out of our map, so it is only missing
+                // if the dictionary is corrupt
+                let column_name = partition.dictionary.lookup_id(column_id).context(
+                    ColumnIdNotFoundInDictionary {
+                        column_id,
+                        partition: &partition.key,
+                    },
+                )?;
+                Ok((column_name, column_index))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // TODO avoid materializing here
+        let data = self.to_arrow_impl(partition, &requested_columns_with_index)?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        // Shouldn't have field selections here (as we are getting the tags...)
+        assert!(!partition_predicate.has_field_restriction());
+
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+
+        // add optional selection to remove time column
+        let plan_builder = if !need_time_column {
+            plan_builder
+        } else {
+            // Create expressions for all columns except time
+            let select_exprs = requested_columns_with_index
+                .iter()
+                .filter_map(|&(column_name, _)| {
+                    if column_name != self.time_column_name() {
+                        Some(Expr::Column(column_name.into()))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            plan_builder.project(select_exprs).context(BuildingPlan)?
+        };
+
+        let plan = plan_builder.build().context(BuildingPlan)?;
+
+        // And finally pivot the plan
+        let plan = make_schema_pivot(plan);
+
+        let table_name =
+            partition
+                .dictionary
+                .lookup_id(self.id)
+                .context(TableIdNotFoundInDictionary {
+                    table_id: self.id,
+                    partition: &partition.key,
+                })?;
+        debug!(
+            "Created column_name plan for table '{}':\n{}",
+            table_name,
+            plan.display_indent_schema()
+        );
+
+        Ok(plan)
+    }
+
+    /// Creates a plan producing no rows with the same (single Utf8
+    /// column) schema as the schema pivot in `tag_column_names_plan`
+    fn empty_tag_column_names_plan() -> Result<LogicalPlan> {
+        let schema = make_schema_pivot_output_schema();
+        let no_names: ArrayRef = Arc::new(StringArray::from(Vec::<&str>::new()));
+        let data = RecordBatch::try_new(schema.clone(), vec![no_names]).context(ArrowError {})?;
+
+        Ok(LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema,
+        })
+    }
+
+    /// Creates a DataFusion LogicalPlan that returns the names of the
+    /// tag columns that have non null values matching the predicate,
+    /// as a single Utf8 column named `tag_key`, sorted by name.
+    ///
+    /// The created plan looks like:
+    ///
+    ///  Sort(tag_key)
+    ///    Projection(non_null_column AS tag_key)
+    ///      <tag_column_names_plan>
+    pub fn tag_keys_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        let plan = self.tag_column_names_plan(partition_predicate, partition)?;
+
+        // the pivot produces a single column
+        let pivot_column_name = plan.schema().field(0).name().clone();
+        let select_exprs = vec![Expr::Alias(
+            Box::new(Expr::Column(pivot_column_name)),
+            TAG_KEY_COLUMN_NAME.into(),
+        )];
+
+        LogicalPlanBuilder::from(&plan)
+            .project(select_exprs)
+            .context(BuildingPlan)?
+            .sort(vec![TAG_KEY_COLUMN_NAME.into_sort_expr()])
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)
+    }
+
+    /// Creates a DataFusion LogicalPlan that returns the name and
+    /// type of each field column that has at least one non null
+    /// value in a row matching the predicate (and that passes any
+    /// field restriction), as two Utf8 columns `field_key` and
+    /// `field_type`, sorted by name.
+    ///
+    /// The field types are reported using the InfluxDB names
+    /// (`float`, `integer`, `string` and `boolean`).
+    ///
+    /// Without general predicate expressions, the plan looks like:
+    ///
+    ///    InMemoryScan
+    ///
+    /// Otherwise the rows are filtered as in `tag_column_names_plan`,
+    /// see `filtered_field_keys_plan`.
+    pub fn field_keys_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        // (name, column index, type) of the candidate fields
+        let mut fields = Vec::new();
+        for (&column_id, &column_index) in &self.column_id_to_index {
+            if self.is_time_column(partition_predicate, column_id)
+                || !partition_predicate.should_include_field(column_id)
+            {
+                continue;
+            }
+
+            let field_type = match &self.columns[column_index] {
+                Column::Tag(_, _) => continue,
+                Column::F64(_, _) => "float",
+                Column::I64(_, _) => "integer",
+                Column::String(_, _) => "string",
+                Column::Bool(_, _) => "boolean",
+                Column::Decimal { .. } => "decimal",
+            };
+
+            let field_key = partition.dictionary.lookup_id(column_id).context(
+                ColumnIdNotFoundInDictionary {
+                    column_id,
+                    partition: &partition.key,
+                },
+            )?;
+            fields.push((field_key, column_index, field_type));
+        }
+
+        fields.sort_unstable();
+
+        if !partition_predicate.partition_exprs.is_empty() && !fields.is_empty() {
+            return self.filtered_field_keys_plan(partition_predicate, &fields, partition);
+        }
+
+        let mut field_keys = Vec::new();
+        for &(field_key, column_index, field_type) in &fields {
+            let has_values = match &self.columns[column_index] {
+                Column::Tag(_, _) => unreachable!("tag columns are not fields"),
+                Column::F64(vals, _) => self.column_matches_predicate(vals, partition_predicate)?,
+                Column::I64(vals, _) => self.column_matches_predicate(vals, partition_predicate)?,
+                Column::String(vals, _) => {
+                    self.column_matches_predicate(vals, partition_predicate)?
+                }
+                Column::Bool(vals, _) => {
+                    self.column_matches_predicate(vals, partition_predicate)?
+                }
+                Column::Decimal { values, .. } => {
+                    self.column_matches_predicate(values, partition_predicate)?
+                }
+            };
+
+            if has_values {
+                field_keys.push((field_key, field_type));
+            }
+        }
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new(FIELD_KEY_COLUMN_NAME, ArrowDataType::Utf8, false),
+            ArrowField::new(FIELD_TYPE_COLUMN_NAME, ArrowDataType::Utf8, false),
+        ]));
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(
+                field_keys.iter().map(|&(k, _)| k).collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                field_keys.iter().map(|&(_, t)| t).collect::<Vec<_>>(),
+            )),
+        ];
+
+        let data = RecordBatch::try_new(schema.clone(), columns).context(ArrowError {})?;
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        })
+        .build()
+        .context(BuildingPlan)
+    }
+
+    /// Creates the plan of `field_keys_plan` for a predicate with
+    /// general expressions, which DataFusion evaluates in the same way
+    /// as in `tag_column_names_plan`. `fields` has the (name, column
+    /// index, type) of each candidate field, sorted by name.
+    ///
+    /// The created plan looks like:
+    ///
+    ///  Sort(field_key)
+    ///    Projection(non_null_column AS field_key, field_type(non_null_column) AS field_type)
+    ///      Extension(PivotSchema)
+    ///        Projection(fields)
+    ///          Filter(predicate)
+    ///            InMemoryScan
+    fn filtered_field_keys_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        fields: &[(&str, usize, &'static str)],
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        // TODO avoid materializing all the columns here
+        let data = self.all_to_arrow(partition)?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+
+        let select_exprs = fields
+            .iter()
+            .map(|&(field_key, _, _)| Expr::Column(field_key.into()))
+            .collect();
+        let plan = plan_builder
+            .project(select_exprs)
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)?;
+
+        // the names of the fields with a non null value after filtering
+        let plan = make_schema_pivot(plan);
+
+        // the type of each field only depends on its name
+        let field_types = fields
+            .iter()
+            .map(|&(field_key, _, field_type)| (field_key.to_string(), field_type))
+            .collect::<HashMap<_, _>>();
+        let field_type_fn: ScalarFunctionImplementation = Arc::new(move |args: &[ArrayRef]| {
+            let field_keys = args[0]
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .expect("field keys are strings");
+            let field_types = (0..field_keys.len())
+                .map(|i| field_types.get(field_keys.value(i)).copied())
+                .collect::<Vec<_>>();
+            Ok(Arc::new(StringArray::from(field_types)) as ArrayRef)
+        });
+        let field_type_udf = logical_plan::create_udf(
+            FIELD_TYPE_COLUMN_NAME,
+            vec![ArrowDataType::Utf8],
+            Arc::new(ArrowDataType::Utf8),
+            field_type_fn,
+        );
+
+        // the pivot produces a single column
+        let pivot_column_name = plan.schema().field(0).name().clone();
+        let select_exprs = vec![
+            Expr::Alias(
+                Box::new(Expr::Column(pivot_column_name.clone())),
+                FIELD_KEY_COLUMN_NAME.into(),
+            ),
+            Expr::Alias(
+                Box::new(field_type_udf.call(vec![Expr::Column(pivot_column_name)])),
+                FIELD_TYPE_COLUMN_NAME.into(),
+            ),
+        ];
+
+        LogicalPlanBuilder::from(&plan)
+            .project(select_exprs)
+            .context(BuildingPlan)?
+            .sort(vec![FIELD_KEY_COLUMN_NAME.into_sort_expr()])
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)
+    }
+
+    /// Creates a DataFusion LogicalPlan that returns column *values* as a
+    /// single column of Strings
+    ///
+    /// If the predicate has no general expressions (only a timestamp
+    /// range, if anything) and `column_name` is a tag, the distinct
+    /// values are computed up front by `distinct_tag_values`, without
+    /// converting the table to arrow, and the plan is simply:
+    ///
+    ///    InMemoryScan
+    ///
+    /// Otherwise the created plan looks like:
+    ///
+    ///    Projection
+    ///        Filter(predicate)
+    ///          InMemoryScan
+    pub fn tag_values_plan(
+        &self,
+        column_name: &str,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        // shouldn't have columns selection (as this is getting tag values...)
+        assert!(!partition_predicate.has_field_restriction());
+
+        if !partition_predicate.partition_exprs.is_empty()
+            || self.tag_column(partition, column_name).is_none()
+        {
+            return self.projected_tag_values_plan(column_name, partition_predicate, partition);
+        }
+
+        let data = self.distinct_tag_values(column_name, partition_predicate, partition)?;
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        })
+        .build()
+        .context(BuildingPlan)
+    }
+
+    /// Creates the general plan of `tag_values_plan`, which projects
+    /// the column `column_name` of the rows that match the predicate
+    fn projected_tag_values_plan(
+        &self,
+        column_name: &str,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        // TODO avoid materializing all the columns here (ideally
+        // DataFusion can prune them out)
+        let data = self.all_to_arrow(partition)?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+        let select_exprs = vec![Expr::Column(column_name.into())];
+
+        // And build the plan!
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+
+        plan_builder
+            .project(select_exprs)
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)
+    }
+
+    /// Creates a SeriesSet plan that produces an output table with rows that match the predicate
+    ///
+    /// The output looks like:
+    /// (tag_col1, tag_col2, ... field1, field2, ... timestamp)
+    ///
+    /// The order of the tag_columns is orderd by name.
+    ///
+    /// The data is sorted on tag_col1, tag_col2, ...) so that all
+    /// rows for a particular series (groups where all tags are the
+    /// same) occur together in the plan
+    pub fn series_set_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+    ) -> Result<SeriesSetPlan> {
+        self.series_set_plan_impl(
+            partition_predicate,
+            None,
+            &SeriesSetPlanOptions::default(),
+            partition,
+        )
+    }
+
+    /// Creates a SeriesSet plan like `series_set_plan` that only
+    /// produces the rows selected by `pagination`, applied after the
+    /// output has been sorted. See `Pagination` for the ordering
+    /// caveats.
+    pub fn paged_series_set_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        pagination: Pagination,
+        partition: &Partition,
+    ) -> Result<SeriesSetPlan> {
+        self.series_set_plan_impl(
+            partition_predicate,
+            None,
+            &SeriesSetPlanOptions {
+                pagination,
+                ..SeriesSetPlanOptions::default()
+            },
+            partition,
+        )
+    }
+
+    /// Creates the plans for computing series set, pulling prefix_columns, if any, as a prefix of the ordering
+    ///
+    /// The plan is further adjusted by `options`, see
+    /// `SeriesSetPlanOptions`.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Limit (if pagination.limit is set)
+    ///      Offset (if pagination.offset is set)
+    ///        Projection (select the columns columns needed)
+    ///          Order by ([group rank], tag_columns, timestamp_column)
+    ///            Filter(predicate, [deduplicated])
+    ///              InMemoryScan
+    pub fn series_set_plan_impl(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        prefix_columns: Option<&[String]>,
+        options: &SeriesSetPlanOptions,
+        partition: &Partition,
+    ) -> Result<SeriesSetPlan> {
+        let SeriesSetPlanOptions {
+            pinned_tags,
+            nulls_first,
+            field_selector,
+            pagination,
+            duplicates,
+            aliases,
+            group_order,
+        } = options;
+        field_selector.validate()?;
+
+        // the rank of each row's group, when ordering by an aggregate
+        let row_ranks = match group_order {
+            GroupOrder::ByKey => None,
+            GroupOrder::ByAggregate(kind, column_name, ascending) => Some(self.group_ranks(
+                partition_predicate,
+                prefix_columns.unwrap_or_default(),
+                *kind,
+                column_name,
+                *ascending,
+                partition,
+            )?),
+        };
+
+        // I wonder if all this string creation will be too slow?
+        let table_name = partition
+            .dictionary
+            .lookup_id(self.id)
+            .context(TableIdNotFoundInDictionary {
+                table_id: self.id,
+                partition: &partition.key,
+            })?
+            .to_string();
+
+        let table_name = Arc::new(table_name);
+        let (mut tag_columns, field_columns) =
+            self.tag_and_field_column_names(partition_predicate, field_selector, partition)?;
+
+        // reorder tag_columns to have the pinned and prefix columns, if requested
+        tag_columns = pin_tags(pinned_tags, tag_columns);
+        if let Some(prefix_columns) = prefix_columns {
+            tag_columns = self.reorder_group_prefix(prefix_columns, tag_columns)?;
+        }
+
+        // only tags and fields may be renamed, as consumers of the
+        // plan find the time column by name
+        for column_name in aliases.keys() {
+            ensure!(
+                tag_columns
+                    .iter()
+                    .chain(field_columns.iter())
+                    .any(|c| c.as_str() == column_name),
+                AliasColumnNotFound { column_name }
+            );
+        }
+
+        // TODO avoid materializing all the columns here (ideally
+        // DataFusion can prune them out)
+        let mut data = self.all_to_arrow(partition)?;
+
+        let has_row_ranks = row_ranks.is_some();
+        if let Some(row_ranks) = row_ranks {
+            let field = ArrowField::new(GROUP_RANK_COLUMN_NAME, ArrowDataType::Int64, false);
+            data = with_extra_column(data, field, Arc::new(Int64Array::from(row_ranks)))?;
+        }
+
+        let deduplicated_rows = self.deduplicated_rows(partition_predicate, *duplicates)?;
+        let has_deduplicated_rows = deduplicated_rows.is_some();
+        if let Some(deduplicated_rows) = deduplicated_rows {
+            let field = ArrowField::new(DEDUPLICATED_COLUMN_NAME, ArrowDataType::Boolean, false);
+            let array = Arc::new(BooleanArray::from(deduplicated_rows));
+            data = with_extra_column(data, field, array)?;
+        }
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        // And build the plan from the bottom up
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        // Filtering
+        let mut plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+        if has_deduplicated_rows {
+            plan_builder = plan_builder
+                .filter(DEDUPLICATED_COLUMN_NAME.into_expr())
+                .context(BuildingPlan)?;
+        }
+
+        let mut sort_exprs = Vec::new();
+        if has_row_ranks {
+            sort_exprs.push(GROUP_RANK_COLUMN_NAME.into_sort_expr());
+        }
+        sort_exprs.extend(
+            tag_columns
+                .iter()
+                .map(|c| c.into_sort_expr_with_nulls(*nulls_first)),
+        );
+        sort_exprs.push(self.time_column_name().into_sort_expr());
+
+        // Order by
+        let plan_builder = plan_builder.sort(sort_exprs).context(BuildingPlan)?;
+
+        // Selection
+        let select_expr = |c: &Arc<String>| match aliases.get(c.as_str()) {
+            Some(alias) => c.into_expr().alias(alias),
+            None => c.into_expr(),
+        };
+        let mut select_exprs = Vec::new();
+        select_exprs.extend(tag_columns.iter().map(select_expr));
+        select_exprs.extend(field_columns.iter().map(select_expr));
+        select_exprs.push(self.time_column_expr());
+
+        let mut plan_builder = plan_builder.project(select_exprs).context(BuildingPlan)?;
+
+        // the plan's columns are named by their aliases
+        let output_name = |c: Arc<String>| match aliases.get(c.as_str()) {
+            Some(alias) => Arc::new(alias.clone()),
+            None => c,
+        };
+        let tag_columns = tag_columns.into_iter().map(output_name).collect();
+        let field_columns = field_columns.into_iter().map(output_name).collect();
+
+        // Pagination (must come after the sort so pages are consistent)
+        if let Some(offset) = pagination.offset {
+            let plan = make_offset(plan_builder.build().context(BuildingPlan)?, offset);
+            plan_builder = LogicalPlanBuilder::from(&plan);
+        }
+
+        if let Some(limit) = pagination.limit {
+            plan_builder = plan_builder.limit(limit).context(BuildingPlan)?;
+        }
+
+        // and finally create the plan
+        let plan = plan_builder.build().context(BuildingPlan)?;
+
+        Ok(SeriesSetPlan {
+            table_name,
+            plan,
+            tag_columns,
+            field_columns,
+        })
+    }
+
+    /// Returns whether each row is kept when the rows of each series
+    /// (in the predicate's timestamp range) with the same time are
+    /// collapsed as specified by `duplicates`, or `None` if no rows are
+    /// removed. Rows without a time are always kept.
+    fn deduplicated_rows(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        duplicates: DuplicateTimestamps,
+    ) -> Result<Option<Vec<bool>>> {
+        if duplicates == DuplicateTimestamps::KeepAll {
+            return Ok(None);
+        }
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedDuplicateTimestampsPredicate
+        );
+
+        let time_column_id = self.predicate_time_column_id(partition_predicate);
+        if !self.column_id_to_index.contains_key(&time_column_id) {
+            return Ok(None);
+        }
+        let times = self.column_i64(time_column_id)?;
+
+        let tag_values = self
+            .columns
+            .iter()
+            .filter_map(|column| match column {
+                Column::Tag(vals, _) => Some(vals.as_slice()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut keep = vec![true; self.row_count()];
+        // the row kept so far for each (series, time)
+        let mut kept_rows: HashMap<(Vec<Option<u32>>, i64), usize> = HashMap::new();
+        for (row, &time) in times.iter().enumerate() {
+            let time = match time {
+                Some(time) if partition_predicate.range.map_or(true, |r| r.contains(time)) => time,
+                _ => continue,
+            };
+            let series = tag_values.iter().map(|vals| vals[row]).collect();
+
+            match kept_rows.entry((series, time)) {
+                Entry::Vacant(entry) => {
+                    entry.insert(row);
+                }
+                Entry::Occupied(mut entry) => match duplicates {
+                    DuplicateTimestamps::KeepFirst => keep[row] = false,
+                    DuplicateTimestamps::KeepLast => keep[entry.insert(row)] = false,
+                    DuplicateTimestamps::Error => return DuplicateSeriesTimestamp { time }.fail(),
+                    DuplicateTimestamps::KeepAll => unreachable!("returned above"),
+                },
+            }
+        }
+
+        Ok(Some(keep))
+    }
+
+    /// Creates a GroupedSeriesSet plan that produces an output table with rows that match the predicate
+    ///
+    /// The output looks like:
+    /// (group_tag_column1, group_tag_column2, ... tag_col1, tag_col2, ... field1, field2, ... timestamp)
+    ///
+    /// The order of the tag_columns is ordered by name.
+    ///
+    /// The data is sorted on tag_col1, tag_col2, ...) so that all
+    /// rows for a particular series (groups where all tags are the
+    /// same) occur together in the plan
+    ///
+    /// The plan is further adjusted by `options`, see
+    /// `SeriesSetPlanOptions`.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Projection (select the columns columns needed)
+    ///      Order by ([group rank], tag_columns, timestamp_column)
+    ///        Filter(predicate)
+    ///          InMemoryScan
+    pub fn grouped_series_set_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        options: &SeriesSetPlanOptions,
+        partition: &Partition,
+    ) -> Result<GroupedSeriesSetPlan> {
+        let series_set_plan = self.series_set_plan_impl(
+            partition_predicate,
+            Some(&group_columns),
+            options,
+            partition,
+        )?;
+        let num_prefix_tag_group_columns = group_columns.len();
+
+        Ok(GroupedSeriesSetPlan {
+            series_set_plan,
+            num_prefix_tag_group_columns,
+        })
+    }
+
+    /// Returns, for each row, the rank of its group (by the values of
+    /// the `group_columns` tags) when the groups are ordered by the
+    /// `kind` aggregate of `column_name` over the rows in the
+    /// predicate's timestamp range. Groups with the same aggregate have
+    /// the same rank, and groups without an aggregate rank last.
+    fn group_ranks(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        kind: AggregateKind,
+        column_name: &str,
+        ascending: bool,
+        partition: &Partition,
+    ) -> Result<Vec<i64>> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedGroupOrderPredicate
+        );
+
+        let column_id = partition
+            .dictionary
+            .lookup_value(column_name)
+            .ok()
+            .filter(|column_id| self.column_id_to_index.contains_key(column_id))
+            .context(InvalidGroupOrderField {
+                column_name,
+                reason: "not a column of the table",
+            })?;
+        let values = self
+            .aggregate_input(column_id, kind)?
+            .context(InvalidGroupOrderField {
+                column_name,
+                reason: "not a numeric column",
+            })?;
+        let GroupAggregates {
+            row_keys,
+            aggregates,
+            ..
+        } = self.group_aggregates(partition_predicate, group_columns, kind, values, partition)?;
+
+        let mut distinct_aggregates = aggregates.values().copied().collect::<Vec<_>>();
+        distinct_aggregates.sort_by(|a, b| a.partial_cmp(b).expect("NaNs were skipped"));
+        distinct_aggregates.dedup();
+        let num_ranks = distinct_aggregates.len();
+
+        let row_ranks = row_keys
+            .iter()
+            .map(|key| {
+                let rank = match aggregates.get(key.as_slice()) {
+                    Some(aggregate) => {
+                        let index = distinct_aggregates
+                            .binary_search_by(|a| {
+                                a.partial_cmp(aggregate).expect("NaNs were skipped")
+                            })
+                            .expect("aggregate is in the distinct aggregates");
+                        if ascending {
+                            index
+                        } else {
+                            num_ranks - 1 - index
+                        }
+                    }
+                    // groups without an aggregate go last
+                    None => num_ranks,
+                };
+                rank as i64
+            })
+            .collect();
+
+        Ok(row_ranks)
+    }
+
+    /// Returns the values of the column `column_id` aggregated by
+    /// `kind`, as f64 (for `AggregateKind::Count`, 1 for each non null
+    /// value), or `None` if `kind` can not aggregate the column
+    fn aggregate_input(
+        &self,
+        column_id: u32,
+        kind: AggregateKind,
+    ) -> Result<Option<Vec<Option<f64>>>> {
+        match kind {
+            AggregateKind::Count => {
+                let null_mask = self.null_mask(column_id)?;
+                Ok(Some(
+                    (0..null_mask.len())
+                        .map(|row| {
+                            if null_mask.value(row) {
+                                Some(1.0)
+                            } else {
+                                None
+                            }
+                        })
+                        .collect(),
+                ))
+            }
+            AggregateKind::Sum
+            | AggregateKind::Min
+            | AggregateKind::Max
+            | AggregateKind::Percentile(_) => match self.column(column_id)? {
+                Column::F64(_, _) | Column::I64(_, _) => {
+                    self.numeric_field_as_f64(column_id).map(Some)
+                }
+                _ => Ok(None),
+            },
+        }
+    }
+
+    /// Computes the `kind` aggregate of `values` (one per row) over the
+    /// rows in the predicate's timestamp range, for each group of rows
+    /// with the same values of the `group_columns` tags. Null and NaN
+    /// values are ignored, and groups without other values have no
+    /// aggregate.
+    fn group_aggregates(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        kind: AggregateKind,
+        values: Vec<Option<f64>>,
+        partition: &Partition,
+    ) -> Result<GroupAggregates> {
+        let (tag_columns, _) =
+            self.tag_and_field_column_names(partition_predicate, &FieldSelector::All, partition)?;
+        let mut group_tag_columns = self.reorder_group_prefix(group_columns, tag_columns)?;
+        group_tag_columns.truncate(group_columns.len());
+
+        let group_tag_values = group_tag_columns
+            .iter()
+            .map(|column_name| self.tag_value_ids(partition, column_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        // without a time column, no row is in a range
+        let times = match partition_predicate.range {
+            Some(_) => self.predicate_times(partition_predicate)?,
+            None => None,
+        };
+
+        let row_keys = (0..self.row_count())
+            .map(|row| group_tag_values.iter().map(|vals| vals[row]).collect())
+            .collect::<Vec<Vec<Option<u32>>>>();
+
+        // the (non NaN) values in range, by group key
+        let mut group_values: HashMap<&[Option<u32>], Vec<f64>> = HashMap::new();
+        for (row, value) in values.into_iter().enumerate() {
+            let value = match value {
+                Some(value) if !value.is_nan() => value,
+                _ => continue,
+            };
+            if let Some(range) = partition_predicate.range {
+                if !range.contains_opt(times.and_then(|times| times[row])) {
+                    continue;
+                }
+            }
+
+            group_values
+                .entry(row_keys[row].as_slice())
+                .or_default()
+                .push(value);
+        }
+
+        let aggregates = group_values
+            .into_iter()
+            .map(|(key, mut values)| (key.to_vec(), kind.aggregate(&mut values)))
+            .collect();
+
+        Ok(GroupAggregates {
+            group_tag_columns,
+            row_keys,
+            aggregates,
+        })
+    }
+
+    /// Creates, for each distinct combination of values of the
+    /// `group_columns` tags in the rows that match the predicate, the
+    /// group key and a series set plan (as for
+    /// `grouped_series_set_plan`) producing only that group's rows.
+    /// This matches the group frames of a storage `read_group`
+    /// response, which precede the series of each group.
+    ///
+    /// Groups are ordered by their key. As for `distinct_group_keys`,
+    /// general predicate expressions are not supported and result in
+    /// an error.
+    pub fn grouped_frames(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        partition: &Partition,
+    ) -> Result<GroupedFrames> {
+        let (group_tag_columns, keys) =
+            self.group_key_values(partition_predicate, group_columns, partition)?;
+
+        let groups = keys
+            .into_iter()
+            .map(|key| {
+                let mut group_predicate = partition_predicate.clone();
+                group_predicate.partition_exprs.extend(
+                    group_tag_columns
+                        .iter()
+                        .zip(key.iter())
+                        .map(|(column_name, value)| {
+                            let column = column_name.into_expr();
+                            match value {
+                                Some(value) => Expr::BinaryExpr {
+                                    left: Box::new(column),
+                                    op: Operator::Eq,
+                                    right: Box::new(Expr::Literal(ScalarValue::Utf8(Some(
+                                        value.to_string(),
+                                    )))),
+                                },
+                                None => Expr::IsNull(Box::new(column)),
+                            }
+                        }),
+                );
+
+                let series_set_plan = self.series_set_plan_impl(
+                    &group_predicate,
+                    Some(&group_columns),
+                    &SeriesSetPlanOptions::default(),
+                    partition,
+                )?;
+
+                let key = group_tag_columns
+                    .iter()
+                    .cloned()
+                    .zip(
+                        key.into_iter()
+                            .map(|value| value.map(|v| Arc::new(v.to_string()))),
+                    )
+                    .collect();
+
+                Ok(GroupFrame {
+                    key,
+                    series_set_plan,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(GroupedFrames { groups })
+    }
+
+    /// Returns the distinct combinations of values of the
+    /// `group_columns` tags in the rows that match the predicate's
+    /// timestamp range, as a batch with one nullable Utf8 column per
+    /// group column (in the order of `group_columns`), sorted by value.
+    ///
+    /// `group_columns` are validated as for `grouped_series_set_plan`.
+    /// General predicate expressions are not supported and result in
+    /// an error.
+    pub fn distinct_group_keys(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        partition: &Partition,
+    ) -> Result<RecordBatch> {
+        let (group_tag_columns, keys) =
+            self.group_key_values(partition_predicate, group_columns, partition)?;
+
+        let fields = group_tag_columns
+            .iter()
+            .map(|column_name| ArrowField::new(column_name, ArrowDataType::Utf8, true))
+            .collect();
+        let schema = Arc::new(ArrowSchema::new(fields));
+
+        let columns = (0..group_tag_columns.len())
+            .map(|i| {
+                let mut builder = StringBuilder::new(keys.len());
+                for key in &keys {
+                    match key[i] {
+                        None => builder.append_null(),
+                        Some(value) => builder.append_value(value),
+                    }
+                    .context(ArrowError {})?;
+                }
+                Ok(Arc::new(builder.finish()) as ArrayRef)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(schema, columns).context(ArrowError {})
+    }
+
+    /// Returns the `group_columns` tag columns (as validated by
+    /// `reorder_prefix`) and the distinct combinations of their
+    /// values in the rows that match the predicate's timestamp range.
+    /// See `distinct_group_keys`.
+    fn group_key_values<'a>(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        partition: &'a Partition,
+    ) -> Result<(Vec<Arc<String>>, BTreeSet<Vec<Option<&'a str>>>)> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedGroupKeysPredicate
+        );
+
+        let (tag_columns, _) =
+            self.tag_and_field_column_names(partition_predicate, &FieldSelector::All, partition)?;
+        let tag_columns = self.reorder_group_prefix(group_columns, tag_columns)?;
+        let mut group_tag_columns = tag_columns;
+        group_tag_columns.truncate(group_columns.len());
+
+        let group_tag_values = group_tag_columns
+            .iter()
+            .map(|column_name| self.tag_value_ids(partition, column_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        // without a time column, no row is in a range
+        let times = match partition_predicate.range {
+            Some(_) => self.predicate_times(partition_predicate)?,
+            None => None,
+        };
+
+        let mut keys = BTreeSet::new();
+        for row in 0..self.row_count() {
+            if let Some(range) = partition_predicate.range {
+                if !range.contains_opt(times.and_then(|times| times[row])) {
+                    continue;
+                }
+            }
+
+            let key = group_tag_values
+                .iter()
+                .map(|vals| {
+                    vals[row]
+                        .map(|value_id| {
+                            partition.dictionary.lookup_id(value_id).context(
+                                TagValueIdNotFoundInDictionary {
+                                    value: value_id,
+                                    partition: &partition.key,
+                                },
+                            )
+                        })
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>>>()?;
+            keys.insert(key);
+        }
+
+        Ok((group_tag_columns, keys))
+    }
+
+    /// Creates a plan that produces an output table with rows that
+    /// match the predicate for all fields in the table.
+    ///
+    /// The output looks like (field0, field1, ..., time)
+    ///
+    /// The data is not sorted in any particular order
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Projection (select the field columns needed)
+    ///        Filter(predicate) [optional]
+    ///          InMemoryScan
+    pub fn field_names_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        field_selector: &FieldSelector,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        field_selector.validate()?;
+
+        // TODO avoid materializing all the columns here (ideally
+        // DataFusion can prune them out)
+        let data = self.all_to_arrow(partition)?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        // And build the plan from the bottom up
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        // Filtering
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+
+        // Selection
+        let select_exprs = self
+            .field_and_time_column_names(partition_predicate, field_selector, partition)?
+            .into_iter()
+            .map(|c| {
+                if c.as_str() == self.time_column_name() {
+                    self.time_column_expr()
+                } else {
+                    c.into_expr()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let plan_builder = plan_builder.project(select_exprs).context(BuildingPlan)?;
+
+        // and finally create the plan
+        plan_builder.build().context(BuildingPlan)
+    }
+
+    /// Creates a plan that produces the values of `exprs`, which may
+    /// be arbitrary expressions of the table's columns (such as `temp
+    /// * 1.8 + 32`), for the rows that match the predicate.
+    ///
+    /// Expressions refer to columns by their names in the table
+    /// (including the time column). Errors if an expression refers to
+    /// a column that is not in the table.
+    ///
+    /// The data is not sorted in any particular order
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Projection (exprs)
+    ///        Filter(predicate) [optional]
+    ///          InMemoryScan
+    pub fn select_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        exprs: Vec<Expr>,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        let mut column_names = HashSet::new();
+        for expr in &exprs {
+            expr_to_column_names(expr, &mut column_names).context(BuildingPlan)?;
+        }
+        let mut column_names = column_names.into_iter().collect::<Vec<_>>();
+        column_names.sort();
+
+        for column_name in column_names {
+            let column_id = partition.dictionary.lookup_value(&column_name).ok();
+            ensure!(
+                column_id.map_or(false, |id| self.column_id_to_index.contains_key(&id)),
+                SelectColumnNotFound { column_name }
+            );
+        }
+
+        let data = self.all_to_arrow(partition)?;
+        let schema = data.schema();
+
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema: schema.clone(),
+            projection: None,
+            projected_schema: schema,
+        });
+
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+
+        plan_builder
+            .project(exprs)
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)
+    }
+
+    /// Creates a plan that produces all columns of the table (sorted
+    /// by name) for the rows that match the predicate, ordered by
+    /// `sort_columns`.
+    ///
+    /// Each entry of `sort_columns` is a (column name, ascending)
+    /// pair, and may name any tag, field or the time column. Nulls
+    /// sort first. Errors if any sort column is not in the table.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Order by (sort_columns)
+    ///      Filter(predicate)
+    ///        InMemoryScan
+    pub fn sorted_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        sort_columns: &[(String, bool)],
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        self.sorted_plan_impl(partition_predicate, sort_columns, false, partition)
+    }
+
+    /// Creates the plan of `sorted_plan`. If `row_numbers` is true,
+    /// the output has an additional (last) non null `Int64` column
+    /// named `ROW_NUMBER_COLUMN_NAME` holding the (0 based) index of
+    /// each row in the table, which is its insertion order before any
+    /// filtering or sorting.
+    pub fn sorted_plan_impl(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        sort_columns: &[(String, bool)],
+        row_numbers: bool,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        let sort_exprs = sort_columns
+            .iter()
+            .map(|(column_name, asc)| {
+                let column_id = partition.dictionary.lookup_value(column_name).ok();
+                ensure!(
+                    column_id.map_or(false, |id| self.column_id_to_index.contains_key(&id)),
+                    SortColumnNotFound { column_name }
+                );
+
+                Ok(Expr::Sort {
+                    expr: Box::new(column_name.as_str().into_expr()),
+                    asc: *asc,
+                    nulls_first: true,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // TODO avoid materializing all the columns here (ideally
+        // DataFusion can prune them out)
+        let data = self.all_to_arrow(partition)?;
+
+        // the row numbers are materialized alongside the data, as
+        // the scan is in insertion order
+        let data = if row_numbers {
+            let mut fields = data.schema().fields().clone();
+            fields.push(ArrowField::new(
+                ROW_NUMBER_COLUMN_NAME,
+                ArrowDataType::Int64,
+                false,
+            ));
+            let mut columns = data.columns().to_vec();
+            columns.push(Arc::new(Int64Array::from(
+                (0..data.num_rows() as i64).collect::<Vec<_>>(),
+            )));
+
+            RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+                .context(ArrowError {})?
+        } else {
+            data
+        };
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        // And build the plan from the bottom up
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        // Filtering
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+
+        // Order by
+        let plan_builder = if sort_exprs.is_empty() {
+            plan_builder
+        } else {
+            plan_builder.sort(sort_exprs).context(BuildingPlan)?
+        };
+
+        plan_builder.build().context(BuildingPlan)
+    }
+
+    /// Creates a plan that produces all columns of the table (sorted
+    /// by name) for the `n` rows with the largest (or, if `ascending`,
+    /// the smallest) values of the numeric field `field` in each group
+    /// of rows with the same values of the tags `group_columns`, such
+    /// as the hottest city of each state. Null and `NaN` values are
+    /// never selected, and ties are broken by insertion order. The
+    /// output is ordered by the group columns and then `field`.
+    ///
+    /// DataFusion has no window functions (such as `row_number()`),
+    /// so the rows are selected while scanning the table and the plan
+    /// filters on a column marking them. General predicate
+    /// expressions are not supported and result in an error.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Order by (group_columns, field)
+    ///      Projection (all columns)
+    ///        Filter(selected rows)
+    ///          InMemoryScan
+    pub fn top_n_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        field: &str,
+        n: usize,
+        ascending: bool,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedTopNPredicate
+        );
+
+        let field_id = partition
+            .dictionary
+            .lookup_value(field)
+            .ok()
+            .filter(|&column_id| !self.is_time_column(partition_predicate, column_id))
+            .filter(|&column_id| {
+                matches!(
+                    self.column(column_id),
+                    Ok(Column::F64(_, _)) | Ok(Column::I64(_, _))
+                )
+            })
+            .context(InvalidTopNField { column_name: field })?;
+        let values = self.numeric_field_as_f64(field_id)?;
+
+        let (tag_columns, _) =
+            self.tag_and_field_column_names(partition_predicate, &FieldSelector::All, partition)?;
+        let mut group_tag_columns = self.reorder_group_prefix(group_columns, tag_columns)?;
+        group_tag_columns.truncate(group_columns.len());
+
+        let group_tag_values = group_tag_columns
+            .iter()
+            .map(|column_name| self.tag_value_ids(partition, column_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        // without a time column, no row is in a range
+        let times = match partition_predicate.range {
+            Some(_) => self.predicate_times(partition_predicate)?,
+            None => None,
+        };
+
+        // (value, row) of the candidate rows, by group key
+        let mut groups: HashMap<Vec<Option<u32>>, Vec<(f64, usize)>> = HashMap::new();
+        for (row, value) in values.into_iter().enumerate() {
+            let value = match value {
+                Some(value) if !value.is_nan() => value,
+                _ => continue,
+            };
+            if let Some(range) = partition_predicate.range {
+                if !range.contains_opt(times.and_then(|times| times[row])) {
+                    continue;
+                }
+            }
+
+            let key = group_tag_values.iter().map(|vals| vals[row]).collect();
+            groups.entry(key).or_default().push((value, row));
+        }
+
+        let mut selected = vec![false; self.row_count()];
+        for rows in groups.values_mut() {
+            rows.sort_by(|(a, a_row), (b, b_row)| {
+                let order = a.partial_cmp(b).expect("NaNs were skipped");
+                let order = if ascending { order } else { order.reverse() };
+                order.then(a_row.cmp(b_row))
+            });
+            for &(_, row) in rows.iter().take(n) {
+                selected[row] = true;
+            }
+        }
+
+        // TODO avoid materializing all the columns here (ideally
+        // DataFusion can prune them out)
+        let data = self.all_to_arrow(partition)?;
+
+        let output_exprs = data
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str().into_expr())
+            .collect::<Vec<_>>();
+
+        let mut fields = data.schema().fields().clone();
+        fields.push(ArrowField::new(
+            TOP_N_SELECTED_COLUMN_NAME,
+            ArrowDataType::Boolean,
+            false,
+        ));
+        let mut columns = data.columns().to_vec();
+        columns.push(Arc::new(BooleanArray::from(selected)));
+        let data = RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+            .context(ArrowError {})?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        // And build the plan from the bottom up
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        let mut sort_exprs = group_tag_columns
+            .iter()
+            .map(|c| c.into_sort_expr())
+            .collect::<Vec<_>>();
+        sort_exprs.push(Expr::Sort {
+            expr: Box::new(field.into_expr()),
+            asc: ascending,
+            nulls_first: true,
+        });
+
+        plan_builder
+            .filter(TOP_N_SELECTED_COLUMN_NAME.into_expr())
+            .context(BuildingPlan)?
+            .project(output_exprs)
+            .context(BuildingPlan)?
+            .sort(sort_exprs)
+            .context(BuildingPlan)?
+            .build()
+            .context(BuildingPlan)
+    }
+
+    /// Creates a plan that produces, for each row that matches the
+    /// predicate, its tags and time and the time elapsed since the
+    /// previous row of the same series, as an arrow
+    /// `Duration(Nanosecond)` column named `TIME_DELTA_COLUMN_NAME`.
+    /// The delta of the first row of each series is null.
+    ///
+    /// The output looks like:
+    /// (group_tag_column1, ... tag_col1, tag_col2, ... timestamp, delta)
+    ///
+    /// and is sorted as for `grouped_series_set_plan`. Rows without a
+    /// time are not part of any series, and are not output.
+    ///
+    /// The version of DataFusion we depend on has no window functions
+    /// (such as `lag`), so the deltas are computed (and the rows
+    /// sorted) before the plan is created. As for `top_n_plan`, only
+    /// the predicate's timestamp range is supported and general
+    /// predicate expressions result in an error.
+    pub fn time_delta_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedTimeDeltaPredicate
+        );
+
+        let (tag_columns, _) =
+            self.tag_and_field_column_names(partition_predicate, &FieldSelector::All, partition)?;
+        let tag_columns = self.reorder_group_prefix(group_columns, tag_columns)?;
+
+        // the (decoded) values of each tag column
+        let tag_values = tag_columns
+            .iter()
+            .map(|column_name| self.decoded_tag_values(partition, column_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        // without a time column, no row has a time, so there are no
+        // rows to output
+        let times = self
+            .predicate_times(partition_predicate)?
+            .unwrap_or_default();
+
+        // (row, time) of the rows in range, sorted by series and then
+        // time. Null tags sort first, as in the series set plans
+        let mut rows = times
+            .iter()
+            .enumerate()
+            .filter_map(|(row, &time)| time.map(|time| (row, time)))
+            .filter(|&(_, time)| {
+                partition_predicate
+                    .range
+                    .map_or(true, |range| range.contains(time))
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|&(row, time)| {
+            let series = tag_values.iter().map(|vals| vals[row]).collect::<Vec<_>>();
+            (series, time)
+        });
+
+        let same_series = |a: usize, b: usize| tag_values.iter().all(|vals| vals[a] == vals[b]);
+        let deltas = rows
+            .iter()
+            .enumerate()
+            .map(
+                |(i, &(row, time))| match i.checked_sub(1).map(|i| rows[i]) {
+                    Some((previous_row, previous_time)) if same_series(previous_row, row) => {
+                        Some(time - previous_time)
+                    }
+                    _ => None,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let mut fields = Vec::with_capacity(tag_columns.len() + 2);
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(tag_columns.len() + 2);
+        for (column_name, vals) in tag_columns.iter().zip(&tag_values) {
+            fields.push(ArrowField::new(column_name, ArrowDataType::Utf8, true));
+            columns.push(Arc::new(StringArray::from(
+                rows.iter().map(|&(row, _)| vals[row]).collect::<Vec<_>>(),
+            )));
+        }
+        fields.push(ArrowField::new(
+            TIME_COLUMN_NAME,
+            ArrowDataType::Int64,
+            false,
+        ));
+        columns.push(Arc::new(Int64Array::from(
+            rows.iter().map(|&(_, time)| time).collect::<Vec<_>>(),
+        )));
+        fields.push(ArrowField::new(
+            TIME_DELTA_COLUMN_NAME,
+            ArrowDataType::Duration(TimeUnit::Nanosecond),
+            true,
+        ));
+        columns.push(Arc::new(DurationNanosecondArray::from(deltas)));
+
+        let data = RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+            .context(ArrowError {})?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        })
+        .build()
+        .context(BuildingPlan)
+    }
+
+    /// Creates a plan that emits one row for every interval of
+    /// `interval_nanos` in the predicate's time range (or, without a
+    /// range or on its unbounded sides, between the first and last
+    /// matching rows) for each group
+    /// of `group_columns`. The time of each row is the start of its
+    /// interval, and each field has its last non null value within the
+    /// interval; intervals without a value are filled as per `fill`.
+    ///
+    /// The output has the group columns, then the fields, then the
+    /// time column, and is ordered by group and then time. Errors if
+    /// there would be more than `MAX_GAP_FILL_INTERVALS` intervals.
+    pub fn gap_fill_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        interval_nanos: i64,
+        fill: FillPolicy,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedGapFillPredicate
+        );
+        ensure!(
+            interval_nanos > 0,
+            InvalidGapFillInterval { interval_nanos }
+        );
+        ensure!(fill != FillPolicy::Linear, UnsupportedFillPolicy { fill });
+
+        let (tag_columns, field_columns) =
+            self.tag_and_field_column_names(partition_predicate, &FieldSelector::All, partition)?;
+        let tag_columns = self.reorder_group_prefix(group_columns, tag_columns)?;
+        let group_columns = &tag_columns[..group_columns.len()];
+
+        // the (decoded) values of each group column
+        let group_values = group_columns
+            .iter()
+            .map(|column_name| self.decoded_tag_values(partition, column_name))
+            .collect::<Result<Vec<_>>>()?;
+
+        let field_names = field_columns
+            .iter()
+            .map(|column_name| column_name.as_str())
+            .collect::<Vec<_>>();
+        let fields_with_index = self.column_names_with_index(partition, &field_names)?;
+        for &(column_name, column_index) in &fields_with_index {
+            if let Column::Decimal { .. } = self.columns[column_index] {
+                return UnsupportedDecimalOperation {
+                    column_name,
+                    operation: "gap filling",
+                }
+                .fail();
+            }
+        }
+
+        // without a time column, no row has a time, so there are no
+        // groups to fill
+        let times = self
+            .predicate_times(partition_predicate)?
+            .unwrap_or_default();
+        // (saturating, as the interval of the earliest times starts
+        // before `i64::MIN`)
+        let interval_start = |time: i64| time.saturating_sub(time.rem_euclid(interval_nanos));
+
+        // (row, time) of the rows in range, sorted by group and then
+        // time. Null tags sort first, as in the series set plans
+        let mut rows = times
+            .iter()
+            .enumerate()
+            .filter_map(|(row, &time)| time.map(|time| (row, time)))
+            .filter(|&(_, time)| {
+                partition_predicate
+                    .range
+                    .map_or(true, |range| range.contains(time))
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|&(row, time)| {
+            let group = group_values
+                .iter()
+                .map(|vals| vals[row])
+                .collect::<Vec<_>>();
+            (group, time)
+        });
+
+        // an open ended side of the range (such as from `time >= 100`)
+        // is clamped to the times of the matching rows
+        let min_time = rows.iter().map(|&(_, time)| time).min();
+        let max_time = rows.iter().map(|&(_, time)| time).max();
+        let (first_time, last_time) = match partition_predicate.range {
+            Some(range) => (
+                if range.start == i64::MIN {
+                    min_time
+                } else {
+                    Some(range.start)
+                },
+                if range.end == i64::MAX {
+                    max_time
+                } else {
+                    Some(range.end.saturating_sub(1))
+                },
+            ),
+            None => (min_time, max_time),
+        };
+        let first_interval = first_time.map_or(0, interval_start);
+        let last_interval = last_time.map_or(0, interval_start);
+
+        // every group has a row for each interval, so a wide range
+        // with a small interval could produce any number of rows
+        let num_intervals = if rows.is_empty() || first_interval > last_interval {
+            0
+        } else {
+            (i128::from(last_interval) - i128::from(first_interval)) / i128::from(interval_nanos)
+                + 1
+        };
+        ensure!(
+            num_intervals <= i128::from(MAX_GAP_FILL_INTERVALS),
+            TooManyGapFillIntervals {
+                num_intervals: u64::try_from(num_intervals).unwrap_or(u64::MAX),
+            }
+        );
+
+        // for each output row, a row of its group and its interval, and
+        // for each field, the row holding its value (if any)
+        let mut output_rows = Vec::new();
+        let mut field_rows = vec![Vec::new(); fields_with_index.len()];
+
+        let same_group = |a: usize, b: usize| group_values.iter().all(|vals| vals[a] == vals[b]);
+        let mut group_start = 0;
+        while group_start < rows.len() {
+            let group_row = rows[group_start].0;
+            let group_end = rows[group_start..]
+                .iter()
+                .position(|&(row, _)| !same_group(group_row, row))
+                .map_or(rows.len(), |len| group_start + len);
+
+            // the last row with a value of each field, by interval
+            let mut interval_rows = BTreeMap::new();
+            for &(row, time) in &rows[group_start..group_end] {
+                let value_rows = interval_rows
+                    .entry(interval_start(time))
+                    .or_insert_with(|| vec![None; fields_with_index.len()]);
+                for (value_row, &(_, column_index)) in value_rows.iter_mut().zip(&fields_with_index)
+                {
+                    if !self.columns[column_index].is_null(row) {
+                        *value_row = Some(row);
+                    }
+                }
+            }
+
+            let mut previous_rows = vec![None; fields_with_index.len()];
+            let mut interval = Some(first_interval).filter(|&time| time <= last_interval);
+            while let Some(time) = interval {
+                let value_rows = interval_rows.get(&time);
+                for (field, previous_row) in previous_rows.iter_mut().enumerate() {
+                    let value_row = match value_rows.and_then(|value_rows| value_rows[field]) {
+                        None if fill == FillPolicy::Previous => *previous_row,
+                        value_row => value_row,
+                    };
+                    *previous_row = value_row;
+                    field_rows[field].push(value_row);
+                }
+                output_rows.push((group_row, time));
+
+                interval = time
+                    .checked_add(interval_nanos)
+                    .filter(|&time| time <= last_interval);
+            }
+
+            group_start = group_end;
+        }
+
+        let field_schema =
+            self.arrow_schema(partition, &fields_with_index, &ToArrowOptions::default());
+
+        let num_columns = group_columns.len() + fields_with_index.len() + 1;
+        let mut fields = Vec::with_capacity(num_columns);
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(num_columns);
+        for (column_name, vals) in group_columns.iter().zip(&group_values) {
+            fields.push(ArrowField::new(column_name, ArrowDataType::Utf8, true));
+            columns.push(Arc::new(StringArray::from(
+                output_rows
+                    .iter()
+                    .map(|&(row, _)| vals[row])
+                    .collect::<Vec<_>>(),
+            )));
+        }
+        for ((field, &(_, column_index)), value_rows) in field_schema
+            .fields()
+            .iter()
+            .zip(&fields_with_index)
+            .zip(&field_rows)
+        {
+            // gaps are null, even in fields without null values
+            fields.push(ArrowField::new(
+                field.name(),
+                field.data_type().clone(),
+                true,
+            ));
+            columns.push(self.column_rows_to_arrow(
+                partition,
+                column_index,
+                field.data_type(),
+                RowSelection::OptionalRows(value_rows),
+            )?);
+        }
+        fields.push(ArrowField::new(
+            TIME_COLUMN_NAME,
+            ArrowDataType::Int64,
+            false,
+        ));
+        columns.push(Arc::new(Int64Array::from(
+            output_rows
+                .iter()
+                .map(|&(_, time)| time)
+                .collect::<Vec<_>>(),
+        )));
+
+        let data = RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+            .context(ArrowError {})?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        })
+        .build()
+        .context(BuildingPlan)
+    }
+
+    /// Creates a plan that counts the non null values of the numeric
+    /// field `field` that match the predicate, in buckets of
+    /// `bucket_width`. The output has a `bucket` column, the index
+    /// `floor(field / bucket_width)` of each non empty bucket, and a
+    /// `count` column, and is ordered by `bucket`.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Order by (bucket)
+    ///      Aggregate (group: floor(field / bucket_width) AS bucket,
+    ///                 aggr: COUNT(field) AS count)
+    ///        Filter(predicate, field IS NOT NULL)
+    ///          InMemoryScan
+    pub fn histogram_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        field: &str,
+        bucket_width: f64,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        ensure!(
+            bucket_width > 0.0 && bucket_width.is_finite(),
+            InvalidBucketWidth { bucket_width }
+        );
+
+        let column = partition
+            .dictionary
+            .lookup_value(field)
+            .ok()
+            .filter(|&column_id| !self.is_time_column(partition_predicate, column_id))
+            .and_then(|column_id| self.column_id_to_index.get(&column_id))
+            .map(|&column_index| &self.columns[column_index]);
+        ensure!(
+            matches!(column, Some(Column::F64(_, _)) | Some(Column::I64(_, _))),
+            InvalidHistogramField { column_name: field }
+        );
+
+        // TODO avoid materializing all the columns here (ideally
+        // DataFusion can prune them out)
+        let data = self.all_to_arrow(partition)?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        // And build the plan from the bottom up
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        // Filtering
+        let plan_builder = self.add_datafusion_predicate(plan_builder, partition_predicate)?;
+        let plan_builder = plan_builder
+            .filter(Expr::IsNotNull(Box::new(field.into_expr())))
+            .context(BuildingPlan)?;
+
+        // Bucketing
+        let bucket_expr = logical_plan::floor(Expr::BinaryExpr {
+            left: Box::new(Expr::Cast {
+                expr: Box::new(field.into_expr()),
+                data_type: ArrowDataType::Float64,
+            }),
+            op: Operator::Divide,
+            right: Box::new(Expr::Literal(ScalarValue::Float64(Some(bucket_width)))),
+        })
+        .alias(HISTOGRAM_BUCKET_COLUMN_NAME);
+        let count_expr = logical_plan::count(field.into_expr()).alias(HISTOGRAM_COUNT_COLUMN_NAME);
+
+        let plan_builder = plan_builder
+            .aggregate(vec![bucket_expr], vec![count_expr])
+            .context(BuildingPlan)?
+            .sort(vec![HISTOGRAM_BUCKET_COLUMN_NAME.into_sort_expr()])
+            .context(BuildingPlan)?;
+
+        // and finally create the plan
+        plan_builder.build().context(BuildingPlan)
+    }
+
+    /// Creates a plan that computes the `kind` aggregate of the field
+    /// `field` over the rows that match the predicate, for each
+    /// distinct combination of values of the `group_columns` tags.
+    /// The output has the group columns and a Float64 column named
+    /// `field` with the aggregate, and is ordered by the group
+    /// columns. Null and NaN values of the field are ignored, and
+    /// groups without other values are not output.
+    ///
+    /// DataFusion has no percentile aggregate, so the aggregates are
+    /// computed before the plan is created. As for `top_n_plan`,
+    /// general predicate expressions are not supported and result in
+    /// an error.
+    ///
+    /// The created plan looks like:
+    ///
+    ///    Order by (group_columns)
+    ///      InMemoryScan
+    pub fn aggregate_plan(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        group_columns: &[String],
+        kind: AggregateKind,
+        field: &str,
+        partition: &Partition,
+    ) -> Result<LogicalPlan> {
+        ensure!(
+            partition_predicate.partition_exprs.is_empty(),
+            UnsupportedAggregatePredicate
+        );
+
+        let field_id = partition
+            .dictionary
+            .lookup_value(field)
+            .ok()
+            .filter(|&column_id| !self.is_time_column(partition_predicate, column_id))
+            .filter(|&column_id| !matches!(self.column(column_id), Err(_) | Ok(Column::Tag(_, _))))
+            .context(InvalidAggregateField {
+                column_name: field,
+                reason: "not a field of the table",
+            })?;
+        let values = self
+            .aggregate_input(field_id, kind)?
+            .context(InvalidAggregateField {
+                column_name: field,
+                reason: "not a numeric field",
+            })?;
+
+        let GroupAggregates {
+            group_tag_columns,
+            aggregates,
+            ..
+        } = self.group_aggregates(partition_predicate, group_columns, kind, values, partition)?;
+        let (keys, aggregates): (Vec<_>, Vec<_>) = aggregates.into_iter().unzip();
+
+        let mut fields = Vec::with_capacity(group_tag_columns.len() + 1);
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(group_tag_columns.len() + 1);
+        for (index, column_name) in group_tag_columns.iter().enumerate() {
+            let values = keys
+                .iter()
+                .map(|key| {
+                    key[index]
+                        .map(|value_id| {
+                            partition.dictionary.lookup_id(value_id).context(
+                                TagValueIdNotFoundInDictionary {
+                                    value: value_id,
+                                    partition: &partition.key,
+                                },
+                            )
+                        })
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            fields.push(ArrowField::new(column_name, ArrowDataType::Utf8, true));
+            columns.push(Arc::new(StringArray::from(values)));
+        }
+        fields.push(ArrowField::new(field, ArrowDataType::Float64, false));
+        columns.push(Arc::new(Float64Array::from(aggregates)));
+
+        let data = RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+            .context(ArrowError {})?;
+
+        let schema = data.schema();
+
+        let projection = None;
+        let projected_schema = schema.clone();
+
+        // And build the plan from the bottom up
+        let plan_builder = LogicalPlanBuilder::from(&LogicalPlan::InMemoryScan {
+            data: vec![vec![data]],
+            schema,
+            projection,
+            projected_schema,
+        });
+
+        let plan_builder = if group_tag_columns.is_empty() {
+            plan_builder
+        } else {
+            let sort_exprs = group_tag_columns
+                .iter()
+                .map(|c| c.into_sort_expr())
+                .collect::<Vec<_>>();
+            plan_builder.sort(sort_exprs).context(BuildingPlan)?
+        };
+
+        plan_builder.build().context(BuildingPlan)
+    }
+
+    // Returns (tag_columns, field_columns) vectors with the names of
+    // all tag and field columns, respectively. The vectors are sorted
+    // by name.
+    fn tag_and_field_column_names(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        field_selector: &FieldSelector,
+        partition: &Partition,
+    ) -> Result<(ArcStringVec, ArcStringVec)> {
+        let mut tag_columns = Vec::with_capacity(self.column_id_to_index.len());
+        let mut field_columns = Vec::with_capacity(self.column_id_to_index.len());
+
+        for (&column_id, &column_index) in &self.column_id_to_index {
+            let column_name = partition.dictionary.lookup_id(column_id).context(
+                ColumnIdNotFoundInDictionary {
+                    column_id,
+                    partition: &partition.key,
+                },
+            )?;
+
+            // a column named TIME_COLUMN_NAME is not a field, even if
+            // this table's time column has a different name
+            if column_name != TIME_COLUMN_NAME && column_name != self.time_column_name() {
+                let column = &self.columns[column_index];
+
+                match column {
+                    Column::Tag(_, _) => tag_columns.push(Arc::new(column_name.to_string())),
+                    _ => {
+                        if partition_predicate.should_include_field(column_id)
+                            && field_selector.selects(column_name, column)
+                        {
+                            field_columns.push(Arc::new(column_name.to_string()))
+                        }
+                    }
+                }
+            }
+        }
+
+        // tag columns are always sorted by name (aka sorted by tag
+        // key) in the output schema, so ensure the columns are sorted
+        // (the select exprs)
+        tag_columns.sort();
+
+        // Sort the field columns too so that the output always comes
+        // out in a predictable order
+        field_columns.sort();
+
+        Ok((tag_columns, field_columns))
+    }
+
+    // Returns (field_columns and time) in sorted order
+    fn field_and_time_column_names(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        field_selector: &FieldSelector,
+        partition: &Partition,
+    ) -> Result<ArcStringVec> {
+        let mut field_columns = Vec::with_capacity(self.column_id_to_index.len());
+
+        for (&column_id, &column_index) in &self.column_id_to_index {
+            let column = &self.columns[column_index];
+            if let Column::Tag(_, _) = column {
+                continue; // skip tags
+            }
+
+            let column_name = partition.dictionary.lookup_id(column_id).context(
+                ColumnIdNotFoundInDictionary {
+                    column_id,
+                    partition: &partition.key,
+                },
+            )?;
+
+            if self.is_time_column(partition_predicate, column_id)
+                || (column_name != TIME_COLUMN_NAME
+                    && partition_predicate.should_include_field(column_id)
+                    && field_selector.selects(column_name, column))
+            {
+                field_columns.push(Arc::new(column_name.to_string()));
+            }
+        }
+
+        // Sort the field columns too so that the output always comes
+        // out in a predictable order
+        field_columns.sort();
+
+        Ok(field_columns)
+    }
+
+    /// Converts this table to an arrow record batch.
+    pub fn to_arrow(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+    ) -> Result<RecordBatch> {
+        // if requested columns is empty, retrieve all columns in the table
+        if requested_columns.is_empty() {
+            self.all_to_arrow(partition)
+        } else {
+            let columns_with_index = self.column_names_with_index(partition, requested_columns)?;
+
+            self.to_arrow_impl(partition, &columns_with_index)
+        }
+    }
+
+    fn column_names_with_index<'a>(
+        &self,
+        partition: &Partition,
+        columns: &[&'a str],
+    ) -> Result<Vec<(&'a str, usize)>> {
+        columns
+            .iter()
+            .map(|&column_name| {
+                let column_id = partition.dictionary.lookup_value(column_name).context(
+                    ColumnNameNotFoundInDictionary {
+                        column_name,
+                        partition: &partition.key,
+                    },
+                )?;
+
+                let column_index =
+                    *self
+                        .column_id_to_index
+                        .get(&column_id)
+                        .context(InternalNoColumnInIndex {
+                            column_name,
+                            column_id,
+                        })?;
+
+                Ok((column_name, column_index))
+            })
+            .collect()
+    }
+
+    /// Converts this table to an arrow record batch as `to_arrow`, but
+    /// stops early with a `Cancelled` error once `cancel` is set (for
+    /// example by another thread, when the query is cancelled). The
+    /// flag is checked before each column and every
+    /// `CANCEL_CHECK_ROWS` rows while converting a column.
+    pub fn to_arrow_cancellable(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+        cancel: &AtomicBool,
+    ) -> Result<RecordBatch> {
+        let columns_with_index = if requested_columns.is_empty() {
+            self.all_columns_with_index(partition)?
+        } else {
+            self.column_names_with_index(partition, requested_columns)?
+        };
+        let schema =
+            Arc::new(self.arrow_schema(partition, &columns_with_index, &ToArrowOptions::default()));
+
+        let columns = columns_with_index
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, column_index))| {
+                ensure!(!cancel.load(Ordering::Relaxed), Cancelled);
+                let data_type = schema.field(i).data_type();
+                self.column_rows_to_arrow(
+                    partition,
+                    column_index,
+                    data_type,
+                    RowSelection::Cancellable(0..self.row_count(), cancel),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // a cancelled conversion stops before the end of the column
+        ensure!(!cancel.load(Ordering::Relaxed), Cancelled);
+
+        RecordBatch::try_new(schema, columns).context(ArrowError {})
+    }
+
+    /// Converts the rows `[start, end)` of this table to an arrow
+    /// record batch, converting (and decoding the tag values of) only
+    /// those rows. As for `to_arrow`, all columns are converted if
+    /// `requested_columns` is empty.
+    ///
+    /// `end` is clamped to the number of rows in the table, and a
+    /// `start` past the (clamped) end produces an empty batch. Errors
+    /// if `start` is greater than `end`.
+    pub fn to_arrow_range(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+        start: usize,
+        end: usize,
+    ) -> Result<RecordBatch> {
+        ensure!(start <= end, InvalidRowRange { start, end });
+        let end = end.min(self.row_count());
+        let start = start.min(end);
+
+        let columns_with_index = if requested_columns.is_empty() {
+            self.all_columns_with_index(partition)?
+        } else {
+            self.column_names_with_index(partition, requested_columns)?
+        };
+        let schema =
+            Arc::new(self.arrow_schema(partition, &columns_with_index, &ToArrowOptions::default()));
+
+        let columns = columns_with_index
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, column_index))| {
+                let data_type = schema.field(i).data_type();
+                self.column_rows_to_arrow(
+                    partition,
+                    column_index,
+                    data_type,
+                    RowSelection::Range(start..end),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(schema, columns).context(ArrowError {})
+    }
+
+    /// Converts the rows of this table that match `partition_predicate`
+    /// to an arrow record batch, without DataFusion. As for
+    /// `to_arrow`, all columns are converted if `requested_columns` is
+    /// empty. Only the requested columns, and only the matching rows
+    /// of those, are converted.
+    ///
+    /// Only predicates that `predicate_bitmap` can evaluate (tag
+    /// equality comparisons and a timestamp range) are supported;
+    /// others result in an error. The rows are in the same order as in
+    /// the table.
+    pub fn to_arrow_filtered(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+        partition_predicate: &PartitionPredicate,
+    ) -> Result<RecordBatch> {
+        let bitmap = self
+            .predicate_bitmap(partition_predicate, partition)?
+            .context(UnsupportedFilteredToArrowPredicate)?;
+        let rows = (0..bitmap.len())
+            .filter(|&row| bitmap.value(row))
+            .collect::<Vec<_>>();
+
+        self.rows_to_arrow(partition, requested_columns, &rows)
+    }
+
+    /// Returns the indexes of the rows of this table whose index mod
+    /// `modulus` is `residue`, a cheap deterministic sample (for
+    /// example every 10th row, with a modulus of 10). Errors unless
+    /// `residue < modulus`.
+    pub fn sample(&self, modulus: usize, residue: usize) -> Result<Vec<usize>> {
+        ensure!(residue < modulus, InvalidSample { modulus, residue });
+
+        Ok((residue..self.row_count()).step_by(modulus).collect())
+    }
+
+    /// Converts the rows of this table selected by `sample` to an
+    /// arrow record batch. As for `to_arrow`, all columns are
+    /// converted if `requested_columns` is empty. Only the sampled
+    /// rows are converted.
+    pub fn to_arrow_sampled(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+        modulus: usize,
+        residue: usize,
+    ) -> Result<RecordBatch> {
+        let rows = self.sample(modulus, residue)?;
+
+        self.rows_to_arrow(partition, requested_columns, &rows)
+    }
+
+    /// Converts the listed `rows` (in order) of the requested columns,
+    /// or all columns if `requested_columns` is empty, to an arrow
+    /// record batch
+    fn rows_to_arrow(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+        rows: &[usize],
+    ) -> Result<RecordBatch> {
+        let columns_with_index = if requested_columns.is_empty() {
+            self.all_columns_with_index(partition)?
+        } else {
+            self.column_names_with_index(partition, requested_columns)?
+        };
+        let schema =
+            Arc::new(self.arrow_schema(partition, &columns_with_index, &ToArrowOptions::default()));
+
+        let columns = columns_with_index
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, column_index))| {
+                let data_type = schema.field(i).data_type();
+                let rows = RowSelection::Rows(rows);
+                self.column_rows_to_arrow(partition, column_index, data_type, rows)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(schema, columns).context(ArrowError {})
+    }
+
+    /// Converts all columns of this table to an arrow `StructArray`
+    /// with one child array per column, so that each element is a
+    /// full row. The struct's fields are those of `arrow_schema_ref`.
+    pub fn to_struct_array(&self, partition: &Partition) -> Result<StructArray> {
+        let batch = self.all_to_arrow(partition)?;
+
+        let children = batch
+            .schema()
+            .fields()
+            .iter()
+            .cloned()
+            .zip(batch.columns().iter().cloned())
+            .collect::<Vec<_>>();
+
+        Ok(StructArray::from(children))
+    }
+
+    /// Convert all columns to an arrow record batch
+    pub fn all_to_arrow(&self, partition: &Partition) -> Result<RecordBatch> {
+        let requested_columns_with_index = self.all_columns_with_index(partition)?;
+        let schema = self.arrow_schema_ref(partition)?;
+
+        self.to_arrow_with_schema(partition, schema, &requested_columns_with_index)
+    }
+
+    /// Converts all rows to a batch of `schema`, whose fields must
+    /// have the types `all_to_arrow` uses for the columns of this
+    /// table they name, such as a schema merged from several tables.
+    /// The field named `TIME_COLUMN_NAME` holds this table's time
+    /// column (whatever its name), and the values of fields naming no
+    /// column of this table are null.
+    pub fn to_arrow_coerced(
+        &self,
+        partition: &Partition,
+        schema: ArrowSchemaRef,
+    ) -> Result<RecordBatch> {
+        let num_rows = self.row_count();
+
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let column_name = field.name().as_str();
+                let column_name = if column_name == TIME_COLUMN_NAME {
+                    self.time_column_name()
+                } else if column_name == self.time_column_name() {
+                    // this table's time column is output as TIME_COLUMN_NAME
+                    return null_array(field.data_type(), num_rows);
+                } else {
+                    column_name
+                };
+
+                let column_index = partition
+                    .dictionary
+                    .lookup_value(column_name)
+                    .ok()
+                    .and_then(|column_id| self.column_id_to_index.get(&column_id));
+                match column_index {
+                    Some(&column_index) => {
+                        self.column_to_arrow(partition, column_index, field.data_type())
+                    }
+                    None => null_array(field.data_type(), num_rows),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(schema, columns).context(ArrowError {})
+    }
+
+    /// Writes the rows of this table matching `partition_predicate`
+    /// (all columns, as converted by `all_to_arrow`) to `writer` as a
+    /// parquet file.
+    ///
+    /// Tag columns are dictionary encoded in the parquet file, as tag
+    /// values typically repeat, while other columns are not. The
+    /// version of the arrow parquet writer we depend on can not write
+    /// arrow dictionary arrays, so tags are still converted to (and
+    /// read back as) `Utf8`.
+    ///
+    /// Only predicates that `predicate_bitmap` can evaluate (tag
+    /// equality comparisons and a timestamp range) are supported;
+    /// others result in an error, without writing anything.
+    pub fn write_parquet<W: ParquetWriter + Send + 'static>(
+        &self,
+        partition: &Partition,
+        partition_predicate: &PartitionPredicate,
+        writer: W,
+        props: ParquetProps,
+    ) -> Result<()> {
+        let bitmap = self
+            .predicate_bitmap(partition_predicate, partition)?
+            .context(UnsupportedParquetPredicate)?;
+
+        let batch = self.all_to_arrow(partition)?;
+        let batch = arrow::compute::filter_record_batch(&batch, &bitmap).context(ArrowError {})?;
+
+        let mut builder = WriterProperties::builder()
+            .set_compression(props.compression)
+            .set_max_row_group_size(props.max_row_group_size)
+            .set_dictionary_enabled(false);
+        for (column_name, column_index) in self.all_columns_with_index(partition)? {
+            if let Column::Tag(_, _) = self.columns[column_index] {
+                builder =
+                    builder.set_column_dictionary_enabled(ColumnPath::from(column_name), true);
+            }
+        }
+
+        let mut writer = ArrowWriter::try_new(writer, batch.schema(), Some(builder.build()))
+            .context(ParquetWriteError)?;
+        writer.write(&batch).context(ParquetWriteError)?;
+        writer.close().context(ParquetWriteError)?;
+
+        Ok(())
+    }
+
+    /// Converts all columns to arrow (as `all_to_arrow`), except that
+    /// the tag columns are replaced by a single (first) non null Utf8
+    /// column named `SERIALIZED_TAGS_COLUMN_NAME`. It holds the tags
+    /// of each row as `name=value` pairs, sorted by name and joined by
+    /// `separator`, such as `city=LA,state=CA`. Null tags are omitted.
+    pub fn to_arrow_with_serialized_tags(
+        &self,
+        partition: &Partition,
+        separator: &str,
+    ) -> Result<RecordBatch> {
+        let all_columns_with_index = self.all_columns_with_index(partition)?;
+        let all_schema = self.arrow_schema_ref(partition)?;
+
+        let (tag_columns, other_columns): (Vec<_>, Vec<_>) = all_columns_with_index
+            .into_iter()
+            .enumerate()
+            .partition(|(_, (_, column_index))| {
+                matches!(self.columns[*column_index], Column::Tag(_, _))
+            });
+
+        let mut builder = StringBuilder::new(self.row_count());
+        let mut tags = String::new();
+        for row in 0..self.row_count() {
+            tags.clear();
+            for (_, (column_name, column_index)) in &tag_columns {
+                if let Column::Tag(vals, _) = &self.columns[*column_index] {
+                    if let Some(value_id) = vals[row] {
+                        let tag_value = partition.dictionary.lookup_id(value_id).context(
+                            TagValueIdNotFoundInDictionary {
+                                value: value_id,
+                                partition: &partition.key,
+                            },
+                        )?;
+                        if !tags.is_empty() {
+                            tags.push_str(separator);
+                        }
+                        tags.push_str(column_name);
+                        tags.push('=');
+                        tags.push_str(tag_value);
+                    }
+                }
+            }
+            builder.append_value(&tags).context(ArrowError {})?;
+        }
+
+        let mut fields = vec![ArrowField::new(
+            SERIALIZED_TAGS_COLUMN_NAME,
+            ArrowDataType::Utf8,
+            false,
+        )];
+        let mut columns = vec![Arc::new(builder.finish()) as ArrayRef];
+        for (i, (_, column_index)) in other_columns {
+            let field = all_schema.field(i);
+            columns.push(self.column_to_arrow(partition, column_index, field.data_type())?);
+            fields.push(field.clone());
+        }
+
+        RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns).context(ArrowError {})
+    }
+
+    /// Returns a stream that converts all columns to arrow (as
+    /// `all_to_arrow`) in batches of at most `batch_size` rows.
+    ///
+    /// Each batch is only converted when the stream is polled for it,
+    /// on a blocking task (see `tokio::task::spawn_blocking`), so the
+    /// stream must be polled within a tokio runtime. As the stream can
+    /// not borrow the table, the columns and the partition dictionary
+    /// are copied when this is called; only one batch is converted to
+    /// arrow at a time.
+    pub fn to_arrow_stream(
+        &self,
+        partition: &Partition,
+        batch_size: usize,
+    ) -> Result<SendableRecordBatchStream> {
+        ensure!(batch_size > 0, InvalidBatchSize { batch_size });
+
+        let schema = self.arrow_schema_ref(partition)?;
+        let columns = self
+            .all_columns_with_index(partition)?
+            .into_iter()
+            .map(|(_, column_index)| self.columns[column_index].clone())
+            .collect();
+
+        let source = ArrowStreamSource {
+            schema,
+            columns,
+            dictionary: partition.dictionary.clone(),
+            partition_key: partition.key.clone(),
+            num_rows: self.row_count(),
+        };
+
+        Ok(Box::pin(TableArrowStream {
+            source: Arc::new(source),
+            batch_size,
+            offset: 0,
+            pending: None,
+        }))
+    }
+
+    /// Returns (column_name, column_index) for all columns in this
+    /// table, sorted by column name
+    fn all_columns_with_index<'a>(
+        &self,
+        partition: &'a Partition,
+    ) -> Result<Vec<(&'a str, usize)>> {
+        let mut requested_columns_with_index = self
+            .column_id_to_index
+            .iter()
+            .map(|(&column_id, &column_index)| {
+                let column_name = partition.dictionary.lookup_id(column_id).context(
+                    ColumnIdNotFoundInDictionary {
+                        column_id,
+                        partition: &partition.key,
+                    },
+                )?;
+                Ok((column_name, column_index))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        requested_columns_with_index.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(requested_columns_with_index)
+    }
+
+    /// Returns the arrow schema of all columns in this table, sorted
+    /// by column name (the schema produced by `all_to_arrow`).
+    ///
+    /// The schema is cached and only rebuilt after the set of
+    /// columns (or the type of a column) changes, not when rows are
+    /// appended.
+    pub fn arrow_schema_ref(&self, partition: &Partition) -> Result<ArrowSchemaRef> {
+        let mut cached_schema = self
+            .cached_arrow_schema
+            .lock()
+            .expect("cached arrow schema lock poisoned");
+
+        if let Some(schema) = cached_schema.as_ref() {
+            return Ok(Arc::clone(schema));
+        }
+
+        let requested_columns_with_index = self.all_columns_with_index(partition)?;
+        let schema = Arc::new(self.arrow_schema(
+            partition,
+            &requested_columns_with_index,
+            &ToArrowOptions::default(),
+        ));
+        *cached_schema = Some(Arc::clone(&schema));
+
+        Ok(schema)
+    }
+
+    /// Releases the structures cached from the columns (such as the
+    /// arrow schema) to reduce memory use, for example under memory
+    /// pressure. The data is kept, and the caches are rebuilt when
+    /// next needed.
+    pub fn release_caches(&mut self) {
+        *self
+            .cached_arrow_schema
+            .get_mut()
+            .expect("cached arrow schema lock poisoned") = None;
+        *self
+            .cached_time_sorted
+            .get_mut()
+            .expect("cached time sorted lock poisoned") = None;
+    }
+
+    /// Returns an estimate of the memory used by this table, in bytes,
+    /// including the column data and any cached structures (see
+    /// `release_caches`)
+    pub fn estimate_size_bytes(&self) -> usize {
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| mem::size_of::<Column>() + column.size())
+            .sum::<usize>();
+
+        let column_index =
+            self.column_id_to_index.capacity() * (mem::size_of::<u32>() + mem::size_of::<usize>());
+
+        let semantic_types = self.semantic_types.capacity()
+            * (mem::size_of::<u32>() + mem::size_of::<SemanticType>());
+
+        let cached_arrow_schema = self
+            .cached_arrow_schema
+            .lock()
+            .expect("cached arrow schema lock poisoned")
+            .as_ref()
+            .map_or(0, |schema| {
+                mem::size_of::<ArrowSchema>()
+                    + schema
+                        .fields()
+                        .iter()
+                        .map(|field| mem::size_of::<ArrowField>() + field.name().len())
+                        .sum::<usize>()
+            });
+
+        mem::size_of::<Self>() + columns + column_index + semantic_types + cached_arrow_schema
+    }
+
+    /// Returns the ids of the tag values (in the partition dictionary)
+    /// referenced by the rows of this table. Once rows are removed,
+    /// dictionary entries that no table references can be pruned by
+    /// a partition level garbage collection.
+    ///
+    /// Note the ids of the table and column names are not included.
+    pub fn referenced_value_ids(&self) -> BTreeSet<u32> {
+        self.columns
+            .iter()
+            .filter_map(|column| match column {
+                Column::Tag(vals, _) => Some(vals.iter().flatten().copied()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Returns the number and size of the distinct tag values of the
+    /// dictionary of `partition` referenced by this table (see
+    /// `referenced_value_ids`), split into those only this table
+    /// references, which removing the table would allow to be pruned,
+    /// and those shared with the other tables of `partition`.
+    pub fn dictionary_stats(&self, partition: &Partition) -> DictionaryStats {
+        let shared_ids = partition
+            .tables
+            .values()
+            .filter(|table| table.id != self.id)
+            .flat_map(|table| table.referenced_value_ids())
+            .collect::<BTreeSet<_>>();
+
+        let mut stats = DictionaryStats::default();
+        for value_id in self.referenced_value_ids() {
+            let bytes = partition
+                .dictionary
+                .lookup_id(value_id)
+                .map_or(0, |value| value.len());
+
+            if shared_ids.contains(&value_id) {
+                stats.shared_values += 1;
+                stats.shared_bytes += bytes;
+            } else {
+                stats.exclusive_values += 1;
+                stats.exclusive_bytes += bytes;
+            }
+        }
+
+        stats
+    }
+
+    /// Discards the cached arrow schema. Must be called whenever
+    /// the set of columns or the type of any column changes
+    fn invalidate_cached_arrow_schema(&self) {
+        *self
+            .cached_arrow_schema
+            .lock()
+            .expect("cached arrow schema lock poisoned") = None;
+    }
+
+    /// Returns the arrow schema of the requested columns
+    ///
+    /// requested columns with index are tuples of column_name, column_index
+    fn arrow_schema(
+        &self,
+        partition: &Partition,
+        requested_columns_with_index: &[(&str, usize)],
+        options: &ToArrowOptions,
+    ) -> ArrowSchema {
+        // semantic types keyed by column index
+        let semantic_types = if options.emit_semantic_types {
+            self.semantic_types
+                .iter()
+                .filter_map(|(column_id, &semantic_type)| {
+                    self.column_id_to_index
+                        .get(column_id)
+                        .map(|&column_index| (column_index, semantic_type))
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        let fields = requested_columns_with_index
+            .iter()
+            .map(|&(column_name, column_index)| {
+                let data_type = match &self.columns[column_index] {
+                    Column::String(vals, _)
+                        if options
+                            .dictionary_strings_max_distinct
+                            .map_or(false, |max| Self::has_at_most_distinct(vals, max)) =>
+                    {
+                        ArrowDataType::Dictionary(
+                            Box::new(ArrowDataType::Int32),
+                            Box::new(ArrowDataType::Utf8),
+                        )
+                    }
+                    Column::String(vals, _) => match options.string_type {
+                        StringArrowType::Utf8 => ArrowDataType::Utf8,
+                        StringArrowType::LargeUtf8 => ArrowDataType::LargeUtf8,
+                        StringArrowType::LargeUtf8Above(threshold) => {
+                            let total_bytes: usize = vals.iter().flatten().map(|s| s.len()).sum();
+                            if total_bytes > threshold {
+                                ArrowDataType::LargeUtf8
+                            } else {
+                                ArrowDataType::Utf8
+                            }
+                        }
+                    },
+                    Column::Tag(vals, _) if options.fixed_size_binary_tags => {
+                        Self::uniform_tag_value_len(partition, vals)
+                            .map_or(ArrowDataType::Utf8, ArrowDataType::FixedSizeBinary)
+                    }
+                    Column::Tag(_, _) => ArrowDataType::Utf8,
+                    Column::F64(_, _) => ArrowDataType::Float64,
+                    Column::I64(_, _) => match semantic_types.get(&column_index) {
+                        None => ArrowDataType::Int64,
+                        Some(SemanticType::Duration) => {
+                            ArrowDataType::Duration(TimeUnit::Nanosecond)
+                        }
+                        Some(SemanticType::Date) => ArrowDataType::Date64(DateUnit::Millisecond),
+                    },
+                    Column::Bool(_, _) => ArrowDataType::Boolean,
+                    Column::Decimal {
+                        scale, precision, ..
+                    } => ArrowDataType::Decimal(*precision as usize, *scale as usize),
+                };
+                let column = &self.columns[column_index];
+                let nullable = !options.exact_nullability || column.null_count() > 0;
+                let mut field = ArrowField::new(column_name, data_type, nullable);
+                if let Some(metadata) = self.column_metadata.get(&self.column_ids[column_index]) {
+                    field.set_metadata(Some(metadata.clone()));
+                }
+                field
+            })
+            .collect();
+
+        ArrowSchema::new(fields)
+    }
+
+    /// Returns true if the (non null) string values `vals` have at most
+    /// `max` distinct values
+    fn has_at_most_distinct(vals: &[Option<String>], max: usize) -> bool {
+        let mut distinct = HashSet::new();
+        vals.iter().flatten().all(|s| {
+            distinct.insert(s.as_str());
+            distinct.len() <= max
+        })
+    }
+
+    /// Returns the length shared by all the (non null) tag values
+    /// `vals`, or `None` if their lengths differ, a value is not in the
+    /// dictionary of `partition` or there are no values
+    fn uniform_tag_value_len(partition: &Partition, vals: &[Option<u32>]) -> Option<i32> {
+        let mut lengths = vals
+            .iter()
+            .flatten()
+            .map(|&value_id| partition.dictionary.lookup_id(value_id).map(|v| v.len()));
+
+        let len = lengths.next()?.ok()?;
+        for other_len in lengths {
+            if other_len.ok()? != len {
+                return None;
+            }
+        }
+
+        i32::try_from(len).ok()
+    }
+
+    /// Converts the single column `column_id` to an arrow array, along
+    /// with the field describing it, without building a
+    /// `RecordBatch`. Produces the same array as `to_arrow_impl`.
+    pub fn column_as_arrow(
+        &self,
+        partition: &Partition,
+        column_id: u32,
+    ) -> Result<(ArrowField, ArrayRef)> {
+        let column_name =
+            partition
+                .dictionary
+                .lookup_id(column_id)
+                .context(ColumnIdNotFoundInDictionary {
+                    column_id,
+                    partition: &partition.key,
+                })?;
+        let column_index =
+            *self
+                .column_id_to_index
+                .get(&column_id)
+                .context(InternalNoColumnInIndex {
+                    column_name,
+                    column_id,
+                })?;
+
+        let schema = self.arrow_schema(
+            partition,
+            &[(column_name, column_index)],
+            &ToArrowOptions::default(),
+        );
+        let field = schema.field(0).clone();
+        let array = self.column_to_arrow(partition, column_index, field.data_type())?;
+
+        Ok((field, array))
+    }
+
+    /// Converts this table to an arrow record batch,
+    ///
+    /// requested columns with index are tuples of column_name, column_index
+    pub fn to_arrow_impl(
+        &self,
+        partition: &Partition,
+        requested_columns_with_index: &[(&str, usize)],
+    ) -> Result<RecordBatch> {
+        self.to_arrow_impl_with_options(
+            partition,
+            requested_columns_with_index,
+            &ToArrowOptions::default(),
+        )
+    }
+
+    /// Converts this table to an arrow record batch, using `options`
+    /// to control the arrow types produced
+    ///
+    /// requested columns with index are tuples of column_name, column_index
+    pub fn to_arrow_impl_with_options(
+        &self,
+        partition: &Partition,
+        requested_columns_with_index: &[(&str, usize)],
+        options: &ToArrowOptions,
+    ) -> Result<RecordBatch> {
+        let schema = Arc::new(self.arrow_schema(partition, requested_columns_with_index, options));
+
+        if let Some(memory_limit) = options.memory_limit {
+            let estimated_bytes = requested_columns_with_index
+                .iter()
+                .enumerate()
+                .map(|(i, &(_, column_index))| {
+                    self.estimate_column_arrow_size(
+                        partition,
+                        column_index,
+                        schema.field(i).data_type(),
+                    )
+                })
+                .sum::<usize>();
+
+            ensure!(
+                estimated_bytes <= memory_limit,
+                ArrowMemoryLimitExceeded {
+                    estimated_bytes,
+                    memory_limit
+                }
+            );
+        }
+
+        self.to_arrow_with_schema(partition, schema, requested_columns_with_index)
+    }
+
+    /// Returns an estimate of the memory, in bytes, used by the
+    /// buffers of the record batch `to_arrow` would create for
+    /// `requested_columns` (all columns if empty), without creating
+    /// it. Unlike `size`, tag values are counted as the decoded
+    /// strings of the arrow output rather than dictionary ids.
+    pub fn estimate_arrow_size(
+        &self,
+        partition: &Partition,
+        requested_columns: &[&str],
+    ) -> Result<usize> {
+        let requested_columns_with_index = if requested_columns.is_empty() {
+            self.all_columns_with_index(partition)?
+        } else {
+            self.column_names_with_index(partition, requested_columns)?
+        };
+        let schema = self.arrow_schema(
+            partition,
+            &requested_columns_with_index,
+            &ToArrowOptions::default(),
+        );
+
+        Ok(requested_columns_with_index
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, column_index))| {
+                self.estimate_column_arrow_size(
+                    partition,
+                    column_index,
+                    schema.field(i).data_type(),
+                )
+            })
+            .sum())
+    }
+
+    /// Returns an estimate of the memory, in bytes, used by the arrow
+    /// array of `data_type` that `column_to_arrow` creates for the
+    /// column at `column_index`
+    fn estimate_column_arrow_size(
+        &self,
+        partition: &Partition,
+        column_index: usize,
+        data_type: &ArrowDataType,
+    ) -> usize {
+        let column = &self.columns[column_index];
+        let num_rows = column.len();
+        let bitmap_size = (num_rows + 7) / 8;
+
+        let values_size = match column {
+            Column::F64(_, _) => num_rows * mem::size_of::<f64>(),
+            Column::I64(_, _) => num_rows * mem::size_of::<i64>(),
+            Column::Bool(_, _) => bitmap_size,
+            Column::Decimal { .. } => num_rows * mem::size_of::<i128>(),
+            Column::String(vals, _) if matches!(data_type, ArrowDataType::Dictionary(_, _)) => {
+                let distinct = vals
+                    .iter()
+                    .flatten()
+                    .map(|s| s.as_str())
+                    .collect::<HashSet<_>>();
+                // keys + the offsets and bytes of the distinct values
+                num_rows * mem::size_of::<i32>()
+                    + (distinct.len() + 1) * mem::size_of::<i32>()
+                    + distinct.iter().map(|s| s.len()).sum::<usize>()
+            }
+            Column::String(vals, _) => {
+                let offset_size = match data_type {
+                    ArrowDataType::LargeUtf8 => mem::size_of::<i64>(),
+                    _ => mem::size_of::<i32>(),
+                };
+                (num_rows + 1) * offset_size + vals.iter().flatten().map(|s| s.len()).sum::<usize>()
+            }
+            Column::Tag(_, _) if matches!(data_type, ArrowDataType::FixedSizeBinary(_)) => {
+                let byte_width = match data_type {
+                    ArrowDataType::FixedSizeBinary(byte_width) => *byte_width as usize,
+                    _ => unreachable!("checked above"),
+                };
+                num_rows * byte_width
+            }
+            Column::Tag(vals, _) => {
+                (num_rows + 1) * mem::size_of::<i32>()
+                    + vals
+                        .iter()
+                        .flatten()
+                        .map(|&value_id| {
+                            partition
+                                .dictionary
+                                .lookup_id(value_id)
+                                .map_or(0, |tag_value| tag_value.len())
+                        })
+                        .sum::<usize>()
+            }
+        };
+
+        // validity bitmap + values
+        bitmap_size + values_size
+    }
+
+    /// Converts the requested columns of this table to an arrow
+    /// record batch with `schema`, which must describe the requested
+    /// columns. The type of each field in `schema` selects the arrow
+    /// array type produced for `Column::String` columns
+    fn to_arrow_with_schema(
+        &self,
+        partition: &Partition,
+        schema: ArrowSchemaRef,
+        requested_columns_with_index: &[(&str, usize)],
+    ) -> Result<RecordBatch> {
+        let columns = requested_columns_with_index
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, column_index))| {
+                self.column_to_arrow(partition, column_index, schema.field(i).data_type())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(schema, columns).context(ArrowError {})
+    }
+
+    /// Converts the column at `column_index` to an arrow array,
+    /// decoding tag values using the dictionary of `partition`.
+    /// `data_type` (from `arrow_schema`) selects the arrow array type
+    /// produced for `Column::String` and `Column::I64` columns
+    fn column_to_arrow(
+        &self,
+        partition: &Partition,
+        column_index: usize,
+        data_type: &ArrowDataType,
+    ) -> Result<ArrayRef> {
+        let rows = RowSelection::Range(0..self.columns[column_index].len());
+        self.column_rows_to_arrow(partition, column_index, data_type, rows)
+    }
+
+    /// Converts the values of `rows` of the column at `column_index`
+    /// to an arrow array, as `column_to_arrow`. Only tag values within
+    /// `rows` are decoded.
+    fn column_rows_to_arrow(
+        &self,
+        partition: &Partition,
+        column_index: usize,
+        data_type: &ArrowDataType,
+        rows: RowSelection<'_>,
+    ) -> Result<ArrayRef> {
+        rows_to_arrow(
+            &self.columns[column_index],
+            &partition.dictionary,
+            &partition.key,
+            data_type,
+            rows,
+        )
+    }
+
+    /// Returns a human readable description of the differences
+    /// between this table and `other`, as the changes needed to turn
+    /// `self` into `other`. An empty result means both tables hold
+    /// the same data.
+    ///
+    /// Columns are matched by name and rows by position. Tag values
+    /// are looked up in the dictionary of each table's partition.
+    /// This is intended as an aid for writing and debugging tests.
+    pub fn diff(
+        &self,
+        self_partition: &Partition,
+        other: &Self,
+        other_partition: &Partition,
+    ) -> Vec<String> {
+        let self_columns = self.columns_by_name(self_partition);
+        let other_columns = other.columns_by_name(other_partition);
+
+        let mut diffs = Vec::new();
+
+        for (name, column) in &self_columns {
+            if !other_columns.contains_key(name) {
+                diffs.push(format!(
+                    "column removed: {} ({})",
+                    name,
+                    column.type_description()
+                ));
+            }
+        }
+
+        for (name, other_column) in &other_columns {
+            match self_columns.get(name) {
+                None => diffs.push(format!(
+                    "column added: {} ({})",
+                    name,
+                    other_column.type_description()
+                )),
+                Some(self_column)
+                    if self_column.type_description() != other_column.type_description() =>
+                {
+                    diffs.push(format!(
+                        "column type changed: {} ({} -> {})",
+                        name,
+                        self_column.type_description(),
+                        other_column.type_description()
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let self_row_count = self.row_count();
+        let other_row_count = other.row_count();
+        if self_row_count != other_row_count {
+            diffs.push(format!(
+                "row count changed: {} -> {}",
+                self_row_count, other_row_count
+            ));
+        }
+
+        for row in 0..self_row_count.min(other_row_count) {
+            for (name, other_column) in &other_columns {
+                if let Some(self_column) = self_columns.get(name) {
+                    let self_value = describe_value(self_column, row, self_partition);
+                    let other_value = describe_value(other_column, row, other_partition);
+                    if self_value != other_value {
+                        diffs.push(format!(
+                            "value changed at row {} column {}: {} -> {}",
+                            row, name, self_value, other_value
+                        ));
+                    }
+                }
+            }
+        }
+
+        diffs
+    }
+
+    /// Returns this table's columns keyed (and thus sorted) by
+    /// name. Column ids missing from the dictionary are named by id
+    fn columns_by_name<'a>(&'a self, partition: &Partition) -> BTreeMap<String, &'a Column> {
+        self.column_id_to_index
+            .iter()
+            .map(|(&column_id, &column_index)| {
+                let column_name = match partition.dictionary.lookup_id(column_id) {
+                    Ok(column_name) => column_name.to_string(),
+                    Err(_) => format!("<column id {}>", column_id),
+                };
+                (column_name, &self.columns[column_index])
+            })
+            .collect()
+    }
+
+    /// returns true if any row in this table could possible match the
+    /// predicate. true does not mean any rows will *actually* match,
+    /// just that the entire table can not be ruled out.
+    ///
+    /// false means that no rows in this table could possibly match
+    ///
+    /// General predicate expressions only prune tables that lack a
+    /// column they reference (which could not be evaluated against the
+    /// table). Their values are never used for pruning, so terms of
+    /// computed columns (such as `temp * 2 > 100`), which can't be
+    /// compared with the column statistics, can't exclude a table whose
+    /// rows could satisfy them.
+    ///
+    /// The exception is equality comparisons of tags with string
+    /// literals (such as `city = 'LA'`): if tag value filters are
+    /// enabled (see `enable_tag_value_filters`), a table whose filter
+    /// rules out the value is pruned, without scanning the column.
+    ///
+    /// The columns consulted are recorded in `scan_stats`
+    pub fn could_match_predicate(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+        scan_stats: &mut ScanStats,
+    ) -> Result<bool> {
+        Ok(
+            self.matches_column_selection(partition_predicate.field_restriction.as_ref())
+                && self.matches_table_name_predicate(
+                    partition_predicate.table_name_predicate.as_ref(),
+                    partition_predicate.table_name_regex.as_ref(),
+                    &partition.dictionary,
+                )
+                && self.matches_timestamp_predicate(partition_predicate, scan_stats)?
+                && self.has_columns(partition_predicate.required_columns.as_ref())
+                && self.matches_tag_value_filters(partition_predicate, partition, scan_stats),
+        )
+    }
+
+    /// Returns false if a tag value filter rules out a tag equality
+    /// of the predicate. Equalities of columns that are not tags of
+    /// this table, or with values not in the dictionary, are left to
+    /// the other checks (and to the query)
+    fn matches_tag_value_filters(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        partition: &Partition,
+        scan_stats: &mut ScanStats,
+    ) -> bool {
+        if self.tag_value_filters.is_none() {
+            return true;
+        }
+
+        for expr in &partition_predicate.partition_exprs {
+            let (column_name, tag_value) = match tag_equality(expr) {
+                Some(tag_equality) => tag_equality,
+                None => continue,
+            };
+            let column_id = match partition.dictionary.lookup_value(column_name) {
+                Ok(column_id) if matches!(self.column(column_id), Ok(Column::Tag(_, _))) => {
+                    column_id
+                }
+                _ => continue,
+            };
+            let value_id = match partition.dictionary.lookup_value(tag_value) {
+                Ok(value_id) => value_id,
+                Err(_) => continue,
+            };
+
+            // the filter is consulted instead of the column's values
+            let matches = self.maybe_contains_tag_value(column_id, value_id);
+            scan_stats.record(column_id, 0, !matches);
+            if !matches {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns true if the table contains at least one of the fields
+    /// requested or there are no specific fields requested.
+    fn matches_column_selection(&self, column_selection: Option<&BTreeSet<u32>>) -> bool {
+        match column_selection {
+            Some(column_selection) => {
+                // figure out if any of the columns exists
+                self.column_id_to_index
+                    .keys()
+                    .any(|column_id| column_selection.contains(column_id))
+            }
+            None => true, // no specific selection
+        }
+    }
+
+    /// Returns true if this table's id is in `table_name_predicate`
+    /// (if any) and its name, as resolved by `dictionary`, matches
+    /// `table_name_regex` (if any)
+    fn matches_table_name_predicate(
+        &self,
+        table_name_predicate: Option<&BTreeSet<u32>>,
+        table_name_regex: Option<&Regex>,
+        dictionary: &Dictionary,
+    ) -> bool {
+        let matches_ids = match table_name_predicate {
+            Some(table_name_predicate) => table_name_predicate.contains(&self.id),
+            None => true, // no table predicate
+        };
+
+        matches_ids
+            && match table_name_regex {
+                Some(table_name_regex) => dictionary
+                    .lookup_id(self.id)
+                    .map(|table_name| table_name_regex.is_match(table_name))
+                    .unwrap_or(false),
+                None => true, // no table regex
+            }
+    }
+
+    /// returns true if there are any timestamps in this table that
+    /// fall within the timestamp range
+    fn matches_timestamp_predicate(
+        &self,
+        partition_predicate: &PartitionPredicate,
+        scan_stats: &mut ScanStats,
+    ) -> Result<bool> {
+        match &partition_predicate.range {
+            None => Ok(true),
+            Some(range) => {
+                let time_column_id = self.predicate_time_column_id(partition_predicate);
+                let time_column = self.column(time_column_id)?;
+                let matches = time_column.has_i64_range(range.start, range.end).context(
+                    ColumnPredicateEvaluation {
+                        column: time_column_id,
+                    },
+                )?;
+
+                // the range is checked against the column statistics,
+                // so no values are read
+                scan_stats.record(time_column_id, 0, !matches);
+                Ok(matches)
+            }
+        }
+    }
+
+    /// returns true if no columns are specified, or the table has all
+    /// columns specified
+    fn has_columns(&self, columns: Option<&PartitionIdSet>) -> bool {
+        if let Some(columns) = columns {
+            match columns {
+                PartitionIdSet::AtLeastOneMissing => return false,
+                PartitionIdSet::Present(symbols) => {
+                    for symbol in symbols {
+                        if !self.column_id_to_index.contains_key(symbol) {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// returns true if there are any rows in column that are non-null
+    /// and within the timestamp range specified by pred
+    pub fn column_matches_predicate<T>(
+        &self,
+        column: &[Option<T>],
+        partition_predicate: &PartitionPredicate,
+    ) -> Result<bool> {
+        match partition_predicate.range {
+            None => Ok(true),
+            Some(range) => {
+                let time_column_id = self.predicate_time_column_id(partition_predicate);
+                let time_column = self.column(time_column_id)?;
+                time_column
+                    .has_non_null_i64_range(column, range.start, range.end)
+                    .context(ColumnPredicateEvaluation {
+                        column: time_column_id,
+                    })
+            }
+        }
+    }
+}
+
+/// Returns `batch` with `array` appended as an additional column
+/// described by `field`
+fn with_extra_column(
+    batch: RecordBatch,
+    field: ArrowField,
+    array: ArrayRef,
+) -> Result<RecordBatch> {
+    let mut fields = batch.schema().fields().clone();
+    fields.push(field);
+    let mut columns = batch.columns().to_vec();
+    columns.push(array);
+    RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns).context(ArrowError {})
+}
+
+/// Reorders tag_columns so that its prefix matches exactly
+/// prefix_columns. Returns an error if there are duplicates, or other
+/// untoward inputs
+fn reorder_prefix(
+    prefix_columns: &[String],
+    tag_columns: Vec<Arc<String>>,
+) -> Result<Vec<Arc<String>>> {
+    // tag_used_set[i[ is true if we have used the value in tag_columns[i]
+    let mut tag_used_set = vec![false; tag_columns.len()];
+
+    // Note that this is an O(N^2) algorithm. We are assuming the
+    // number of tag columns is reasonably small
+
+    // map from prefix_column[idx] -> index in tag_columns
+    let prefix_map = prefix_columns
+        .iter()
+        .map(|pc| {
+            let found_location = tag_columns
+                .iter()
+                .enumerate()
+                .find(|(_, c)| pc == c.as_ref());
+
+            if let Some((index, _)) = found_location {
+                if tag_used_set[index] {
+                    DuplicateGroupColumn { column_name: pc }.fail()
+                } else {
+                    tag_used_set[index] = true;
+                    Ok(index)
+                }
+            } else {
+                GroupColumnNotFound {
+                    column_name: pc,
+                    all_tag_column_names: tag_columns
+                        .iter()
+                        .map(|s| s.as_ref() as &str)
+                        .collect::<Vec<_>>()
+                        .as_slice()
+                        .join(", "),
+                }
+                .fail()
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut new_tag_columns = prefix_map
+        .iter()
+        .map(|&i| tag_columns[i].clone())
+        .collect::<Vec<_>>();
+
+    new_tag_columns.extend(tag_columns.into_iter().enumerate().filter_map(|(i, c)| {
+        // already used in prefix
+        if tag_used_set[i] {
+            None
+        } else {
+            Some(c)
+        }
+    }));
+
+    Ok(new_tag_columns)
+}
+
+/// Reorders `tag_columns` so that the tags named in `pinned_tags`
+/// come first, in the order given, followed by the remaining tags in
+/// their existing order. Pinned names that are not in `tag_columns`
+/// are ignored.
+fn pin_tags(pinned_tags: &[String], tag_columns: Vec<Arc<String>>) -> Vec<Arc<String>> {
+    let (mut pinned, rest): (Vec<_>, Vec<_>) = tag_columns
+        .into_iter()
+        .partition(|c| pinned_tags.contains(c.as_ref()));
+    pinned.sort_by_key(|c| pinned_tags.iter().position(|p| p == c.as_ref()));
+    pinned.extend(rest);
+    pinned
+}
+
+/// Replaces comparisons against null literals in `expr` with boolean
+/// constants so that the filter only passes rows for which `expr`
+/// would be TRUE under SQL three-valued logic.
+///
+/// Such a comparison evaluates to NULL (unknown). Within a tree of
+/// AND / OR / NOT, treating unknown as FALSE in positions under an
+/// even number of NOTs (and TRUE under an odd number) yields a TRUE
+/// result exactly when the original expression is TRUE. `negated`
+/// tracks whether `expr` is under an odd number of NOTs.
+fn rewrite_null_comparisons(expr: Expr, negated: bool) -> Expr {
+    match expr {
+        Expr::BinaryExpr { left, op, right } => {
+            if matches!(op, Operator::And | Operator::Or) {
+                Expr::BinaryExpr {
+                    left: Box::new(rewrite_null_comparisons(*left, negated)),
+                    op,
+                    right: Box::new(rewrite_null_comparisons(*right, negated)),
+                }
+            } else if is_comparison(&op) && (is_null_valued(&left) || is_null_valued(&right)) {
+                Expr::Literal(ScalarValue::Boolean(Some(negated)))
+            } else {
+                Expr::BinaryExpr { left, op, right }
+            }
+        }
+        Expr::Not(expr) => Expr::Not(Box::new(rewrite_null_comparisons(*expr, !negated))),
+        Expr::Nested(expr) => Expr::Nested(Box::new(rewrite_null_comparisons(*expr, negated))),
+        expr => expr,
+    }
+}
+
+/// Returns true if `op` compares its arguments (and thus evaluates to
+/// NULL if either argument is NULL)
+fn is_comparison(op: &Operator) -> bool {
+    matches!(
+        op,
+        Operator::Eq
+            | Operator::NotEq
+            | Operator::Lt
+            | Operator::LtEq
+            | Operator::Gt
+            | Operator::GtEq
+            | Operator::Like
+            | Operator::NotLike
+    )
+}
+
+/// Returns true if `expr` always evaluates to NULL: a (typed) null
+/// literal, or arithmetic / casts involving one
+fn is_null_valued(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(value) => matches!(
+            value,
+            ScalarValue::Boolean(None)
+                | ScalarValue::Float32(None)
+                | ScalarValue::Float64(None)
+                | ScalarValue::Int8(None)
+                | ScalarValue::Int16(None)
+                | ScalarValue::Int32(None)
+                | ScalarValue::Int64(None)
+                | ScalarValue::UInt8(None)
+                | ScalarValue::UInt16(None)
+                | ScalarValue::UInt32(None)
+                | ScalarValue::UInt64(None)
+                | ScalarValue::Utf8(None)
+                | ScalarValue::LargeUtf8(None)
+        ),
+        Expr::BinaryExpr { left, op, right } => {
+            matches!(
+                op,
+                Operator::Plus
+                    | Operator::Minus
+                    | Operator::Multiply
+                    | Operator::Divide
+                    | Operator::Modulus
+            ) && (is_null_valued(left) || is_null_valued(right))
+        }
+        Expr::Cast { expr, .. } | Expr::Nested(expr) => is_null_valued(expr),
+        _ => false,
+    }
+}
+
+/// Appends line protocol to a `Table` incrementally, see
+/// `Table::line_protocol_sink`
+#[derive(Debug)]
+pub struct LineProtocolSink<'a> {
+    table: &'a mut Table,
+    dictionary: &'a mut Dictionary,
+    /// Totals of the data appended through this sink
+    stats: AppendStats,
+}
+
+impl<'a> LineProtocolSink<'a> {
+    /// Parses `line` and appends it to the table, as
+    /// `Table::append_from_line_protocol` does, returning what was
+    /// added. `line` may also hold several (or no) lines.
+    pub fn push_line(&mut self, line: &str) -> Result<AppendStats> {
+        let stats = self
+            .table
+            .append_from_line_protocol(self.dictionary, line)?;
+        self.stats.rows_appended += stats.rows_appended;
+        self.stats.columns_added += stats.columns_added;
+        Ok(stats)
+    }
+
+    /// Returns the totals of the data appended by `push_line` so far
+    pub fn stats(&self) -> AppendStats {
+        self.stats
+    }
+}
+
+/// The data `Table::to_arrow_stream` converts, copied from the table
+/// and its partition
+#[derive(Debug)]
+struct ArrowStreamSource {
+    schema: ArrowSchemaRef,
+    /// The columns, in the order of the fields of `schema`
+    columns: Vec<Column>,
+    dictionary: Dictionary,
+    partition_key: String,
+    num_rows: usize,
+}
+
+impl ArrowStreamSource {
+    /// Converts `rows` of all columns to a batch of `schema`
+    fn to_arrow(&self, rows: Range<usize>) -> Result<RecordBatch> {
+        let columns = self
+            .columns
+            .iter()
+            .zip(self.schema.fields())
+            .map(|(column, field)| {
+                rows_to_arrow(
+                    column,
+                    &self.dictionary,
+                    &self.partition_key,
+                    field.data_type(),
+                    RowSelection::Range(rows.clone()),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        RecordBatch::try_new(Arc::clone(&self.schema), columns).context(ArrowError {})
+    }
+}
+
+/// Stream created by `Table::to_arrow_stream` that converts the rows of
+/// `source` in batches of at most `batch_size` rows, one at a time
+struct TableArrowStream {
+    source: Arc<ArrowStreamSource>,
+    batch_size: usize,
+    /// The first row of the next batch
+    offset: usize,
+    /// The blocking task converting the current batch, if any
+    pending: Option<tokio::task::JoinHandle<Result<RecordBatch>>>,
+}
+
+impl Stream for TableArrowStream {
+    type Item = arrow::error::Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.pending.is_none() {
+            let num_rows = self.source.num_rows;
+            if self.offset >= num_rows {
+                return Poll::Ready(None);
+            }
+
+            let rows = self.offset..num_rows.min(self.offset.saturating_add(self.batch_size));
+            self.offset = rows.end;
+
+            let source = Arc::clone(&self.source);
+            self.pending = Some(tokio::task::spawn_blocking(move || source.to_arrow(rows)));
+        }
+
+        let pending = self.pending.as_mut().expect("the conversion was started");
+        let res = match Pin::new(pending).poll(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.pending = None;
+
+        let batch = match res {
+            Ok(Ok(batch)) => Ok(batch),
+            Ok(Err(e)) => Err(arrow::error::ArrowError::ComputeError(e.to_string())),
+            Err(e) => Err(arrow::error::ArrowError::ComputeError(format!(
+                "Converting a table to arrow failed: {}",
+                e
+            ))),
+        };
+        Poll::Ready(Some(batch))
+    }
+}
+
+impl RecordBatchStream for TableArrowStream {
+    fn schema(&self) -> ArrowSchemaRef {
+        Arc::clone(&self.source.schema)
+    }
+}
+
+/// The input of `Table::merge_sorted` from which to take the next row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergeSource {
+    A,
+    B,
+}
+
+/// Returns the order in which to take the rows of two inputs, each
+/// sorted on time (ignoring nulls), to produce output sorted on time
+fn merge_order(a_times: &[Option<i64>], b_times: &[Option<i64>]) -> Vec<MergeSource> {
+    let mut order = Vec::with_capacity(a_times.len() + b_times.len());
+    let (mut a_index, mut b_index) = (0, 0);
+
+    while a_index < a_times.len() && b_index < b_times.len() {
+        let source = match (a_times[a_index], b_times[b_index]) {
+            (Some(a_time), Some(b_time)) if b_time < a_time => MergeSource::B,
+            (Some(_), None) => MergeSource::B,
+            _ => MergeSource::A,
+        };
+
+        match source {
+            MergeSource::A => a_index += 1,
+            MergeSource::B => b_index += 1,
+        }
+        order.push(source);
+    }
+
+    order.extend(std::iter::repeat(MergeSource::A).take(a_times.len() - a_index));
+    order.extend(std::iter::repeat(MergeSource::B).take(b_times.len() - b_index));
+    order
+}
+
+/// Merges the values of `a` and `b` (either of which may be missing
+/// from its input) in `order`, see `Table::merge_sorted`. Tag values
+/// of `b` are translated from the dictionary of `b_partition` into
+/// that of `a_partition`.
+fn merge_columns(
+    column_id: u32,
+    a: Option<&Column>,
+    b: Option<&Column>,
+    order: &[MergeSource],
+    a_partition: &mut Partition,
+    b_partition: &Partition,
+) -> Result<Column> {
+    if let (Some(a), Some(b)) = (a, b) {
+        ensure!(
+            a.column_type() == b.column_type(),
+            SchemaMismatch {
+                column: column_id,
+                existing_column_type: a.type_description(),
+                inserted_value_type: b.type_description(),
+            }
+        );
+    }
+
+    let column = a.or(b).expect("column is present in at least one input");
+    Ok(match column {
+        Column::F64(_, _) => {
+            let (vals, stats) = merge_values(a, b, order, |column| match column {
+                Column::F64(vals, stats) => Some((vals, stats)),
+                _ => None,
+            });
+            Column::F64(vals, stats)
+        }
+        Column::I64(_, _) => {
+            let (vals, stats) = merge_values(a, b, order, |column| match column {
+                Column::I64(vals, stats) => Some((vals, stats)),
+                _ => None,
+            });
+            Column::I64(vals, stats)
+        }
+        Column::String(_, _) => {
+            let (vals, stats) = merge_values(a, b, order, |column| match column {
+                Column::String(vals, stats) => Some((vals, stats)),
+                _ => None,
+            });
+            Column::String(vals, stats)
+        }
+        Column::Bool(_, _) => {
+            let (vals, stats) = merge_values(a, b, order, |column| match column {
+                Column::Bool(vals, stats) => Some((vals, stats)),
+                _ => None,
+            });
+            Column::Bool(vals, stats)
+        }
+        Column::Tag(_, _) => {
+            let b = match b {
+                Some(Column::Tag(vals, stats)) => {
+                    let vals = vals
+                        .iter()
+                        .map(|value_id| {
+                            value_id
+                                .map(|value_id| -> Result<u32> {
+                                    let tag_value = b_partition
+                                        .dictionary
+                                        .lookup_id(value_id)
+                                        .context(TagValueIdNotFoundInDictionary {
+                                            value: value_id,
+                                            partition: &b_partition.key,
+                                        })?;
+                                    Ok(a_partition.dictionary.lookup_value_or_insert(tag_value))
+                                })
+                                .transpose()
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Some(Column::Tag(vals, stats.clone()))
+                }
+                _ => None,
+            };
+
+            let (vals, stats) = merge_values(a, b.as_ref(), order, |column| match column {
+                Column::Tag(vals, stats) => Some((vals, stats)),
+                _ => None,
+            });
+            Column::Tag(vals, stats)
+        }
+        &Column::Decimal {
+            scale, precision, ..
+        } => {
+            let values = |column: Option<&Column>| match column {
+                Some(Column::Decimal { values, .. }) => Some(values.as_slice()),
+                _ => None,
+            };
+            Column::Decimal {
+                values: merge_column_values(values(a), values(b), order),
+                scale,
+                precision,
+            }
+        }
+    })
+}
+
+/// Merges the values of the `a` and `b` columns in `order`, and their
+/// statistics, where `parts` extracts the values and statistics of a
+/// column. At least one of `a` and `b` must be of the expected type.
+fn merge_values<'a, T, S>(
+    a: Option<&'a Column>,
+    b: Option<&'a Column>,
+    order: &[MergeSource],
+    parts: impl Fn(&'a Column) -> Option<(&'a Vec<Option<T>>, &'a Statistics<S>)>,
+) -> (Vec<Option<T>>, Statistics<S>)
+where
+    T: Clone + 'a,
+    S: PartialEq + PartialOrd + std::fmt::Debug + std::fmt::Display + Clone + 'a,
+{
+    let a = a.and_then(&parts);
+    let b = b.and_then(&parts);
+
+    let vals = merge_column_values(
+        a.map(|(vals, _)| vals.as_slice()),
+        b.map(|(vals, _)| vals.as_slice()),
+        order,
+    );
+
+    let stats = match (a, b) {
+        (Some((_, a_stats)), Some((_, b_stats))) => {
+            let mut stats = a_stats.clone();
+            stats.update(b_stats.min.clone());
+            stats.update(b_stats.max.clone());
+            stats.count = a_stats.count + b_stats.count;
+            stats
+        }
+        (Some((_, stats)), None) | (None, Some((_, stats))) => stats.clone(),
+        (None, None) => panic!("column is present in at least one input"),
+    };
+
+    (vals, stats)
+}
+
+/// Merges the values `a` and `b` (either of which may be missing, in
+/// which case its rows are null) in `order`
+fn merge_column_values<T: Clone>(
+    a: Option<&[Option<T>]>,
+    b: Option<&[Option<T>]>,
+    order: &[MergeSource],
+) -> Vec<Option<T>> {
+    let (mut a_index, mut b_index) = (0, 0);
+    order
+        .iter()
+        .map(|source| match source {
+            MergeSource::A => {
+                a_index += 1;
+                a.and_then(|vals| vals[a_index - 1].clone())
+            }
+            MergeSource::B => {
+                b_index += 1;
+                b.and_then(|vals| vals[b_index - 1].clone())
+            }
+        })
+        .collect()
+}
+
+/// Converts the values of the arrow `array` of column `column_name`
+/// to a `Column` of `column_type` (as selected by
+/// `Table::arrow_column_type`), preceded by `leading_nulls` null
+/// values. Tag values are added to `dictionary`.
+///
+/// Returns `None` if all values of `array` are null.
+fn arrow_to_column(
+    dictionary: &mut Dictionary,
+    column_name: &str,
+    array: &ArrayRef,
+    column_type: ColumnType,
+    leading_nulls: usize,
+) -> Result<Option<Column>> {
+    fn with_stats<T>(
+        leading_nulls: usize,
+        values: impl Iterator<Item = Option<T>>,
+    ) -> Option<(Vec<Option<T>>, Statistics<T>)>
+    where
+        T: PartialEq + PartialOrd + std::fmt::Debug + std::fmt::Display + Clone,
+    {
+        let mut vals = vec![None; leading_nulls];
+        vals.extend(values);
+
+        let mut stats: Option<Statistics<T>> = None;
+        for v in vals.iter().flatten() {
+            match stats.as_mut() {
+                Some(stats) => stats.update(v.clone()),
+                None => stats = Some(Statistics::new(v.clone())),
+            }
+        }
+
+        stats.map(|stats| (vals, stats))
+    }
+
+    let column = match column_type {
+        ColumnType::F64 => {
+            let array =
+                arrow::compute::cast(array, &ArrowDataType::Float64).context(ArrowError {})?;
+            let array = downcast::<Float64Array>(&array);
+            let values = (0..array.len()).map(|i| array_value(array, i, |i| array.value(i)));
+            with_stats(leading_nulls, values).map(|(vals, stats)| Column::F64(vals, stats))
+        }
+        ColumnType::I64 => {
+            // nanoseconds per unit of timestamp columns
+            let scale = match array.data_type() {
+                ArrowDataType::Timestamp(TimeUnit::Second, _) => 1_000_000_000,
+                ArrowDataType::Timestamp(TimeUnit::Millisecond, _) => 1_000_000,
+                ArrowDataType::Timestamp(TimeUnit::Microsecond, _) => 1_000,
+                _ => 1,
+            };
+            let array =
+                arrow::compute::cast(array, &ArrowDataType::Int64).context(ArrowError {})?;
+            let array = downcast::<Int64Array>(&array);
+            let values = (0..array.len())
+                .map(|i| {
+                    array_value(array, i, |i| array.value(i))
+                        .map(|v| {
+                            v.checked_mul(scale)
+                                .context(TimestampOverflow { column_name })
+                        })
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>>>()?;
+            with_stats(leading_nulls, values.into_iter())
+                .map(|(vals, stats)| Column::I64(vals, stats))
+        }
+        ColumnType::Bool => {
+            let array = downcast::<BooleanArray>(array);
+            let values = (0..array.len()).map(|i| array_value(array, i, |i| array.value(i)));
+            with_stats(leading_nulls, values).map(|(vals, stats)| Column::Bool(vals, stats))
+        }
+        ColumnType::String => {
+            let array = downcast::<StringArray>(array);
+            let values =
+                (0..array.len()).map(|i| array_value(array, i, |i| array.value(i).to_string()));
+            with_stats(leading_nulls, values).map(|(vals, stats)| Column::String(vals, stats))
+        }
+        ColumnType::Tag => {
+            let array = downcast::<StringArray>(array);
+            let values =
+                (0..array.len()).map(|i| array_value(array, i, |i| array.value(i).to_string()));
+            with_stats(leading_nulls, values).map(|(vals, stats)| {
+                let vals = vals
+                    .into_iter()
+                    .map(|v| v.map(|v| dictionary.lookup_value_or_insert(&v)))
+                    .collect();
+                Column::Tag(vals, stats)
+            })
+        }
+        ColumnType::Decimal { scale, precision } => {
+            let array = downcast::<DecimalArray>(array);
+            let mut values = vec![None; leading_nulls];
+            values.extend((0..array.len()).map(|i| array_value(array, i, |i| array.value(i))));
+            if values.iter().all(Option::is_none) {
+                None
+            } else {
+                Some(Column::Decimal {
+                    values,
+                    scale,
+                    precision,
+                })
+            }
+        }
+    };
+
+    Ok(column)
+}
+
+/// Converts the values of `rows` of `column` to an arrow array of
+/// `data_type`, as `Table::column_rows_to_arrow`, looking up tag values
+/// in `dictionary` (of the partition with key `partition_key`)
+fn rows_to_arrow(
+    column: &Column,
+    dictionary: &Dictionary,
+    partition_key: &str,
+    data_type: &ArrowDataType,
+    rows: RowSelection<'_>,
+) -> Result<ArrayRef> {
+    let len = rows.len();
+    let arrow_col: ArrayRef = match column {
+        Column::String(vals, _) if matches!(data_type, ArrowDataType::Dictionary(_, _)) => {
+            let mut builder = StringDictionaryBuilder::new(
+                PrimitiveBuilder::<Int32Type>::new(len),
+                StringBuilder::new(len),
+            );
+
+            for v in rows.select(vals) {
+                match v {
+                    None => builder.append_null(),
+                    Some(s) => builder.append(s).map(|_key| ()),
+                }
+                .context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::String(vals, _) if data_type == &ArrowDataType::LargeUtf8 => {
+            let mut builder = LargeStringBuilder::with_capacity(len, len * 10);
+
+            for v in rows.select(vals) {
+                match v {
+                    None => builder.append_null(),
+                    Some(s) => builder.append_value(s),
+                }
+                .context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::String(vals, _) => {
+            let mut builder = StringBuilder::with_capacity(len, len * 10);
+
+            for v in rows.select(vals) {
+                match v {
+                    None => builder.append_null(),
+                    Some(s) => builder.append_value(s),
+                }
+                .context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::Tag(vals, _) if matches!(data_type, ArrowDataType::FixedSizeBinary(_)) => {
+            let byte_width = match data_type {
+                ArrowDataType::FixedSizeBinary(byte_width) => *byte_width,
+                _ => unreachable!("checked above"),
+            };
+            let mut builder = FixedSizeBinaryBuilder::new(len, byte_width);
+
+            for v in rows.select(vals) {
+                match v {
+                    None => builder.append_null(),
+                    Some(value_id) => {
+                        let tag_value = dictionary.lookup_id(*value_id).context(
+                            TagValueIdNotFoundInDictionary {
+                                value: *value_id,
+                                partition: partition_key,
+                            },
+                        )?;
+                        builder.append_value(tag_value.as_bytes())
+                    }
+                }
+                .context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::Tag(vals, _) => {
+            let mut builder = StringBuilder::with_capacity(len, len * 10);
+
+            for v in rows.select(vals) {
+                match v {
+                    None => builder.append_null(),
+                    Some(value_id) => {
+                        let tag_value = dictionary.lookup_id(*value_id).context(
+                            TagValueIdNotFoundInDictionary {
+                                value: *value_id,
+                                partition: partition_key,
+                            },
+                        )?;
+                        builder.append_value(tag_value)
+                    }
+                }
+                .context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::F64(vals, _) => {
+            let mut builder = Float64Builder::new(len);
+
+            for v in rows.select(vals) {
+                builder.append_option(*v).context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::I64(vals, _) if data_type == &ArrowDataType::Duration(TimeUnit::Nanosecond) => {
+            Arc::new(DurationNanosecondArray::from(
+                rows.select(vals).copied().collect::<Vec<_>>(),
+            ))
+        }
+        Column::I64(vals, _) if data_type == &ArrowDataType::Date64(DateUnit::Millisecond) => {
+            Arc::new(Date64Array::from(
+                rows.select(vals).copied().collect::<Vec<_>>(),
+            ))
+        }
+        Column::I64(vals, _) => {
+            let mut builder = Int64Builder::new(len);
+
+            for v in rows.select(vals) {
+                builder.append_option(*v).context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::Bool(vals, _) => {
+            let mut builder = BooleanBuilder::new(len);
+
+            for v in rows.select(vals) {
+                builder.append_option(*v).context(ArrowError {})?;
+            }
+
+            Arc::new(builder.finish())
+        }
+        Column::Decimal {
+            values,
+            scale,
+            precision,
+        } => decimal_array(rows.select(values), *precision, *scale)?,
+    };
+
+    Ok(arrow_col)
+}
+
+/// Returns a `Decimal` arrow array of the unscaled decimal `values`
+fn decimal_array<'a>(
+    values: impl Iterator<Item = &'a Option<i128>>,
+    precision: u8,
+    scale: u8,
+) -> Result<ArrayRef> {
+    let mut builder = DecimalBuilder::new(0, precision as usize, scale as usize);
+    for v in values {
+        match v {
+            Some(v) => builder.append_value(*v),
+            None => builder.append_null(),
+        }
+        .context(ArrowError {})?;
+    }
+
+    Ok(Arc::new(builder.finish()))
+}
+
+/// Returns an arrow array of `len` nulls of `data_type`, which must be
+/// a type `Table::all_to_arrow` creates
+fn null_array(data_type: &ArrowDataType, len: usize) -> Result<ArrayRef> {
+    Ok(match data_type {
+        ArrowDataType::Utf8 => Arc::new(StringArray::from(vec![None::<&str>; len])),
+        ArrowDataType::Float64 => Arc::new(Float64Array::from(vec![None; len])),
+        ArrowDataType::Int64 => Arc::new(Int64Array::from(vec![None; len])),
+        ArrowDataType::Duration(TimeUnit::Nanosecond) => {
+            Arc::new(DurationNanosecondArray::from(vec![None; len]))
+        }
+        ArrowDataType::Date64(DateUnit::Millisecond) => {
+            Arc::new(Date64Array::from(vec![None; len]))
+        }
+        ArrowDataType::Boolean => Arc::new(BooleanArray::from(vec![None; len])),
+        &ArrowDataType::Decimal(precision, scale) => {
+            let values = vec![None; len];
+            decimal_array(values.iter(), precision as u8, scale as u8)?
+        }
+        data_type => {
+            return InternalUnsupportedNullArrayType {
+                data_type: data_type.clone(),
+            }
+            .fail()
+        }
+    })
+}
+
+/// Downcasts `array` to `T`, which must be its concrete type
+fn downcast<T: 'static>(array: &ArrayRef) -> &T {
+    array
+        .as_any()
+        .downcast_ref::<T>()
+        .expect("array type matches its data type")
+}
+
+/// Returns `value(i)`, or `None` if entry `i` of `array` is null
+fn array_value<T>(array: &dyn Array, i: usize, value: impl Fn(usize) -> T) -> Option<T> {
+    if array.is_null(i) {
+        None
+    } else {
+        Some(value(i))
+    }
+}
+
+/// Returns the string representation of a WAL `value` whose type no
+/// `Column` can store (see `UnknownValueTypePolicy::Stringify`), or
+/// `None` if it can not be converted
+fn stringify_value(value: &wb::Value<'_>) -> Option<String> {
+    match value.value_type() {
+        wb::ColumnValue::U64Value => value.value_as_u64value().map(|v| v.value().to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the minimum and maximum of `values`, or `None` if there
+/// are no values
+fn values_min_max<T: Ord + Copy>(values: impl Iterator<Item = T>) -> Option<(T, T)> {
+    values.fold(None, |min_max, v| {
+        Some(match min_max {
+            None => (v, v),
+            Some((min, max)) => (min.min(v), max.max(v)),
+        })
+    })
+}
+
+/// What is known about a column name while checking and appending
+/// the rows of a batch, see `BatchColumns`
+#[derive(Debug, Clone, Copy)]
+struct BatchColumn {
+    /// The dictionary id of the name, or `None` if it was not in the
+    /// dictionary when first seen (and has not been added since)
+    id: Option<u32>,
+
+    /// The type the column would have after appending the rows
+    /// checked so far, if it has any values
+    column_type: Option<ColumnType>,
+
+    /// The number of the last checked row with a value of the column
+    last_row: usize,
+}
+
+/// The column names of a batch of rows resolved by
+/// `Table::check_row_types` and `Table::append_row`: the same names
+/// recur in most rows, so each is only looked up in the dictionary
+/// once per batch
+#[derive(Debug, Default)]
+struct BatchColumns<'a> {
+    columns: HashMap<&'a str, BatchColumn>,
+
+    /// The number of rows checked so far
+    num_rows: usize,
+}
+
+impl<'a> BatchColumns<'a> {
+    /// Returns the dictionary id of `column_name`, if known
+    fn id(&self, column_name: &str) -> Option<u32> {
+        self.columns.get(column_name).and_then(|column| column.id)
+    }
+
+    /// Records that `column_name` was added to the dictionary as
+    /// `column_id`
+    fn set_id(&mut self, column_name: &str, column_id: u32) {
+        if let Some(column) = self.columns.get_mut(column_name) {
+            column.id = Some(column_id);
+        }
+    }
+}
+
+/// The rows of a column converted by `Table::column_rows_to_arrow`
+#[derive(Debug, Clone)]
+enum RowSelection<'a> {
+    /// A contiguous range of rows
+    Range(Range<usize>),
+
+    /// The listed rows, in order
+    Rows(&'a [usize]),
+
+    /// The listed rows, in order, with a null for each missing row
+    OptionalRows(&'a [Option<usize>]),
+
+    /// A contiguous range of rows, ending early once the flag is set
+    /// (which is checked every `CANCEL_CHECK_ROWS` rows)
+    Cancellable(Range<usize>, &'a AtomicBool),
+}
+
+/// Number of rows `Table::to_arrow_cancellable` converts between
+/// checks of its cancellation flag
+const CANCEL_CHECK_ROWS: usize = 4096;
+
+impl<'a> RowSelection<'a> {
+    /// Returns the number of selected rows
+    fn len(&self) -> usize {
+        match self {
+            Self::Range(range) | Self::Cancellable(range, _) => range.len(),
+            Self::Rows(rows) => rows.len(),
+            Self::OptionalRows(rows) => rows.len(),
+        }
+    }
+
+    /// Returns the values of `vals` in the selected rows
+    fn select<'v, T>(
+        &'v self,
+        vals: &'v [Option<T>],
+    ) -> Box<dyn Iterator<Item = &'v Option<T>> + 'v> {
+        match self {
+            Self::Range(range) => Box::new(vals[range.clone()].iter()),
+            Self::Rows(rows) => Box::new(rows.iter().map(move |&row| &vals[row])),
+            Self::OptionalRows(rows) => Box::new(
+                rows.iter()
+                    .map(move |row| row.map_or(&None, |row| &vals[row])),
+            ),
+            Self::Cancellable(range, cancel) => Box::new(
+                vals[range.clone()]
+                    .iter()
+                    .enumerate()
+                    .take_while(move |(i, _)| {
+                        i % CANCEL_CHECK_ROWS != 0 || !cancel.load(Ordering::Relaxed)
+                    })
+                    .map(|(_, v)| v),
+            ),
+        }
+    }
+}
+
+/// Number of bits in each `TagValueFilter`
+const TAG_VALUE_FILTER_BITS: usize = 4096;
+
+/// Number of bits set in a `TagValueFilter` for each value
+const TAG_VALUE_FILTER_HASHES: u64 = 3;
+
+/// A bloom filter over the value ids of a tag column: it may report
+/// that it contains an id that was never inserted (a false positive),
+/// but never that it does not contain an id that was
+#[derive(Debug, Clone)]
+struct TagValueFilter {
+    bits: Vec<u64>,
+}
+
+impl TagValueFilter {
+    fn new() -> Self {
+        Self {
+            bits: vec![0; TAG_VALUE_FILTER_BITS / 64],
+        }
+    }
+
+    fn insert(&mut self, value_id: u32) {
+        for bit in Self::bit_indexes(value_id) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn maybe_contains(&self, value_id: u32) -> bool {
+        Self::bit_indexes(value_id).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the indexes of the bits for `value_id`, derived from two
+    /// halves of one hash (as described by Kirsch and Mitzenmacher)
+    fn bit_indexes(value_id: u32) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        value_id.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32);
+
+        (0..TAG_VALUE_FILTER_HASHES).map(move |i| {
+            (h1.wrapping_add(i.wrapping_mul(h2)) % TAG_VALUE_FILTER_BITS as u64) as usize
+        })
+    }
+}
+
+/// Returns the `(column name, value)` of `expr` if it is an
+/// equality comparison of a column with a (non null) string literal,
+/// written either way around
+fn tag_equality(expr: &Expr) -> Option<(&str, &str)> {
+    match expr {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::Eq,
+            right,
+        } => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(name), Expr::Literal(ScalarValue::Utf8(Some(value))))
+            | (Expr::Literal(ScalarValue::Utf8(Some(value))), Expr::Column(name)) => {
+                Some((name, value))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the non null values of `vals` in the rows whose time (in
+/// `times`) is within `range`, if any. Without `times` (a table with
+/// no time column) no row is within a range.
+fn values_in_range<'a, T>(
+    vals: &'a [Option<T>],
+    times: Option<&'a [Option<i64>]>,
+    range: Option<TimestampRange>,
+) -> impl Iterator<Item = &'a T> + 'a {
+    vals.iter()
+        .enumerate()
+        .filter(move |(row, _)| match (range, times) {
+            (Some(range), Some(times)) => range.contains_opt(times[*row]),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .filter_map(|(_, val)| val.as_ref())
+}
+
+/// Formats the value in `row` of `column` for `Table::diff`, looking
+/// up tag values in the dictionary of `partition`
+fn describe_value(column: &Column, row: usize, partition: &Partition) -> String {
+    fn describe<T: std::fmt::Display>(v: Option<&Option<T>>) -> String {
+        match v {
+            Some(Some(v)) => v.to_string(),
+            Some(None) | None => "NULL".to_string(),
+        }
+    }
+
+    match column {
+        Column::F64(vals, _) => describe(vals.get(row)),
+        Column::I64(vals, _) => describe(vals.get(row)),
+        Column::String(vals, _) => describe(vals.get(row)),
+        Column::Bool(vals, _) => describe(vals.get(row)),
+        Column::Decimal { values, scale, .. } => match values.get(row) {
+            Some(Some(v)) => format_decimal(*v, *scale),
+            Some(None) | None => "NULL".to_string(),
+        },
+        Column::Tag(vals, _) => match vals.get(row) {
+            Some(Some(value_id)) => match partition.dictionary.lookup_id(*value_id) {
+                Ok(tag_value) => tag_value.to_string(),
+                Err(_) => format!("<tag value id {}>", value_id),
+            },
+            Some(None) | None => "NULL".to_string(),
+        },
+    }
+}
+
+/// Traits to help creating DataFuson expressions from strings
+trait IntoExpr {
+    /// Creates a DataFuson expr
+    fn into_expr(&self) -> Expr;
+
+    /// creates a DataFusion SortExpr
+    fn into_sort_expr(&self) -> Expr {
+        self.into_sort_expr_with_nulls(true)
+    }
+
+    /// creates a DataFusion SortExpr, sorting nulls first or last as
+    /// specified by `nulls_first`
+    fn into_sort_expr_with_nulls(&self, nulls_first: bool) -> Expr {
+        Expr::Sort {
+            expr: Box::new(self.into_expr()),
+            asc: true, // Sort ASCENDING
+            nulls_first,
+        }
+    }
+}
+
+impl IntoExpr for Arc<String> {
+    fn into_expr(&self) -> Expr {
+        Expr::Column(self.as_ref().clone())
+    }
+}
+
+impl IntoExpr for str {
+    fn into_expr(&self) -> Expr {
+        Expr::Column(self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::util::pretty::pretty_format_batches;
+    use influxdb_line_protocol::ParsedLine;
+    use query::{
+        exec::Executor,
+        predicate::{Predicate, PredicateBuilder},
+        simple_predicate::parse_simple_predicate,
+    };
+    use test_helpers::str_vec_to_arc_vec;
+    use tokio::stream::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn test_has_columns() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let state_symbol = dictionary.id("state").unwrap();
+        let new_symbol = dictionary.lookup_value_or_insert("not_a_columns");
+
+        assert!(table.has_columns(None));
+
+        let pred = PartitionIdSet::AtLeastOneMissing;
+        assert!(!table.has_columns(Some(&pred)));
+
+        let set = BTreeSet::<u32>::new();
+        let pred = PartitionIdSet::Present(set);
+        assert!(table.has_columns(Some(&pred)));
+
+        let mut set = BTreeSet::new();
+        set.insert(state_symbol);
+        let pred = PartitionIdSet::Present(set);
+        assert!(table.has_columns(Some(&pred)));
+
+        let mut set = BTreeSet::new();
+        set.insert(new_symbol);
+        let pred = PartitionIdSet::Present(set);
+        assert!(!table.has_columns(Some(&pred)));
+
+        let mut set = BTreeSet::new();
+        set.insert(state_symbol);
+        set.insert(new_symbol);
+        let pred = PartitionIdSet::Present(set);
+        assert!(!table.has_columns(Some(&pred)));
+    }
+
+    #[test]
+    fn test_matches_table_name_predicate() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let h2o_symbol = dictionary.id("h2o").unwrap();
+
+        assert!(table.matches_table_name_predicate(None, None, dictionary));
+
+        let set = BTreeSet::new();
+        assert!(!table.matches_table_name_predicate(Some(&set), None, dictionary));
+
+        let mut set = BTreeSet::new();
+        set.insert(h2o_symbol);
+        assert!(table.matches_table_name_predicate(Some(&set), None, dictionary));
+
+        // Some symbol that is not the same as h2o_symbol
+        assert_ne!(37377, h2o_symbol);
+        let mut set = BTreeSet::new();
+        set.insert(37377);
+        assert!(!table.matches_table_name_predicate(Some(&set), None, dictionary));
+    }
+
+    #[test]
+    fn test_could_match_predicate_scan_stats() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        let time_column_id = dictionary.lookup_value(TIME_COLUMN_NAME).unwrap();
+
+        let mut scan_stats = ScanStats::default();
+
+        // no range, so the time column is not consulted
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(table
+            .could_match_predicate(&partition_predicate, &partition, &mut scan_stats)
+            .unwrap());
+        assert_eq!(scan_stats, ScanStats::default());
+
+        // range overlapping the data
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 300)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(table
+            .could_match_predicate(&partition_predicate, &partition, &mut scan_stats)
+            .unwrap());
+
+        // range entirely after the data prunes the table
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(251, 300)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(!table
+            .could_match_predicate(&partition_predicate, &partition, &mut scan_stats)
+            .unwrap());
+
+        let expected = ColumnScanStats {
+            checks: 2,
+            rows_examined: 0,
+            pruned: 1,
+        };
+        assert_eq!(scan_stats.column(time_column_id), Some(&expected));
+        assert_eq!(scan_stats.columns().count(), 1);
+    }
+
+    #[test]
+    fn test_row_count_matching() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let row_count_matching = |predicate: &Predicate| {
+            let partition_predicate = partition.compile_predicate(predicate).unwrap();
+            table.row_count_matching(&partition_predicate).unwrap()
+        };
+
+        // no predicate, or a range containing all the rows
+        assert_eq!(
+            row_count_matching(&PredicateBuilder::default().build()),
+            Some(3)
+        );
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(100, 251)
+            .build();
+        assert_eq!(row_count_matching(&predicate), Some(3));
+
+        // a range excluding all the rows
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(251, 300)
+            .build();
+        assert_eq!(row_count_matching(&predicate), Some(0));
+
+        // a range containing only some rows needs a scan
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 300)
+            .build();
+        assert_eq!(row_count_matching(&predicate), None);
+
+        // as do general expressions
+        let predicate = parse_simple_predicate("city = 'LA'").unwrap();
+        assert_eq!(row_count_matching(&predicate), None);
+    }
+
+    #[test]
+    fn test_type_conflict_policy() {
+        let lp_lines = vec!["h2o,state=MA val=1i 100", "h2o,state=MA val=2.5 200"];
+
+        // default policy rejects the float written to the integer column
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let res = try_write_lines_to_table(&mut table, dictionary, lp_lines.clone());
+        assert!(res.is_err(), "Expected error, got {:?}", res);
+
+        // promotion converts the existing values to f64
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        table.type_conflict_policy = TypeConflictPolicy::PromoteNumeric;
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let val_id = dictionary.id("val").unwrap();
+        match table.column(val_id).unwrap() {
+            Column::F64(vals, _) => assert_eq!(vals, &vec![Some(1.0), Some(2.5)]),
+            col => panic!("Expected F64 column, got {:?}", col),
+        }
+    }
+
+    #[test]
+    fn test_arrow_schema_ref_cached() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec!["h2o,state=MA,city=Boston temp=70.4 100"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let batch1 = table.all_to_arrow(&partition).unwrap();
+        let batch2 = table.all_to_arrow(&partition).unwrap();
+        assert!(Arc::ptr_eq(&batch1.schema(), &batch2.schema()));
+
+        // appending rows with the same columns keeps the schema
+        let dictionary = &mut partition.dictionary;
+        let lp_lines = vec!["h2o,state=MA,city=Boston temp=72.4 250"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let batch3 = table.all_to_arrow(&partition).unwrap();
+        assert!(Arc::ptr_eq(&batch1.schema(), &batch3.schema()));
+        assert_eq!(batch3.num_rows(), 2);
+
+        // adding a column rebuilds it
+        let dictionary = &mut partition.dictionary;
+        let lp_lines = vec!["h2o,state=MA,city=Boston temp=72.4,other=1i 300"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let batch4 = table.all_to_arrow(&partition).unwrap();
+        assert!(!Arc::ptr_eq(&batch1.schema(), &batch4.schema()));
+        assert_eq!(batch4.schema().fields().len(), 5);
+    }
+
+    #[test]
+    fn test_matches_table_name_regex() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec!["h2o,state=MA,city=Boston temp=70.4 100"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let matching = Regex::new("^h2.*").unwrap();
+        assert!(table.matches_table_name_predicate(None, Some(&matching), dictionary));
+
+        let not_matching = Regex::new("^cpu.*").unwrap();
+        assert!(!table.matches_table_name_predicate(None, Some(&not_matching), dictionary));
+
+        // both the id set and the regex must match
+        let mut set = BTreeSet::new();
+        set.insert(37377);
+        assert!(!table.matches_table_name_predicate(Some(&set), Some(&matching), dictionary));
+
+        // the regex is applied via the compiled predicate too
+        let predicate = PredicateBuilder::default()
+            .table_regex(not_matching)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(!table
+            .could_match_predicate(&partition_predicate, &partition, &mut ScanStats::default())
+            .unwrap());
+
+        let predicate = PredicateBuilder::default().table_regex(matching).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(table
+            .could_match_predicate(&partition_predicate, &partition, &mut ScanStats::default())
+            .unwrap());
+    }
+
+    #[test]
+    fn test_tag_value_filters() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        // values written both before and after the filters are enabled
+        let lp_lines = (0..1000)
+            .map(|i| format!("h2o,city=city{} temp={}.0 {}", i, i, i))
+            .collect::<Vec<_>>();
+        let lp_lines = lp_lines.iter().map(|l| l.as_str()).collect::<Vec<_>>();
+        write_lines_to_table(&mut table, dictionary, lp_lines[..500].to_vec());
+        table.enable_tag_value_filters();
+        write_lines_to_table(&mut table, dictionary, lp_lines[500..].to_vec());
+
+        let city_id = dictionary.lookup_value("city").unwrap();
+        let temp_id = dictionary.lookup_value("temp").unwrap();
+
+        // no false negatives
+        for i in 0..1000 {
+            let value_id = dictionary.lookup_value(&format!("city{}", i)).unwrap();
+            assert!(
+                table.maybe_contains_tag_value(city_id, value_id),
+                "city{}",
+                i
+            );
+        }
+
+        // only tag columns contain tag values
+        let value_id = dictionary.lookup_value("city0").unwrap();
+        assert!(!table.maybe_contains_tag_value(temp_id, value_id));
+
+        let mut other = Table::new(dictionary.lookup_value_or_insert("o2"));
+        write_lines_to_table(
+            &mut other,
+            dictionary,
+            vec!["o2,city=Boston reading=1.0 100"],
+        );
+        let mut la = Table::new(dictionary.lookup_value_or_insert("o2"));
+        write_lines_to_table(&mut la, dictionary, vec!["o2,city=LA reading=1.0 100"]);
+        la.enable_tag_value_filters();
+
+        let could_match = |table: &Table, predicate: &str| {
+            let predicate = parse_simple_predicate(predicate).unwrap();
+            let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+            let mut scan_stats = ScanStats::default();
+            let matches = table
+                .could_match_predicate(&partition_predicate, &partition, &mut scan_stats)
+                .unwrap();
+            (matches, scan_stats)
+        };
+
+        // the filter prunes the table without reading any values
+        let (matches, scan_stats) = could_match(&la, "city = 'Boston'");
+        assert!(!matches);
+        let city_stats = scan_stats.column(city_id).unwrap();
+        assert_eq!((city_stats.pruned, city_stats.rows_examined), (1, 0));
+
+        assert!(could_match(&la, "city = 'LA'").0);
+        assert!(could_match(&table, "city = 'city999'").0);
+
+        // tables without filters are not pruned on tag values
+        assert!(could_match(&other, "city = 'LA'").0);
+    }
+
+    #[tokio::test]
+    async fn test_could_match_computed_column_predicate() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        // no temp exceeds 100, but twice the second one does
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=40.0 100",
+            "h2o,state=MA,city=Boston temp=60.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let mut other = Table::new(dictionary.lookup_value_or_insert("o2"));
+        write_lines_to_table(&mut other, dictionary, vec!["o2,state=MA reading=1.0 100"]);
+
+        // temp * 2 > 100
+        let expr = Expr::BinaryExpr {
+            left: Box::new(Expr::BinaryExpr {
+                left: Box::new(logical_plan::col("temp")),
+                op: Operator::Multiply,
+                right: Box::new(logical_plan::lit(2.0)),
+            }),
+            op: Operator::Gt,
+            right: Box::new(logical_plan::lit(100.0)),
+        };
+        let predicate = PredicateBuilder::default().add_expr(expr).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        assert!(table
+            .could_match_predicate(&partition_predicate, &partition, &mut ScanStats::default())
+            .unwrap());
+
+        // a table without the temp column can't match
+        assert!(!other
+            .could_match_predicate(&partition_predicate, &partition, &mut ScanStats::default())
+            .unwrap());
+
+        // and the table does have a matching row
+        let plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+        let results = run_plan(plan.plan).await;
+
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 60   | 200  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[test]
+    fn test_duplicate_column_in_row() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        write_lines_to_table(&mut table, dictionary, vec!["h2o,state=MA temp=70.4 100"]);
+
+        let lp_lines = vec!["h2o,state=MA temp=71.4,temp=72.4 200"];
+        let res = validate_lines(&table, dictionary, &lp_lines.join("\n"));
+        assert!(
+            matches!(res, Err(Error::DuplicateColumnInRow { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let res = try_write_lines_to_table(&mut table, dictionary, lp_lines);
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            format!(
+                "Row insert to table {} contains column temp more than once",
+                table.id
+            )
+        );
+
+        // the row was not (partially) appended
+        assert_eq!(table.row_count(), 1);
+        assert!(table.columns.iter().all(|c| c.len() == 1));
+    }
+
+    #[test]
+    fn test_validate_rows() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec!["h2o,state=MA,city=Boston temp=70.4,count=1i 100"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // compatible rows (including new columns) pass
+        let res = validate_lines(
+            &table,
+            dictionary,
+            "h2o,state=MA,city=Boston temp=71.4,count=2i,desc=\"ok\" 200",
+        );
+        assert!(res.is_ok(), "unexpected result: {:?}", res);
+
+        // conflicting with an existing column
+        let res = validate_lines(
+            &table,
+            dictionary,
+            "h2o,state=MA,city=Boston temp=72.4 300\nh2o,state=MA,city=Boston temp=1i 400",
+        );
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Column error on column temp: Unable to insert i64 type into a column of f64"
+        );
+
+        // conflicting with a column added earlier in the same batch
+        let res = validate_lines(
+            &table,
+            dictionary,
+            "h2o,state=MA,city=Boston new_field=1.0 300\nh2o,state=MA,city=Boston new_field=true 400",
+        );
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Column error on column new_field: Unable to insert bool type into a column of f64"
+        );
+
+        // promoting integers to floats is allowed when requested
+        let res = validate_lines(&table, dictionary, "h2o count=1.5 300");
+        assert!(res.is_err());
+        table.type_conflict_policy = TypeConflictPolicy::PromoteNumeric;
+        let res = validate_lines(&table, dictionary, "h2o count=1.5 300\nh2o count=2.5 400");
+        assert!(res.is_ok(), "unexpected result: {:?}", res);
+
+        // the table was not modified
+        assert_eq!(table.row_count(), 1);
+        assert_eq!(table.num_columns(), 5);
+        assert!(matches!(
+            table.columns[table.column_id_to_index[&dictionary.lookup_value("count").unwrap()]],
+            Column::I64(_, _)
+        ));
+        assert!(dictionary.lookup_value("new_field").is_err());
+    }
+
+    #[test]
+    fn test_append_rows_strict() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let expected = ArrowSchema::new(vec![
+            ArrowField::new("state", ArrowDataType::Utf8, true),
+            ArrowField::new("temp", ArrowDataType::Float64, true),
+            ArrowField::new("desc", ArrowDataType::Utf8, true),
+            ArrowField::new(
+                "time",
+                ArrowDataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]);
+
+        let mut append = |table: &mut Table, lp: &str| {
+            let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
+            let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+            let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+            let entry = batch.entries().expect("at least one entry").get(0);
+            let table_batch = entry
+                .table_batches()
+                .expect("there were table batches")
+                .get(0);
+            let rows = table_batch.rows().expect("Had rows in the batch");
+            table.append_rows_strict(dictionary, &rows, &expected)
+        };
+
+        // a subset of the expected columns is fine
+        append(
+            &mut table,
+            "h2o,state=MA temp=70.4 100\nh2o,state=CA temp=90.0,desc=\"hot\" 200",
+        )
+        .unwrap();
+        assert_eq!(table.row_count(), 2);
+
+        // a new column rejects the whole write, including earlier rows
+        let err = append(
+            &mut table,
+            "h2o,state=MA temp=72.4 300\nh2o,state=MA,city=Boston temp=72.4 400",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column 'city' is not in the expected schema"
+        );
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(table.num_columns(), 4);
+
+        // as does a value of the wrong type
+        let err = append(&mut table, "h2o,state=MA temp=72i 300").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column 'temp' has i64 values, but is of arrow type Float64 in the expected schema"
+        );
+        let err = append(&mut table, "h2o,temp=warm 300").unwrap_err();
+        assert!(matches!(err, Error::UnexpectedColumnType { .. }));
+
+        // tags and strings are both Utf8, so are checked against the
+        // existing columns
+        let err = append(&mut table, "h2o state=\"MA\",temp=72.4 300").unwrap_err();
+        assert!(matches!(err, Error::ColumnError { .. }));
+        assert_eq!(table.row_count(), 2);
+    }
+
+    /// Runs `Table::validate_rows` on the rows of the WAL entry
+    /// created from the line protocol `lp`
+    fn validate_lines(table: &Table, dictionary: &Dictionary, lp: &str) -> Result<()> {
+        let lines: Vec<_> = parse_lines(lp).map(|l| l.unwrap()).collect();
+        let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+        let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+
+        for entry in batch.entries().expect("at least one entry") {
+            for batch in entry.table_batches().expect("there were table batches") {
+                let rows = batch.rows().expect("Had rows in the batch");
+                table.validate_rows(dictionary, &rows)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rows_tracked() {
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 250",
+            "h2o,state=MA,city=Boston temp=72.4 100",
+            "h2o,state=CA,city=LA temp=90.0 400",
+            "h2o,state=CA,city=LA temp=91.0 200",
+        ];
+        let lp_data = lp_lines.join("\n");
+
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lines: Vec<_> = parse_lines(&lp_data).map(|l| l.unwrap()).collect();
+        let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+        let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+
+        let mut time_ranges = vec![];
+        for entry in batch.entries().expect("at least one entry") {
+            for batch in entry.table_batches().expect("there were table batches") {
+                let rows = batch.rows().expect("Had rows in the batch");
+                time_ranges.push(table.append_rows_tracked(dictionary, &rows, None).unwrap());
+            }
+        }
+
+        assert_eq!(time_ranges, vec![Some((100, 400))]);
+        assert_eq!(table.row_count(), 4);
+    }
+
+    #[test]
+    fn test_append_late_column_type_conflict() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        // humidity is introduced by the second row, as a float, and
+        // back-filled with a null for the first row
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=MA temp=72.4,humidity=50.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // a later row with an int humidity is rejected, naming the
+        // column. It also has a new tag, and values for existing
+        // columns before the conflicting one
+        let res = try_write_lines_to_table(
+            &mut table,
+            dictionary,
+            vec!["h2o,state=CA,city=LA humidity=5i 300"],
+        );
+        let err = res.unwrap_err();
+        assert!(
+            matches!(&err, Error::ColumnError { column, .. } if column == "humidity"),
+            "unexpected error: {:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "Column error on column humidity: Unable to insert i64 type into a column of f64"
+        );
+
+        // the rejected row left the table unchanged
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(table.columns.len(), 4);
+        assert!(table.columns.iter().all(|col| col.len() == 2));
+        assert!(dictionary.lookup_value("city").is_err());
+
+        write_lines_to_table(
+            &mut table,
+            dictionary,
+            vec!["h2o,state=CA humidity=5.0 300"],
+        );
+
+        let batch = table
+            .to_arrow(&partition, &["state", "humidity", "temp", "time"])
+            .unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        let expected = vec![
+            "+-------+----------+------+------+",
+            "| state | humidity | temp | time |",
+            "+-------+----------+------+------+",
+            "| MA    |          | 70.4 | 100  |",
+            "| MA    | 50       | 72.4 | 200  |",
+            "| CA    | 5        |      | 300  |",
+            "+-------+----------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[test]
+    fn test_unknown_value_type_policy() {
+        // rows of (state tag, u64 count, time)
+        let data = u64_rows_table_batch(&[("MA", 1, 100), ("CA", u64::MAX, 200)]);
+        let table_batch = flatbuffers::get_root::<wb::TableWriteBatch<'_>>(&data);
+        let rows = table_batch.rows().expect("Had rows in the batch");
+
+        let append = |policy| {
+            let mut partition = Partition::new("dummy_partition_key");
+            let dictionary = &mut partition.dictionary;
+            let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+            table.unknown_value_type_policy = policy;
+            table
+                .append_rows(dictionary, &rows)
+                .map(|_| table.all_to_arrow(&partition).unwrap())
+        };
+
+        let res = append(UnknownValueTypePolicy::Reject);
+        assert!(
+            matches!(&res, Err(Error::ColumnError { column, .. }) if column == "count"),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let batch = append(UnknownValueTypePolicy::Skip).unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+-------+------+",
+            "| state | time |",
+            "+-------+------+",
+            "| MA    | 100  |",
+            "| CA    | 200  |",
+            "+-------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let batch = append(UnknownValueTypePolicy::Stringify).unwrap();
+        assert_eq!(batch.schema().field(0).data_type(), &ArrowDataType::Utf8);
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+----------------------+-------+------+",
+            "| count                | state | time |",
+            "+----------------------+-------+------+",
+            "| 1                    | MA    | 100  |",
+            "| 18446744073709551615 | CA    | 200  |",
+            "+----------------------+-------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[test]
+    fn test_append_rows_partition_time_bounds() {
+        let append_impl = |policy, lp_lines: &[&str], validate, metrics: &mut IngestMetrics| {
+            let mut partition = Partition::new("dummy_partition_key");
+            let dictionary = &mut partition.dictionary;
+            let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+            table.partition_time_bounds = Some((100, 200));
+            table.out_of_bounds_policy = policy;
+
+            let lp_data = lp_lines.join("\n");
+            let lines: Vec<_> = parse_lines(&lp_data).map(|l| l.unwrap()).collect();
+            let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+            let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+            let entry = batch.entries().expect("at least one entry").get(0);
+            let table_batch = entry
+                .table_batches()
+                .expect("there were table batches")
+                .get(0);
+            let rows = table_batch.rows().expect("Had rows in the batch");
+
+            if validate {
+                table.validate_rows(dictionary, &rows)?;
+            }
+            table
+                .append_rows_tracked(dictionary, &rows, Some(metrics))
+                .map(|_| table.row_count())
+        };
+        let append = |policy, lp_lines: &[&str], metrics: &mut IngestMetrics| {
+            append_impl(policy, lp_lines, true, metrics)
+        };
+
+        // the bounds are inclusive
+        let in_bounds = ["h2o,state=MA temp=70.4 100", "h2o,state=MA temp=72.4 200"];
+        for &policy in &[OutOfBoundsPolicy::Reject, OutOfBoundsPolicy::Warn] {
+            let mut metrics = IngestMetrics::default();
+            assert_eq!(append(policy, &in_bounds, &mut metrics).unwrap(), 2);
+            assert_eq!(metrics.rows_outside_partition, 0);
+        }
+
+        let out_of_bounds = ["h2o,state=MA temp=70.4 100", "h2o,state=MA temp=72.4 201"];
+
+        let mut metrics = IngestMetrics::default();
+        let res = append(OutOfBoundsPolicy::Reject, &out_of_bounds, &mut metrics);
+        assert!(
+            matches!(
+                res,
+                Err(Error::RowOutsidePartition {
+                    time: 201,
+                    min_time: 100,
+                    max_time: 200
+                })
+            ),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(metrics.rows_appended, 0);
+
+        let mut metrics = IngestMetrics::default();
+        let res = append(OutOfBoundsPolicy::Warn, &out_of_bounds, &mut metrics);
+        assert_eq!(res.unwrap(), 2);
+        assert_eq!(metrics.rows_appended, 2);
+        assert_eq!(metrics.rows_outside_partition, 1);
+
+        // an out of bounds row rejected by a type conflict is not
+        // counted as appended outside the partition
+        let conflicting = ["h2o,state=MA temp=70.4 100", "h2o,state=MA temp=1i 201"];
+        let mut metrics = IngestMetrics::default();
+        let res = append_impl(OutOfBoundsPolicy::Warn, &conflicting, false, &mut metrics);
+        assert!(res.is_err(), "unexpected result: {:?}", res);
+        assert_eq!(metrics.rows_appended, 1);
+        assert_eq!(metrics.type_conflicts, 1);
+        assert_eq!(metrics.rows_outside_partition, 0);
+    }
+
+    #[test]
+    fn test_append_rows_metrics() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+        let mut metrics = IngestMetrics::default();
+
+        let mut append = |lp_lines: &[&str], metrics: &mut IngestMetrics| {
+            let lp_data = lp_lines.join("\n");
+            let lines: Vec<_> = parse_lines(&lp_data).map(|l| l.unwrap()).collect();
+            let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+            let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+            let entry = batch.entries().expect("at least one entry").get(0);
+            let table_batch = entry
+                .table_batches()
+                .expect("there were table batches")
+                .get(0);
+            let rows = table_batch.rows().expect("Had rows in the batch");
+            table.append_rows_tracked(dictionary, &rows, Some(metrics))
+        };
+
+        // humidity is only in the second row
+        append(
+            &[
+                "h2o,state=MA temp=70.4 100",
+                "h2o,state=MA temp=72.4,humidity=50.0 200",
+                "h2o,state=CA temp=90.0 300",
+            ],
+            &mut metrics,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metrics,
+            IngestMetrics {
+                rows_appended: 3,
+                // state, temp, time and then humidity
+                columns_created: 4,
+                // humidity for the first and third rows
+                null_backfills: 2,
+                type_conflicts: 0,
+                rows_outside_partition: 0,
+            }
+        );
+
+        // a type conflict is counted, even though the row is rejected
+        assert!(append(&["h2o,state=CA temp=1i 400"], &mut metrics).is_err());
+        assert_eq!(metrics.type_conflicts, 1);
+        assert_eq!(metrics.rows_appended, 3);
+    }
+
+    #[test]
+    fn test_append_rows_column_name_lookups() {
+        let lp_lines: Vec<_> = (0..50)
+            .map(|i| format!("h2o,state=MA,city=Boston temp={}.5 {}", i, i))
+            .collect();
+        let lp_data = lp_lines.join("\n");
+        let lines: Vec<_> = parse_lines(&lp_data).map(|l| l.unwrap()).collect();
+        let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+        let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+        let entry = batch.entries().expect("at least one entry").get(0);
+        let table_batch = entry
+            .table_batches()
+            .expect("there were table batches")
+            .get(0);
+        let rows = table_batch.rows().expect("Had rows in the batch");
+
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        // each of the 4 column names is looked up (and then inserted)
+        // once, and the 2 tag values of every row are looked up
+        let initial_lookups = dictionary.value_lookups();
+        table.append_rows(dictionary, &rows).unwrap();
+        assert_eq!(table.row_count(), 50);
+        assert_eq!(dictionary.value_lookups() - initial_lookups, 4 * 2 + 50 * 2);
+
+        // appending the rows one batch at a time looks up the names for
+        // every row, and finds the same ids
+        let mut other_partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut other_partition.dictionary;
+        let mut other_table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+        let initial_lookups = dictionary.value_lookups();
+        for line in &lp_lines {
+            let lines: Vec<_> = parse_lines(line).map(|l| l.unwrap()).collect();
+            let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+            let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+            let entry = batch.entries().expect("at least one entry").get(0);
+            let table_batch = entry
+                .table_batches()
+                .expect("there were table batches")
+                .get(0);
+            let rows = table_batch.rows().expect("Had rows in the batch");
+            other_table.append_rows(dictionary, &rows).unwrap();
+        }
+        assert_eq!(
+            dictionary.value_lookups() - initial_lookups,
+            4 * 2 + 49 * 4 + 50 * 2
+        );
+
+        assert_eq!(table.column_id_to_index, other_table.column_id_to_index);
+        let batch = table.to_arrow(&partition, &[]).unwrap();
+        let other_batch = other_table.to_arrow(&other_partition, &[]).unwrap();
+        assert_eq!(
+            pretty_format_batches(&[batch]).unwrap(),
+            pretty_format_batches(&[other_batch]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_line_protocol_sink() {
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4,humidity=50.0 250",
+            "h2o,state=CA,city=LA temp=90.0,count=2i 200",
+        ];
+
+        let mut bulk_partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut bulk_partition.dictionary;
+        let mut bulk_table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(&mut bulk_table, dictionary, lp_lines.clone());
+
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let mut sink = table.line_protocol_sink(dictionary);
+        let stats = sink.push_line(lp_lines[0]).unwrap();
+        assert_eq!(
+            stats,
+            AppendStats {
+                rows_appended: 1,
+                columns_added: 4,
+            }
+        );
+        for line in &lp_lines[1..] {
+            sink.push_line(line).unwrap();
+        }
+        assert!(sink.push_line("h2o,state=MA temp=").is_err());
+        assert_eq!(
+            sink.stats(),
+            AppendStats {
+                rows_appended: 3,
+                columns_added: 6,
+            }
+        );
+
+        let diff = table.diff(&partition, &bulk_table, &bulk_partition);
+        assert!(diff.is_empty(), "unexpected differences: {:?}", diff);
+    }
+
+    #[test]
+    fn test_append_record_batch() {
+        use arrow::array::{Float32Array, Int32Array, TimestampMillisecondArray, UInt64Array};
+
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(&mut table, dictionary, vec!["h2o,state=MA temp=70.4 100"]);
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("state", ArrowDataType::Utf8, true),
+            ArrowField::new("temp", ArrowDataType::Float32, true),
+            ArrowField::new("count", ArrowDataType::Int32, true),
+            ArrowField::new(
+                "time",
+                ArrowDataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+        ]));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![Some("CA"), None])),
+            Arc::new(Float32Array::from(vec![Some(90.5), None])),
+            Arc::new(Int32Array::from(vec![Some(3), Some(-4)])),
+            Arc::new(TimestampMillisecondArray::from(vec![2, 3])),
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+
+        let stats = table
+            .append_record_batch(dictionary, &batch, &["state"])
+            .unwrap();
+        assert_eq!(
+            stats,
+            AppendStats {
+                rows_appended: 2,
+                columns_added: 1,
+            }
+        );
+
+        let state_id = dictionary.lookup_value("state").unwrap();
+        assert!(matches!(table.column(state_id).unwrap(), Column::Tag(_, _)));
+
+        // lossy or conflicting types are rejected, leaving the table unchanged
+        let append =
+            |table: &mut Table, dictionary: &mut Dictionary, field: ArrowField, array: ArrayRef| {
+                let schema = Arc::new(ArrowSchema::new(vec![field]));
+                let batch = RecordBatch::try_new(schema, vec![array]).unwrap();
+                table.append_record_batch(dictionary, &batch, &[])
+            };
+
+        let res = append(
+            &mut table,
+            dictionary,
+            ArrowField::new("big", ArrowDataType::UInt64, true),
+            Arc::new(UInt64Array::from(vec![u64::MAX])),
+        );
+        assert!(
+            matches!(res, Err(Error::UnsupportedArrowType { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let res = append(
+            &mut table,
+            dictionary,
+            ArrowField::new("temp", ArrowDataType::Int32, true),
+            Arc::new(Int32Array::from(vec![1])),
+        );
+        assert!(
+            matches!(&res, Err(Error::ColumnError { column, .. }) if column == "temp"),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(table.row_count(), 3);
+
+        let batch = table.all_to_arrow(&partition).unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        let expected = vec![
+            "+-------+-------+------+---------+",
+            "| count | state | temp | time    |",
+            "+-------+-------+------+---------+",
+            "|       | MA    | 70.4 | 100     |",
+            "| 3     | CA    | 90.5 | 2000000 |",
+            "| -4    |       |      | 3000000 |",
+            "+-------+-------+------+---------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[test]
+    fn test_append_vector_field() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("lights"));
+        write_lines_to_table(
+            &mut table,
+            dictionary,
+            vec!["lights,room=kitchen on=true 100"],
+        );
+
+        table
+            .append_vector_field(&mut partition, "rgb", &[0.5, 0.25, 1.0], 200)
+            .unwrap();
+        assert_eq!(table.row_count(), 2);
+
+        for column_name in &["rgb_0", "rgb_1", "rgb_2"] {
+            let column_id = partition.dictionary.lookup_value(column_name).unwrap();
+            assert!(matches!(
+                table.column(column_id).unwrap(),
+                Column::F64(_, _)
+            ));
+        }
+
+        let batch = table.all_to_arrow(&partition).unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+------+-------+-------+-------+---------+------+",
+            "| on   | rgb_0 | rgb_1 | rgb_2 | room    | time |",
+            "+------+-------+-------+-------+---------+------+",
+            "| true |       |       |       | kitchen | 100  |",
+            "|      | 0.5   | 0.25  | 1     |         | 200  |",
+            "+------+-------+-------+-------+---------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[test]
+    fn test_decimal_values() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("meter"));
+
+        // a value using the high 64 bits, and a negative one
+        let large = 10_i128.pow(30) + 7;
+        let data = decimal_rows_table_batch(&[(12345, 100), (-50, 200), (large, 300)], 2, 38);
+        let table_batch = flatbuffers::get_root::<wb::TableWriteBatch<'_>>(&data);
+        let rows = table_batch.rows().expect("Had rows in the batch");
+        table.append_rows(dictionary, &rows).unwrap();
+
+        let batch = table.to_arrow(&partition, &["price", "time"]).unwrap();
+        assert_eq!(
+            batch.schema().field(0).data_type(),
+            &ArrowDataType::Decimal(38, 2)
+        );
+        let prices = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<DecimalArray>()
+            .unwrap();
+        let prices = (0..prices.len())
+            .map(|i| prices.value(i))
+            .collect::<Vec<_>>();
+        assert_eq!(prices, vec![12345, -50, large]);
+
+        let price_id = partition.dictionary.lookup_value("price").unwrap();
+        let price = table.column(price_id).unwrap();
+        assert_eq!(describe_value(price, 0, &partition), "123.45");
+        assert_eq!(describe_value(price, 1, &partition), "-0.50");
+
+        // the arrow batch converts back to the same column
+        let mut other_partition = Partition::new("dummy_partition_key");
+        let other_dictionary = &mut other_partition.dictionary;
+        let mut other_table = Table::new(other_dictionary.lookup_value_or_insert("meter"));
+        other_table
+            .append_record_batch(other_dictionary, &batch, &[])
+            .unwrap();
+        assert!(table
+            .diff(&partition, &other_table, &other_partition)
+            .is_empty());
+
+        // decimals with a different scale are a different type
+        let data = decimal_rows_table_batch(&[(1, 400)], 3, 38);
+        let table_batch = flatbuffers::get_root::<wb::TableWriteBatch<'_>>(&data);
+        let rows = table_batch.rows().expect("Had rows in the batch");
+        let res = table.append_rows(&mut partition.dictionary, &rows);
+        assert!(
+            matches!(res, Err(Error::ColumnError { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(table.row_count(), 3);
+    }
+
+    #[test]
+    fn test_append_from_line_protocol() {
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0,count=2i 200",
+        ];
+        let lp_data = lp_lines.join("\n");
+
+        // ingest the lines manually via the WAL
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lines: Vec<_> = parse_lines(&lp_data).map(|l| l.unwrap()).collect();
+        let data = split_lines_into_write_entry_partitions(partition_key_func, &lines);
+        let batch = flatbuffers::get_root::<wb::WriteBufferBatch<'_>>(&data);
+        for entry in batch.entries().expect("at least one entry") {
+            for batch in entry.table_batches().expect("there were table batches") {
+                let rows = batch.rows().expect("Had rows in the batch");
+                table.append_rows(dictionary, &rows).unwrap();
+            }
+        }
+
+        // and directly from line protocol
+        let mut lp_partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut lp_partition.dictionary;
+        let mut lp_table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let stats = lp_table
+            .append_from_line_protocol(dictionary, &lp_data)
+            .unwrap();
+        assert_eq!(
+            stats,
+            AppendStats {
+                rows_appended: 3,
+                columns_added: 5,
+            }
+        );
+
+        let diff = table.diff(&partition, &lp_table, &lp_partition);
+        assert!(diff.is_empty(), "unexpected differences: {:?}", diff);
+
+        // appending more rows only reports the new data
+        let stats = lp_table
+            .append_from_line_protocol(dictionary, "h2o,state=MA,city=Boston temp=71.0 300")
+            .unwrap();
+        assert_eq!(
+            stats,
+            AppendStats {
+                rows_appended: 1,
+                columns_added: 0,
+            }
+        );
+
+        // parse errors are reported
+        let res = lp_table.append_from_line_protocol(dictionary, "h2o,state=MA temp=");
+        assert!(
+            matches!(res, Err(Error::ParsingLineProtocol { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(lp_table.row_count(), 4);
+
+        // lines of other measurements are rejected, and nothing is
+        // appended
+        let res = lp_table.append_from_line_protocol(
+            dictionary,
+            "h2o,state=MA temp=71.5 400\no2,state=MA level=3.0 400",
+        );
+        assert!(
+            matches!(
+                &res,
+                Err(Error::MeasurementMismatch { measurement, .. }) if measurement == "o2"
+            ),
+            "unexpected result: {:?}",
+            res.map(|_| ())
+        );
+        assert_eq!(lp_table.row_count(), 4);
+    }
+
+    #[test]
+    fn test_is_time_sorted() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        // empty table
+        assert!(table.is_time_sorted().unwrap());
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=MA temp=72.4 100",
+            "h2o,state=CA temp=90.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        assert!(table.is_time_sorted().unwrap());
+        // cached result
+        assert!(table.is_time_sorted().unwrap());
+
+        // appending rows invalidates the cached result
+        write_lines_to_table(&mut table, dictionary, vec!["h2o,state=CA temp=90.0 150"]);
+        assert!(!table.is_time_sorted().unwrap());
+
+        // and stays out of order
+        write_lines_to_table(&mut table, dictionary, vec!["h2o,state=CA temp=90.0 300"]);
+        assert!(!table.is_time_sorted().unwrap());
+    }
+
+    #[test]
+    fn test_compact_to_sorted() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 300",
+            "h2o,state=CA,city=LA temp=90.0 100",
+            "h2o,state=MA temp=72.4,reading=\"hot\" 200",
+            "h2o,state=CA,city=SF temp=60.0 100",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        assert!(!table.is_time_sorted().unwrap());
+
+        let sorted = table.compact_to_sorted().unwrap();
+        assert!(sorted.is_time_sorted().unwrap());
+        assert_eq!(sorted.row_count(), 4);
+        assert_eq!(
+            sorted.time_values(&partition).unwrap(),
+            &[Some(100), Some(100), Some(200), Some(300)]
+        );
+
+        // the same rows, with ties (LA and SF) in their original order
+        let batch = sorted.all_to_arrow(&partition).unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+--------+---------+-------+------+------+",
+            "| city   | reading | state | temp | time |",
+            "+--------+---------+-------+------+------+",
+            "| LA     |         | CA    | 90   | 100  |",
+            "| SF     |         | CA    | 60   | 100  |",
+            "|        | hot     | MA    | 72.4 | 200  |",
+            "| Boston |         | MA    | 70.4 | 300  |",
+            "+--------+---------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // the original table is unchanged
+        assert_eq!(
+            table.time_values(&partition).unwrap(),
+            &[Some(300), Some(100), Some(200), Some(100)]
+        );
+    }
+
+    #[test]
+    fn test_schema_fingerprint() {
+        let mut a_partition = Partition::new("a_partition_key");
+        let dictionary = &mut a_partition.dictionary;
+        let mut a = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(&mut a, dictionary, vec!["h2o,state=MA temp=70.4 100"]);
+
+        // b assigns different dictionary ids to the same columns
+        let mut b_partition = Partition::new("b_partition_key");
+        let dictionary = &mut b_partition.dictionary;
+        dictionary.lookup_value_or_insert("some_other_value");
+        let mut b = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(&mut b, dictionary, vec!["h2o,state=CA temp=90.0 200"]);
+
+        assert_eq!(
+            a.schema_fingerprint(&a_partition).unwrap(),
+            b.schema_fingerprint(&b_partition).unwrap()
+        );
+
+        // c has the same column names, but temp is an integer
+        let mut c_partition = Partition::new("c_partition_key");
+        let dictionary = &mut c_partition.dictionary;
+        let mut c = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(&mut c, dictionary, vec!["h2o,state=MA temp=70i 100"]);
+
+        assert_ne!(
+            a.schema_fingerprint(&a_partition).unwrap(),
+            c.schema_fingerprint(&c_partition).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_iter_series() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        // sorted by city and then state, with nulls first
+        let lp_lines = vec![
+            "h2o,state=MA temp=60.0 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=91.0 300",
+            "h2o,state=CA,city=LA temp=92.0 400",
+            "h2o,state=MA,city=LA temp=80.0 100",
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // Boston sorts before LA, so the rows are not sorted
+        let res = table.iter_series(&partition).map(|_| ());
+        assert!(
+            matches!(res, Err(Error::TableNotSeriesSorted { row: 5, .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let mut table = Table::new(partition.dictionary.lookup_value_or_insert("o2"));
+        let lp_lines = vec![
+            "o2,state=MA temp=60.0 100",
+            "o2,state=CA,city=Boston temp=72.4 200",
+            "o2,state=MA,city=Boston temp=70.4 100",
+            "o2,state=MA,city=Boston temp=72.4 200",
+            "o2,state=CA,city=LA temp=90.0 200",
+            "o2,state=CA,city=LA temp=91.0 300",
+        ];
+        write_lines_to_table(&mut table, &mut partition.dictionary, lp_lines);
+
+        let key =
+            |city: Option<&str>, state: &str| vec![city.map(String::from), Some(state.into())];
+        let series = table.iter_series(&partition).unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            series,
+            vec![
+                (key(None, "MA"), 0..1),
+                (key(Some("Boston"), "CA"), 1..2),
+                (key(Some("Boston"), "MA"), 2..4),
+                (key(Some("LA"), "CA"), 4..6),
+            ]
+        );
+
+        // a table without tags is a single series
+        let mut table = Table::new(partition.dictionary.lookup_value_or_insert("cpu"));
+        let lp_lines = vec!["cpu usage=1.0 100", "cpu usage=2.0 200"];
+        write_lines_to_table(&mut table, &mut partition.dictionary, lp_lines);
+        let series = table.iter_series(&partition).unwrap().collect::<Vec<_>>();
+        assert_eq!(series, vec![(vec![], 0..2)]);
+    }
+
+    #[test]
+    fn test_merge_from() {
+        let lp_lines = vec!["h2o,state=MA temp=70.4 100", "h2o,state=CA temp=90.0 200"];
+        let other_lp_lines = vec![
+            "h2o,state=MA humidity=50.0 100",
+            "h2o,state=CA temp=91.0,humidity=20.0 200",
+            "h2o,state=MA humidity=55.0 300",
+        ];
+
+        let merge = |strategy| {
+            let mut partition = Partition::new("a_partition_key");
+            let dictionary = &mut partition.dictionary;
+            let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+            write_lines_to_table(&mut table, dictionary, lp_lines.clone());
+
+            // other has its own dictionary
+            let mut other_partition = Partition::new("b_partition_key");
+            let dictionary = &mut other_partition.dictionary;
+            dictionary.lookup_value_or_insert("some_other_value");
+            let mut other = Table::new(dictionary.lookup_value_or_insert("h2o"));
+            write_lines_to_table(&mut other, dictionary, other_lp_lines.clone());
+
+            table
+                .merge_from(&mut partition, &other, &other_partition, strategy)
+                .unwrap();
+
+            let batch = table.all_to_arrow(&partition).unwrap();
+            let results = pretty_format_batches(&[batch]).unwrap();
+            results
+                .trim()
+                .split('\n')
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let expected = vec![
+            "+----------+-------+------+------+",
+            "| humidity | state | temp | time |",
+            "+----------+-------+------+------+",
+            "|          | MA    | 70.4 | 100  |",
+            "|          | CA    | 90   | 200  |",
+            "| 50       | MA    |      | 100  |",
+            "| 20       | CA    | 91   | 200  |",
+            "| 55       | MA    |      | 300  |",
+            "+----------+-------+------+------+",
+        ];
+        assert_eq!(expected, merge(MergeStrategy::Append), "expected output");
+
+        // the fields of rows of the same series and time are combined,
+        // with the value of the other table winning for CA's temp
+        let expected = vec![
+            "+----------+-------+------+------+",
+            "| humidity | state | temp | time |",
+            "+----------+-------+------+------+",
+            "| 50       | MA    | 70.4 | 100  |",
+            "| 20       | CA    | 91   | 200  |",
+            "| 55       | MA    |      | 300  |",
+            "+----------+-------+------+------+",
+        ];
+        assert_eq!(
+            expected,
+            merge(MergeStrategy::CoalesceBySeriesTime),
+            "expected output"
+        );
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let mut a_partition = Partition::new("a_partition_key");
+        let dictionary = &mut a_partition.dictionary;
+        let mut a = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        let lp_lines = vec!["h2o,state=MA temp=70.4 100", "h2o,state=MA temp=72.4 300"];
+        write_lines_to_table(&mut a, dictionary, lp_lines);
+
+        // b has an extra tag and field, and its own dictionary
+        let mut b_partition = Partition::new("b_partition_key");
+        let dictionary = &mut b_partition.dictionary;
+        let mut b = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        let lp_lines = vec![
+            "h2o,state=CA,city=LA temp=90.5 200",
+            "h2o,state=TX,city=Austin temp=95.5,wet=true 300",
+            "h2o,state=CA,city=LA temp=91.5 400",
+        ];
+        write_lines_to_table(&mut b, dictionary, lp_lines);
+
+        a.set_column_metadata(&a_partition, "temp", "unit", "celsius")
+            .unwrap();
+        b.set_column_metadata(&b_partition, "temp", "unit", "fahrenheit")
+            .unwrap();
+        b.set_column_metadata(&b_partition, "city", "source", "geo")
+            .unwrap();
+
+        let merged = Table::merge_sorted(&a, &mut a_partition, &b, &b_partition).unwrap();
+        assert_eq!(merged.row_count(), 5);
+        assert!(merged.is_time_sorted().unwrap());
+
+        // the metadata of columns in both inputs is taken from a
+        let assert_metadata = |column_name, key: &str, value: &str| {
+            let schema = merged.arrow_schema_ref(&a_partition).unwrap();
+            let mut expected = BTreeMap::new();
+            expected.insert(key.to_string(), value.to_string());
+            assert_eq!(
+                schema.field_with_name(column_name).unwrap().metadata(),
+                &Some(expected)
+            );
+        };
+        assert_metadata("temp", "unit", "celsius");
+        assert_metadata("city", "source", "geo");
+
+        let batch = merged.all_to_arrow(&a_partition).unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        let expected = vec![
+            "+--------+-------+------+------+------+",
+            "| city   | state | temp | time | wet  |",
+            "+--------+-------+------+------+------+",
+            "|        | MA    | 70.4 | 100  |      |",
+            "| LA     | CA    | 90.5 | 200  |      |",
+            "|        | MA    | 72.4 | 300  |      |",
+            "| Austin | TX    | 95.5 | 300  | true |",
+            "| LA     | CA    | 91.5 | 400  |      |",
+            "+--------+-------+------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // inputs must be sorted on time
+        let dictionary = &mut b_partition.dictionary;
+        write_lines_to_table(&mut b, dictionary, vec!["h2o,state=CA temp=90.0 150"]);
+        let res = Table::merge_sorted(&a, &mut a_partition, &b, &b_partition);
+        assert!(
+            matches!(res, Err(Error::TableNotTimeSorted { .. })),
+            "Expected error, got {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        write_lines_to_table(
+            &mut table,
+            dictionary,
+            vec!["h2o,state=MA,city=Boston temp=70.4 100"],
+        );
+
+        table.rename(&mut partition, "water").unwrap();
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+        assert_eq!(series_set_plan.table_name.as_ref(), "water");
+
+        // renaming to the current name is a no-op
+        table.rename(&mut partition, "water").unwrap();
+        assert_eq!(partition.dictionary.lookup_id(table.id).unwrap(), "water");
+
+        // can't rename to the name of another table in the partition
+        let other_id = partition.dictionary.lookup_value_or_insert("o2");
+        partition.tables.insert(other_id, Table::new(other_id));
+        let res = table.rename(&mut partition, "o2");
+        assert!(
+            matches!(res, Err(Error::TableNameCollision { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(partition.dictionary.lookup_id(table.id).unwrap(), "water");
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA temp=90.0,other=1i 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        // removes the state column, shifting the index of later columns
+        table.coalesce_columns(&partition, "city", "state").unwrap();
+
+        let expected = table.column_id_to_index.clone();
+        let expected_batch = table.all_to_arrow(&partition).unwrap();
+        let expected_results = pretty_format_batches(&[expected_batch]).unwrap();
+
+        // corrupt the index: a stale entry, and swapped indexes
+        let state_id = partition.dictionary.lookup_value("state").unwrap();
+        let temp_id = partition.dictionary.lookup_value("temp").unwrap();
+        let time_id = partition.dictionary.lookup_value("time").unwrap();
+        table.column_id_to_index.insert(state_id, 4);
+        table.column_id_to_index.insert(temp_id, expected[&time_id]);
+        table.column_id_to_index.insert(time_id, expected[&temp_id]);
+
+        table.rebuild_index(&partition).unwrap();
+        assert_eq!(table.column_id_to_index, expected);
+        let batch = table.all_to_arrow(&partition).unwrap();
+        assert_eq!(pretty_format_batches(&[batch]).unwrap(), expected_results);
+
+        // the ids of columns added directly are unknown
+        table.columns.push(Column::new_empty(ColumnType::F64));
+        let res = table.rebuild_index(&partition);
+        assert!(
+            matches!(
+                res,
+                Err(Error::ColumnIdsOutOfSync {
+                    num_columns: 5,
+                    num_column_ids: 4
+                })
+            ),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_column_ids() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=MA,city=Boston temperature=72.4 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let assert_in_sync = |table: &Table| {
+            assert_eq!(table.column_ids().len(), table.num_columns());
+            for (column_index, column_id) in table.column_ids().iter().enumerate() {
+                assert_eq!(table.column_id_to_index[column_id], column_index);
+            }
+        };
+
+        assert_in_sync(&table);
+        let column_names = table
+            .column_ids()
+            .iter()
+            .map(|&column_id| partition.dictionary.lookup_id(column_id).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            column_names,
+            vec!["state", "temp", "time", "city", "temperature"]
+        );
+
+        // removing a column keeps the ids in sync
+        table
+            .coalesce_columns(&partition, "temp", "temperature")
+            .unwrap();
+        assert_in_sync(&table);
+        let temperature_id = partition.dictionary.lookup_value("temperature").unwrap();
+        assert!(!table.column_ids().contains(&temperature_id));
+    }
+
+    #[test]
+    fn test_coalesce_columns() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=MA temperature=72.4 200",
+            "h2o,state=CA temp=90.0,temperature=91.0 300",
+            "h2o,state=CA count=1i 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        assert_eq!(table.num_columns(), 5);
+        table
+            .set_column_metadata(&partition, "temperature", "unit", "celsius")
+            .unwrap();
+
+        table
+            .coalesce_columns(&partition, "temp", "temperature")
+            .unwrap();
+
+        assert_eq!(table.num_columns(), 4);
+        let temperature_id = partition.dictionary.lookup_value("temperature").unwrap();
+        assert!(!table.column_id_to_index.contains_key(&temperature_id));
+        assert!(!table.column_metadata.contains_key(&temperature_id));
+
+        let temp_id = partition.dictionary.lookup_value("temp").unwrap();
+        assert_eq!(
+            table.numeric_field_as_f64(temp_id).unwrap(),
+            vec![Some(70.4), Some(72.4), Some(90.0), None]
+        );
+        // the remaining columns are unaffected by the removal
+        let count_id = partition.dictionary.lookup_value("count").unwrap();
+        assert_eq!(
+            table.column_i64(count_id).unwrap(),
+            &[None, None, None, Some(1)]
+        );
+
+        // can't coalesce columns of different types
+        let res = table.coalesce_columns(&partition, "temp", "count");
+        assert!(
+            matches!(res, Err(Error::CoalesceColumnTypeMismatch { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(table.num_columns(), 4);
+
+        // nor unknown columns
+        let res = table.coalesce_columns(&partition, "temp", "temperature");
+        assert!(
+            matches!(res, Err(Error::CoalesceColumnNotFound { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        // decimals with different scales fail to coalesce, leaving
+        // both columns in place
+        let mut partition = Partition::new("dummy_partition_key");
+        let mut table = Table::new(partition.dictionary.lookup_value_or_insert("meter"));
+        let append_decimals = |table: &mut Table, partition: &mut Partition, scale| {
+            let data = decimal_rows_table_batch(&[(12345, scale as i64 * 100)], scale, 38);
+            let table_batch = flatbuffers::get_root::<wb::TableWriteBatch<'_>>(&data);
+            let rows = table_batch.rows().expect("Had rows in the batch");
+            table.append_rows(&mut partition.dictionary, &rows).unwrap();
+        };
+        append_decimals(&mut table, &mut partition, 2);
+        let renames = vec![("price".to_string(), "old_price".to_string())]
+            .into_iter()
+            .collect();
+        table.apply_rename_map(&mut partition, &renames).unwrap();
+        append_decimals(&mut table, &mut partition, 3);
+        assert_eq!(table.num_columns(), 3);
+
+        let res = table.coalesce_columns(&partition, "old_price", "price");
+        assert!(
+            matches!(res, Err(Error::ColumnError { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(table.num_columns(), 3);
+        let column = |column_name| {
+            let column_id = partition.dictionary.lookup_value(column_name).unwrap();
+            table.column(column_id).unwrap()
+        };
+        assert_eq!(describe_value(column("old_price"), 0, &partition), "123.45");
+        assert_eq!(describe_value(column("price"), 1, &partition), "12.345");
+    }
+
+    #[test]
+    fn test_apply_rename_map() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec!["h2o,c=MA a=70.4,b=1i 100", "h2o,c=CA a=90.0,b=2i 200"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let renames = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(from, to)| (from.to_string(), to.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let to_string = |table: &Table, partition: &Partition| {
+            let batch = table.to_arrow(partition, &[]).unwrap();
+            let results = pretty_format_batches(&[batch]).unwrap();
+            results
+                .trim()
+                .split('\n')
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // swap a and b, while renaming c
+        table
+            .apply_rename_map(
+                &mut partition,
+                &renames(&[("a", "b"), ("b", "a"), ("c", "d")]),
+            )
+            .unwrap();
+
+        let expected = vec![
+            "+---+------+----+------+",
+            "| a | b    | d  | time |",
+            "+---+------+----+------+",
+            "| 1 | 70.4 | MA | 100  |",
+            "| 2 | 90   | CA | 200  |",
+            "+---+------+----+------+",
+        ];
+        let renamed = to_string(&table, &partition);
+        assert_eq!(renamed, expected);
+
+        // a rename onto a column that keeps its name changes nothing
+        let err = table
+            .apply_rename_map(&mut partition, &renames(&[("b", "x"), ("a", "d")]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can not rename column 'a' to 'd': another column would have that name"
+        );
+        assert_eq!(to_string(&table, &partition), renamed);
+
+        // nor can two columns be renamed to the same name
+        let err = table
+            .apply_rename_map(&mut partition, &renames(&[("a", "x"), ("b", "x")]))
+            .unwrap_err();
+        assert!(matches!(err, Error::RenameColumnCollision { .. }));
+
+        let err = table
+            .apply_rename_map(&mut partition, &renames(&[("c", "x")]))
+            .unwrap_err();
+        assert!(matches!(err, Error::RenameColumnNotFound { .. }));
+
+        let err = table
+            .apply_rename_map(&mut partition, &renames(&[("time", "x")]))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidTimeColumnRename { .. }));
+        assert_eq!(to_string(&table, &partition), renamed);
+    }
+
+    #[test]
+    fn test_column_id_not_in_dictionary() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(
+            &mut table,
+            dictionary,
+            vec!["h2o,state=MA,city=Boston temp=70.4 100"],
+        );
+
+        // a partition whose dictionary only has the table name, so
+        // the ids of the table's columns are not found
+        let mut corrupt_partition = Partition::new("corrupt_partition_key");
+        assert_eq!(
+            corrupt_partition.dictionary.lookup_value_or_insert("h2o"),
+            table.id
+        );
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let res = table.tag_column_names_plan(&partition_predicate, &corrupt_partition);
+        assert!(
+            matches!(res, Err(Error::ColumnIdNotFoundInDictionary { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let res = table.series_set_plan(&partition_predicate, &corrupt_partition);
+        assert!(
+            matches!(res, Err(Error::ColumnIdNotFoundInDictionary { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let res = table.field_and_time_column_names(
+            &partition_predicate,
+            &FieldSelector::All,
+            &corrupt_partition,
+        );
+        assert!(
+            matches!(res, Err(Error::ColumnIdNotFoundInDictionary { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let res = table.schema_fingerprint(&corrupt_partition);
+        assert!(
+            matches!(res, Err(Error::ColumnIdNotFoundInDictionary { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        // a table whose own id is not in the dictionary, although its
+        // columns are
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(1000);
+        write_lines_to_table(
+            &mut table,
+            dictionary,
+            vec!["h2o,state=MA,city=Boston temp=70.4 100"],
+        );
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let res = table.tag_column_names_plan(&partition_predicate, &partition);
+        assert!(
+            matches!(
+                res,
+                Err(Error::TableIdNotFoundInDictionary { table_id: 1000, .. })
+            ),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_column_counts() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        assert_eq!(table.num_columns(), 0);
+        assert_eq!(table.num_tag_columns(), 0);
+        assert_eq!(table.num_field_columns(&partition), 0);
+
+        let dictionary = &mut partition.dictionary;
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston other_temp=70.4,reading=1i 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        assert_eq!(table.num_columns(), 6);
+        assert_eq!(table.num_tag_columns(), 2);
+        assert_eq!(table.num_field_columns(&partition), 3);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston,county=Suffolk temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // the other table uses a different partition (and dictionary)
+        let mut other_partition = Partition::new("other_partition_key");
+        let dictionary = &mut other_partition.dictionary;
+        let mut other_table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4,reading=1i 100",
+            "h2o,state=CA,city=Boston temp=72.4 250",
+            "h2o,state=MA,city=Boston temp=73.0 300",
+        ];
+        write_lines_to_table(&mut other_table, dictionary, lp_lines);
+
+        assert!(table.diff(&partition, &table, &partition).is_empty());
+
+        let diff = table.diff(&partition, &other_table, &other_partition);
+        let expected = vec![
+            "column removed: county (tag)",
+            "column added: reading (i64)",
+            "row count changed: 2 -> 3",
+            "value changed at row 1 column state: MA -> CA",
+        ];
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn test_numeric_field_as_f64() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA ival=1i,fval=1.0 100",
+            "h2o,state=MA fval=2.0 200",
+            "h2o,state=MA ival=3i,fval=3.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let ival_id = dictionary.id("ival").unwrap();
+        let fval_id = dictionary.id("fval").unwrap();
+        let state_id = dictionary.id("state").unwrap();
+
+        let ivals = table.numeric_field_as_f64(ival_id).unwrap();
+        let fvals = table.numeric_field_as_f64(fval_id).unwrap();
+        assert_eq!(ivals, vec![Some(1.0), None, Some(3.0)]);
+        assert_eq!(fvals, vec![Some(1.0), Some(2.0), Some(3.0)]);
+
+        let res = table.numeric_field_as_f64(state_id);
+        assert!(res.is_err(), "Expected error, got {:?}", res);
+    }
+
+    #[test]
+    fn test_reserve() {
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+        ];
+        let more_lp_lines = vec![
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=MA,city=Boston temp=71.0 400",
+        ];
+
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+        write_lines_to_table(&mut table, dictionary, lp_lines.clone());
+
+        table.reserve(more_lp_lines.len());
+        let capacities = table
+            .columns
+            .iter()
+            .map(|c| c.capacity())
+            .collect::<Vec<_>>();
+        assert!(capacities.iter().all(|&c| c >= 5));
+
+        write_lines_to_table(&mut table, dictionary, more_lp_lines.clone());
+
+        // no column was reallocated
+        let new_capacities = table
+            .columns
+            .iter()
+            .map(|c| c.capacity())
+            .collect::<Vec<_>>();
+        assert_eq!(capacities, new_capacities);
+
+        // and the data is the same as without reserving
+        let mut expected_partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut expected_partition.dictionary;
+        let mut expected_table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+        write_lines_to_table(&mut expected_table, dictionary, lp_lines);
+        write_lines_to_table(&mut expected_table, dictionary, more_lp_lines);
+
+        let diff = table.diff(&partition, &expected_table, &expected_partition);
+        assert!(diff.is_empty(), "unexpected differences: {:?}", diff);
+    }
+
+    #[test]
+    fn test_column_as_arrow() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=CA other=1.0 200",
+            "h2o,state=MA temp=72.4 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let temp_id = partition.dictionary.lookup_value("temp").unwrap();
+        let (field, array) = table.column_as_arrow(&partition, temp_id).unwrap();
+        assert_eq!(field.name(), "temp");
+        assert_eq!(field.data_type(), &ArrowDataType::Float64);
+
+        let array = array.as_any().downcast_ref::<Float64Array>().unwrap();
+        let values = (0..array.len())
+            .map(|i| {
+                if array.is_null(i) {
+                    None
+                } else {
+                    Some(array.value(i))
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(70.4), None, Some(72.4)]);
+
+        // tags are decoded
+        let state_id = partition.dictionary.lookup_value("state").unwrap();
+        let (field, array) = table.column_as_arrow(&partition, state_id).unwrap();
+        assert_eq!(field.data_type(), &ArrowDataType::Utf8);
+        let array = array.as_any().downcast_ref::<StringArray>().unwrap();
+        let values = (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>();
+        assert_eq!(values, vec!["MA", "CA", "MA"]);
+    }
+
+    #[test]
+    fn test_to_arrow_semantic_types() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA elapsed=1000i,born=86400000i,count=1i 100",
+            "h2o,state=MA elapsed=2000i,count=2i 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let elapsed_id = partition.dictionary.lookup_value("elapsed").unwrap();
+        let born_id = partition.dictionary.lookup_value("born").unwrap();
+        table.set_semantic_type(elapsed_id, SemanticType::Duration);
+        table.set_semantic_type(born_id, SemanticType::Date);
+        assert_eq!(
+            table.semantic_type(elapsed_id),
+            Some(SemanticType::Duration)
+        );
+
+        let requested = table
+            .column_names_with_index(&partition, &["born", "count", "elapsed"])
+            .unwrap();
+
+        let data_types = |options: ToArrowOptions| {
+            let batch = table
+                .to_arrow_impl_with_options(&partition, &requested, &options)
+                .unwrap();
+            assert_eq!(batch.num_rows(), 2);
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.data_type().clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Int64 is the default
+        assert_eq!(
+            data_types(ToArrowOptions::default()),
+            vec![
+                ArrowDataType::Int64,
+                ArrowDataType::Int64,
+                ArrowDataType::Int64
+            ]
+        );
+
+        let options = ToArrowOptions {
+            emit_semantic_types: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            data_types(options),
+            vec![
+                ArrowDataType::Date64(DateUnit::Millisecond),
+                ArrowDataType::Int64,
+                ArrowDataType::Duration(TimeUnit::Nanosecond),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_column_metadata() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        write_lines_to_table(&mut table, dictionary, vec!["h2o,state=MA temp=70.4 100"]);
+
+        // cache the schema before setting the metadata
+        assert!(table
+            .arrow_schema_ref(&partition)
+            .unwrap()
+            .field_with_name("temp")
+            .unwrap()
+            .metadata()
+            .is_none());
+
+        table
+            .set_column_metadata(&partition, "temp", "unit", "celsius")
+            .unwrap();
+
+        let mut expected = BTreeMap::new();
+        expected.insert("unit".to_string(), "celsius".to_string());
+
+        let batch = table.to_arrow(&partition, &["state", "temp"]).unwrap();
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).metadata(), &None);
+        assert_eq!(schema.field(1).metadata(), &Some(expected.clone()));
+
+        let schema = table.arrow_schema_ref(&partition).unwrap();
+        let temp = schema.field_with_name("temp").unwrap();
+        assert_eq!(temp.metadata(), &Some(expected));
+
+        let err = table
+            .set_column_metadata(&partition, "humidity", "unit", "percent")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column 'humidity' to set metadata on not found in table"
+        );
+    }
+
+    #[test]
+    fn test_to_arrow_exact_nullability() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=MA temp=72.4,humidity=50.0 200",
+            "h2o,state=CA temp=90.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let requested = table
+            .column_names_with_index(&partition, &["humidity", "state", "temp", "time"])
+            .unwrap();
+
+        let nullable = |options: ToArrowOptions| {
+            let batch = table
+                .to_arrow_impl_with_options(&partition, &requested, &options)
+                .unwrap();
+            let schema = batch.schema();
+            for (field, column) in schema.fields().iter().zip(batch.columns()) {
+                // the flag never contradicts the data
+                assert!(field.is_nullable() || column.null_count() == 0);
+            }
+            schema
+                .fields()
+                .iter()
+                .map(|f| f.is_nullable())
+                .collect::<Vec<_>>()
+        };
+
+        // all fields are nullable by default
+        assert_eq!(
+            nullable(ToArrowOptions::default()),
+            vec![true, true, true, true]
+        );
+
+        let options = ToArrowOptions {
+            exact_nullability: true,
+            ..Default::default()
+        };
+        assert_eq!(nullable(options), vec![true, false, false, false]);
+    }
+
+    #[test]
+    fn test_to_arrow_fixed_size_binary_tags() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,hash=0a1b2c3d,state=MA temp=70.4 100",
+            "h2o,state=CA temp=72.4 200",
+            "h2o,hash=ffee0011,state=MA temp=90.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let requested = table
+            .column_names_with_index(&partition, &["hash", "state", "temp"])
+            .unwrap();
+
+        let options = ToArrowOptions {
+            fixed_size_binary_tags: true,
+            ..Default::default()
+        };
+        let batch = table
+            .to_arrow_impl_with_options(&partition, &requested, &options)
+            .unwrap();
+
+        let data_types = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.data_type().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            data_types,
+            vec![
+                ArrowDataType::FixedSizeBinary(8),
+                // "MA" and "CA" also have the same length
+                ArrowDataType::FixedSizeBinary(2),
+                ArrowDataType::Float64,
+            ]
+        );
+
+        let hashes = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::FixedSizeBinaryArray>()
+            .unwrap();
+        assert_eq!(hashes.value(0), b"0a1b2c3d");
+        assert!(hashes.is_null(1));
+        assert_eq!(hashes.value(2), b"ffee0011");
+
+        // values of different lengths fall back to Utf8
+        write_lines_to_table(
+            &mut table,
+            &mut partition.dictionary,
+            vec!["h2o,hash=abc temp=1.0 400"],
+        );
+        let batch = table
+            .to_arrow_impl_with_options(&partition, &requested, &options)
+            .unwrap();
+        assert_eq!(batch.schema().field(0).data_type(), &ArrowDataType::Utf8);
+    }
+
+    #[test]
+    fn test_to_arrow_dictionary_strings() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        // a status field with long constant runs, and a field with a
+        // distinct value per row
+        let lp_lines = (0..12)
+            .map(|i| {
+                let status = if (4..10).contains(&i) { "error" } else { "ok" };
+                format!("h2o status=\"{}\",desc=\"row {}\" {}", status, i, i)
+            })
+            .collect::<Vec<_>>();
+        let lp_lines = lp_lines.iter().map(|s| s.as_str()).collect();
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        write_lines_to_table(
+            &mut table,
+            &mut partition.dictionary,
+            vec!["h2o desc=\"no status\" 12"],
+        );
+
+        let requested = table
+            .column_names_with_index(&partition, &["status", "desc"])
+            .unwrap();
+
+        let options = ToArrowOptions {
+            dictionary_strings_max_distinct: Some(4),
+            ..Default::default()
+        };
+        let batch = table
+            .to_arrow_impl_with_options(&partition, &requested, &options)
+            .unwrap();
+
+        let dictionary_type = ArrowDataType::Dictionary(
+            Box::new(ArrowDataType::Int32),
+            Box::new(ArrowDataType::Utf8),
+        );
+        assert_eq!(batch.schema().field(0).data_type(), &dictionary_type);
+        // too many distinct values
+        assert_eq!(batch.schema().field(1).data_type(), &ArrowDataType::Utf8);
+
+        // each distinct value is stored once
+        let statuses = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::DictionaryArray<Int32Type>>()
+            .unwrap();
+        assert_eq!(statuses.values().len(), 2);
+        assert_eq!(statuses.len(), 13);
+
+        let statuses = arrow::compute::cast(batch.column(0), &ArrowDataType::Utf8).unwrap();
+        let statuses = statuses.as_any().downcast_ref::<StringArray>().unwrap();
+        let expected = (0..12)
+            .map(|i| if (4..10).contains(&i) { "error" } else { "ok" })
+            .collect::<Vec<_>>();
+        for (row, expected) in expected.iter().enumerate() {
+            assert_eq!(statuses.value(row), *expected, "row {}", row);
+        }
+        assert!(statuses.is_null(12));
+
+        // without the option the column is dense
+        let batch = table.to_arrow_impl(&partition, &requested).unwrap();
+        assert_eq!(batch.schema().field(0).data_type(), &ArrowDataType::Utf8);
+
+        // the size estimate accounts for the encoding
+        let estimate = |options: &ToArrowOptions| {
+            let schema = table.arrow_schema(&partition, &requested[..1], options);
+            table.estimate_column_arrow_size(
+                &partition,
+                requested[0].1,
+                schema.field(0).data_type(),
+            )
+        };
+        assert!(estimate(&options) < estimate(&ToArrowOptions::default()));
+    }
+
+    #[test]
+    fn test_referenced_value_ids() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA temp=90.0,desc=\"LA\" 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines.clone());
+
+        // a table holding a subset of the rows
+        let mut retained = Table::new(table.id);
+        write_lines_to_table(&mut retained, dictionary, lp_lines[..2].to_vec());
+
+        let value_ids = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| partition.dictionary.lookup_value(value).unwrap())
+                .collect::<BTreeSet<_>>()
+        };
+
+        // string field values are not in the dictionary
+        assert_eq!(
+            table.referenced_value_ids(),
+            value_ids(&["MA", "Boston", "CA", "LA"])
+        );
+
+        // the subset of the rows references fewer values
+        assert_eq!(
+            retained.referenced_value_ids(),
+            value_ids(&["MA", "Boston"])
+        );
+
+        assert!(Table::new(table.id).referenced_value_ids().is_empty());
+    }
+
+    #[test]
+    fn test_dictionary_stats() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+
+        let mut h2o = Table::new(dictionary.lookup_value_or_insert("h2o"));
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+        write_lines_to_table(&mut h2o, dictionary, lp_lines);
+
+        let mut o2 = Table::new(dictionary.lookup_value_or_insert("o2"));
+        write_lines_to_table(&mut o2, dictionary, vec!["o2,city=Boston reading=1.0 100"]);
+
+        // only the other tables of the partition are considered
+        assert_eq!(
+            h2o.dictionary_stats(&partition),
+            DictionaryStats {
+                exclusive_values: 4,
+                exclusive_bytes: 12,
+                shared_values: 0,
+                shared_bytes: 0,
+            }
+        );
+
+        partition.tables.insert(o2.id, o2);
+        assert_eq!(
+            h2o.dictionary_stats(&partition),
+            DictionaryStats {
+                // MA, CA and LA
+                exclusive_values: 3,
+                exclusive_bytes: 6,
+                // Boston
+                shared_values: 1,
+                shared_bytes: 6,
+            }
+        );
+
+        // a table in partition.tables is not compared with itself
+        partition.tables.insert(h2o.id, h2o);
+        let o2_id = partition.dictionary.lookup_value("o2").unwrap();
+        assert_eq!(
+            partition.tables[&o2_id].dictionary_stats(&partition),
+            DictionaryStats {
+                exclusive_values: 0,
+                exclusive_bytes: 0,
+                shared_values: 1,
+                shared_bytes: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_with_schema() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+
+        let schema = [
+            ("city", ColumnType::Tag),
+            ("temp", ColumnType::F64),
+            ("time", ColumnType::I64),
+        ];
+        let mut table = Table::with_schema(
+            dictionary.lookup_value_or_insert("h2o"),
+            dictionary,
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(table.row_count(), 0);
+        assert_eq!(table.num_columns(), 3);
+
+        // re-adding a column with the same type is a no-op, with a
+        // different type it is an error
+        table
+            .add_column(dictionary, "temp", ColumnType::F64)
+            .unwrap();
+        assert_eq!(table.num_columns(), 3);
+        let res = table.add_column(dictionary, "temp", ColumnType::String);
+        assert!(
+            matches!(&res, Err(Error::ColumnError { column, .. }) if column == "temp"),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let lp_lines = vec!["h2o,city=Boston temp=70.4 100", "h2o,city=LA temp=90.0 200"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(table.num_columns(), 3);
+
+        // the statistics only describe the written values
+        let temp_id = dictionary.lookup_value("temp").unwrap();
+        match &table.columns[table.column_id_to_index[&temp_id]] {
+            Column::F64(_, stats) => {
+                assert_eq!((stats.min, stats.max, stats.count), (70.4, 90.0, 2));
+            }
+            c => panic!("Expected F64 column, got {:?}", c),
+        }
+
+        // columns added later are null for the existing rows
+        table
+            .add_column(dictionary, "state", ColumnType::Tag)
+            .unwrap();
+        let state_id = dictionary.lookup_value("state").unwrap();
+        let state = &table.columns[table.column_id_to_index[&state_id]];
+        assert_eq!(state.len(), 2);
+        assert_eq!(state.null_count(), 2);
+    }
+
+    #[test]
+    fn test_release_caches() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4,desc=\"ok\" 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let base_size = table.estimate_size_bytes();
+        assert!(base_size > 0);
+
+        // populate the caches
+        table.all_to_arrow(&partition).unwrap();
+        assert!(table.is_time_sorted().unwrap());
+        assert!(table.estimate_size_bytes() > base_size);
+
+        table.release_caches();
+        assert_eq!(table.estimate_size_bytes(), base_size);
+
+        // the data is unchanged, and the caches are rebuilt on demand
+        assert_eq!(table.row_count(), 2);
+        assert!(table.is_time_sorted().unwrap());
+        let batch = table.all_to_arrow(&partition).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert!(table.estimate_size_bytes() > base_size);
+    }
+
+    #[test]
+    fn test_tag_value_counts() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=CA,city=LA temp=91.0 400",
+            "h2o,state=MA,city=Cambridge temp=71.0 300",
+            "h2o,state=TX temp=95.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let counts = table
+            .tag_value_counts("city", &partition_predicate, &partition)
+            .unwrap();
+        assert_eq!(
+            counts,
+            vec![
+                ("LA".to_string(), 3),
+                ("Boston".to_string(), 2),
+                ("Cambridge".to_string(), 1),
+            ]
+        );
+
+        // only rows within the timestamp range are counted
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(200, 360)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let counts = table
+            .tag_value_counts("city", &partition_predicate, &partition)
+            .unwrap();
+        assert_eq!(
+            counts,
+            vec![
+                ("LA".to_string(), 2),
+                ("Boston".to_string(), 1),
+                ("Cambridge".to_string(), 1),
+            ]
+        );
+
+        // unknown tags have no values, fields are not tags
+        let counts = table
+            .tag_value_counts("county", &partition_predicate, &partition)
+            .unwrap();
+        assert!(counts.is_empty());
+        assert!(table
+            .tag_value_counts("temp", &partition_predicate, &partition)
+            .is_err());
+
+        // a table without a time column has no rows in the range
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let counts = table
+            .tag_value_counts("state", &partition_predicate, &partition)
+            .unwrap();
+        assert!(counts.is_empty());
+    }
+
+    #[test]
+    fn test_distinct_field_values() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4,count=2i,desc=\"ok\",flag=true 100",
+            "h2o,state=MA temp=72.4,count=1i,desc=\"ok\" 200",
+            "h2o,state=CA temp=70.4,count=2i,desc=\"bad\",flag=true 300",
+            "h2o,state=CA temp=90.0,count=3i 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let distinct_values = |column_name: &str, partition_predicate: &PartitionPredicate| {
+            let batch = table
+                .distinct_field_values(column_name, partition_predicate, &partition)
+                .unwrap();
+            assert_eq!(batch.schema().field(0).name(), column_name);
+            pretty_format_batches(&[batch])
+                .unwrap()
+                .trim()
+                .split('\n')
+                .skip(3)
+                .map(|line| line.trim_matches(|c| c == '|' || c == ' ').to_string())
+                .filter(|line| !line.starts_with('+'))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            distinct_values("temp", &partition_predicate),
+            vec!["70.4", "72.4", "90"]
+        );
+        assert_eq!(
+            distinct_values("count", &partition_predicate),
+            vec!["1", "2", "3"]
+        );
+        assert_eq!(
+            distinct_values("desc", &partition_predicate),
+            vec!["bad", "ok"]
+        );
+        assert_eq!(distinct_values("flag", &partition_predicate), vec!["true"]);
+
+        // only rows within the timestamp range are considered
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 350)
+            .build();
+        let range_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert_eq!(
+            distinct_values("temp", &range_predicate),
+            vec!["70.4", "72.4"]
+        );
+
+        let res = table.distinct_field_values("state", &partition_predicate, &partition);
+        assert!(
+            matches!(res, Err(Error::DistinctOnTag { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+        for column_name in &["time", "not_a_column"] {
+            let res = table.distinct_field_values(column_name, &partition_predicate, &partition);
+            assert!(
+                matches!(res, Err(Error::NotAFieldColumn { .. })),
+                "unexpected result: {:?}",
+                res
+            );
+        }
+
+        // a table without a time column has no rows in the range
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let range_predicate = partition.compile_predicate(&predicate).unwrap();
+        let batch = table
+            .distinct_field_values("temp", &range_predicate, &partition)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tag_values_plan() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA temp=90.0 300",
+            "h2o,state=CA,city=SF temp=60.0 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // a range only predicate uses the distinct values, which match
+        // the (deduplicated) output of the general plan
+        let predicate = PredicateBuilder::default().timestamp_range(0, 300).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let plan = table
+            .tag_values_plan("city", &partition_predicate, &partition)
+            .unwrap();
+        assert!(matches!(plan, LogicalPlan::InMemoryScan { .. }));
+        let results = run_plan(plan).await;
+
+        let expected = vec![
+            "+--------+",
+            "| city   |",
+            "+--------+",
+            "| Boston |",
+            "| LA     |",
+            "+--------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let plan = table
+            .projected_tag_values_plan("city", &partition_predicate, &partition)
+            .unwrap();
+        let projected_results = run_plan(plan).await;
+        assert_eq!(projected_results.len(), expected.len() + 1);
+        assert_eq!(
+            results.into_iter().collect::<BTreeSet<_>>(),
+            projected_results.into_iter().collect::<BTreeSet<_>>()
+        );
+
+        // general expressions use the general plan
+        let predicate = parse_simple_predicate("state = 'CA'").unwrap();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = table
+            .tag_values_plan("state", &partition_predicate, &partition)
+            .unwrap();
+        assert!(!matches!(plan, LogicalPlan::InMemoryScan { .. }));
+
+        let err = table
+            .distinct_tag_values(
+                "temp",
+                &partition.compile_predicate(&predicate).unwrap(),
+                &partition,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::UnsupportedDistinctTagValuesPredicate));
+        let partition_predicate = partition
+            .compile_predicate(&PredicateBuilder::default().build())
+            .unwrap();
+        let err = table
+            .distinct_tag_values("temp", &partition_predicate, &partition)
+            .unwrap_err();
+        assert!(matches!(err, Error::NotATagColumn { .. }));
+
+        // a table without a time column has no tag values in a range,
+        // as with the general plan
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let partition_predicate = partition
+            .compile_predicate(&PredicateBuilder::default().build())
+            .unwrap();
+        let batch = table
+            .distinct_tag_values("state", &partition_predicate, &partition)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let predicate = PredicateBuilder::default().timestamp_range(0, 500).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let batch = table
+            .distinct_tag_values("state", &partition_predicate, &partition)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 0);
+        let plan = table
+            .tag_values_plan("state", &partition_predicate, &partition)
+            .unwrap();
+        let batches = Executor::new().run_logical_plan(plan).await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+    }
+
+    #[test]
+    fn test_time_values() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        // a table without rows has no time column
+        let empty_table = Table::new(dictionary.lookup_value_or_insert("empty_table"));
+
+        let lp_lines = vec![
+            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
+            "h2o,state=MA,city=Kingston,zz_tag=B temp=70.2 100",
+            "h2o,state=CA,city=Boston temp=70.3 250",
+            "h2o,state=MA,city=Boston,zz_tag=A temp=70.4 1000",
+            "h2o,state=MA,city=Boston temp=70.5,other=5.0 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        assert_eq!(
+            table.time_values(&partition).unwrap(),
+            &[Some(800), Some(100), Some(250), Some(1000), Some(250)]
+        );
+
+        let res = empty_table.time_values(&partition);
+        assert!(
+            matches!(res, Err(Error::TimeColumnNotFound { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_escaped_column_names() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("m"));
+
+        let lp_lines = vec![r"m,a\ b=1 v=2 1", r"m,a\ b=2,c\\d=3 v=3 2"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // keys are stored unescaped
+        assert!(partition.dictionary.lookup_value("a b").is_ok());
+        assert!(partition.dictionary.lookup_value(r"a\ b").is_err());
+        // an escaped backslash is unescaped exactly once
+        assert!(partition.dictionary.lookup_value(r"c\d").is_ok());
+
+        let rows = table
+            .matching_rows(&[("a b", "1")], None, &partition)
+            .unwrap();
+        assert_eq!(rows, vec![0]);
+
+        let rows = table
+            .matching_rows(&[(r"c\d", "3")], None, &partition)
+            .unwrap();
+        assert_eq!(rows, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_matching_rows() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=91.0 300",
+            "h2o,state=CA,city=LA temp=92.0 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let rows = table
+            .matching_rows(
+                &[("city", "LA")],
+                Some(TimestampRange::new(150, 320)),
+                &partition,
+            )
+            .unwrap();
+        assert_eq!(rows, vec![1, 3]);
+
+        // compare to the same predicate evaluated by DataFusion
+        let predicate = PredicateBuilder::default()
+            .add_expr(Expr::BinaryExpr {
+                left: Box::new(Expr::Column("city".into())),
+                op: Operator::Eq,
+                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("LA".into())))),
+            })
+            .timestamp_range(150, 320)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = table
+            .field_names_plan(&partition_predicate, &FieldSelector::All, &partition)
+            .unwrap();
+        let batches = Executor::new().run_logical_plan(plan).await.unwrap();
+        let mut df_times = Vec::new();
+        for batch in &batches {
+            let times = batch
+                .column(batch.schema().index_of(TIME_COLUMN_NAME).unwrap())
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            df_times.extend((0..times.len()).map(|i| times.value(i)));
+        }
+
+        let time_id = partition.dictionary.lookup_value(TIME_COLUMN_NAME).unwrap();
+        let times = table.column_i64(time_id).unwrap();
+        let matching_times = rows
+            .iter()
+            .map(|&row| times[row].unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(matching_times, df_times);
+
+        // multiple tags without a time range
+        let rows = table
+            .matching_rows(&[("city", "Boston"), ("state", "MA")], None, &partition)
+            .unwrap();
+        assert_eq!(rows, vec![0, 2]);
+
+        // unknown tags and values match nothing
+        let rows = table
+            .matching_rows(&[("county", "Suffolk")], None, &partition)
+            .unwrap();
+        assert!(rows.is_empty());
+        let rows = table
+            .matching_rows(&[("city", "Austin")], None, &partition)
+            .unwrap();
+        assert!(rows.is_empty());
+
+        // fields are not tags
+        assert!(table
+            .matching_rows(&[("temp", "LA")], None, &partition)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_matching_rows_nulls() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        // city is null in some rows
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=91.0 300",
+            "h2o,state=MA temp=72.4 350",
+            "h2o,state=CA,city=LA temp=92.0 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let time_id = partition.dictionary.lookup_value(TIME_COLUMN_NAME).unwrap();
+
+        for &(city, state) in &[("LA", "CA"), ("Boston", "MA")] {
+            let rows = table
+                .matching_rows(&[("city", city), ("state", state)], None, &partition)
+                .unwrap();
+            let times = table.column_i64(time_id).unwrap();
+            let matching_times = rows
+                .iter()
+                .map(|&row| times[row].unwrap())
+                .collect::<Vec<_>>();
+
+            // the same predicate evaluated by DataFusion
+            let tag_eq = |name: &str, value: &str| Expr::BinaryExpr {
+                left: Box::new(Expr::Column(name.into())),
+                op: Operator::Eq,
+                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some(value.into())))),
+            };
+            let predicate = PredicateBuilder::default()
+                .add_expr(tag_eq("city", city))
+                .add_expr(tag_eq("state", state))
+                .build();
+            let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+            let plan = table
+                .field_names_plan(&partition_predicate, &FieldSelector::All, &partition)
+                .unwrap();
+            let batches = Executor::new().run_logical_plan(plan).await.unwrap();
+            let mut df_times = Vec::new();
+            for batch in &batches {
+                let times = batch
+                    .column(batch.schema().index_of(TIME_COLUMN_NAME).unwrap())
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap();
+                df_times.extend((0..times.len()).map(|i| times.value(i)));
+            }
+
+            assert_eq!(matching_times, df_times, "city={}, state={}", city, state);
+        }
+
+        // rows with a null city never match, even though their state does
+        let rows = table
+            .matching_rows(&[("city", "LA"), ("state", "CA")], None, &partition)
+            .unwrap();
+        assert_eq!(rows, vec![2, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_to_arrow_stream() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=MA,city=Boston other_temp=71.0 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let expected = table.all_to_arrow(&partition).unwrap();
+        let mut stream = table.to_arrow_stream(&partition, 2).unwrap();
+        let schema = stream.schema();
+
+        // the stream does not see rows appended after it was created
+        let dictionary = &mut partition.dictionary;
+        write_lines_to_table(&mut table, dictionary, vec!["h2o,state=CA temp=80.0 500"]);
+
+        let mut batches = Vec::new();
+        while let Some(batch) = stream.next().await {
+            batches.push(batch.unwrap());
+        }
+
+        let num_rows = batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>();
+        assert_eq!(num_rows, vec![2, 2, 1]);
+
+        assert_eq!(schema, expected.schema());
+        assert_eq!(
+            pretty_format_batches(&batches).unwrap(),
+            pretty_format_batches(&[expected]).unwrap()
+        );
+
+        assert!(table.to_arrow_stream(&partition, 0).is_err());
+    }
+
+    #[test]
+    fn test_to_arrow_range() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
+            "h2o,state=MA,city=Kingston,zz_tag=B temp=70.2 100",
+            "h2o,state=CA,city=Boston temp=70.3 250",
+            "h2o,state=MA,city=Boston,zz_tag=A temp=70.4 1000",
+            "h2o,state=MA,city=Boston temp=70.5,other=5.0 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let requested_columns = ["city", "state", "zz_tag", "temp", "time"];
+        let batch = table
+            .to_arrow_range(&partition, &requested_columns, 1, 3)
+            .unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        let expected = vec![
+            "+----------+-------+--------+------+------+",
+            "| city     | state | zz_tag | temp | time |",
+            "+----------+-------+--------+------+------+",
+            "| Kingston | MA    | B      | 70.2 | 100  |",
+            "| Boston   | CA    |        | 70.3 | 250  |",
+            "+----------+-------+--------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // all columns
+        let batch = table.to_arrow_range(&partition, &[], 1, 3).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 6);
+
+        // the end is clamped to the table
+        let batch = table
+            .to_arrow_range(&partition, &requested_columns, 3, 100)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let batch = table
+            .to_arrow_range(&partition, &requested_columns, 10, 100)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 0);
+
+        let res = table.to_arrow_range(&partition, &requested_columns, 3, 1);
+        assert!(
+            matches!(res, Err(Error::InvalidRowRange { start: 3, end: 1 })),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_to_arrow_cancellable() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        // more rows than are converted between checks of the flag
+        let num_rows = 2 * CANCEL_CHECK_ROWS + 1;
+        let lp = (0..num_rows)
+            .map(|i| format!("h2o,state=MA temp={}.5 {}", i, i))
+            .collect::<Vec<_>>();
+        write_lines_to_table(
+            &mut table,
+            dictionary,
+            lp.iter().map(|line| line.as_str()).collect(),
+        );
+
+        let requested_columns = ["state", "temp", "time"];
+        let cancel = AtomicBool::new(false);
+        let batch = table
+            .to_arrow_cancellable(&partition, &requested_columns, &cancel)
+            .unwrap();
+        assert_eq!(batch.num_rows(), num_rows);
+        let expected = table.to_arrow(&partition, &requested_columns).unwrap();
+        assert_eq!(
+            pretty_format_batches(&[batch]).unwrap(),
+            pretty_format_batches(&[expected]).unwrap()
+        );
+
+        // as by a query being cancelled while the batch is built
+        cancel.store(true, Ordering::Relaxed);
+        let res = table.to_arrow_cancellable(&partition, &requested_columns, &cancel);
+        assert!(
+            matches!(res, Err(Error::Cancelled)),
+            "unexpected result: {:?}",
+            res
+        );
+
+        // setting the flag part way through a column stops its
+        // conversion at the next check
+        let cancel = AtomicBool::new(false);
+        let rows = RowSelection::Cancellable(0..num_rows, &cancel);
+        let values = vec![Some(1); num_rows];
+        let mut selected = rows.select(&values);
+        assert_eq!(selected.by_ref().take(10).count(), 10);
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(selected.count(), CANCEL_CHECK_ROWS - 10);
+    }
+
+    #[test]
+    fn test_to_struct_array() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4,reading=\"ok\" 250",
+            "h2o,state=CA temp=90.0,count=4i 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let schema = table.arrow_schema_ref(&partition).unwrap();
+        let array = table.to_struct_array(&partition).unwrap();
+
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.num_columns(), schema.fields().len());
+        assert_eq!(
+            array.data_type(),
+            &ArrowDataType::Struct(schema.fields().clone())
+        );
+
+        let expected_names = schema
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(array.column_names(), expected_names);
+
+        // each child holds the values of the corresponding column
+        let batch = table.all_to_arrow(&partition).unwrap();
+        for (i, column) in batch.columns().iter().enumerate() {
+            assert_eq!(array.column(i).data(), column.data(), "column {}", i);
+        }
+    }
+
+    #[test]
+    fn test_null_mask() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 100",
+            "h2o,state=MA other=1.0 200",
+            "h2o,state=MA temp=72.4 300",
+            "h2o,city=Boston other=2.0 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let mask = |column_name: &str| {
+            let column_id = partition.dictionary.lookup_value(column_name).unwrap();
+            let mask = table.null_mask(column_id).unwrap();
+            assert_eq!(mask.null_count(), 0);
+            (0..mask.len()).map(|i| mask.value(i)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(mask("temp"), vec![true, false, true, false]);
+        assert_eq!(mask("other"), vec![false, true, false, true]);
+        assert_eq!(mask("state"), vec![true, true, true, false]);
+        assert_eq!(mask("time"), vec![true, true, true, true]);
+    }
+
+    #[test]
+    fn test_to_arrow_large_utf8() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA desc=\"a long description\" 100",
+            "h2o,state=MA desc=\"another long description\" 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let requested = table
+            .column_names_with_index(&partition, &["desc", "state"])
+            .unwrap();
+
+        let data_types = |options: ToArrowOptions| {
+            let batch = table
+                .to_arrow_impl_with_options(&partition, &requested, &options)
+                .unwrap();
+            assert_eq!(batch.num_rows(), 2);
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.data_type().clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Utf8 is the default
+        assert_eq!(
+            data_types(ToArrowOptions::default()),
+            vec![ArrowDataType::Utf8, ArrowDataType::Utf8]
+        );
+
+        // Tags are always Utf8
+        let options = ToArrowOptions {
+            string_type: StringArrowType::LargeUtf8,
+            ..Default::default()
+        };
+        assert_eq!(
+            data_types(options),
+            vec![ArrowDataType::LargeUtf8, ArrowDataType::Utf8]
+        );
+
+        let options = ToArrowOptions {
+            string_type: StringArrowType::LargeUtf8Above(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            data_types(options),
+            vec![ArrowDataType::LargeUtf8, ArrowDataType::Utf8]
+        );
+
+        let options = ToArrowOptions {
+            string_type: StringArrowType::LargeUtf8Above(1000),
+            ..Default::default()
+        };
+        assert_eq!(
+            data_types(options),
+            vec![ArrowDataType::Utf8, ArrowDataType::Utf8]
+        );
+    }
+
+    #[test]
+    fn test_to_arrow_memory_limit() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4,desc=\"ok\",flag=true,count=1i 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let requested = table.all_columns_with_index(&partition).unwrap();
+
+        let options = ToArrowOptions {
+            memory_limit: Some(16),
+            ..Default::default()
+        };
+        let res = table.to_arrow_impl_with_options(&partition, &requested, &options);
+        assert!(
+            matches!(res, Err(Error::ArrowMemoryLimitExceeded { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let options = ToArrowOptions {
+            memory_limit: Some(1024 * 1024),
+            ..Default::default()
+        };
+        let batch = table
+            .to_arrow_impl_with_options(&partition, &requested, &options)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 7);
+    }
+
+    #[test]
+    fn test_estimate_arrow_size() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=Massachusetts,city=Boston temp=70.4,desc=\"ok\",flag=true,count=1i 100",
+            "h2o,state=California,city=Los\\ Angeles temp=90.0 200",
+            "h2o,state=California temp=85.0,desc=\"a longer description\" 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // sum of the lengths of the buffers actually allocated
+        let batch_size = |batch: &RecordBatch| {
+            batch
+                .columns()
+                .iter()
+                .map(|array| {
+                    let data = array.data();
+                    data.buffers().iter().map(|b| b.len()).sum::<usize>()
+                        + data.null_buffer().map_or(0, |b| b.len())
+                })
+                .sum::<usize>()
+        };
+
+        for requested in &[vec![], vec!["state", "city"], vec!["temp", "desc"]] {
+            let estimate = table.estimate_arrow_size(&partition, requested).unwrap();
+            let actual = batch_size(&table.to_arrow(&partition, requested).unwrap());
+
+            // the estimate always includes a validity bitmap, which
+            // arrow omits for arrays without nulls
+            let tolerance = requested.len().max(table.columns.len());
+            assert!(
+                estimate >= actual && estimate - actual <= tolerance,
+                "estimate {} vs actual {} for {:?}",
+                estimate,
+                actual,
+                requested
+            );
+        }
+
+        // the estimate accounts for the decoded tag values
+        let tags = table.estimate_arrow_size(&partition, &["state"]).unwrap();
+        assert!(tags > "MassachusettsCaliforniaCalifornia".len());
+
+        let err = table
+            .estimate_arrow_size(&partition, &["not_a_column"])
+            .unwrap_err();
+        assert!(matches!(err, Error::ColumnNameNotFoundInDictionary { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+
+        assert_eq!(series_set_plan.table_name.as_ref(), "table_name");
+        assert_eq!(
+            series_set_plan.tag_columns,
+            *str_vec_to_arc_vec(&["city", "state"])
+        );
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["temp"])
+        );
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(series_set_plan.plan).await;
+
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 70.4 | 100  |",
+            "| Boston | MA    | 72.4 | 250  |",
+            "| LA     | CA    | 90   | 200  |",
+            "| LA     | CA    | 90   | 350  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_time_column_name() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table =
+            Table::with_time_column_name(dictionary.lookup_value_or_insert("h2o"), "ts");
+        assert_eq!(table.time_column_name(), "ts");
+
+        // the line protocol timestamps are not the times of the rows
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4,ts=100i 1",
+            "h2o,state=MA,city=Boston temp=72.4,ts=250i 2",
+            "h2o,state=CA,city=LA temp=90.0,ts=200i 3",
+            "h2o,state=CA,city=LA temp=91.0,ts=350i 4",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        assert_eq!(
+            table.time_values(&partition).unwrap(),
+            &[Some(100), Some(250), Some(200), Some(350)]
+        );
+        assert_eq!(table.num_field_columns(&partition), 1);
+
+        // timestamp predicates apply to ts
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 300)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(table
+            .could_match_predicate(&partition_predicate, &partition, &mut ScanStats::default())
+            .unwrap());
+
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["temp"])
+        );
+
+        // and ts is output as the time column
+        let results = run_plan(series_set_plan.plan).await;
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 72.4 | 250  |",
+            "| LA     | CA    | 90   | 200  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(1000, 2000)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        assert!(!table
+            .could_match_predicate(&partition_predicate, &partition, &mut ScanStats::default())
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_simple_predicate() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let cases = vec![
+            (
+                "city = 'LA' and time > 200",
+                vec![
+                    "+------+-------+------+------+",
+                    "| city | state | temp | time |",
+                    "+------+-------+------+------+",
+                    "| LA   | CA    | 90   | 350  |",
+                    "+------+-------+------+------+",
+                ],
+            ),
+            (
+                "state = 'MA' AND temp > 71.0 and time <= 250",
+                vec![
+                    "+--------+-------+------+------+",
+                    "| city   | state | temp | time |",
+                    "+--------+-------+------+------+",
+                    "| Boston | MA    | 72.4 | 250  |",
+                    "+--------+-------+------+------+",
+                ],
+            ),
+        ];
+
+        for (predicate, expected) in cases {
+            let parsed = parse_simple_predicate(predicate).unwrap();
+            let partition_predicate = partition.compile_predicate(&parsed).unwrap();
+            let series_set_plan = table
+                .series_set_plan(&partition_predicate, &partition)
+                .expect("creating the series set plan");
+
+            let results = run_plan(series_set_plan.plan).await;
+            assert_eq!(expected, results, "predicate: {}", predicate);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_order() {
+        // test that the columns and rows come out in the right order (tags then timestamp)
+
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
+            "h2o,state=MA,city=Kingston,zz_tag=B temp=70.2 100",
+            "h2o,state=CA,city=Boston temp=70.3 250",
+            "h2o,state=MA,city=Boston,zz_tag=A temp=70.4 1000",
+            "h2o,state=MA,city=Boston temp=70.5,other=5.0 250",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+
+        assert_eq!(series_set_plan.table_name.as_ref(), "table_name");
+        assert_eq!(
+            series_set_plan.tag_columns,
+            *str_vec_to_arc_vec(&["city", "state", "zz_tag"])
+        );
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["other", "temp"])
+        );
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(series_set_plan.plan).await;
+
+        let expected = vec![
+            "+----------+-------+--------+-------+------+------+",
+            "| city     | state | zz_tag | other | temp | time |",
+            "+----------+-------+--------+-------+------+------+",
+            "| Boston   | CA    |        |       | 70.3 | 250  |",
+            "| Boston   | MA    |        | 5     | 70.5 | 250  |",
+            "| Boston   | MA    | A      |       | 70.4 | 1000 |",
+            "| Kingston | MA    | A      |       | 70.1 | 800  |",
+            "| Kingston | MA    | B      |       | 70.2 | 100  |",
+            "+----------+-------+--------+-------+------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_null_literal() {
+        // test that comparisons against typed null literals never match
+
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let temp_gt_null = Expr::BinaryExpr {
+            left: Box::new(Expr::Column("temp".into())),
+            op: Operator::Gt,
+            right: Box::new(Expr::Literal(ScalarValue::Float64(None))),
+        };
+        let state_eq_ma = Expr::BinaryExpr {
+            left: Box::new(Expr::Column("state".into())),
+            op: Operator::Eq,
+            right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("MA".into())))),
+        };
+
+        // temp > NULL
+        let predicate = PredicateBuilder::default()
+            .add_expr(temp_gt_null.clone())
+            .build();
+        assert_eq!(
+            count_series_set_rows(&table, &partition, &predicate).await,
+            0
+        );
+
+        // NOT (temp > NULL) is also unknown, so matches nothing
+        let predicate = PredicateBuilder::default()
+            .add_expr(Expr::Not(Box::new(temp_gt_null.clone())))
+            .build();
+        assert_eq!(
+            count_series_set_rows(&table, &partition, &predicate).await,
+            0
+        );
+
+        // state = 'MA' OR temp > NULL matches only the MA row
+        let predicate = PredicateBuilder::default()
+            .add_expr(Expr::BinaryExpr {
+                left: Box::new(state_eq_ma),
+                op: Operator::Or,
+                right: Box::new(temp_gt_null),
+            })
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+
+        let results = run_plan(series_set_plan.plan).await;
+
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 70.4 | 100  |",
+            "+--------+-------+------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+    }
+
+    /// Returns the number of rows produced by the series set plan
+    /// for `predicate`
+    async fn count_series_set_rows(
+        table: &Table,
+        partition: &Partition,
+        predicate: &query::predicate::Predicate,
+    ) -> usize {
+        let partition_predicate = partition.compile_predicate(predicate).unwrap();
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, partition)
+            .expect("creating the series set plan");
+
+        Executor::new()
+            .run_logical_plan(series_set_plan.plan)
+            .await
+            .expect("ok running plan")
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_paged_series_set_plan() {
+        // test that offset / limit select a page of the sorted output
+
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
+            "h2o,state=MA,city=Kingston,zz_tag=B temp=70.2 100",
+            "h2o,state=CA,city=Boston temp=70.3 250",
+            "h2o,state=MA,city=Boston,zz_tag=A temp=70.4 1000",
+            "h2o,state=MA,city=Boston temp=70.5,other=5.0 250",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let pagination = Pagination {
+            offset: Some(2),
+            limit: Some(2),
+        };
+        let series_set_plan = table
+            .paged_series_set_plan(&partition_predicate, pagination, &partition)
+            .expect("creating the series set plan");
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(series_set_plan.plan).await;
+
+        let expected = vec![
+            "+----------+-------+--------+-------+------+------+",
+            "| city     | state | zz_tag | other | temp | time |",
+            "+----------+-------+--------+-------+------+------+",
+            "| Boston   | MA    | A      |       | 70.4 | 1000 |",
+            "| Kingston | MA    | A      |       | 70.1 | 800  |",
+            "+----------+-------+--------+-------+------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_duplicate_timestamps() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 100",
+            "h2o,state=MA,city=Boston temp=73.4 200",
+            "h2o,state=CA,city=LA temp=90.0 100",
+            "h2o,state=MA,city=Boston temp=74.4 100",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let series_set_plan = |duplicates| {
+            table.series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    duplicates,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+        };
+
+        // all five rows, plus the header and borders
+        let plan = series_set_plan(DuplicateTimestamps::KeepAll).unwrap();
+        let results = run_plan(plan.plan).await;
+        assert_eq!(results.len(), 5 + 4, "unexpected output: {:#?}", results);
+
+        let plan = series_set_plan(DuplicateTimestamps::KeepFirst).unwrap();
+        let results = run_plan(plan.plan).await;
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 70.4 | 100  |",
+            "| Boston | MA    | 73.4 | 200  |",
+            "| LA     | CA    | 90   | 100  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let plan = series_set_plan(DuplicateTimestamps::KeepLast).unwrap();
+        let results = run_plan(plan.plan).await;
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 74.4 | 100  |",
+            "| Boston | MA    | 73.4 | 200  |",
+            "| LA     | CA    | 90   | 100  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let res = series_set_plan(DuplicateTimestamps::Error);
+        assert!(
+            matches!(res, Err(Error::DuplicateSeriesTimestamp { time: 100 })),
+            "unexpected result: {:?}",
+            res.map(|_| ())
+        );
+
+        // there are no duplicates outside of the timestamp range
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 250)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    duplicates: DuplicateTimestamps::Error,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .unwrap();
+        let results = run_plan(plan.plan).await;
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| Boston | MA    | 73.4 | 200  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_filter() {
+        // test that filters are applied reasonably
+
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(Expr::BinaryExpr {
+                left: Box::new(Expr::Column("city".into())),
+                op: Operator::Eq,
+                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("LA".into())))),
+            })
+            .timestamp_range(190, 210)
+            .build();
+
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let series_set_plan = table
+            .series_set_plan(&partition_predicate, &partition)
+            .expect("creating the series set plan");
+
+        assert_eq!(series_set_plan.table_name.as_ref(), "table_name");
+        assert_eq!(
+            series_set_plan.tag_columns,
+            *str_vec_to_arc_vec(&["city", "state"])
+        );
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["temp"])
+        );
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(series_set_plan.plan).await;
+
+        let expected = vec![
+            "+------+-------+------+------+",
+            "| city | state | temp | time |",
+            "+------+-------+------+------+",
+            "| LA   | CA    | 90   | 200  |",
+            "+------+-------+------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_aliases() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let mut aliases = HashMap::new();
+        aliases.insert("temp".to_string(), "temperature_c".to_string());
+
+        let series_set_plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    aliases,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the series set plan");
+
+        let schema = series_set_plan.plan.schema();
+        let names = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["city", "state", "temperature_c", "time"]);
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["temperature_c"])
+        );
+
+        let results = run_plan(series_set_plan.plan).await;
+        let expected = vec![
+            "+--------+-------+---------------+------+",
+            "| city   | state | temperature_c | time |",
+            "+--------+-------+---------------+------+",
+            "| Boston | MA    | 70.4          | 100  |",
+            "| LA     | CA    | 90            | 200  |",
+            "+--------+-------+---------------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // only output tags and fields can be aliased
+        for column_name in &["not_a_column", TIME_COLUMN_NAME] {
+            let mut aliases = HashMap::new();
+            aliases.insert(column_name.to_string(), "renamed".to_string());
+            let res = table.series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    aliases,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            );
+            assert!(
+                matches!(res, Err(Error::AliasColumnNotFound { .. })),
+                "unexpected result: {:?}",
+                res
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_series_set_plan_pinned_tags() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
+            "h2o,zz_tag=B,state=MA,city=Boston temp=70.4 100",
+            "h2o,zz_tag=A,state=CA,city=LA temp=90.0 200",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        // pins that are not tags of the table are ignored
+        let pinned_tags = vec!["zz_tag".to_string(), "absent".to_string()];
+        let series_set_plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    pinned_tags,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the series set plan");
+
+        assert_eq!(
+            series_set_plan.tag_columns,
+            *str_vec_to_arc_vec(&["zz_tag", "city", "state"])
+        );
+
+        let results = run_plan(series_set_plan.plan).await;
+        let expected = vec![
+            "+--------+----------+-------+------+------+",
+            "| zz_tag | city     | state | temp | time |",
+            "+--------+----------+-------+------+------+",
+            "| A      | Kingston | MA    | 70.1 | 800  |",
+            "| A      | LA       | CA    | 90   | 200  |",
+            "| B      | Boston   | MA    | 70.4 | 100  |",
+            "+--------+----------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_grouped_frames() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 400)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let group_columns = vec![String::from("state")];
+        let frames = table
+            .grouped_frames(&partition_predicate, &group_columns, &partition)
+            .expect("creating the grouped frames");
+
+        let keys = frames
+            .groups
+            .iter()
+            .map(|group| {
+                group
+                    .key
+                    .iter()
+                    .map(|(tag, value)| format!("{}={}", tag, value.as_ref().unwrap()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![vec!["state=CA"], vec!["state=MA"]]);
+
+        let mut groups = frames.groups.into_iter();
+
+        let ca = groups.next().unwrap();
+        let results = run_plan(ca.series_set_plan.plan).await;
+        let expected = vec![
+            "+-------+------+------+------+",
+            "| state | city | temp | time |",
+            "+-------+------+------+------+",
+            "| CA    | LA   | 90   | 200  |",
+            "| CA    | LA   | 90   | 350  |",
+            "+-------+------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let ma = groups.next().unwrap();
+        let results = run_plan(ma.series_set_plan.plan).await;
+        let expected = vec![
+            "+-------+--------+------+------+",
+            "| state | city   | temp | time |",
+            "+-------+--------+------+------+",
+            "| MA    | Boston | 72.4 | 250  |",
+            "+-------+--------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_grouped_series_set_plan() {
+        // test that filters are applied reasonably
+
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default()
+            .add_expr(Expr::BinaryExpr {
+                left: Box::new(Expr::Column("city".into())),
+                op: Operator::Eq,
+                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("LA".into())))),
+            })
+            .timestamp_range(190, 210)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let group_columns = vec![String::from("state")];
+
+        let grouped_series_set_plan = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions::default(),
+                &partition,
+            )
+            .expect("creating the grouped_series set plan");
+
+        assert_eq!(grouped_series_set_plan.num_prefix_tag_group_columns, 1);
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(grouped_series_set_plan.series_set_plan.plan).await;
+
+        let expected = vec![
+            "+-------+------+------+------+",
+            "| state | city | temp | time |",
+            "+-------+------+------+------+",
+            "| CA    | LA   | 90   | 200  |",
+            "+-------+------+------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[test]
+    fn test_grouped_series_set_plan_group_by_time() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec!["h2o,state=MA,city=Boston temp=70.4 100"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let group_columns = vec![String::from("state"), String::from("time")];
+        let err = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions::default(),
+                &partition,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Can not group by the time column 'time': use a window aggregate to group rows by time"
+        );
+
+        // other missing columns are still reported as such
+        let group_columns = vec![String::from("county")];
+        let err = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions::default(),
+                &partition,
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::GroupColumnNotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_grouped_series_set_plan_nulls_last() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,city=Kingston temp=71.4 150",
+            "h2o,state=CA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=91.0 250",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let group_columns = vec![String::from("state")];
+
+        let grouped_series_set_plan = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions::default(),
+                &partition,
+            )
+            .expect("creating the grouped_series set plan");
+        let results = run_plan(grouped_series_set_plan.series_set_plan.plan).await;
+
+        let expected = vec![
+            "+-------+----------+------+------+",
+            "| state | city     | temp | time |",
+            "+-------+----------+------+------+",
+            "|       | Kingston | 71.4 | 150  |",
+            "| CA    |          | 90   | 200  |",
+            "| CA    | LA       | 91   | 250  |",
+            "| MA    | Boston   | 70.4 | 100  |",
+            "+-------+----------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // rows missing a group or tag value are ordered last
+        let grouped_series_set_plan = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions {
+                    nulls_first: false,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the grouped_series set plan");
+        let results = run_plan(grouped_series_set_plan.series_set_plan.plan).await;
+
+        let expected = vec![
+            "+-------+----------+------+------+",
+            "| state | city     | temp | time |",
+            "+-------+----------+------+------+",
+            "| CA    | LA       | 91   | 250  |",
+            "| CA    |          | 90   | 200  |",
+            "| MA    | Boston   | 70.4 | 100  |",
+            "|       | Kingston | 71.4 | 150  |",
+            "+-------+----------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_grouped_series_set_plan_group_order() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=CA,city=SF temp=60.0 300",
+            "h2o,state=NY,city=NYC temp=50.0 300",
+            "h2o,state=TX,city=Austin temp=95.0 100",
+            "h2o,state=TX,city=Austin temp=96.0 200",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let group_columns = vec![String::from("state")];
+
+        // by descending row count, with MA and TX (tied) ordered by key
+        let group_order = GroupOrder::ByAggregate(AggregateKind::Count, "time".into(), false);
+        let grouped_series_set_plan = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions {
+                    group_order,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the grouped_series set plan");
+
+        let results = run_plan(grouped_series_set_plan.series_set_plan.plan).await;
+
+        let expected = vec![
+            "+-------+--------+------+------+",
+            "| state | city   | temp | time |",
+            "+-------+--------+------+------+",
+            "| CA    | LA     | 90   | 200  |",
+            "| CA    | LA     | 90   | 350  |",
+            "| CA    | SF     | 60   | 300  |",
+            "| MA    | Boston | 70.4 | 100  |",
+            "| MA    | Boston | 72.4 | 250  |",
+            "| TX    | Austin | 95   | 100  |",
+            "| TX    | Austin | 96   | 200  |",
+            "| NY    | NYC    | 50   | 300  |",
+            "+-------+--------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // the aggregate only considers rows in the timestamp range
+        let predicate = PredicateBuilder::default().timestamp_range(0, 210).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let group_order = GroupOrder::ByAggregate(AggregateKind::Max, "temp".into(), true);
+        let grouped_series_set_plan = table
+            .grouped_series_set_plan(
+                &partition_predicate,
+                &group_columns,
+                &SeriesSetPlanOptions {
+                    group_order,
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the grouped_series set plan");
+
+        let results = run_plan(grouped_series_set_plan.series_set_plan.plan).await;
+
+        let expected = vec![
+            "+-------+--------+------+------+",
+            "| state | city   | temp | time |",
+            "+-------+--------+------+------+",
+            "| MA    | Boston | 70.4 | 100  |",
+            "| CA    | LA     | 90   | 200  |",
+            "| TX    | Austin | 95   | 100  |",
+            "| TX    | Austin | 96   | 200  |",
+            "+-------+--------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // only numeric columns can be summed
+        let group_order = GroupOrder::ByAggregate(AggregateKind::Sum, "city".into(), true);
+        let res = table.grouped_series_set_plan(
+            &partition_predicate,
+            &group_columns,
+            &SeriesSetPlanOptions {
+                group_order,
+                ..SeriesSetPlanOptions::default()
+            },
+            &partition,
+        );
+        assert!(
+            matches!(
+                &res,
+                Err(Error::InvalidGroupOrderField { column_name, .. }) if column_name == "city"
+            ),
+            "unexpected result: {:?}",
+            res.map(|_| ())
+        );
+
+        // a table without a time column has no aggregates in a range,
+        // so every group ranks last
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let predicate = PredicateBuilder::default().timestamp_range(0, 500).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let ranks = table
+            .group_ranks(
+                &partition_predicate,
+                &["state".to_string()],
+                AggregateKind::Sum,
+                "temp",
+                true,
+                &partition,
+            )
+            .unwrap();
+        assert_eq!(ranks, vec![0, 0]);
+    }
+
+    #[test]
+    fn test_field_type_histogram() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        // the data of test_field_name_plan
+        let lp_lines = vec![
+            "h2o,tag1=foo,tag2=bar field1=70.6,field3=2 100",
+            "h2o,tag1=foo,tag2=bar field1=70.4,field2=\"ss\" 100",
+            "h2o,tag1=foo,tag2=bar field1=70.5,field2=\"ss\" 100",
+            "h2o,tag1=foo,tag2=bar field1=70.6,field4=true 1000",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let mut expected = HashMap::new();
+        expected.insert(ColumnType::Tag, 2);
+        expected.insert(ColumnType::F64, 2);
+        expected.insert(ColumnType::String, 1);
+        expected.insert(ColumnType::Bool, 1);
+        assert_eq!(table.field_type_histogram(&partition), expected);
+
+        let empty = Table::new(partition.dictionary.lookup_value_or_insert("empty"));
+        assert!(empty.field_type_histogram(&partition).is_empty());
+    }
+
+    #[test]
+    fn test_schema_json() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4,desc=\"ok\",flag=true,count=1i 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let expected = concat!(
+            r#"{"measurement":"h2o","tags":["city","state"],"fields":["#,
+            r#"{"name":"count","type":"integer"},{"name":"desc","type":"string"},"#,
+            r#"{"name":"flag","type":"boolean"},{"name":"temp","type":"float"}]}"#
+        );
+        assert_eq!(table.schema_json(&partition).unwrap(), expected);
+
+        let empty = Table::new(partition.dictionary.lookup_value_or_insert("empty"));
+        assert_eq!(
+            empty.schema_json(&partition).unwrap(),
+            r#"{"measurement":"empty","tags":[],"fields":[]}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_field_name_plan() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            // Order this so field3 comes before field2
+            // (and thus the columns need to get reordered)
+            "h2o,tag1=foo,tag2=bar field1=70.6,field3=2 100",
+            "h2o,tag1=foo,tag2=bar field1=70.4,field2=\"ss\" 100",
+            "h2o,tag1=foo,tag2=bar field1=70.5,field2=\"ss\" 100",
+            "h2o,tag1=foo,tag2=bar field1=70.6,field4=true 1000",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().timestamp_range(0, 200).build();
+
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let field_names_set_plan = table
+            .field_names_plan(&partition_predicate, &FieldSelector::All, &partition)
+            .expect("creating the field_name plan");
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(field_names_set_plan).await;
+
+        let expected = vec![
+            "+--------+--------+--------+--------+------+",
+            "| field1 | field2 | field3 | field4 | time |",
+            "+--------+--------+--------+--------+------+",
+            "| 70.6   |        | 2      |        | 100  |",
+            "| 70.4   | ss     |        |        | 100  |",
+            "| 70.5   | ss     |        |        | 100  |",
+            "+--------+--------+--------+--------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_field_selector() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4,humidity=0.5,count=2i,desc=\"ok\" 100",
+            "h2o,state=CA temp=90.0,flag=true 200",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        // only the float fields
+        let field_selector = FieldSelector::ByType(ColumnType::F64);
+        let plan = table
+            .field_names_plan(&partition_predicate, &field_selector, &partition)
+            .expect("creating the field_name plan");
+        let results = run_plan(plan).await;
+
+        let expected = vec![
+            "+----------+------+------+",
+            "| humidity | temp | time |",
+            "+----------+------+------+",
+            "| 0.5      | 70.4 | 100  |",
+            "|          | 90   | 200  |",
+            "+----------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let series_set_plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    field_selector: field_selector.clone(),
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the series set plan");
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["humidity", "temp"])
+        );
+
+        // fields by name
+        let field_selector = FieldSelector::ByName(vec!["count".into(), "flag".into()]);
+        let series_set_plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    field_selector: field_selector.clone(),
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the series set plan");
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["count", "flag"])
+        );
+
+        // tags are not fields
+        let field_selector = FieldSelector::ByType(ColumnType::Tag);
+        let res = table.field_names_plan(&partition_predicate, &field_selector, &partition);
+        assert!(
+            matches!(res, Err(Error::InvalidFieldSelectorType { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[tokio::test]
+    async fn test_field_selector_glob() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,tag1=foo,tag2=bar field1=70.6,field3=2 100",
+            "h2o,tag1=foo,tag2=bar field1=70.4,field2=\"ss\" 100",
+            "h2o,tag1=foo,tag2=bar field1=70.5,field2=\"ss\" 100",
+            "h2o,tag1=foo,tag2=bar field1=70.6,field4=true 1000",
+            "h2o,tag1=foo,tag2=bar other_field=1.0 1000",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let field_selector = FieldSelector::Glob("field*".into());
+        let series_set_plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    field_selector: field_selector.clone(),
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the series set plan");
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["field1", "field2", "field3", "field4"])
+        );
+
+        let field_selector = FieldSelector::Glob("*field?".into());
+        let series_set_plan = table
+            .series_set_plan_impl(
+                &partition_predicate,
+                None,
+                &SeriesSetPlanOptions {
+                    field_selector: field_selector.clone(),
+                    ..SeriesSetPlanOptions::default()
+                },
+                &partition,
+            )
+            .expect("creating the series set plan");
+        assert_eq!(
+            series_set_plan.field_columns,
+            *str_vec_to_arc_vec(&["field1", "field2", "field3", "field4"])
+        );
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("cpu.*", "cpu.0"));
+        assert!(glob_matches("cpu.*", "cpu."));
+        assert!(!glob_matches("cpu.*", "cpu"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("c?u", "cpu"));
+        assert!(!glob_matches("c?u", "cu"));
+        assert!(glob_matches("*_field", "other_field"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("a*b*c", "aXbYbZ"));
+        assert!(!glob_matches("field", "field1"));
+    }
+
+    #[tokio::test]
+    async fn test_sorted_plan() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=CA,city=LA temp=90.0 200",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let sort_columns = vec![("temp".to_string(), false), ("time".to_string(), true)];
+        let plan = table
+            .sorted_plan(&partition_predicate, &sort_columns, &partition)
+            .expect("creating the sorted plan");
+
+        // run the created plan, ensuring the output is as expected
+        let results = run_plan(plan).await;
+
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| LA     | CA    | 90   | 200  |",
+            "| LA     | CA    | 90   | 350  |",
+            "| Boston | MA    | 72.4 | 250  |",
+            "| Boston | MA    | 70.4 | 100  |",
+            "+--------+-------+------+------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+
+        // sorting on a column that doesn't exist is an error
+        let sort_columns = vec![("not_a_column".to_string(), true)];
+        let res = table.sorted_plan(&partition_predicate, &sort_columns, &partition);
+        assert!(
+            matches!(res, Err(Error::SortColumnNotFound { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_column_min_max() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4,count=5i 100",
+            "h2o,state=MA,city=Boston temp=72.4,desc=\"warm\" 250",
+            "h2o,state=CA,city=LA temp=-90.0,count=-2i,desc=\"cold\" 200",
+            "h2o,state=CA,city=LA temp=91.0,count=12i,desc=\"Sunny\",other=1.0 350",
+            "h2o,city=Worcester temp=71.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let min_max = |column_name| table.column_min_max(&partition, column_name).unwrap();
+
+        assert_eq!(
+            min_max("temp"),
+            Some((
+                ScalarValue::Float64(Some(-90.0)),
+                ScalarValue::Float64(Some(91.0))
+            ))
+        );
+        assert_eq!(
+            min_max("count"),
+            Some((ScalarValue::Int64(Some(-2)), ScalarValue::Int64(Some(12))))
+        );
+        // lexicographic, so upper case sorts first
+        assert_eq!(
+            min_max("desc"),
+            Some((
+                ScalarValue::Utf8(Some("Sunny".into())),
+                ScalarValue::Utf8(Some("warm".into()))
+            ))
+        );
+        assert_eq!(
+            min_max("city"),
+            Some((
+                ScalarValue::Utf8(Some("Boston".into())),
+                ScalarValue::Utf8(Some("Worcester".into()))
+            ))
+        );
+        assert_eq!(
+            min_max("time"),
+            Some((ScalarValue::Int64(Some(100)), ScalarValue::Int64(Some(350))))
+        );
+
+        assert!(table.column_min_max(&partition, "not_a_column").is_err());
+    }
+
+    #[test]
+    fn test_delete_where() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=91.0,reading=\"hot\" 300",
+            "h2o,state=CA temp=80.0 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        dictionary.lookup_value_or_insert("o2");
+
+        let delete_predicate = |table: &mut Table, predicate: &Predicate| {
+            let partition_predicate = partition.compile_predicate(predicate).unwrap();
+            table.delete_where(&partition_predicate, &partition)
+        };
+        let delete = |table: &mut Table, predicate: &str| {
+            delete_predicate(table, &parse_simple_predicate(predicate).unwrap())
+        };
+
+        // general expressions are not supported
+        let res = delete(&mut table, "temp > 71.0");
+        assert!(
+            matches!(res, Err(Error::UnsupportedDeletePredicate)),
+            "unexpected result: {:?}",
+            res
+        );
+        assert_eq!(table.row_count(), 5);
+
+        // a delete scoped to another table leaves this one untouched
+        let mut predicate = parse_simple_predicate("city = 'LA'").unwrap();
+        predicate.table_names = Some(vec!["o2".to_string()].into_iter().collect());
+        assert_eq!(delete_predicate(&mut table, &predicate).unwrap(), 0);
+        assert_eq!(table.row_count(), 5);
+
+        assert_eq!(delete(&mut table, "city = 'LA'").unwrap(), 2);
+        assert_eq!(delete(&mut table, "city = 'LA'").unwrap(), 0);
+        assert_eq!(table.row_count(), 3);
+
+        let batch = table.all_to_arrow(&partition).unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        // the reading column only had values in deleted rows
+        let expected = vec![
+            "+--------+---------+-------+------+------+",
+            "| city   | reading | state | temp | time |",
+            "+--------+---------+-------+------+------+",
+            "| Boston |         | MA    | 70.4 | 100  |",
+            "| Boston |         | MA    | 72.4 | 250  |",
+            "|        |         | CA    | 80   | 350  |",
+            "+--------+---------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // the statistics only reflect the remaining rows
+        let temp_id = partition.dictionary.lookup_value("temp").unwrap();
+        match table.column(temp_id).unwrap() {
+            Column::F64(_, stats) => {
+                assert_eq!((stats.min, stats.max, stats.count), (70.4, 80.0, 3));
+            }
+            column => panic!("Expected F64 column, got {:?}", column),
+        }
+    }
+
+    #[test]
+    fn test_predicate_bitmap() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,city=LA temp=91.0 300",
+            "h2o,state=MA,city=Worcester temp=71.0 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+        dictionary.lookup_value_or_insert("o2");
+
+        let partition_bitmap = |partition_predicate: &PartitionPredicate| {
+            table
+                .predicate_bitmap(partition_predicate, &partition)
+                .unwrap()
+                .map(|bitmap| {
+                    (0..bitmap.len())
+                        .map(|i| bitmap.value(i))
+                        .collect::<Vec<_>>()
+                })
+        };
+        let predicate_bitmap = |predicate: &Predicate| {
+            partition_bitmap(&partition.compile_predicate(predicate).unwrap())
+        };
+        let bitmap =
+            |predicate: &str| predicate_bitmap(&parse_simple_predicate(predicate).unwrap());
+
+        assert_eq!(bitmap(""), Some(vec![true; 5]));
+        assert_eq!(
+            bitmap("state = 'MA' and time > 100"),
+            Some(vec![false, true, false, false, true])
+        );
+        // no row has this value
+        assert_eq!(bitmap("state = 'NY'"), Some(vec![false; 5]));
+        // general expressions are not evaluated directly
+        assert_eq!(bitmap("temp > 71.0"), None);
+
+        // predicates excluding the table match no rows
+        let predicate = PredicateBuilder::default()
+            .tables(vec!["o2".into()])
+            .add_expr(parse_simple_predicate("state = 'MA'").unwrap().exprs[0].clone())
+            .build();
+        assert_eq!(predicate_bitmap(&predicate), Some(vec![false; 5]));
+        let predicate = PredicateBuilder::default()
+            .table_regex(Regex::new("^o2$").unwrap())
+            .build();
+        assert_eq!(predicate_bitmap(&predicate), Some(vec![false; 5]));
+        let predicate = PredicateBuilder::default()
+            .tables(vec!["table_name".into()])
+            .build();
+        assert_eq!(predicate_bitmap(&predicate), Some(vec![true; 5]));
+
+        let mut partition_predicate = partition
+            .compile_predicate(&PredicateBuilder::default().build())
+            .unwrap();
+        partition_predicate.required_columns = Some(PartitionIdSet::AtLeastOneMissing);
+        assert_eq!(partition_bitmap(&partition_predicate), Some(vec![false; 5]));
+
+        // one bitmap filters two different projections
+        let predicate = parse_simple_predicate("state = 'MA' and time > 100").unwrap();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let bitmap = table
+            .predicate_bitmap(&partition_predicate, &partition)
+            .unwrap()
+            .unwrap();
+
+        let city_batch = table.to_arrow(&partition, &["city"]).unwrap();
+        let temp_batch = table.to_arrow(&partition, &["temp", "time"]).unwrap();
+        let city_batch = arrow::compute::filter_record_batch(&city_batch, &bitmap).unwrap();
+        let temp_batch = arrow::compute::filter_record_batch(&temp_batch, &bitmap).unwrap();
+
+        let results = pretty_format_batches(&[city_batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+-----------+",
+            "| city      |",
+            "+-----------+",
+            "| Boston    |",
+            "| Worcester |",
+            "+-----------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let results = pretty_format_batches(&[temp_batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+------+------+",
+            "| temp | time |",
+            "+------+------+",
+            "| 72.4 | 250  |",
+            "| 71   | 350  |",
+            "+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_to_arrow_filtered() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=91.0,reading=\"hot\" 300",
+            "h2o,state=CA temp=80.0 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = parse_simple_predicate("city = 'LA'").unwrap();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let requested_columns = ["time", "city", "reading", "temp"];
+
+        let batch = table
+            .to_arrow_filtered(&partition, &requested_columns, &partition_predicate)
+            .unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        // the same as the DataFusion plan
+        let exprs = requested_columns
+            .iter()
+            .map(|&column_name| logical_plan::col(column_name))
+            .collect();
+        let plan = table
+            .select_plan(&partition_predicate, exprs, &partition)
+            .unwrap();
+        let expected = run_plan(plan).await;
+        assert_eq!(expected, results, "expected output");
+
+        let expected = vec![
+            "+------+------+---------+------+",
+            "| time | city | reading | temp |",
+            "+------+------+---------+------+",
+            "| 200  | LA   |         | 90   |",
+            "| 300  | LA   | hot     | 91   |",
+            "+------+------+---------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // general expressions are not supported
+        let predicate = parse_simple_predicate("temp > 71.0").unwrap();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let res = table.to_arrow_filtered(&partition, &[], &partition_predicate);
+        assert!(
+            matches!(res, Err(Error::UnsupportedFilteredToArrowPredicate)),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[test]
+    fn test_to_arrow_sampled() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=91.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        assert_eq!(table.sample(2, 0).unwrap(), vec![0, 2]);
+        assert_eq!(table.sample(2, 1).unwrap(), vec![1, 3]);
+        assert_eq!(table.sample(3, 2).unwrap(), vec![2]);
+        assert_eq!(table.sample(10, 5).unwrap(), Vec::<usize>::new());
+
+        // every other row
+        let batch = table
+            .to_arrow_sampled(&partition, &["city", "temp", "time"], 2, 0)
+            .unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+        let expected = vec![
+            "+--------+------+------+",
+            "| city   | temp | time |",
+            "+--------+------+------+",
+            "| Boston | 70.4 | 100  |",
+            "| Boston | 72.4 | 250  |",
+            "+--------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let err = table.sample(2, 2).unwrap_err();
+        assert!(matches!(err, Error::InvalidSample { .. }));
+        let err = table.sample(0, 0).unwrap_err();
+        assert!(matches!(err, Error::InvalidSample { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_select_plan() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.0 100",
+            "h2o,state=MA,city=Boston temp=72.5 250",
+            "h2o,state=CA,city=LA temp=90.0 350",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 400)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        // temp * 1.8 + 32
+        let fahrenheit = Expr::BinaryExpr {
+            left: Box::new(Expr::BinaryExpr {
+                left: Box::new(logical_plan::col("temp")),
+                op: Operator::Multiply,
+                right: Box::new(logical_plan::lit(1.8)),
+            }),
+            op: Operator::Plus,
+            right: Box::new(logical_plan::lit(32.0)),
+        };
+        let exprs = vec![
+            logical_plan::col("city"),
+            fahrenheit.alias("temp_f"),
+            logical_plan::col("time"),
+        ];
+
+        let plan = table
+            .select_plan(&partition_predicate, exprs, &partition)
+            .expect("creating the select plan");
+        let results = run_plan(plan).await;
+
+        let expected = vec![
+            "+--------+--------+------+",
+            "| city   | temp_f | time |",
+            "+--------+--------+------+",
+            "| Boston | 162.5  | 250  |",
+            "| LA     | 194    | 350  |",
+            "+--------+--------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // expressions may only refer to columns of the table
+        let exprs = vec![logical_plan::col("humidity")];
+        let res = table.select_plan(&partition_predicate, exprs, &partition);
+        assert!(
+            matches!(
+                &res,
+                Err(Error::SelectColumnNotFound { column_name }) if column_name == "humidity"
+            ),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sorted_plan_row_numbers() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=CA,city=LA temp=91.0 200",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        // the row numbers are the insertion order, before sorting
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 400)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let sort_columns = vec![("time".to_string(), true)];
+        let plan = table
+            .sorted_plan_impl(&partition_predicate, &sort_columns, true, &partition)
+            .expect("creating the sorted plan");
+
+        let results = run_plan(plan).await;
+
+        let expected = vec![
+            "+--------+-------+------+------+------+",
+            "| city   | state | temp | time | _row |",
+            "+--------+-------+------+------+------+",
+            "| LA     | CA    | 91   | 200  | 3    |",
+            "| Boston | MA    | 72.4 | 250  | 1    |",
+            "| LA     | CA    | 90   | 350  | 2    |",
+            "+--------+-------+------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+    }
+
+    #[tokio::test]
+    async fn test_time_delta_plan() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=MA,city=Boston temp=72.4 100",
+            "h2o,state=MA,city=Boston temp=73.4 180",
+            "h2o,state=CA,city=LA temp=91.0 350",
+            "h2o,state=MA temp=60.0 400",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let time_deltas = |predicate: query::predicate::Predicate| {
+            let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+            let group_columns = vec![String::from("state")];
+            table.time_delta_plan(&partition_predicate, &group_columns, &partition)
+        };
+
+        let res = time_deltas(parse_simple_predicate("temp > 71.0").unwrap());
+        assert!(
+            matches!(res, Err(Error::UnsupportedTimeDeltaPredicate)),
+            "unexpected result: {:?}",
+            res
+        );
 
-    // Note that this is an O(N^2) algorithm. We are assuming the
-    // number of tag columns is reasonably small
+        let plan = time_deltas(PredicateBuilder::default().timestamp_range(0, 300).build());
+        let batches = Executor::new()
+            .run_logical_plan(plan.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
 
-    // map from prefix_column[idx] -> index in tag_columns
-    let prefix_map = prefix_columns
-        .iter()
-        .map(|pc| {
-            let found_location = tag_columns
-                .iter()
-                .enumerate()
-                .find(|(_, c)| pc == c.as_ref());
+        let schema = batch.schema();
+        let names = schema
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["state", "city", "time", "delta"]);
+
+        let strings = |i: usize| {
+            let array = batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            (0..array.len())
+                .map(|row| array.value(row).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strings(0), vec!["CA", "MA", "MA", "MA"]);
+        assert_eq!(strings(1), vec!["LA", "Boston", "Boston", "Boston"]);
+
+        let times = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(times.values(), &[200, 100, 180, 250]);
+
+        // the first row of each series has no delta
+        let deltas = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<DurationNanosecondArray>()
+            .unwrap();
+        let deltas = (0..deltas.len())
+            .map(|row| Some(deltas.value(row)).filter(|_| deltas.is_valid(row)))
+            .collect::<Vec<_>>();
+        assert_eq!(deltas, vec![None, None, Some(80), Some(70)]);
 
-            if let Some((index, _)) = found_location {
-                if tag_used_set[index] {
-                    DuplicateGroupColumn { column_name: pc }.fail()
-                } else {
-                    tag_used_set[index] = true;
-                    Ok(index)
-                }
-            } else {
-                GroupColumnNotFound {
-                    column_name: pc,
-                    all_tag_column_names: tag_columns
-                        .iter()
-                        .map(|s| s.as_ref() as &str)
-                        .collect::<Vec<_>>()
-                        .as_slice()
-                        .join(", "),
-                }
-                .fail()
-            }
-        })
-        .collect::<Result<Vec<_>>>()?;
+        // the MA series without a city is separate, and sorts first
+        let plan = time_deltas(PredicateBuilder::default().build());
+        let batches = Executor::new()
+            .run_logical_plan(plan.unwrap())
+            .await
+            .unwrap();
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 6);
+        assert_eq!(batch.column(1).null_count(), 1);
+        assert!(batch.column(1).is_null(2));
+        let deltas = batch.column(3);
+        let null_rows = (0..6)
+            .filter(|&row| deltas.is_null(row))
+            .collect::<Vec<_>>();
+        assert_eq!(null_rows, vec![0, 2, 3]);
 
-    let mut new_tag_columns = prefix_map
-        .iter()
-        .map(|&i| tag_columns[i].clone())
-        .collect::<Vec<_>>();
+        // a table without a time column has no rows with a time
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let partition_predicate = partition
+            .compile_predicate(&PredicateBuilder::default().build())
+            .unwrap();
+        let plan = table
+            .time_delta_plan(&partition_predicate, &["state".to_string()], &partition)
+            .unwrap();
+        let batches = Executor::new().run_logical_plan(plan).await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+    }
 
-    new_tag_columns.extend(tag_columns.into_iter().enumerate().filter_map(|(i, c)| {
-        // already used in prefix
-        if tag_used_set[i] {
-            None
-        } else {
-            Some(c)
-        }
-    }));
+    #[tokio::test]
+    async fn test_gap_fill_plan() {
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
 
-    Ok(new_tag_columns)
-}
+        let lp_lines = vec![
+            "h2o,state=MA temp=70.4 120",
+            "h2o,state=MA temp=71.4 150",
+            "h2o,state=MA temp=72.4 410",
+            "h2o,state=CA temp=90.0 250",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
 
-/// Traits to help creating DataFuson expressions from strings
-trait IntoExpr {
-    /// Creates a DataFuson expr
-    fn into_expr(&self) -> Expr;
+        let gap_fill = |predicate: query::predicate::Predicate, interval_nanos, fill| {
+            let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+            let group_columns = vec![String::from("state")];
+            table.gap_fill_plan(
+                &partition_predicate,
+                &group_columns,
+                interval_nanos,
+                fill,
+                &partition,
+            )
+        };
 
-    /// creates a DataFusion SortExpr
-    fn into_sort_expr(&self) -> Expr {
-        Expr::Sort {
-            expr: Box::new(self.into_expr()),
-            asc: true, // Sort ASCENDING
-            nulls_first: true,
-        }
-    }
-}
+        let res = gap_fill(
+            parse_simple_predicate("temp > 71.0").unwrap(),
+            100,
+            FillPolicy::Null,
+        );
+        assert!(
+            matches!(res, Err(Error::UnsupportedGapFillPredicate)),
+            "unexpected result: {:?}",
+            res
+        );
 
-impl IntoExpr for Arc<String> {
-    fn into_expr(&self) -> Expr {
-        Expr::Column(self.as_ref().clone())
-    }
-}
+        let res = gap_fill(PredicateBuilder::default().build(), 0, FillPolicy::Null);
+        assert!(
+            matches!(
+                res,
+                Err(Error::InvalidGapFillInterval { interval_nanos: 0 })
+            ),
+            "unexpected result: {:?}",
+            res
+        );
 
-impl IntoExpr for str {
-    fn into_expr(&self) -> Expr {
-        Expr::Column(self.to_string())
-    }
-}
+        let res = gap_fill(PredicateBuilder::default().build(), 100, FillPolicy::Linear);
+        assert!(
+            matches!(
+                res,
+                Err(Error::UnsupportedFillPolicy {
+                    fill: FillPolicy::Linear
+                })
+            ),
+            "unexpected result: {:?}",
+            res
+        );
 
-#[cfg(test)]
-mod tests {
-    use arrow::util::pretty::pretty_format_batches;
-    use data_types::data::split_lines_into_write_entry_partitions;
-    use datafusion::{logical_plan::Operator, scalar::ScalarValue};
-    use influxdb_line_protocol::{parse_lines, ParsedLine};
-    use query::{exec::Executor, predicate::PredicateBuilder};
-    use test_helpers::str_vec_to_arc_vec;
+        // each group has a row for every interval in range, with the
+        // last value within the interval
+        let plan = gap_fill(
+            PredicateBuilder::default().timestamp_range(0, 500).build(),
+            100,
+            FillPolicy::Null,
+        );
+        let results = run_plan(plan.unwrap()).await;
+        let expected = vec![
+            "+-------+------+------+",
+            "| state | temp | time |",
+            "+-------+------+------+",
+            "| CA    |      | 0    |",
+            "| CA    |      | 100  |",
+            "| CA    | 90   | 200  |",
+            "| CA    |      | 300  |",
+            "| CA    |      | 400  |",
+            "| MA    |      | 0    |",
+            "| MA    | 71.4 | 100  |",
+            "| MA    |      | 200  |",
+            "| MA    |      | 300  |",
+            "| MA    | 72.4 | 400  |",
+            "+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
 
-    use super::*;
+        // without a range, the intervals span the matching rows, and
+        // gaps take the previous value of their group
+        let plan = gap_fill(
+            PredicateBuilder::default().build(),
+            100,
+            FillPolicy::Previous,
+        );
+        let results = run_plan(plan.unwrap()).await;
+        let expected = vec![
+            "+-------+------+------+",
+            "| state | temp | time |",
+            "+-------+------+------+",
+            "| CA    |      | 100  |",
+            "| CA    | 90   | 200  |",
+            "| CA    | 90   | 300  |",
+            "| CA    | 90   | 400  |",
+            "| MA    | 71.4 | 100  |",
+            "| MA    | 71.4 | 200  |",
+            "| MA    | 71.4 | 300  |",
+            "| MA    | 72.4 | 400  |",
+            "+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
 
-    #[test]
-    fn test_has_columns() {
-        // setup a test table
+        // an open ended range only spans the matching rows on its
+        // unbounded side
+        let plan = gap_fill(
+            parse_simple_predicate("time >= 100").unwrap(),
+            100,
+            FillPolicy::Null,
+        );
+        let results = run_plan(plan.unwrap()).await;
+        let expected = vec![
+            "+-------+------+------+",
+            "| state | temp | time |",
+            "+-------+------+------+",
+            "| CA    |      | 100  |",
+            "| CA    | 90   | 200  |",
+            "| CA    |      | 300  |",
+            "| CA    |      | 400  |",
+            "| MA    | 71.4 | 100  |",
+            "| MA    |      | 200  |",
+            "| MA    |      | 300  |",
+            "| MA    | 72.4 | 400  |",
+            "+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let plan = gap_fill(
+            PredicateBuilder::default()
+                .timestamp_range(i64::MIN, 300)
+                .build(),
+            100,
+            FillPolicy::Null,
+        );
+        let results = run_plan(plan.unwrap()).await;
+        let expected = vec![
+            "+-------+------+------+",
+            "| state | temp | time |",
+            "+-------+------+------+",
+            "| CA    |      | 100  |",
+            "| CA    | 90   | 200  |",
+            "| MA    | 71.4 | 100  |",
+            "| MA    |      | 200  |",
+            "+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // the number of intervals is limited
+        let res = gap_fill(
+            PredicateBuilder::default()
+                .timestamp_range(0, 1_000_000)
+                .build(),
+            1,
+            FillPolicy::Null,
+        );
+        assert!(
+            matches!(res, Err(Error::TooManyGapFillIntervals { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        // a table without a time column has no rows to fill
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let partition_predicate = partition
+            .compile_predicate(&PredicateBuilder::default().timestamp_range(0, 500).build())
+            .unwrap();
+        let plan = table
+            .gap_fill_plan(
+                &partition_predicate,
+                &["state".to_string()],
+                100,
+                FillPolicy::Null,
+                &partition,
+            )
+            .unwrap();
+        let batches = Executor::new().run_logical_plan(plan).await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_top_n_plan() {
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
         let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
@@ -1037,318 +13264,553 @@ mod tests {
         let lp_lines = vec![
             "h2o,state=MA,city=Boston temp=70.4 100",
             "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
         ];
-
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
-        let state_symbol = dictionary.id("state").unwrap();
-        let new_symbol = dictionary.lookup_value_or_insert("not_a_columns");
-
-        assert!(table.has_columns(None));
-
-        let pred = PartitionIdSet::AtLeastOneMissing;
-        assert!(!table.has_columns(Some(&pred)));
-
-        let set = BTreeSet::<u32>::new();
-        let pred = PartitionIdSet::Present(set);
-        assert!(table.has_columns(Some(&pred)));
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let group_columns = vec!["state".to_string()];
+
+        // the hottest row of each state (ties keep the first row)
+        let plan = table
+            .top_n_plan(
+                &partition_predicate,
+                &group_columns,
+                "temp",
+                1,
+                false,
+                &partition,
+            )
+            .expect("creating the top n plan");
+        let results = run_plan(plan).await;
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| LA     | CA    | 90   | 200  |",
+            "| Boston | MA    | 72.4 | 250  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
 
-        let mut set = BTreeSet::new();
-        set.insert(state_symbol);
-        let pred = PartitionIdSet::Present(set);
-        assert!(table.has_columns(Some(&pred)));
+        // the coldest rows within the time range
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 400)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = table
+            .top_n_plan(
+                &partition_predicate,
+                &group_columns,
+                "temp",
+                1,
+                true,
+                &partition,
+            )
+            .expect("creating the top n plan");
+        let results = run_plan(plan).await;
+        let expected = vec![
+            "+--------+-------+------+------+",
+            "| city   | state | temp | time |",
+            "+--------+-------+------+------+",
+            "| LA     | CA    | 90   | 200  |",
+            "| Boston | MA    | 72.4 | 250  |",
+            "+--------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
 
-        let mut set = BTreeSet::new();
-        set.insert(new_symbol);
-        let pred = PartitionIdSet::Present(set);
-        assert!(!table.has_columns(Some(&pred)));
+        let res = table.top_n_plan(
+            &partition_predicate,
+            &group_columns,
+            "city",
+            1,
+            true,
+            &partition,
+        );
+        assert!(
+            matches!(res, Err(Error::InvalidTopNField { .. })),
+            "unexpected result: {:?}",
+            res
+        );
 
-        let mut set = BTreeSet::new();
-        set.insert(state_symbol);
-        set.insert(new_symbol);
-        let pred = PartitionIdSet::Present(set);
-        assert!(!table.has_columns(Some(&pred)));
+        // a table without a time column has no rows in the range
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = table
+            .top_n_plan(
+                &partition_predicate,
+                &group_columns,
+                "temp",
+                1,
+                true,
+                &partition,
+            )
+            .expect("creating the top n plan");
+        let batches = Executor::new().run_logical_plan(plan).await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
     }
 
     #[test]
-    fn test_matches_table_name_predicate() {
-        // setup a test table
+    fn test_to_arrow_with_serialized_tags() {
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
         let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
 
         let lp_lines = vec![
             "h2o,state=MA,city=Boston temp=70.4 100",
-            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA temp=91.0 300",
+            "h2o temp=92.0 400",
         ];
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
-        let h2o_symbol = dictionary.id("h2o").unwrap();
-
-        assert!(table.matches_table_name_predicate(None));
-
-        let set = BTreeSet::new();
-        assert!(!table.matches_table_name_predicate(Some(&set)));
+        let batch = table
+            .to_arrow_with_serialized_tags(&partition, ",")
+            .unwrap();
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
 
-        let mut set = BTreeSet::new();
-        set.insert(h2o_symbol);
-        assert!(table.matches_table_name_predicate(Some(&set)));
+        let expected = vec![
+            "+----------------------+------+------+",
+            "| tags                 | temp | time |",
+            "+----------------------+------+------+",
+            "| city=Boston,state=MA | 70.4 | 100  |",
+            "| city=LA,state=CA     | 90   | 200  |",
+            "| state=CA             | 91   | 300  |",
+            "|                      | 92   | 400  |",
+            "+----------------------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
 
-        // Some symbol that is not the same as h2o_symbol
-        assert_ne!(37377, h2o_symbol);
-        let mut set = BTreeSet::new();
-        set.insert(37377);
-        assert!(!table.matches_table_name_predicate(Some(&set)));
+        let batch = table
+            .to_arrow_with_serialized_tags(&partition, " ")
+            .unwrap();
+        let tags = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(tags.value(1), "city=LA state=CA");
     }
 
     #[tokio::test]
-    async fn test_series_set_plan() {
-        // setup a test table
+    async fn test_aggregate_plan() {
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
         let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
 
         let lp_lines = vec![
             "h2o,state=MA,city=Boston temp=70.4 100",
-            "h2o,state=MA,city=Boston temp=72.4 250",
+            "h2o,state=MA,city=Boston temp=80.0 150",
+            "h2o,state=MA,city=Cambridge temp=72.4 250",
             "h2o,state=CA,city=LA temp=90.0 200",
-            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=CA,city=LA temp=91.0 350",
         ];
-
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
         let predicate = PredicateBuilder::default().build();
         let partition_predicate = partition.compile_predicate(&predicate).unwrap();
-        let series_set_plan = table
-            .series_set_plan(&partition_predicate, &partition)
-            .expect("creating the series set plan");
+        let group_columns = vec!["state".to_string()];
 
-        assert_eq!(series_set_plan.table_name.as_ref(), "table_name");
-        assert_eq!(
-            series_set_plan.tag_columns,
-            *str_vec_to_arc_vec(&["city", "state"])
+        // the median temperature of each state
+        let p50 = match Aggregate::percentile(0.5).unwrap() {
+            Aggregate::Percentile(percentile) => percentile,
+            agg => panic!("unexpected aggregate: {:?}", agg),
+        };
+        let plan = table
+            .aggregate_plan(
+                &partition_predicate,
+                &group_columns,
+                AggregateKind::Percentile(p50),
+                "temp",
+                &partition,
+            )
+            .expect("creating the aggregate plan");
+        let results = run_plan(plan).await;
+        let expected = vec![
+            "+-------+------+",
+            "| state | temp |",
+            "+-------+------+",
+            "| CA    | 90.5 |",
+            "| MA    | 72.4 |",
+            "+-------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // only the rows within the time range are aggregated
+        let predicate = PredicateBuilder::default()
+            .timestamp_range(150, 300)
+            .build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = table
+            .aggregate_plan(
+                &partition_predicate,
+                &group_columns,
+                AggregateKind::try_from(&Aggregate::Max).unwrap(),
+                "temp",
+                &partition,
+            )
+            .expect("creating the aggregate plan");
+        let results = run_plan(plan).await;
+        let expected = vec![
+            "+-------+------+",
+            "| state | temp |",
+            "+-------+------+",
+            "| CA    | 90   |",
+            "| MA    | 80   |",
+            "+-------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        let res = table.aggregate_plan(
+            &partition_predicate,
+            &group_columns,
+            AggregateKind::Percentile(p50),
+            "city",
+            &partition,
         );
-        assert_eq!(
-            series_set_plan.field_columns,
-            *str_vec_to_arc_vec(&["temp"])
+        assert!(
+            matches!(
+                &res,
+                Err(Error::InvalidAggregateField { column_name, .. }) if column_name == "city"
+            ),
+            "unexpected result: {:?}",
+            res.map(|_| ())
         );
 
-        // run the created plan, ensuring the output is as expected
-        let results = run_plan(series_set_plan.plan).await;
+        let res = AggregateKind::try_from(&Aggregate::Mean);
+        assert!(
+            matches!(res, Err(Error::UnsupportedAggregate { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+    }
+
+    #[tokio::test]
+    async fn test_histogram_plan() {
+        // setup a test table
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
+            "h2o,state=MA,city=Kingston,zz_tag=B temp=70.2 100",
+            "h2o,state=CA,city=Boston temp=70.3 250",
+            "h2o,state=MA,city=Boston,zz_tag=A temp=70.4 1000",
+            "h2o,state=MA,city=Boston temp=70.5,other=5.0 250",
+            "h2o,state=CA,city=LA temp=92.5 300",
+            "h2o,state=CA,city=LA temp=85.0 400",
+            "h2o,state=CA,city=LA other=1.0 500",
+        ];
+
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let plan = table
+            .histogram_plan(&partition_predicate, "temp", 10.0, &partition)
+            .expect("creating the histogram plan");
+        let results = run_plan(plan).await;
 
         let expected = vec![
-            "+--------+-------+------+------+",
-            "| city   | state | temp | time |",
-            "+--------+-------+------+------+",
-            "| Boston | MA    | 70.4 | 100  |",
-            "| Boston | MA    | 72.4 | 250  |",
-            "| LA     | CA    | 90   | 200  |",
-            "| LA     | CA    | 90   | 350  |",
-            "+--------+-------+------+------+",
+            "+--------+-------+",
+            "| bucket | count |",
+            "+--------+-------+",
+            "| 7      | 5     |",
+            "| 8      | 1     |",
+            "| 9      | 1     |",
+            "+--------+-------+",
         ];
         assert_eq!(expected, results, "expected output");
+
+        // only positive widths and numeric fields are supported
+        let res = table.histogram_plan(&partition_predicate, "temp", 0.0, &partition);
+        assert!(
+            matches!(res, Err(Error::InvalidBucketWidth { .. })),
+            "unexpected result: {:?}",
+            res
+        );
+
+        for field in &["city", "time", "not_a_column"] {
+            let res = table.histogram_plan(&partition_predicate, field, 10.0, &partition);
+            assert!(
+                matches!(res, Err(Error::InvalidHistogramField { .. })),
+                "unexpected result: {:?}",
+                res
+            );
+        }
     }
 
     #[tokio::test]
-    async fn test_series_set_plan_order() {
-        // test that the columns and rows come out in the right order (tags then timestamp)
-
+    async fn test_add_partition_key_column() {
         // setup a test table
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
         let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
 
         let lp_lines = vec![
-            "h2o,zz_tag=A,state=MA,city=Kingston temp=70.1 800",
-            "h2o,state=MA,city=Kingston,zz_tag=B temp=70.2 100",
-            "h2o,state=CA,city=Boston temp=70.3 250",
-            "h2o,state=MA,city=Boston,zz_tag=A temp=70.4 1000",
-            "h2o,state=MA,city=Boston temp=70.5,other=5.0 250",
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Boston temp=72.4 250",
         ];
 
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
         let predicate = PredicateBuilder::default().build();
         let partition_predicate = partition.compile_predicate(&predicate).unwrap();
-        let series_set_plan = table
-            .series_set_plan(&partition_predicate, &partition)
-            .expect("creating the series set plan");
 
-        assert_eq!(series_set_plan.table_name.as_ref(), "table_name");
-        assert_eq!(
-            series_set_plan.tag_columns,
-            *str_vec_to_arc_vec(&["city", "state", "zz_tag"])
-        );
-        assert_eq!(
-            series_set_plan.field_columns,
-            *str_vec_to_arc_vec(&["other", "temp"])
-        );
+        let plan = table
+            .field_names_plan(&partition_predicate, &FieldSelector::All, &partition)
+            .expect("creating the field_name plan");
+        let plan = Table::add_partition_key_column(plan, &partition, PARTITION_KEY_COLUMN_NAME)
+            .expect("adding the partition key column");
 
         // run the created plan, ensuring the output is as expected
-        let results = run_plan(series_set_plan.plan).await;
+        let results = run_plan(plan).await;
 
         let expected = vec![
-            "+----------+-------+--------+-------+------+------+",
-            "| city     | state | zz_tag | other | temp | time |",
-            "+----------+-------+--------+-------+------+------+",
-            "| Boston   | CA    |        |       | 70.3 | 250  |",
-            "| Boston   | MA    |        | 5     | 70.5 | 250  |",
-            "| Boston   | MA    | A      |       | 70.4 | 1000 |",
-            "| Kingston | MA    | A      |       | 70.1 | 800  |",
-            "| Kingston | MA    | B      |       | 70.2 | 100  |",
-            "+----------+-------+--------+-------+------+------+",
+            "+------+------+---------------------+",
+            "| temp | time | _partition          |",
+            "+------+------+---------------------+",
+            "| 70.4 | 100  | dummy_partition_key |",
+            "| 72.4 | 250  | dummy_partition_key |",
+            "+------+------+---------------------+",
         ];
 
         assert_eq!(expected, results, "expected output");
     }
 
     #[tokio::test]
-    async fn test_series_set_plan_filter() {
-        // test that filters are applied reasonably
-
+    async fn test_tag_keys_plan() {
         // setup a test table
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
-        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
 
         let lp_lines = vec![
             "h2o,state=MA,city=Boston temp=70.4 100",
-            "h2o,state=MA,city=Boston temp=72.4 250",
-            "h2o,state=CA,city=LA temp=90.0 200",
-            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=MA,county=Suffolk temp=72.4 250",
         ];
-
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
-        let predicate = PredicateBuilder::default()
-            .add_expr(Expr::BinaryExpr {
-                left: Box::new(Expr::Column("city".into())),
-                op: Operator::Eq,
-                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("LA".into())))),
-            })
-            .timestamp_range(190, 210)
-            .build();
-
+        let predicate = PredicateBuilder::default().timestamp_range(0, 200).build();
         let partition_predicate = partition.compile_predicate(&predicate).unwrap();
 
-        let series_set_plan = table
-            .series_set_plan(&partition_predicate, &partition)
-            .expect("creating the series set plan");
+        let plan = table
+            .tag_keys_plan(&partition_predicate, &partition)
+            .expect("creating the tag_keys plan");
 
-        assert_eq!(series_set_plan.table_name.as_ref(), "table_name");
-        assert_eq!(
-            series_set_plan.tag_columns,
-            *str_vec_to_arc_vec(&["city", "state"])
-        );
-        assert_eq!(
-            series_set_plan.field_columns,
-            *str_vec_to_arc_vec(&["temp"])
-        );
+        assert_eq!(plan.schema().fields().len(), 1);
+        assert_eq!(plan.schema().field(0).name(), TAG_KEY_COLUMN_NAME);
+        assert_eq!(plan.schema().field(0).data_type(), &ArrowDataType::Utf8);
 
-        // run the created plan, ensuring the output is as expected
-        let results = run_plan(series_set_plan.plan).await;
+        let results = run_plan(plan).await;
 
         let expected = vec![
-            "+------+-------+------+------+",
-            "| city | state | temp | time |",
-            "+------+-------+------+------+",
-            "| LA   | CA    | 90   | 200  |",
-            "+------+-------+------+------+",
+            "+---------+",
+            "| tag_key |",
+            "+---------+",
+            "| city    |",
+            "| state   |",
+            "+---------+",
         ];
 
         assert_eq!(expected, results, "expected output");
     }
 
     #[tokio::test]
-    async fn test_grouped_series_set_plan() {
-        // test that filters are applied reasonably
+    async fn test_tag_column_names_plan_no_tags() {
+        // setup a test table with only fields and time
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
+
+        let lp_lines = vec!["h2o temp=70.4 100", "h2o temp=72.4,count=1i 250"];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let predicate = PredicateBuilder::default().timestamp_range(0, 200).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let plan = table
+            .tag_column_names_plan(&partition_predicate, &partition)
+            .expect("creating the tag_column_names plan");
+
+        assert_eq!(plan.schema().fields().len(), 1);
+        assert_eq!(plan.schema().field(0).data_type(), &ArrowDataType::Utf8);
 
+        let batches = Executor::new()
+            .run_logical_plan(plan)
+            .await
+            .expect("ok running plan");
+        let num_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(num_rows, 0);
+
+        // also works when building on top of it
+        let plan = table
+            .tag_keys_plan(&partition_predicate, &partition)
+            .expect("creating the tag_keys plan");
+        assert_eq!(plan.schema().field(0).name(), TAG_KEY_COLUMN_NAME);
+    }
+
+    #[tokio::test]
+    async fn test_field_keys_plan() {
         // setup a test table
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
-        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+        let mut table = Table::new(dictionary.lookup_value_or_insert("h2o"));
 
         let lp_lines = vec![
-            "h2o,state=MA,city=Boston temp=70.4 100",
-            "h2o,state=MA,city=Boston temp=72.4 250",
-            "h2o,state=CA,city=LA temp=90.0 200",
-            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=MA temp=70.4,count=1i 100",
+            "h2o,state=MA desc=\"foo\",flag=true 100",
+            "h2o,state=MA other=1.0 1000",
         ];
-
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
+        let predicate = PredicateBuilder::default().timestamp_range(0, 200).build();
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+
+        let plan = table
+            .field_keys_plan(&partition_predicate, &partition)
+            .expect("creating the field_keys plan");
+
+        let schema = plan.schema();
+        assert_eq!(schema.fields().len(), 2);
+        assert_eq!(schema.field(0).name(), FIELD_KEY_COLUMN_NAME);
+        assert_eq!(schema.field(0).data_type(), &ArrowDataType::Utf8);
+        assert_eq!(schema.field(1).name(), FIELD_TYPE_COLUMN_NAME);
+        assert_eq!(schema.field(1).data_type(), &ArrowDataType::Utf8);
+
+        let results = run_plan(plan).await;
+
+        let expected = vec![
+            "+-----------+------------+",
+            "| field_key | field_type |",
+            "+-----------+------------+",
+            "| count     | integer    |",
+            "| desc      | string     |",
+            "| flag      | boolean    |",
+            "| temp      | float      |",
+            "+-----------+------------+",
+        ];
+
+        assert_eq!(expected, results, "expected output");
+
+        // general expressions only report fields with values in the
+        // rows they match
+        write_lines_to_table(
+            &mut table,
+            &mut partition.dictionary,
+            vec!["h2o,state=CA temp=90.0,level=3i 150"],
+        );
+        let field_keys = |predicate: query::predicate::Predicate| {
+            let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+            table.field_keys_plan(&partition_predicate, &partition)
+        };
+
         let predicate = PredicateBuilder::default()
             .add_expr(Expr::BinaryExpr {
-                left: Box::new(Expr::Column("city".into())),
+                left: Box::new(Expr::Column("state".into())),
                 op: Operator::Eq,
-                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("LA".into())))),
+                right: Box::new(Expr::Literal(ScalarValue::Utf8(Some("CA".into())))),
             })
-            .timestamp_range(190, 210)
+            .timestamp_range(0, 200)
             .build();
-        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let plan = field_keys(predicate).expect("creating the field_keys plan");
+        let schema = plan.schema();
+        assert_eq!(schema.field(0).name(), FIELD_KEY_COLUMN_NAME);
+        assert_eq!(schema.field(1).name(), FIELD_TYPE_COLUMN_NAME);
+        assert_eq!(schema.field(1).data_type(), &ArrowDataType::Utf8);
 
-        let group_columns = vec![String::from("state")];
-
-        let grouped_series_set_plan = table
-            .grouped_series_set_plan(&partition_predicate, &group_columns, &partition)
-            .expect("creating the grouped_series set plan");
-
-        assert_eq!(grouped_series_set_plan.num_prefix_tag_group_columns, 1);
-
-        // run the created plan, ensuring the output is as expected
-        let results = run_plan(grouped_series_set_plan.series_set_plan.plan).await;
+        let results = run_plan(plan).await;
+        let expected = vec![
+            "+-----------+------------+",
+            "| field_key | field_type |",
+            "+-----------+------------+",
+            "| level     | integer    |",
+            "| temp      | float      |",
+            "+-----------+------------+",
+        ];
+        assert_eq!(expected, results, "expected output");
 
+        let predicate = parse_simple_predicate("temp < 80.0").unwrap();
+        let results = run_plan(field_keys(predicate).unwrap()).await;
         let expected = vec![
-            "+-------+------+------+------+",
-            "| state | city | temp | time |",
-            "+-------+------+------+------+",
-            "| CA    | LA   | 90   | 200  |",
-            "+-------+------+------+------+",
+            "+-----------+------------+",
+            "| field_key | field_type |",
+            "+-----------+------------+",
+            "| count     | integer    |",
+            "| temp      | float      |",
+            "+-----------+------------+",
         ];
+        assert_eq!(expected, results, "expected output");
 
+        let predicate = parse_simple_predicate("other > 0.5").unwrap();
+        let results = run_plan(field_keys(predicate).unwrap()).await;
+        let expected = vec![
+            "+-----------+------------+",
+            "| field_key | field_type |",
+            "+-----------+------------+",
+            "| other     | float      |",
+            "+-----------+------------+",
+        ];
         assert_eq!(expected, results, "expected output");
     }
 
-    #[tokio::test]
-    async fn test_field_name_plan() {
+    #[test]
+    fn test_distinct_group_keys() {
         // setup a test table
         let mut partition = Partition::new("dummy_partition_key");
         let dictionary = &mut partition.dictionary;
         let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
 
         let lp_lines = vec![
-            // Order this so field3 comes before field2
-            // (and thus the columns need to get reordered)
-            "h2o,tag1=foo,tag2=bar field1=70.6,field3=2 100",
-            "h2o,tag1=foo,tag2=bar field1=70.4,field2=\"ss\" 100",
-            "h2o,tag1=foo,tag2=bar field1=70.5,field2=\"ss\" 100",
-            "h2o,tag1=foo,tag2=bar field1=70.6,field4=true 1000",
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=MA,city=Cambridge temp=72.4 250",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=CA,city=LA temp=90.0 350",
+            "h2o,state=TX,city=Austin temp=95.0 1000",
+            "h2o,city=Nowhere temp=1.0 300",
         ];
 
         write_lines_to_table(&mut table, dictionary, lp_lines);
 
-        let predicate = PredicateBuilder::default().timestamp_range(0, 200).build();
-
+        let predicate = PredicateBuilder::default().timestamp_range(0, 500).build();
         let partition_predicate = partition.compile_predicate(&predicate).unwrap();
 
-        let field_names_set_plan = table
-            .field_names_plan(&partition_predicate, &partition)
-            .expect("creating the field_name plan");
-
-        // run the created plan, ensuring the output is as expected
-        let results = run_plan(field_names_set_plan).await;
+        let batch = table
+            .distinct_group_keys(&partition_predicate, &["state".to_string()], &partition)
+            .expect("computing distinct group keys");
+        let results = pretty_format_batches(&[batch]).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
 
         let expected = vec![
-            "+--------+--------+--------+--------+------+",
-            "| field1 | field2 | field3 | field4 | time |",
-            "+--------+--------+--------+--------+------+",
-            "| 70.6   |        | 2      |        | 100  |",
-            "| 70.4   | ss     |        |        | 100  |",
-            "| 70.5   | ss     |        |        | 100  |",
-            "+--------+--------+--------+--------+------+",
+            "+-------+",
+            "| state |",
+            "+-------+",
+            "|       |",
+            "| CA    |",
+            "| MA    |",
+            "+-------+",
         ];
-
         assert_eq!(expected, results, "expected output");
+
+        // unknown group columns are an error
+        let res =
+            table.distinct_group_keys(&partition_predicate, &["not_a_tag".to_string()], &partition);
+        assert!(res.is_err(), "Expected error, got {:?}", res);
+
+        // a table without a time column has no rows in the range
+        let mut partition = Partition::new("dummy_partition_key");
+        let table = table_without_time_column(&mut partition);
+        let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+        let batch = table
+            .distinct_group_keys(&partition_predicate, &["state".to_string()], &partition)
+            .unwrap();
+        assert_eq!(batch.num_rows(), 0);
     }
 
     #[test]
@@ -1453,8 +13915,39 @@ mod tests {
             .collect::<Vec<_>>()
     }
 
+    /// Creates a table with a `state` tag and a `temp` field, but no
+    /// time column
+    fn table_without_time_column(partition: &mut Partition) -> Table {
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("no_time"));
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("state", ArrowDataType::Utf8, true),
+            ArrowField::new("temp", ArrowDataType::Float64, true),
+        ]));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec!["MA", "CA"])),
+            Arc::new(Float64Array::from(vec![70.4, 90.0])),
+        ];
+        let batch = RecordBatch::try_new(schema, columns).unwrap();
+        table
+            .append_record_batch(dictionary, &batch, &["state"])
+            .unwrap();
+        table
+    }
+
     ///  Insert the line protocol lines in `lp_lines` into this table
     fn write_lines_to_table(table: &mut Table, dictionary: &mut Dictionary, lp_lines: Vec<&str>) {
+        try_write_lines_to_table(table, dictionary, lp_lines).expect("Appended the row");
+    }
+
+    ///  Insert the line protocol lines in `lp_lines` into this table,
+    ///  returning the first error encountered, if any
+    fn try_write_lines_to_table(
+        table: &mut Table,
+        dictionary: &mut Dictionary,
+        lp_lines: Vec<&str>,
+    ) -> Result<()> {
         let lp_data = lp_lines.join("\n");
 
         let lines: Vec<_> = parse_lines(&lp_data).map(|l| l.unwrap()).collect();
@@ -1468,14 +13961,217 @@ mod tests {
             let table_batches = entry.table_batches().expect("there were table batches");
             for batch in table_batches {
                 let rows = batch.rows().expect("Had rows in the batch");
-                table
-                    .append_rows(dictionary, &rows)
-                    .expect("Appended the row");
+                table.append_rows(dictionary, &rows)?;
             }
         }
+
+        Ok(())
+    }
+
+    /// Returns a serialized `TableWriteBatch` with a row for each
+    /// `(state, count, time)` in `rows`, where `state` is a tag and
+    /// `count` is a `U64Value`, which line protocol does not produce
+    fn u64_rows_table_batch(rows: &[(&str, u64, i64)]) -> Vec<u8> {
+        let mut fbb = flatbuffers::FlatBufferBuilder::new();
+
+        let rows = rows
+            .iter()
+            .map(|&(state, count, time)| {
+                let column = fbb.create_string("state");
+                let value = fbb.create_string(state);
+                let value =
+                    wb::TagValue::create(&mut fbb, &wb::TagValueArgs { value: Some(value) });
+                let state = wb::Value::create(
+                    &mut fbb,
+                    &wb::ValueArgs {
+                        column: Some(column),
+                        value_type: wb::ColumnValue::TagValue,
+                        value: Some(value.as_union_value()),
+                    },
+                );
+
+                let column = fbb.create_string("count");
+                let value = wb::U64Value::create(&mut fbb, &wb::U64ValueArgs { value: count });
+                let count = wb::Value::create(
+                    &mut fbb,
+                    &wb::ValueArgs {
+                        column: Some(column),
+                        value_type: wb::ColumnValue::U64Value,
+                        value: Some(value.as_union_value()),
+                    },
+                );
+
+                let column = fbb.create_string("time");
+                let value = wb::I64Value::create(&mut fbb, &wb::I64ValueArgs { value: time });
+                let time = wb::Value::create(
+                    &mut fbb,
+                    &wb::ValueArgs {
+                        column: Some(column),
+                        value_type: wb::ColumnValue::I64Value,
+                        value: Some(value.as_union_value()),
+                    },
+                );
+
+                let values = fbb.create_vector(&[state, count, time]);
+                wb::Row::create(
+                    &mut fbb,
+                    &wb::RowArgs {
+                        values: Some(values),
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let rows = fbb.create_vector(&rows);
+        let name = fbb.create_string("h2o");
+        let table_batch = wb::TableWriteBatch::create(
+            &mut fbb,
+            &wb::TableWriteBatchArgs {
+                name: Some(name),
+                rows: Some(rows),
+            },
+        );
+        fbb.finish(table_batch, None);
+
+        fbb.finished_data().to_vec()
+    }
+
+    /// Returns a serialized `TableWriteBatch` with a row for each
+    /// `(price, time)` in `rows`, where `price` is a `DecimalValue`
+    /// with `scale` and `precision`
+    fn decimal_rows_table_batch(rows: &[(i128, i64)], scale: u8, precision: u8) -> Vec<u8> {
+        let mut fbb = flatbuffers::FlatBufferBuilder::new();
+
+        let rows = rows
+            .iter()
+            .map(|&(price, time)| {
+                let column = fbb.create_string("price");
+                let value = wb::DecimalValue::create(
+                    &mut fbb,
+                    &wb::DecimalValueArgs {
+                        high: (price >> 64) as i64,
+                        low: price as u64,
+                        scale,
+                        precision,
+                    },
+                );
+                let price = wb::Value::create(
+                    &mut fbb,
+                    &wb::ValueArgs {
+                        column: Some(column),
+                        value_type: wb::ColumnValue::DecimalValue,
+                        value: Some(value.as_union_value()),
+                    },
+                );
+
+                let column = fbb.create_string("time");
+                let value = wb::I64Value::create(&mut fbb, &wb::I64ValueArgs { value: time });
+                let time = wb::Value::create(
+                    &mut fbb,
+                    &wb::ValueArgs {
+                        column: Some(column),
+                        value_type: wb::ColumnValue::I64Value,
+                        value: Some(value.as_union_value()),
+                    },
+                );
+
+                let values = fbb.create_vector(&[price, time]);
+                wb::Row::create(
+                    &mut fbb,
+                    &wb::RowArgs {
+                        values: Some(values),
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let rows = fbb.create_vector(&rows);
+        let name = fbb.create_string("meter");
+        let table_batch = wb::TableWriteBatch::create(
+            &mut fbb,
+            &wb::TableWriteBatchArgs {
+                name: Some(name),
+                rows: Some(rows),
+            },
+        );
+        fbb.finish(table_batch, None);
+
+        fbb.finished_data().to_vec()
     }
 
     fn partition_key_func(_: &ParsedLine<'_>) -> String {
         String::from("the_partition_key")
     }
+
+    #[test]
+    fn test_write_parquet() {
+        use arrow_deps::parquet::{
+            arrow::{ArrowReader, ParquetFileArrowReader},
+            file::{reader::SerializedFileReader, writer::InMemoryWriteableCursor},
+            util::cursor::SliceableCursor,
+        };
+        use std::rc::Rc;
+
+        let mut partition = Partition::new("dummy_partition_key");
+        let dictionary = &mut partition.dictionary;
+        let mut table = Table::new(dictionary.lookup_value_or_insert("table_name"));
+
+        let lp_lines = vec![
+            "h2o,state=MA,city=Boston temp=70.4 100",
+            "h2o,state=CA,city=LA temp=90.0 200",
+            "h2o,state=MA,city=Boston temp=72.4,reading=\"cold\" 250",
+            "h2o,state=CA,city=LA temp=91.0 300",
+        ];
+        write_lines_to_table(&mut table, dictionary, lp_lines);
+
+        let write_parquet = |predicate: &str| {
+            let predicate = parse_simple_predicate(predicate).unwrap();
+            let partition_predicate = partition.compile_predicate(&predicate).unwrap();
+            let cursor = InMemoryWriteableCursor::default();
+            table
+                .write_parquet(
+                    &partition,
+                    &partition_predicate,
+                    cursor.clone(),
+                    ParquetProps::default(),
+                )
+                .map(|_| cursor.data())
+        };
+
+        // general expressions are not supported
+        let res = write_parquet("temp > 71.0");
+        assert!(
+            matches!(res, Err(Error::UnsupportedParquetPredicate)),
+            "unexpected result: {:?}",
+            res
+        );
+
+        let data = write_parquet("state = 'MA'").unwrap();
+        let file_reader = SerializedFileReader::new(SliceableCursor::new(data)).unwrap();
+        let mut arrow_reader = ParquetFileArrowReader::new(Rc::new(file_reader));
+        let batches = arrow_reader
+            .get_record_reader(1024)
+            .unwrap()
+            .collect::<arrow::error::Result<Vec<_>>>()
+            .unwrap();
+
+        let results = pretty_format_batches(&batches).unwrap();
+        let results = results.trim().split('\n').collect::<Vec<_>>();
+
+        let expected = vec![
+            "+--------+---------+-------+------+------+",
+            "| city   | reading | state | temp | time |",
+            "+--------+---------+-------+------+------+",
+            "| Boston |         | MA    | 70.4 | 100  |",
+            "| Boston | cold    | MA    | 72.4 | 250  |",
+            "+--------+---------+-------+------+------+",
+        ];
+        assert_eq!(expected, results, "expected output");
+
+        // the column types round trip
+        assert_eq!(
+            batches[0].schema().fields(),
+            table.arrow_schema_ref(&partition).unwrap().fields()
+        );
+    }
 }